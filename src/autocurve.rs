@@ -0,0 +1,106 @@
+//! Programming of PWM driver automatic fan curves, so that fans stay controlled without the daemon
+
+use std::iter;
+
+use anyhow::Context as _;
+
+use crate::{
+    fan::Curve,
+    pwm::{self, ControlMode, Pwm},
+};
+
+/// Automatic fan curve points for a PWM supporting `count` of them, as temperatures in
+/// millidegrees Celsius and PWM values for a normal PWM up to `max`
+///
+/// Curves with fewer points are padded by repeating their last point, so that speed stays flat
+/// above it.
+pub(crate) fn auto_points(
+    curve: &Curve,
+    count: usize,
+    max: pwm::Value,
+) -> anyhow::Result<Vec<(i64, pwm::Value)>> {
+    anyhow::ensure!(
+        count > 0,
+        "No automatic fan curve point files, the driver does not support automatic fan curves"
+    );
+    let points = curve.points();
+    anyhow::ensure!(
+        points.len() <= count,
+        "Curve has {} points, but the automatic fan curve only supports {count}",
+        points.len()
+    );
+    let last = *points.last().context("Empty curve")?;
+    #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Ok(points
+        .iter()
+        .copied()
+        .chain(iter::repeat(last))
+        .take(count)
+        .map(|(temp, speed)| {
+            (
+                (temp * 1000.0).round() as i64,
+                (f64::from(speed) * f64::from(max)).round() as pwm::Value,
+            )
+        })
+        .collect())
+}
+
+/// Write the automatic fan curve of a PWM, and hand it fan control by writing `auto_mode` to its
+/// `pwmX_enable` file
+pub(crate) fn program(
+    pwm: &Pwm<()>,
+    curve: &Curve,
+    auto_mode: u8,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let points = auto_points(curve, pwm.auto_point_count(), pwm.max())
+        .with_context(|| format!("Failed to program PWM {pwm} automatic fan curve"))?;
+    for (index, (temp, value)) in (1..).zip(points) {
+        if dry_run {
+            log::info!(
+                "PWM {pwm} automatic curve point {index} would be set to {temp} millidegrees \
+                 Celsius, PWM value {value}"
+            );
+        } else {
+            pwm.set_auto_point(index, temp, value)?;
+            log::info!(
+                "PWM {pwm} automatic curve point {index} set to {temp} millidegrees Celsius, \
+                 PWM value {value}"
+            );
+        }
+    }
+    let mode = ControlMode::from(auto_mode);
+    if dry_run {
+        log::info!("PWM {pwm} mode would be set to {mode}");
+    } else {
+        pwm.set_mode(mode)?;
+        log::info!("PWM {pwm} mode set to {mode}, fan is controlled by the driver");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_points() {
+        let curve: Curve = "35:0,45:50,50:100".parse().unwrap();
+        assert_eq!(
+            auto_points(&curve, 3, 255).unwrap(),
+            vec![(35000, 0), (45000, 128), (50000, 255)]
+        );
+        assert_eq!(
+            auto_points(&curve, 5, 100).unwrap(),
+            vec![
+                (35000, 0),
+                (45000, 50),
+                (50000, 100),
+                (50000, 100),
+                (50000, 100)
+            ]
+        );
+        assert!(auto_points(&curve, 2, 255).is_err());
+        assert!(auto_points(&curve, 0, 255).is_err());
+    }
+}
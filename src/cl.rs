@@ -2,49 +2,144 @@
 
 use std::{
     fmt, fs, io,
-    ops::Range,
-    path::{self, PathBuf},
+    net::{SocketAddr, ToSocketAddrs as _},
+    ops::{Range, RangeInclusive},
+    path::{self, Path, PathBuf},
     str::FromStr,
+    time::Duration,
 };
 
-use clap::{Parser, Subcommand};
+use anyhow::Context as _;
+use clap::{Parser, Subcommand, ValueEnum};
 
-use crate::{fan::Thresholds, probe::Temp};
+use crate::{
+    fan::{Curve, Thresholds},
+    pid,
+    probe::Temp,
+    profile::TimeWindow,
+    pwm,
+};
 
 /// Speed percentage
 pub(crate) type Percentage = u8;
 
+/// PWM filepath value to use all discovered PWMs
+pub(crate) const PWM_AUTO: &str = "auto";
+
 /// PWM operation settings
 #[derive(Clone, Debug)]
 pub(crate) struct PwmSettings {
     /// Sysfs filepath
     pub filepath: PathBuf,
-    /// Fan characteristics
-    pub thresholds: Thresholds,
+    /// Fan characteristics, if set
+    pub thresholds: Option<Thresholds>,
+    /// Are higher PWM values lower speeds
+    pub inverted: bool,
+}
+
+impl PwmSettings {
+    /// Does this stand for all discovered PWMs
+    pub(crate) fn is_auto(&self) -> bool {
+        self.filepath == Path::new(PWM_AUTO)
+    }
 }
 
 impl FromStr for PwmSettings {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut tokens = s.rsplitn(3, ':');
-        let stop = tokens
-            .next()
-            .ok_or("Missing stop value")?
-            .parse()
-            .map_err(|_| "Invalid stop value")?;
-        let start = tokens
-            .next()
-            .ok_or("Missing start value")?
-            .parse()
-            .map_err(|_| "Invalid start value")?;
-        let filepath = tokens.next().ok_or("Missing filepath")?.into();
+        let tokens: Vec<&str> = s.rsplitn(3, ':').collect();
+        let (filepath, thresholds) = match tokens.as_slice() {
+            [filepath] => (filepath, None),
+            [stop, start, filepath] => (
+                filepath,
+                Some(Thresholds {
+                    min_start: start.parse().map_err(|_| "Invalid start value")?,
+                    max_stop: stop.parse().map_err(|_| "Invalid stop value")?,
+                }),
+            ),
+            _ => return Err("Missing start or stop value"),
+        };
+        if filepath.is_empty() {
+            return Err("Missing filepath");
+        }
         Ok(Self {
-            filepath,
-            thresholds: Thresholds {
-                min_start: start,
-                max_stop: stop,
-            },
+            filepath: filepath.into(),
+            thresholds,
+            inverted: false,
+        })
+    }
+}
+
+/// PWM value for full speed, for PWMs not using the 0-255 range
+#[derive(Clone, Debug)]
+pub(crate) struct PwmMax {
+    /// Sysfs filepath
+    pub filepath: PathBuf,
+    /// Value for full speed
+    pub max: pwm::Value,
+}
+
+impl PwmMax {
+    /// Value for full speed set for a PWM, if any
+    pub(crate) fn find(pwm_maxs: &[Self], filepath: &Path) -> Option<pwm::Value> {
+        pwm_maxs
+            .iter()
+            .find(|m| m.filepath == filepath)
+            .map(|m| m.max)
+    }
+}
+
+impl FromStr for PwmMax {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (filepath, max) = s.rsplit_once('=').ok_or("Missing maximum value")?;
+        if filepath.is_empty() {
+            return Err("Missing filepath");
+        }
+        let max = max.parse().map_err(|_| "Invalid maximum value")?;
+        if max == 0 {
+            return Err("Invalid maximum value");
+        }
+        Ok(Self {
+            filepath: filepath.into(),
+            max,
+        })
+    }
+}
+
+/// `pwmX_enable` value for manual control, for drivers not using 1
+#[derive(Clone, Debug)]
+pub(crate) struct PwmEnableValue {
+    /// Sysfs filepath
+    pub filepath: PathBuf,
+    /// Manual control mode value
+    pub value: u8,
+}
+
+impl PwmEnableValue {
+    /// Manual control mode value set for a PWM, if any
+    pub(crate) fn find(values: &[Self], filepath: &Path) -> Option<u8> {
+        values
+            .iter()
+            .find(|v| v.filepath == filepath)
+            .map(|v| v.value)
+    }
+}
+
+impl FromStr for PwmEnableValue {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (filepath, value) = s.rsplit_once('=').ok_or("Missing enable value")?;
+        if filepath.is_empty() {
+            return Err("Missing filepath");
+        }
+        let value = value.parse().map_err(|_| "Invalid enable value")?;
+        Ok(Self {
+            filepath: filepath.into(),
+            value,
         })
     }
 }
@@ -52,7 +147,7 @@ impl FromStr for PwmSettings {
 /// Hwmon path and temperature range
 #[derive(Clone, Debug)]
 pub(crate) struct HwmonSettings {
-    /// Sysfs filepath
+    /// Sysfs filepath, or `chip:label` sensor
     pub filepath: PathBuf,
     /// Temperature range
     pub temp: Option<Range<Temp>>,
@@ -62,8 +157,20 @@ impl FromStr for HwmonSettings {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut tokens = s.splitn(3, ':');
-        let filepath = tokens.next().ok_or("Missing filepath")?.into();
+        let mut tokens = s.splitn(4, ':');
+        let first = tokens.next().ok_or("Missing filepath")?;
+        let filepath = if first.contains(path::MAIN_SEPARATOR) {
+            first.into()
+        } else {
+            let label = tokens
+                .next()
+                .filter(|l| !l.is_empty())
+                .ok_or("Missing sensor label")?;
+            format!("{first}:{label}").into()
+        };
+        if tokens.clone().count() > 2 {
+            return Err("Too many values");
+        }
         let start = tokens
             .next()
             .map(str::parse)
@@ -74,22 +181,534 @@ impl FromStr for HwmonSettings {
             .map(str::parse)
             .transpose()
             .map_err(|_| "Invalid max speed temp value")?;
-        Ok(Self {
-            filepath,
-            temp: if let (Some(start), Some(end)) = (start, end) {
-                Some(Range { start, end })
-            } else {
-                None
+        let temp = if let (Some(start), Some(end)) = (start, end) {
+            let range = Range { start, end };
+            check_parsed_range(&range)?;
+            Some(range)
+        } else {
+            None
+        };
+        Ok(Self { filepath, temp })
+    }
+}
+
+impl HwmonSettings {
+    /// Is this hwmon referenced by a selector, ie. as a zone input
+    pub(crate) fn is_selected_by(&self, selector: &DriveSelector) -> bool {
+        match selector {
+            DriveSelector::DrivePath(p) => *p == self.filepath,
+            // `chip:label` sensors have no path separator
+            DriveSelector::Interface(s) => Path::new(s) == self.filepath,
+            DriveSelector::Serial(_) | DriveSelector::Model(_) => false,
+        }
+    }
+
+    /// Convert temperature range from `unit` to Celsius
+    pub(crate) fn into_celsius(self, unit: TempUnit) -> Self {
+        Self {
+            temp: self.temp.map(|r| unit.range_to_celsius(&r)),
+            ..self
+        }
+    }
+}
+
+/// Physically sane temperature settings, in Celsius
+pub(crate) const TEMP_BOUNDS: RangeInclusive<Temp> = -10.0..=120.0;
+
+/// Check that a temperature setting in Celsius is within [`TEMP_BOUNDS`]
+pub(crate) fn check_temp(temp: Temp) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        TEMP_BOUNDS.contains(&temp),
+        "Temperature {} is out of the {} to {} range",
+        TempUnit::Celsius.display(temp),
+        TempUnit::Celsius.display(*TEMP_BOUNDS.start()),
+        TempUnit::Celsius.display(*TEMP_BOUNDS.end())
+    );
+    Ok(())
+}
+
+/// Check that a temperature range in Celsius is not inverted or empty, and within
+/// [`TEMP_BOUNDS`]
+pub(crate) fn check_temp_range(range: &Range<Temp>) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        range.start < range.end,
+        "Min speed temperature {} must be lower than max speed temperature {}",
+        TempUnit::Celsius.display(range.start),
+        TempUnit::Celsius.display(range.end)
+    );
+    check_temp(range.start)?;
+    check_temp(range.end)
+}
+
+/// Check that a parsed temperature range, in any unit, has finite bounds and is not inverted or
+/// empty
+fn check_parsed_range(range: &Range<Temp>) -> Result<(), &'static str> {
+    if !range.start.is_finite() {
+        return Err("Invalid min speed temp value");
+    }
+    if !range.end.is_finite() {
+        return Err("Invalid max speed temp value");
+    }
+    if range.start >= range.end {
+        return Err("Min speed temp value must be lower than max speed temp value");
+    }
+    Ok(())
+}
+
+/// Default drive temperature range
+const DEFAULT_DRIVE_TEMP_RANGE: Range<Temp> = Range {
+    start: 30.0,
+    end: 50.0,
+};
+
+/// Default PID control mode setpoint, in Celsius
+pub(crate) const DEFAULT_PID_SETPOINT: Temp = 40.0;
+
+/// Temperature range for drives matching a selector
+pub(crate) type SelectorTempRange = (DriveSelector, Range<Temp>);
+
+/// Drive temperature range value, bound of the range for all drives or range for some drives
+#[derive(Clone, Debug)]
+pub(crate) enum DriveTempRange {
+    /// Bound of the range for all drives
+    Temp(Temp),
+    /// Range for drives matching a selector
+    Drive(DriveSelector, Range<Temp>),
+}
+
+impl FromStr for DriveTempRange {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((selector, range)) = s.split_once('=') else {
+            return s
+                .parse()
+                .ok()
+                .filter(|t: &Temp| t.is_finite())
+                .map(Self::Temp)
+                .ok_or("Invalid temperature value");
+        };
+        Ok(Self::Drive(selector.parse()?, temp_range(range)?))
+    }
+}
+
+/// Parse temperature range from `TEMP:TEMP`
+fn temp_range(s: &str) -> Result<Range<Temp>, &'static str> {
+    let (start, end) = s.split_once(':').ok_or("Missing max speed temp value")?;
+    let start = start.parse().map_err(|_| "Invalid min speed temp value")?;
+    let end = end.parse().map_err(|_| "Invalid max speed temp value")?;
+    let range = Range { start, end };
+    check_parsed_range(&range)?;
+    Ok(range)
+}
+
+impl fmt::Display for DriveTempRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DriveTempRange::Temp(temp) => write!(f, "{temp}"),
+            DriveTempRange::Drive(selector, range) => {
+                write!(f, "{selector}={}:{}", range.start, range.end)
+            }
+        }
+    }
+}
+
+impl DriveTempRange {
+    /// Convert temperatures from `unit` to Celsius
+    pub(crate) fn into_celsius(self, unit: TempUnit) -> Self {
+        match self {
+            Self::Temp(temp) => Self::Temp(unit.to_celsius(temp)),
+            Self::Drive(selector, range) => Self::Drive(selector, unit.range_to_celsius(&range)),
+        }
+    }
+
+    /// Split values in Celsius into the range for all drives, and the ranges for drive selectors,
+    /// checking them with [`check_temp_range`]
+    pub(crate) fn split(
+        values: Vec<Self>,
+    ) -> anyhow::Result<(Range<Temp>, Vec<SelectorTempRange>)> {
+        let mut temps = Vec::new();
+        let mut selector_ranges = Vec::new();
+        for value in values {
+            match value {
+                Self::Temp(temp) => temps.push(temp),
+                Self::Drive(selector, range) => selector_ranges.push((selector, range)),
+            }
+        }
+        let range = match temps.as_slice() {
+            [] => DEFAULT_DRIVE_TEMP_RANGE,
+            [start, end] => Range {
+                start: *start,
+                end: *end,
             },
+            _ => anyhow::bail!(
+                "Expected 2 temperatures for the drive temperature range, got {}",
+                temps.len()
+            ),
+        };
+        check_temp_range(&range).context("Invalid drive temperature range")?;
+        for (selector, selector_range) in &selector_ranges {
+            check_temp_range(selector_range)
+                .with_context(|| format!("Invalid drive {selector} temperature range"))?;
+        }
+        Ok((range, selector_ranges))
+    }
+}
+
+/// Fan zone settings
+#[derive(Clone, Debug)]
+pub(crate) struct ZoneSettings {
+    /// Drive selectors or hwmon filepaths
+    pub inputs: Vec<DriveSelector>,
+    /// PWM filepaths
+    pub pwms: Vec<PathBuf>,
+    /// Interval between updates, instead of the daemon one
+    pub interval: Option<Duration>,
+}
+
+impl FromStr for ZoneSettings {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (inputs, pwms) = s.split_once('=').ok_or("Missing PWM filepaths")?;
+        let (pwms, interval) = match pwms.rsplit_once('@') {
+            Some((pwms, interval)) => {
+                let interval =
+                    humantime::parse_duration(interval).map_err(|_| "Invalid zone interval")?;
+                if interval.is_zero() {
+                    return Err("Invalid zone interval");
+                }
+                (pwms, Some(interval))
+            }
+            None => (pwms, None),
+        };
+        let inputs = inputs
+            .split(',')
+            .filter(|i| !i.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if inputs.is_empty() {
+            return Err("Missing drives or hwmons");
+        }
+        let pwms: Vec<PathBuf> = pwms
+            .split(',')
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+            .collect();
+        if pwms.is_empty() {
+            return Err("Missing PWM filepaths");
+        }
+        Ok(Self {
+            inputs,
+            pwms,
+            interval,
+        })
+    }
+}
+
+/// Temperature offset for drives or a hwmon
+#[derive(Clone, Debug)]
+pub(crate) struct TempOffset {
+    /// Drive selector or hwmon filepath
+    pub input: DriveSelector,
+    /// Offset to add to probed temperatures
+    pub offset: Temp,
+}
+
+impl FromStr for TempOffset {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (input, offset) = s.split_once('=').ok_or("Missing temperature offset")?;
+        if input.is_empty() {
+            return Err("Missing drive or hwmon");
+        }
+        let offset: Temp = offset.parse().map_err(|_| "Invalid temperature offset")?;
+        if !offset.is_finite() {
+            return Err("Invalid temperature offset");
+        }
+        Ok(Self {
+            input: input.parse()?,
+            offset,
+        })
+    }
+}
+
+impl TempOffset {
+    /// Convert offset from `unit` to Celsius
+    pub(crate) fn into_celsius(self, unit: TempUnit) -> Self {
+        Self {
+            offset: unit.delta_to_celsius(self.offset),
+            ..self
+        }
+    }
+
+    /// Index of the hwmon this offset is for, if any
+    pub(crate) fn hwmon_index(&self, hwmons: &[HwmonSettings]) -> Option<usize> {
+        hwmons.iter().position(|h| h.is_selected_by(&self.input))
+    }
+}
+
+/// Weight of some drives in the fan speed of their zones
+#[derive(Clone, Debug)]
+pub(crate) struct DriveWeight {
+    /// Drive selector
+    pub selector: DriveSelector,
+    /// Strictly positive weight
+    pub weight: f64,
+}
+
+impl FromStr for DriveWeight {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, weight) = s.split_once('=').ok_or("Missing drive weight")?;
+        if selector.is_empty() {
+            return Err("Missing drive");
+        }
+        let weight: f64 = weight.parse().map_err(|_| "Invalid drive weight")?;
+        if !weight.is_finite() || weight <= 0.0 {
+            return Err("Drive weight must be a strictly positive number");
+        }
+        Ok(Self {
+            selector: selector.parse()?,
+            weight,
+        })
+    }
+}
+
+/// Scale of the values of a hwmon temperature input
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, strum::EnumString, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub(crate) enum TempScale {
+    /// Millidegrees Celsius, as documented for sysfs `tempN_input` files
+    #[default]
+    Milli,
+    /// Whole degrees Celsius, for non-conforming drivers
+    Degree,
+}
+
+impl TempScale {
+    /// Convert a raw sysfs value to Celsius
+    pub(crate) fn to_celsius(self, raw: i64) -> Temp {
+        #[expect(clippy::cast_precision_loss)]
+        let raw = raw as f64;
+        match self {
+            Self::Milli => raw / 1000.0,
+            Self::Degree => raw,
+        }
+    }
+}
+
+/// Temperature scale override for a hwmon
+#[derive(Clone, Debug)]
+pub(crate) struct HwmonTempScale {
+    /// Hwmon filepath or `chip:label` sensor
+    pub input: DriveSelector,
+    /// Scale
+    pub scale: TempScale,
+}
+
+impl FromStr for HwmonTempScale {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (input, scale) = s.rsplit_once('=').ok_or("Missing temperature scale")?;
+        if input.is_empty() {
+            return Err("Missing hwmon");
+        }
+        Ok(Self {
+            input: input.parse()?,
+            scale: scale
+                .parse()
+                .map_err(|_| "Invalid temperature scale, expected milli or degree")?,
         })
     }
 }
 
+impl HwmonTempScale {
+    /// Index of the hwmon this scale is for, if any
+    pub(crate) fn hwmon_index(&self, hwmons: &[HwmonSettings]) -> Option<usize> {
+        hwmons.iter().position(|h| h.is_selected_by(&self.input))
+    }
+}
+
+/// NVMe temperature sensor to probe
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum NvmeSensor {
+    /// Temperature reported for the whole drive
+    Composite,
+    /// Highest temperature of all sensors
+    #[default]
+    Max,
+    /// Sensor labeled `Sensor N` (ie. controller or NAND flash)
+    Sensor(u8),
+}
+
+impl FromStr for NvmeSensor {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "composite" => Ok(Self::Composite),
+            "max" => Ok(Self::Max),
+            n => n
+                .parse()
+                .ok()
+                .filter(|n| *n > 0)
+                .map(Self::Sensor)
+                .ok_or("Invalid NVMe sensor, expected composite, max or a sensor number"),
+        }
+    }
+}
+
+impl fmt::Display for NvmeSensor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Composite => write!(f, "composite"),
+            Self::Max => write!(f, "max"),
+            Self::Sensor(n) => write!(f, "{n}"),
+        }
+    }
+}
+
+/// NVMe sensor value, sensor for all drives or for some drives
+#[derive(Clone, Debug)]
+pub(crate) enum DriveNvmeSensor {
+    /// Sensor for all drives
+    Sensor(NvmeSensor),
+    /// Sensor for drives matching a selector
+    Drive(DriveSelector, NvmeSensor),
+}
+
+impl FromStr for DriveNvmeSensor {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((selector, sensor)) = s.split_once('=') else {
+            return s.parse().map(Self::Sensor);
+        };
+        Ok(Self::Drive(selector.parse()?, sensor.parse()?))
+    }
+}
+
+impl DriveNvmeSensor {
+    /// Split values into the sensor for all drives, and the sensors for drive selectors
+    pub(crate) fn split(
+        values: &[Self],
+    ) -> anyhow::Result<(NvmeSensor, Vec<(DriveSelector, NvmeSensor)>)> {
+        let mut sensors = Vec::new();
+        let mut selector_sensors = Vec::new();
+        for value in values {
+            match value {
+                Self::Sensor(sensor) => sensors.push(*sensor),
+                Self::Drive(selector, sensor) => selector_sensors.push((selector.clone(), *sensor)),
+            }
+        }
+        let sensor = match sensors.as_slice() {
+            [] => NvmeSensor::default(),
+            [sensor] => *sensor,
+            _ => anyhow::bail!(
+                "Expected a single NVMe sensor for all drives, got {}",
+                sensors.len()
+            ),
+        };
+        Ok((sensor, selector_sensors))
+    }
+}
+
+/// Parse and resolve socket address from `HOST:PORT`
+fn socket_addr(s: &str) -> Result<SocketAddr, String> {
+    s.to_socket_addrs()
+        .map_err(|e| format!("Invalid address {s:?}: {e}"))?
+        .next()
+        .ok_or_else(|| format!("No address found for {s:?}"))
+}
+
 /// Parse percentage integer value
 fn percentage(s: &str) -> Result<u8, String> {
     clap_num::number_range(s, 0, 100)
 }
 
+/// Parse non null percentage integer value
+fn non_null_percentage(s: &str) -> Result<u8, String> {
+    clap_num::number_range(s, 1, 100)
+}
+
+/// Parse temperature smoothing factor
+fn smoothing_alpha(s: &str) -> Result<f64, String> {
+    let val: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if val > 0.0 && val <= 1.0 {
+        Ok(val)
+    } else {
+        Err("Smoothing factor must be in ]0-1]".to_owned())
+    }
+}
+
+/// Parse PID gain value
+fn gain(s: &str) -> Result<f64, String> {
+    let val: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if pid::is_valid_gain(val) {
+        Ok(val)
+    } else {
+        Err("Gain must be a positive number".to_owned())
+    }
+}
+
+/// Parse speed blend weight
+fn blend_weight(s: &str) -> Result<f64, String> {
+    let val: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if val.is_finite() && val > 0.0 {
+        Ok(val)
+    } else {
+        Err("Weight must be a strictly positive number".to_owned())
+    }
+}
+
+/// Parse temperature decay rate
+fn decay_rate(s: &str) -> Result<Temp, String> {
+    let val: Temp = s.parse().map_err(|e| format!("{e}"))?;
+    if val.is_finite() && val > 0.0 {
+        Ok(val)
+    } else {
+        Err("Decay rate must be a strictly positive number".to_owned())
+    }
+}
+
+/// Parse `NAME=VALUE` fan profile setting
+fn profile_value<T, F>(s: &str, parse: F) -> Result<(String, T), String>
+where
+    F: FnOnce(&str) -> Result<T, String>,
+{
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| "Expected PROFILE=VALUE".to_owned())?;
+    if name.is_empty() {
+        return Err("Empty profile name".to_owned());
+    }
+    Ok((name.to_owned(), parse(value)?))
+}
+
+/// Parse fan profile time window
+fn profile_window(s: &str) -> Result<(String, TimeWindow), String> {
+    profile_value(s, |v| v.parse().map_err(ToOwned::to_owned))
+}
+
+/// Parse fan profile temperature range
+fn profile_temp_range(s: &str) -> Result<(String, Range<Temp>), String> {
+    profile_value(s, |v| temp_range(v).map_err(ToOwned::to_owned))
+}
+
+/// Parse fan profile temperature curve
+fn profile_temp_curve(s: &str) -> Result<(String, Curve), String> {
+    profile_value(s, |v| v.parse().map_err(ToOwned::to_owned))
+}
+
+/// Parse fan profile minimum speed percentage
+fn profile_percentage(s: &str) -> Result<(String, Percentage), String> {
+    profile_value(s, percentage)
+}
+
 /// Hddfancontrol command line arguments
 #[derive(Parser, Debug)]
 #[command(version, about)]
@@ -98,6 +717,14 @@ pub(crate) struct Args {
     #[arg(short, default_value_t = log::Level::Info)]
     pub verbosity: log::Level,
 
+    /// Per module logging levels, overriding -v when set
+    /// (ie. `hddfancontrol::probe=trace,hddfancontrol=info`).
+    /// Modules are `hddfancontrol::{control,device,fan,probe,pwm,sysfs}`,
+    /// `hddfancontrol::device` includes drive power state queries and hwmon probing,
+    /// `hddfancontrol::probe` drive temperature probing methods.
+    #[arg(long)]
+    pub log_filter: Option<String>,
+
     /// Format for timestamps in logs
     #[arg(
         long,
@@ -111,19 +738,328 @@ pub(crate) struct Args {
     #[arg(long, env = "LOG_DIR", default_value = ".")]
     pub log_dir: PathBuf,
 
-    /// Max size of each log file before rotating, e.g. "10MB", "500KB"
-    #[arg(long, default_value = "10MB", help = "Maximum logfile size before rotation")]
-    pub log_max_size: String,
+    /// Max size of each log file before rotating, e.g. "10MB", "500KB"
+    #[arg(
+        long,
+        default_value = "10MB",
+        help = "Maximum logfile size before rotation"
+    )]
+    pub log_max_size: String,
+
+    /// Number of rotated log files to retain
+    #[arg(long, default_value_t = 5, help = "Number of rotated logfiles to keep")]
+    pub log_retain: usize,
+
+    /// Never write to PWM files, only log what the daemon or `program-auto-curve` would write.
+    /// Drive probing and fan speed computation run normally, and fan settings are not restored
+    /// on exit.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Main action
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// Log record layout
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum LogFormat {
+    /// `[datetime] [LEVEL] message`, with datetime set by `--log-datetime-format`
+    Bracketed,
+    /// RFC 3339 timestamp followed by level and message, recognized by lnav and most log viewers
+    Rfc3339,
+    /// logfmt `key=value` pairs
+    Logfmt,
+}
+
+/// Where daemon logs are written
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum LogBackend {
+    /// Rotated files in --log-dir, duplicated to stdout unless --no-stdout, or stdout only
+    /// with --stdout-only
+    File,
+    /// systemd journal, with drive, temperature and speed structured fields
+    #[cfg(feature = "journald")]
+    Journald,
+}
+
+/// When log files are rotated
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub(crate) enum LogRotate {
+    /// When the file reaches --log-max-size
+    #[default]
+    Size,
+    /// Every day, or when the file reaches --log-max-size
+    Daily,
+    /// Every hour, or when the file reaches --log-max-size
+    Hourly,
+}
+
+impl LogRotate {
+    /// Logger rotation criterion, with max file size in bytes
+    pub(crate) fn criterion(self, max_size: u64) -> flexi_logger::Criterion {
+        use flexi_logger::{Age, Criterion};
+        match self {
+            Self::Size => Criterion::Size(max_size),
+            Self::Daily => Criterion::AgeOrSize(Age::Day, max_size),
+            Self::Hourly => Criterion::AgeOrSize(Age::Hour, max_size),
+        }
+    }
+}
+
+/// Fan speed control strategy
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum ControlMode {
+    /// Speed proportional to where the temperature is in its range
+    Curve,
+    /// PID controller holding the hottest drive at a setpoint temperature
+    Pid,
+}
+
+/// Fan speed when all drives are spun down
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum SpunDownBehavior {
+    /// Set fans to -m/--min-fan-speed-prct
+    Min,
+    /// Keep the last fan speed
+    Hold,
+    /// Stop fans, they start again using their start value when a drive spins up
+    Off,
+}
+
+/// Reaction to a drive temperature probing failure
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum ProbeErrorAction {
+    /// Fail the iteration, see --max-consecutive-errors
+    Exit,
+    /// Ignore the drive temperature, and set all fans to full speed after
+    /// --probe-error-threshold consecutive failures for a drive, until probing recovers
+    MaxSpeed,
+    /// Ignore the drive temperature
+    Ignore,
+}
+
+/// How temperatures of several drives are combined
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum Aggregation {
+    /// Hottest drive
+    Max,
+    /// Average of the drives
+    Mean,
+    /// 90th percentile of the drives (nearest rank), ignoring the hottest 10%
+    P90,
+}
+
+impl Aggregation {
+    /// Combine values, `None` if there are none
+    pub(crate) fn aggregate(self, values: &[f64]) -> Option<f64> {
+        match self {
+            Aggregation::Max => values.iter().copied().reduce(f64::max),
+            #[expect(clippy::cast_precision_loss)]
+            Aggregation::Mean => {
+                (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+            }
+            Aggregation::P90 => {
+                let mut sorted = values.to_vec();
+                sorted.sort_unstable_by(f64::total_cmp);
+                // Nearest rank, so that with few drives this is the hottest drive
+                let rank = sorted.len().saturating_mul(9).div_ceil(10);
+                sorted.get(rank.checked_sub(1)?).copied()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Aggregation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Aggregation::Max => write!(f, "max"),
+            Aggregation::Mean => write!(f, "mean"),
+            Aggregation::P90 => write!(f, "p90"),
+        }
+    }
+}
+
+/// Statistic of the drive temperatures over a time window
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum WindowStat {
+    /// Highest temperature
+    #[default]
+    Max,
+    /// Average temperature
+    Mean,
+    /// Temperature percentile (nearest rank), in [1-100]
+    Percentile(u8),
+}
+
+impl WindowStat {
+    /// Compute statistic of values, `None` if there are none
+    pub(crate) fn compute(self, values: &[f64]) -> Option<f64> {
+        match self {
+            WindowStat::Max => values.iter().copied().reduce(f64::max),
+            #[expect(clippy::cast_precision_loss)]
+            WindowStat::Mean => {
+                (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+            }
+            WindowStat::Percentile(percentile) => {
+                let mut sorted = values.to_vec();
+                sorted.sort_unstable_by(f64::total_cmp);
+                let rank = sorted
+                    .len()
+                    .saturating_mul(usize::from(percentile))
+                    .div_ceil(100);
+                sorted.get(rank.checked_sub(1)?).copied()
+            }
+        }
+    }
+}
+
+impl FromStr for WindowStat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "max" => Ok(Self::Max),
+            "mean" => Ok(Self::Mean),
+            _ => {
+                let percentile: u8 = s
+                    .strip_prefix('p')
+                    .and_then(|p| p.parse().ok())
+                    .ok_or("Invalid statistic, expected max, mean or pXX")?;
+                if (1..=100).contains(&percentile) {
+                    Ok(Self::Percentile(percentile))
+                } else {
+                    Err("Invalid percentile, expected 1 to 100")
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for WindowStat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowStat::Max => write!(f, "max"),
+            WindowStat::Mean => write!(f, "mean"),
+            WindowStat::Percentile(percentile) => write!(f, "p{percentile}"),
+        }
+    }
+}
+
+/// Shape of the fan speed ramp between the bounds of a temperature range
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub(crate) enum CurveShape {
+    /// Speed proportional to the position in the range
+    #[default]
+    Linear,
+    /// Square of the position in the range
+    Quadratic,
+    /// Cube of the position in the range
+    Cubic,
+    /// Exponential of the position in the range, staying low longest
+    Exp,
+}
+
+impl CurveShape {
+    /// Growth rate of the exponential shape
+    const EXP_RATE: f64 = 4.0;
+
+    /// Map a position in a range, in [0-1], to a speed ratio in [0-1], 0 and 1 being unchanged
+    pub(crate) fn apply(self, position: f64) -> f64 {
+        match self {
+            Self::Linear => position,
+            Self::Quadratic => position.powi(2),
+            Self::Cubic => position.powi(3),
+            Self::Exp => (Self::EXP_RATE * position).exp_m1() / Self::EXP_RATE.exp_m1(),
+        }
+    }
+}
+
+/// Temperature unit for arguments and logs
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub(crate) enum TempUnit {
+    /// Degrees Celsius
+    #[default]
+    Celsius,
+    /// Degrees Fahrenheit
+    Fahrenheit,
+}
+
+impl TempUnit {
+    /// Convert a temperature in this unit to Celsius
+    pub(crate) fn to_celsius(self, temp: Temp) -> Temp {
+        match self {
+            Self::Celsius => temp,
+            Self::Fahrenheit => (temp - 32.0) / 1.8,
+        }
+    }
+
+    /// Convert a temperature difference in this unit to Celsius
+    pub(crate) fn delta_to_celsius(self, delta: Temp) -> Temp {
+        match self {
+            Self::Celsius => delta,
+            Self::Fahrenheit => delta / 1.8,
+        }
+    }
+
+    /// Convert a temperature difference in Celsius to this unit
+    pub(crate) fn delta_from_celsius(self, delta: Temp) -> Temp {
+        match self {
+            Self::Celsius => delta,
+            Self::Fahrenheit => delta * 1.8,
+        }
+    }
+
+    /// Unit symbol
+    pub(crate) fn symbol(self) -> &'static str {
+        match self {
+            Self::Celsius => "°C",
+            Self::Fahrenheit => "°F",
+        }
+    }
+
+    /// Convert a temperature range in this unit to Celsius
+    pub(crate) fn range_to_celsius(self, range: &Range<Temp>) -> Range<Temp> {
+        Range {
+            start: self.to_celsius(range.start),
+            end: self.to_celsius(range.end),
+        }
+    }
+
+    /// Display a temperature in Celsius in this unit
+    pub(crate) fn display(self, temp: Temp) -> DisplayTemp {
+        DisplayTemp { temp, unit: self }
+    }
+}
 
-    /// Number of rotated log files to retain
-    #[arg(long, default_value_t = 5, help = "Number of rotated logfiles to keep")]
-    pub log_retain: usize,
+/// Temperature in Celsius, displayed in a unit with its symbol
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DisplayTemp {
+    /// Temperature in Celsius
+    temp: Temp,
+    /// Display unit
+    unit: TempUnit,
+}
 
-    /// Main action
-    #[command(subcommand)]
-    pub command: Command,
+impl fmt::Display for DisplayTemp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = match self.unit {
+            TempUnit::Celsius => self.temp,
+            // Round to hide conversion noise, ie. 38.5°C giving 101.30000000000001°F
+            TempUnit::Fahrenheit => ((self.temp * 1.8 + 32.0) * 100.0).round() / 100.0,
+        };
+        let symbol = self.unit.symbol();
+        if let Some(precision) = f.precision() {
+            write!(f, "{value:.precision$}{symbol}")
+        } else {
+            write!(f, "{value}{symbol}")
+        }
+    }
 }
 
+/// Directory of drive symlinks named after their interface, model and serial number
+const DRIVES_BY_ID_DIR: &str = "/dev/disk/by-id";
+
 /// Drive selector matching 0 or more drives
 #[derive(Clone, Debug)]
 pub(crate) enum DriveSelector {
@@ -131,36 +1067,110 @@ pub(crate) enum DriveSelector {
     Interface(String),
     /// A single drive path
     DrivePath(PathBuf),
+    /// Drive with a serial number
+    Serial(String),
+    /// Drives with a model matching a pattern, `*` matching any characters
+    Model(String),
 }
 
 impl DriveSelector {
     /// Resolve selector to drive paths
     pub(crate) fn to_drive_paths(&self) -> io::Result<Vec<PathBuf>> {
+        self.to_drive_paths_in(Path::new(DRIVES_BY_ID_DIR))
+    }
+
+    /// Resolve selector to drive paths, from the drive symlinks in `by_id_dir`
+    fn to_drive_paths_in(&self, by_id_dir: &Path) -> io::Result<Vec<PathBuf>> {
         match self {
             DriveSelector::Interface(itf) => {
-                let drives = fs::read_dir("/dev/disk/by-id")?.collect::<io::Result<Vec<_>>>()?;
                 let prefix = format!("{itf}-");
-                Ok(drives
+                Ok(by_id_drives(by_id_dir)?
                     .into_iter()
-                    .map(|e| e.path())
-                    .filter(|p| {
-                        p.file_name().and_then(|f| f.to_str()).is_some_and(|f| {
-                            f.starts_with(&prefix)
-                                && !f.trim_end_matches(char::is_numeric).ends_with("-part")
-                        })
-                    })
+                    .filter(|(name, _)| name.starts_with(&prefix))
+                    .map(|(_, path)| path)
                     .collect())
             }
             DriveSelector::DrivePath(p) => Ok(vec![p.to_owned()]),
+            DriveSelector::Serial(_) | DriveSelector::Model(_) => {
+                let drives = by_id_drives(by_id_dir)?;
+                let mut paths: Vec<PathBuf> = Vec::new();
+                for (name, path) in &drives {
+                    let Some((model, serial)) = name
+                        .split_once('-')
+                        .and_then(|(_, model_serial)| model_serial.rsplit_once('_'))
+                    else {
+                        continue;
+                    };
+                    let matched = match self {
+                        DriveSelector::Serial(s) => serial == s,
+                        DriveSelector::Model(m) => wildcard_match(&m.replace(' ', "_"), model),
+                        DriveSelector::Interface(_) | DriveSelector::DrivePath(_) => false,
+                    };
+                    if matched {
+                        let dev_path = path.canonicalize()?;
+                        if !paths.contains(&dev_path) {
+                            paths.push(dev_path);
+                        }
+                    }
+                }
+                if paths.is_empty() {
+                    let mut names: Vec<&str> = drives.iter().map(|(n, _)| n.as_str()).collect();
+                    names.sort_unstable();
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!(
+                            "No drive matches selector {self}, available drives: {}",
+                            names.join(", ")
+                        ),
+                    ));
+                }
+                Ok(paths)
+            }
         }
     }
 }
 
+/// Drive symlink names and paths in `by_id_dir`, excluding partitions
+fn by_id_drives(by_id_dir: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    Ok(fs::read_dir(by_id_dir)?
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter()
+        .filter_map(|e| {
+            let name = e.file_name().into_string().ok()?;
+            (!name.trim_end_matches(char::is_numeric).ends_with("-part")).then(|| (name, e.path()))
+        })
+        .collect())
+}
+
+/// Does `s` match `pattern`, where `*` matches any sequence of characters
+fn wildcard_match(pattern: &str, s: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(first) = parts.next() else {
+        return s.is_empty();
+    };
+    let Some(mut rest) = s.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        let Some(idx) = rest.find(part) else {
+            return false;
+        };
+        rest = rest.get(idx + part.len()..).unwrap_or_default();
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
 impl fmt::Display for DriveSelector {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             DriveSelector::Interface(itf) => write!(f, "{itf}"),
-            DriveSelector::DrivePath(p) => write!(f, "{p:?}"),
+            DriveSelector::DrivePath(p) => write!(f, "{}", p.display()),
+            DriveSelector::Serial(s) => write!(f, "serial:{s}"),
+            DriveSelector::Model(m) => write!(f, "model:{m}"),
         }
     }
 }
@@ -169,7 +1179,17 @@ impl FromStr for DriveSelector {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.contains(path::MAIN_SEPARATOR) {
+        if let Some(serial) = s.strip_prefix("serial:") {
+            if serial.is_empty() {
+                return Err("Empty serial number");
+            }
+            Ok(Self::Serial(serial.to_owned()))
+        } else if let Some(model) = s.strip_prefix("model:") {
+            if model.is_empty() {
+                return Err("Empty model pattern");
+            }
+            Ok(Self::Model(model.to_owned()))
+        } else if s.contains(path::MAIN_SEPARATOR) {
             Ok(Self::DrivePath(s.into()))
         } else {
             Ok(Self::Interface(s.to_owned()))
@@ -179,25 +1199,138 @@ impl FromStr for DriveSelector {
 
 /// Main command
 #[derive(Subcommand, Debug)]
+#[expect(clippy::large_enum_variant)]
 pub(crate) enum Command {
     /// Start fan control daemon
     Daemon {
+        /// TOML configuration file to read daemon arguments from.
+        /// Keys are argument long names with `_` instead of `-` (ie. `min_fan_speed_prct = 10`),
+        /// values use the command line syntax, as arrays for arguments taking several values,
+        /// and booleans for flags.
+        /// Arguments passed on the command line take precedence.
+        /// On SIGHUP, drive settings (drives, drive temperature ranges and offsets, and zones)
+        /// are reloaded from the command line and this file.
+        #[arg(long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
         /// Drive path(s) to get temperature from (ie. `/dev/sdX`).
         /// Interface type selectors are also supported (ie. `ata` to
-        /// select all drives matching `/dev/disk/by-id/ata-*`).
-        #[arg(short, long, num_args = 1.., required = true)]
+        /// select all drives matching `/dev/disk/by-id/ata-*`), as well as serial number
+        /// (ie. `serial:WD-WCC4N1234567`) and model (ie. `model:WDC_WD40*`) selectors.
+        /// Can be omitted to control fans only from -w/--hwmons temperatures.
+        #[arg(short, long, num_args = 1.., required_unless_present_any = ["config", "hwmons"])]
         drives: Vec<DriveSelector>,
 
         /// PWM filepath(s) with values at which the fan start and stop moving.
         /// Use the 'pwm-test' command to find these values.
-        /// Format is `PWM_PATH:STAT_VAL:STOP_VAL`
-        /// (ie. `/sys/class/hwmon/hwmonX/device/pwmY:200:75`)
-        #[arg(short, long, num_args = 1.., required = true)]
+        /// Format is `PWM_PATH[:STAT_VAL:STOP_VAL]`
+        /// (ie. `/sys/class/hwmon/hwmonX/device/pwmY:200:75`), values can be omitted to use
+        /// the ones from --thresholds-file.
+        /// `auto` uses all PWMs found in `/sys/class/hwmon/` that can be set to manual control,
+        /// with values from --thresholds-file.
+        #[arg(short, long, num_args = 1.., required_unless_present = "config")]
         pwm: Vec<PwmSettings>,
 
-        /// Temperatures in Celcius at which the fan(s) will be set to minimum/maximum speed.
-        #[arg(short = 't', long, value_name = "TEMP", num_args = 2, default_values_t = vec![30.0, 50.0])]
-        drive_temp_range: Vec<Temp>,
+        /// PWM filepath(s) where higher values are lower fan speeds.
+        /// Start/stop values are still given as for a normal PWM, 0 being the stopped fan.
+        #[arg(long, value_name = "PWM_PATH", num_args = 1..)]
+        pwm_invert: Vec<PathBuf>,
+
+        /// PWM value for full speed, for PWMs not using the 0-255 range, as `PWM_PATH=MAX`
+        /// (ie. `/sys/class/hwmon/hwmonX/device/pwmY=100`).
+        /// Detected from the `pwmY_max` sysfs file if it exists, 255 otherwise.
+        #[arg(long, value_name = "PWM_PATH=MAX", num_args = 1..)]
+        pwm_max: Vec<PwmMax>,
+
+        /// Value written to `pwmY_enable` to take manual control of a PWM, for drivers not using
+        /// 1, as `PWM_PATH=VALUE` (ie. `/sys/class/hwmon/hwmonX/device/pwmY=0`).
+        #[arg(long, value_name = "PWM_PATH=VALUE", num_args = 1..)]
+        pwm_enable_value: Vec<PwmEnableValue>,
+
+        /// File with fan start/stop values written by the 'pwm-test' command, used for
+        /// -p/--pwm filepaths without values.
+        #[arg(long, value_name = "FILE")]
+        thresholds_file: Option<PathBuf>,
+
+        /// Temperatures at which the fan(s) will be set to minimum/maximum speed,
+        /// in --temp-unit, 30 50 °C if not set.
+        /// Ranges for some drives can also be set with `DRIVE_SELECTOR=TEMP:TEMP`
+        /// (ie. `35 50 /dev/nvme0n1=30:42`), other drives use the global range.
+        /// Fan speed is the highest one computed for each drive with its range.
+        #[arg(short = 't', long, value_name = "TEMP", num_args = 1..)]
+        drive_temp_range: Vec<DriveTempRange>,
+
+        /// Custom fan curve for drive temperatures, instead of -t/--drive-temp-range.
+        /// Format is `TEMP:PRCT,TEMP:PRCT,...` with strictly increasing temperatures
+        /// in --temp-unit (ie. `35:0,45:50,50:100`).
+        /// Speed is interpolated between points, and is the one of the first/last point
+        /// below/above the curve.
+        #[arg(long, value_name = "CURVE", conflicts_with = "drive_temp_range")]
+        drive_temp_curve: Option<Curve>,
+
+        /// Read the drive fan curve from a file, instead of --drive-temp-curve.
+        /// The file has `TEMP,PRCT` CSV lines (an optional header line and `#` comments are
+        /// ignored), or is a JSON array of `{"temp": TEMP, "speed": PRCT}` objects if its
+        /// extension is `.json`, temperatures being in --temp-unit.
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["drive_temp_range", "drive_temp_curve"])]
+        curve_file: Option<PathBuf>,
+
+        /// Shape of the fan speed ramp between the minimum and maximum speed temperatures of
+        /// -t/--drive-temp-range and -w/--hwmons ranges. Non linear shapes keep fans quiet longer,
+        /// then ramp up steeply near the end of the range.
+        #[arg(long, value_enum, default_value_t = CurveShape::Linear)]
+        curve_shape: CurveShape,
+
+        /// Fan profile active during a daily time window, as `NAME=HH:MM-HH:MM` in local time
+        /// (ie. `night=22:00-07:00`), overriding drive temperature settings with those set by
+        /// the --profile-* options for this name.
+        /// If windows overlap, the first profile given is used.
+        #[arg(long, value_name = "PROFILE=WINDOW", value_parser = profile_window)]
+        profile: Vec<(String, TimeWindow)>,
+
+        /// Drive temperature range of a fan profile for all drives, as `NAME=TEMP:TEMP` in
+        /// --temp-unit (ie. `night=40:55`).
+        #[arg(long, value_name = "PROFILE=RANGE", value_parser = profile_temp_range)]
+        profile_temp_range: Vec<(String, Range<Temp>)>,
+
+        /// Drive temperature curve of a fan profile, as `NAME=TEMP:PRCT,...` in --temp-unit
+        /// (ie. `night=40:0,50:40,55:100`), see --drive-temp-curve.
+        #[arg(long, value_name = "PROFILE=CURVE", value_parser = profile_temp_curve)]
+        profile_temp_curve: Vec<(String, Curve)>,
+
+        /// Minimum fan speed percentage of a fan profile, as `NAME=PRCT` (ie. `night=10`).
+        #[arg(long, value_name = "PROFILE=PRCT", value_parser = profile_percentage)]
+        profile_min_fan_speed_prct: Vec<(String, Percentage)>,
+
+        /// Weight in ]0-1] of a new drive temperature reading in its exponential moving average,
+        /// used for fan speed decisions instead of the raw value.
+        /// Lower values smooth more, 1 disables smoothing.
+        #[arg(long, default_value_t = 1.0, value_parser = smoothing_alpha)]
+        temp_smoothing_alpha: f64,
+
+        /// Duration of the sliding window of drive temperatures, ie. '5min', so that fan speed
+        /// follows a statistic of the readings in this window (see --window-stat) instead of
+        /// the last one, and brief temperature spikes do not spin fans up.
+        #[arg(long)]
+        temp_window: Option<humantime::Duration>,
+
+        /// Statistic of the drive temperatures in the --temp-window window used for fan speed:
+        /// `max`, `mean`, or a percentile as `pXX` (ie. `p95`).
+        #[arg(long, default_value_t = WindowStat::Max, requires = "temp_window")]
+        window_stat: WindowStat,
+
+        /// Rate in --temp-unit degrees per minute at which the drive temperature used for fan
+        /// speed decays from its peak, ie. '0.5'. Rises are followed instantly, so fans do not
+        /// slow down as soon as a transient reading dips.
+        #[arg(long, value_name = "RATE", value_parser = decay_rate)]
+        temp_decay: Option<Temp>,
+
+        /// Temperature change in --temp-unit below which fan speed is not changed, to stop speed
+        /// oscillating when temperature hovers around a value, in curve control mode.
+        /// Applies to drive and hwmon temperatures, the fan speed still never goes below
+        /// -m/--min-fan-speed-prct.
+        #[arg(long, value_name = "TEMP", default_value_t = 0.0)]
+        hysteresis_temp: Temp,
 
         /// Minimum percentage of full fan speed to set the fan to.
         /// Never set to 0 unless you have other fans to cool down your system,
@@ -205,31 +1338,461 @@ pub(crate) enum Command {
         #[arg(short, long, default_value_t = 20, value_parser=percentage)]
         min_fan_speed_prct: Percentage,
 
+        /// Minimum PWM value to set the fans to, instead of -m/--min-fan-speed-prct, for fans
+        /// stalling below a precise value.
+        /// It is converted to a speed for each fan with its --pwm-max and stop value. A value at
+        /// or below the stop value of a fan sets no minimum, since the fan is stopped there.
+        #[arg(long, value_name = "PWM_VALUE", conflicts_with = "min_fan_speed_prct")]
+        min_fan_speed_raw: Option<pwm::Value>,
+
+        /// Set the minimum speed of each fan with measured start/stop values (given with --pwm,
+        /// or found in --thresholds-file) just above its stop value, so that it never stalls.
+        /// The highest of this and -m/--min-fan-speed-prct or --min-fan-speed-raw is used.
+        #[arg(long)]
+        auto_min_speed: bool,
+
+        /// Maximum fan speed percentage change per interval, to ramp speed gradually instead of
+        /// abruptly.
+        #[arg(long, value_parser = non_null_percentage)]
+        max_speed_step_prct: Option<Percentage>,
+
+        /// When a temperature reaches the end of its range, set fans to full speed immediately
+        /// instead of ramping their speed according to --max-speed-step-prct.
+        #[arg(long)]
+        no_ramp_emergency: bool,
+
+        /// Fan speed percentage change below which the fan speed is not changed, to avoid PWM
+        /// writes for tiny temperature wiggles.
+        /// Full speed, stopping the fan, boosts and failsafe conditions are applied regardless.
+        #[arg(long, value_parser = non_null_percentage)]
+        speed_deadband_prct: Option<Percentage>,
+
         /// Interval to check temperature and adjust fan speed, ie. '30s', '3min'.
         #[arg(short, long, default_value = "20s")]
         interval: humantime::Duration,
 
+        /// Shortest interval to check temperature and adjust fan speed, ie. '5s', for an interval
+        /// adapting to fan speed instead of -i/--interval.
+        /// The interval is interpolated between --interval-max when fans are stopped, and this
+        /// value when fans run at full speed, so that the daemon reacts faster when hot.
+        #[arg(long, requires = "interval_max", conflicts_with = "interval")]
+        interval_min: Option<humantime::Duration>,
+
+        /// Longest interval to check temperature and adjust fan speed, ie. '1min', see
+        /// --interval-min.
+        #[arg(long, requires = "interval_min", conflicts_with = "interval")]
+        interval_max: Option<humantime::Duration>,
+
+        /// Shorter delay before the second iteration after startup or a drive settings reload,
+        /// ie. '2s', so that fan speeds quickly converge before following the normal interval.
+        #[arg(long)]
+        startup_poll: Option<humantime::Duration>,
+
+        /// How long a drive confirmed to be spun up is assumed to stay so, before querying its
+        /// state again, ie. '1min', '0s' to query every interval.
+        /// A drive that spins down may be considered active for up to this duration, so this
+        /// only applies to drives whose probing method does not wake them up.
+        #[arg(long, default_value = "0s")]
+        drive_state_cache_ttl: humantime::Duration,
+
+        /// Do not probe spun down drives with no I/O since the previous iteration according to
+        /// /proc/diskstats, even if their probing method supports it, so that they are considered
+        /// cool. Probing resumes as soon as the drive has I/O activity again.
+        #[arg(long)]
+        skip_idle_sleeping: bool,
+
+        /// Number of temperature readings to ignore after a drive spins up.
+        /// Right after waking up, a drive can report a stale temperature from before it went
+        /// to sleep, or one that does not account for airflow yet, which would wrongly
+        /// lower fan speed, ie. '1'.
+        #[arg(long, default_value_t = 0)]
+        post_wake_ignored_samples: u32,
+
+        /// Temperature in --temp-unit below which fans are stopped instead of running at
+        /// -m/--min-fan-speed-prct, for the hottest drive of their zone(s).
+        /// Fans start again when the temperature reaches this value plus --fan-off-hysteresis,
+        /// using their start value.
+        /// Additional hwmon probes (-w/--hwmons) can still keep fans running.
+        #[arg(long, value_name = "TEMP")]
+        fan_off_below: Option<Temp>,
+
+        /// Temperature change in --temp-unit above --fan-off-below to reach before starting
+        /// stopped fans again.
+        #[arg(
+            long,
+            value_name = "TEMP",
+            default_value_t = 2.0,
+            requires = "fan_off_below"
+        )]
+        fan_off_hysteresis: Temp,
+
+        /// Match drive selectors again every this number of iterations, to monitor newly attached
+        /// drives and stop monitoring removed ones, 0 to disable.
+        #[arg(long, default_value_t = 15)]
+        drive_rescan_iterations: u32,
+
+        /// Interval to check PWMs are still in manual control mode while fan speeds are unchanged,
+        /// ie. '1min', and set them back if the firmware or another program changed them.
+        /// '0s' checks at each iteration.
+        #[arg(long, default_value = "1min")]
+        reassert_interval: humantime::Duration,
+
+        /// Read back PWM values after writing them, and log if they differ, ie. because another
+        /// driver or the firmware controls the PWM.
+        #[arg(long)]
+        verify_writes: bool,
+
+        /// Maximum difference between a PWM value written and read back, with --verify-writes.
+        #[arg(
+            long,
+            value_name = "VALUE",
+            default_value_t = 2,
+            requires = "verify_writes"
+        )]
+        verify_writes_tolerance: pwm::Value,
+
+        /// Minimum duration to keep a fan running once started, ie. '5min', regardless of
+        /// temperature, to prevent short cycling.
+        #[arg(long, default_value = "0s")]
+        min_fan_runtime: humantime::Duration,
+
+        /// Minimum duration to keep a fan stopped once stopped, ie. '5min', regardless of
+        /// temperature, to prevent short cycling.
+        /// Fans are still started immediately when full speed is required.
+        #[arg(long, default_value = "0s")]
+        min_fan_offtime: humantime::Duration,
+
+        /// Duration over which fan speeds increase progressively after startup, ie. '30s', from
+        /// the speed fans were running at to the speed computed, to avoid a loud burst after a
+        /// cold start. Fans are still set immediately to full speed when it is required.
+        #[arg(long)]
+        startup_ramp: Option<humantime::Duration>,
+
+        /// Duration in milliseconds to run a stopped fan at full speed before setting it to a PWM
+        /// value below its start value, for fans that do not start at a low duty cycle.
+        /// 0 disables the kick, stopped fans are then started at their start value.
+        #[arg(long, value_name = "MS", default_value_t = 0)]
+        spinup_kick_ms: u64,
+
+        /// Duration to keep using the last temperature of a spun down drive that can not be
+        /// probed without waking it up, ie. '30min', so that fans do not slow down as soon as a
+        /// hot drive goes to sleep.
+        /// If not set, spun down drives that can not be probed are ignored.
+        #[arg(long)]
+        sleep_temp_hold: Option<humantime::Duration>,
+
+        /// Rate in --temp-unit degrees per minute at which the last temperature of a spun down
+        /// drive decays toward the start of its temperature range, see --sleep-temp-hold.
+        #[arg(
+            long,
+            value_name = "RATE",
+            default_value_t = 0.0,
+            requires = "sleep_temp_hold"
+        )]
+        sleep_temp_decay: Temp,
+
+        /// How temperatures of the drives of a zone are combined to compute fan speed.
+        /// With --control-mode curve, this combines the fan speeds computed for each drive from
+        /// its temperature range.
+        #[arg(long, value_enum, default_value_t = Aggregation::Max)]
+        aggregation: Aggregation,
+
+        /// Fan speed when all drives are spun down.
+        /// Additional hwmon probes (-w/--hwmons) can still set a higher speed.
+        #[arg(long, value_enum, default_value_t = SpunDownBehavior::Min)]
+        spun_down_behavior: SpunDownBehavior,
+
+        /// Fan speed control strategy for drive temperatures.
+        /// In both modes, additional hwmon probes (-w/--hwmons) still set a minimum speed
+        /// according to their temperature range.
+        #[arg(long, value_enum, default_value_t = ControlMode::Curve)]
+        control_mode: ControlMode,
+
+        /// Temperature in --temp-unit to hold the hottest drive at, in PID control mode,
+        /// 40 °C if not set.
+        #[arg(long, value_name = "TEMP")]
+        pid_setpoint: Option<Temp>,
+
+        /// PID gains as `KP:KI:KD`, in fan speed percentage per °C above setpoint, per °C·minute
+        /// above setpoint, and per °C per minute of increase, in PID control mode.
+        /// Either a single value for all fans, or one per fan in the same order as -p/--pwm.
+        #[arg(long, num_args = 1.., default_value = "5:1:0")]
+        pid_gains: Vec<pid::Gains>,
+
+        /// Override the proportional gain of --pid-gains for all fans.
+        #[arg(long, value_parser = gain)]
+        pid_kp: Option<f64>,
+
+        /// Override the integral gain of --pid-gains for all fans.
+        #[arg(long, value_parser = gain)]
+        pid_ki: Option<f64>,
+
+        /// Override the derivative gain of --pid-gains for all fans.
+        #[arg(long, value_parser = gain)]
+        pid_kd: Option<f64>,
+
+        /// Fan speed percentage to add for each °C per minute of temperature increase, for the
+        /// sensor rising the fastest, to react to sudden temperature spikes before the
+        /// temperature gets high. The added speed is capped to 50%, and the rate is only
+        /// computed from samples no further apart than twice the interval.
+        #[arg(long, default_value_t = 0.0)]
+        derivative_gain: f64,
+
+        /// Weight of the drive speed when blending drive and hwmon speeds of a zone, by weighted
+        /// average with --blend-hwmon-weight, instead of using the highest of the two.
+        #[arg(long, value_parser = blend_weight, requires = "blend_hwmon_weight")]
+        blend_drive_weight: Option<f64>,
+
+        /// Weight of the hwmon speed when blending drive and hwmon speeds of a zone, see
+        /// --blend-drive-weight.
+        #[arg(long, value_parser = blend_weight, requires = "blend_drive_weight")]
+        blend_hwmon_weight: Option<f64>,
+
         /// Also control fan speed according to these additional hwmon temperature probes.
-        /// Format is `HWMON_PATH[:TEMP_MIN_SPEED:TEMP_MAX_SPEED]`
-        /// (ie. `/sys/devices/platform/coretemp.0/hwmon/hwmonX/tempY_input:45:75`).
+        /// Format is `HWMON_PATH[:TEMP_MIN_SPEED:TEMP_MAX_SPEED]`, temperatures being in
+        /// --temp-unit (ie. `/sys/devices/platform/coretemp.0/hwmon/hwmonX/tempY_input:45:75`).
+        /// Instead of a path, a sensor can be selected by hwmon chip name and label, which do not
+        /// change across boots (ie. `nct6775:SYSTIN:40:60`).
         /// If missing, target temperature range will be autodetected or use a default value.
         /// WARNING: Don't use for CPU sensors, unless you have low TDP CPU. You may also need to set
         /// a low value for -i/--interval parameter to react quickly to sudden temperature increase.
         #[arg(short = 'w', long)]
         hwmons: Vec<HwmonSettings>,
 
-        /// hddtemp daemon TCP port.
-        #[arg(long, default_value_t = 7634)]
-        hddtemp_daemon_port: u16,
+        /// Scale of the values reported by a hwmon of -w/--hwmons, as `HWMON=SCALE`, scale being
+        /// `milli` for millidegrees Celsius as documented for sysfs, or `degree` for drivers
+        /// reporting whole degrees (ie. `nct6775:SYSTIN=degree`).
+        /// Applies to the temperature input, and to the max and critical temperatures used for
+        /// the default range.
+        #[arg(long = "temp-scale", value_name = "HWMON=SCALE")]
+        temp_scales: Vec<HwmonTempScale>,
+
+        /// Fan zone, so that fans are controlled by some drives and hwmons only.
+        /// Format is `INPUT,...=PWM_PATH,...`, inputs being drive selectors as for -d/--drives
+        /// or hwmon filepaths as for -w/--hwmons, and PWM paths without their start/stop values
+        /// (ie. `/dev/sda,/dev/sdb=/sys/class/hwmon/hwmonX/device/pwmY`).
+        /// Can be repeated, a drive may be in several zones, and a fan in several zones runs at
+        /// the highest speed of its zones.
+        /// Without zones, all fans are controlled by all drives and hwmons.
+        /// A zone can be updated at its own interval with an `@INTERVAL` suffix
+        /// (ie. `/dev/nvme0n1=/sys/class/hwmon/hwmonX/device/pwmY@3s`), its drives not being
+        /// queried or probed in between, while zones without one use the -i/--interval pace.
+        #[arg(long = "zone", value_name = "ZONE")]
+        zones: Vec<ZoneSettings>,
+
+        /// Offset in --temp-unit to add to temperatures probed for some drives or a hwmon,
+        /// to calibrate a sensor (ie. `/dev/sda=-4`).
+        /// Format is `INPUT=OFFSET`, input being a drive selector as for -d/--drives or a hwmon
+        /// filepath as for -w/--hwmons.
+        /// Can be repeated, the first offset matching a drive applies.
+        #[arg(
+            long = "temp-offset",
+            value_name = "INPUT=OFFSET",
+            allow_hyphen_values = true
+        )]
+        temp_offsets: Vec<TempOffset>,
+
+        /// Weight of some drives in the fan speed of their zones, as `DRIVE_SELECTOR=WEIGHT` (ie.
+        /// `/dev/sda=2`), drives without weight having a weight of 1.
+        /// The speed computed for each drive from its temperature is multiplied by its weight
+        /// relative to the highest weight of its zone, before combining speeds with
+        /// --aggregation, so a drive with half the highest weight contributes half its speed.
+        /// Equal weights are the same as no weights. Only applies with --control-mode curve.
+        /// Can be repeated, the first weight matching a drive applies.
+        #[arg(long = "drive-weight", value_name = "DRIVE_SELECTOR=WEIGHT")]
+        drive_weights: Vec<DriveWeight>,
+
+        /// Drive(s) to never probe while spun down, even if their probing method supports it,
+        /// ie. for a drive that is sometimes woken up by drivetemp reads on some controllers.
+        /// Uses the same selectors as -d/--drives, and can be repeated.
+        #[arg(long, value_name = "DRIVE_SELECTOR")]
+        never_wake: Vec<DriveSelector>,
+
+        /// hddtemp daemon TCP port [default: 7634].
+        /// Drives with no other probing method wait for the daemon if it is given.
+        #[arg(long)]
+        hddtemp_daemon_port: Option<u16>,
+
+        /// hddtemp daemon address as `HOST:PORT`, to query a remote daemon instead of the local
+        /// one on --hddtemp-daemon-port.
+        /// Can be repeated, daemons are queried in order until one reports the drive.
+        #[arg(long, value_name = "HOST:PORT", value_parser = socket_addr, conflicts_with = "hddtemp_daemon_port")]
+        hddtemp_daemon: Vec<SocketAddr>,
+
+        /// Shell command printing a drive temperature in Celsius, tried before other probing
+        /// methods, ie. `/usr/local/bin/backplane-temp %DRIVE%`.
+        /// `%DRIVE%` is replaced by the drive path. A non-zero exit status, an output that is not
+        /// a number or a timeout are probing failures.
+        /// It is also run while drives are spun down, so it must not wake them up.
+        #[arg(long, value_name = "COMMAND")]
+        temp_command: Option<String>,
+
+        /// Duration after which --temp-command is killed, and probing fails.
+        #[arg(long, default_value = "10s", requires = "temp_command")]
+        temp_command_timeout: humantime::Duration,
+
+        /// Temperature sensor probed for NVMe drives: `composite` for the temperature reported
+        /// for the whole drive, `max` for the highest of all sensors, or a sensor number
+        /// (ie. `2` for the sensor labeled `Sensor 2`, often the NAND flash, which runs hotter
+        /// than the controller), `max` if not set.
+        /// Sensors for some drives can also be set with `DRIVE_SELECTOR=SENSOR`
+        /// (ie. `composite /dev/nvme1n1=2`), other drives use the global sensor.
+        #[arg(long = "nvme-sensor", value_name = "SENSOR", num_args = 1..)]
+        nvme_sensors: Vec<DriveNvmeSensor>,
 
         /// Restore fan settings on exit, otherwise the fans are run at full speed on exit.
         #[arg(short, long)]
         restore_fan_settings: bool,
 
+        /// Restore the original PWM control mode on exit (ie. automatic control by the
+        /// motherboard), while running fans at full speed, otherwise PWMs are left in manual
+        /// mode.
+        /// Implied by -r/--restore-fan-settings.
+        #[arg(long)]
+        restore_enable_mode: bool,
+
+        /// Print the effective configuration (drives matched with their probing method and
+        /// temperature range, fans with their start/stop values, ...) to standard output,
+        /// then exit without setting fan speeds. It is always logged at startup.
+        #[arg(long)]
+        print_config: bool,
+
+        /// Run a single control iteration, then exit, ie. for cron driven setups or debugging.
+        /// The fan speeds set are kept on exit, unless --once-restore is set.
+        #[arg(long)]
+        once: bool,
+
+        /// With --once, apply the usual exit behavior after the iteration: fans at full speed, or
+        /// restored with -r/--restore-fan-settings.
+        #[arg(long, requires = "once")]
+        once_restore: bool,
+
+        /// Maximum total duration to restore fan settings on exit, each fan getting an equal share
+        /// of the remaining time, so that a hung PWM write (ie. wedged driver) does not prevent
+        /// the other fans from being restored.
+        #[arg(long, default_value = "10s")]
+        shutdown_timeout: humantime::Duration,
+
+        /// Number of consecutive failed iterations (ie. failing to probe a drive or set a fan
+        /// speed) after which the daemon exits with an error, so that the service manager can
+        /// restart the daemon cleanly. A successful iteration resets the count.
+        /// The current fan speed is kept during tolerated failures.
+        /// Defaults to exiting on the first failure.
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        max_consecutive_errors: u32,
+
+        /// What to do when probing a drive temperature fails.
+        #[arg(long, value_enum, default_value_t = ProbeErrorAction::Exit)]
+        on_probe_error: ProbeErrorAction,
+
+        /// Number of consecutive probing failures for a drive before setting all fans to full
+        /// speed, with `--on-probe-error max-speed`.
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+        probe_error_threshold: u32,
+
+        /// Number of times to retry a failed drive temperature probe before it counts as a
+        /// probing failure, for transient errors. Spun down drives are not retried.
+        #[arg(long, default_value_t = 0)]
+        probe_retries: u32,
+
+        /// Delay before retrying a failed drive temperature probe, doubled for each next retry,
+        /// see --probe-retries.
+        #[arg(long, default_value = "500ms")]
+        probe_retry_delay: humantime::Duration,
+
+        /// Maximum duration to wait for a drive temperature probe, drives being probed
+        /// concurrently. A probe timing out is a probing failure, see --on-probe-error.
+        #[arg(long, default_value = "30s")]
+        probe_timeout: humantime::Duration,
+
+        /// Drive temperature in --temp-unit at or above which all fans are set to full speed,
+        /// regardless of control mode and boost.
+        #[arg(long, visible_alias = "critical-temp", value_name = "TEMP")]
+        drive_critical_temp: Option<Temp>,
+
+        /// Shell command to run when a drive temperature reaches --drive-critical-temp
+        /// (ie. to send a notification, or power off).
+        /// It is run once, and again only after all drive temperatures got below the critical
+        /// temperature.
+        /// The drive filepath and temperature in Celsius are set in the `HDDFANCONTROL_DRIVE` and
+        /// `HDDFANCONTROL_TEMP` environment variables.
+        #[arg(long, value_name = "COMMAND", requires = "drive_critical_temp")]
+        critical_command: Option<String>,
+
+        /// Read the warning temperature threshold NVMe drives report, above which they throttle
+        /// their performance, and set the fans of the zones of a drive reaching it to full speed,
+        /// regardless of its temperature range.
+        #[arg(long)]
+        respect_drive_thresholds: bool,
+
+        /// Monitor fan RPM, to detect fans not moving while their speed is set to at least their
+        /// start value. The RPM file of each fan is found as for the 'pwm-test' command, which may
+        /// take some time at startup if there are several candidates.
+        #[arg(long)]
+        monitor_rpm: bool,
+
+        /// Log the RPM of each fan alongside its speed at each iteration, for diagnostics.
+        /// RPM files are found as with --monitor-rpm, but a fan whose RPM file is not found is
+        /// only warned about.
+        #[arg(long)]
+        log_rpm: bool,
+
+        /// Number of consecutive intervals a fan is not moving while it should before it is
+        /// considered failed, with --monitor-rpm.
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(1..))]
+        rpm_fail_threshold: u32,
+
+        /// Set all fans to full speed while a fan is considered failed, with --monitor-rpm.
+        #[arg(long, requires = "monitor_rpm")]
+        rpm_fail_max_speed: bool,
+
+        /// Unit of temperatures in arguments and logs.
+        /// PID settings, control socket commands and metrics always use Celsius.
+        #[arg(long, value_enum, default_value_t = TempUnit::Celsius)]
+        temp_unit: TempUnit,
+
+        /// Where to write logs.
+        /// With `journald`, --log-dir, --log-max-size, --log-retain, --log-rotate and --log-format
+        /// are ignored, no file is created, and nothing is logged to stdout.
+        #[arg(long, value_enum, default_value_t = LogBackend::File)]
+        log_backend: LogBackend,
+
         /// Directory to write logs into (will contain subdirectory `logs/`)
         #[arg(long, env = "LOG_DIR", default_value = ".")]
         log_dir: PathBuf,
 
+        /// Subdirectory of --log-dir for the log files.
+        #[arg(long, default_value = "logs")]
+        log_subdir: PathBuf,
+
+        /// Write log files directly into --log-dir, instead of --log-subdir.
+        #[arg(long, conflicts_with = "log_subdir")]
+        no_log_subdir: bool,
+
+        /// Base name of the log files, which are named `BASENAME_rCURRENT.log` for the current
+        /// one, and `BASENAME_rNNNNN.log` once rotated.
+        #[arg(long, default_value = "log")]
+        log_basename: String,
+
+        /// Do not create the `hddfancontrol.log` symlink to the current log file in --log-dir,
+        /// ie. on filesystems without symlink support.
+        /// Failing to create it is only a warning.
+        #[arg(long)]
+        no_log_symlink: bool,
+
+        /// Do not duplicate file logs to stdout, ie. under systemd where stdout also goes to the
+        /// journal, to not store logs twice.
+        /// Only applies to the `file` log backend.
+        #[arg(long, conflicts_with = "stdout_only")]
+        no_stdout: bool,
+
+        /// Only log to stdout, without writing log files, ie. under systemd where the journal
+        /// captures stdout.
+        /// Only applies to the `file` log backend, --log-dir and other log file settings are then
+        /// ignored.
+        #[arg(long)]
+        stdout_only: bool,
+
         /// Max size of a single log file (e.g., "10MB" or "1h")
         #[arg(long, default_value = "10MB")]
         log_max_size: String,
@@ -238,15 +1801,885 @@ pub(crate) enum Command {
         #[arg(long, default_value_t = 5)]
         log_retain: usize,
 
+        /// When to rotate the log file, time based rotation also rotating when it reaches
+        /// --log-max-size
+        #[arg(long, value_enum, default_value_t)]
+        log_rotate: LogRotate,
+
         /// Format string for log timestamps
         #[arg(long, env = "LOG_DATETIME_FORMAT", default_value = "%d.%m.%Y %H:%M:%S")]
         log_datetime_format: String,
+
+        /// Unix socket to listen on for commands to the running daemon.
+        /// Supported commands (one per line):
+        /// `status` returns the last temperature readings and fan speeds.
+        /// `boost PRCT DURATION [FAN ...]` raises fans (all, or given by name or PWM path)
+        /// to at least a speed for a duration (ie. `boost 100 10min`), higher speeds computed from
+        /// temperatures still take precedence.
+        /// `max-temps` returns the highest temperature seen for each drive,
+        /// `reset-max-temps [DRIVE ...]` resets them.
+        /// `pid-setpoint TEMP` changes the PID control mode setpoint, in --temp-unit.
+        /// `history` returns the readings of the last iterations, see --history-size.
+        #[arg(long)]
+        control_socket: Option<PathBuf>,
+
+        /// Number of control loop iterations to keep readings of in memory, for the `history`
+        /// control socket command.
+        #[arg(long, default_value_t = 60)]
+        history_size: usize,
+
+        /// Address to serve Prometheus metrics on, at `/metrics` (ie. `127.0.0.1:9090`).
+        #[cfg(feature = "metrics")]
+        #[arg(long, value_name = "ADDR")]
+        metrics_listen: Option<SocketAddr>,
+
+        /// Send drive temperatures and fan speeds as InfluxDB line protocol measurements over
+        /// UDP at each iteration (ie. to a Telegraf socket listener at `127.0.0.1:8094`).
+        /// Format is `HOST:PORT`; send failures are only logged.
+        #[cfg(feature = "influx")]
+        #[arg(long, value_name = "HOST:PORT")]
+        influx_udp: Option<String>,
+
+        /// MQTT broker to publish drive temperatures and fan speeds to at each iteration, as
+        /// `HOST[:PORT]`, port being 1883 if not set.
+        /// If the broker is unavailable, publishing is retried every 30 seconds without
+        /// affecting fan control.
+        #[cfg(feature = "mqtt")]
+        #[arg(long, value_name = "HOST[:PORT]")]
+        mqtt_broker: Option<String>,
+
+        /// Prefix of the MQTT topics, ie. `<PREFIX>/drive/sda/temperature`, also used as MQTT
+        /// client identifier, so it must be unique among the hosts publishing to a broker.
+        #[cfg(feature = "mqtt")]
+        #[arg(long, default_value = "hddfancontrol", requires = "mqtt_broker")]
+        mqtt_topic_prefix: String,
+
+        /// Publish Home Assistant MQTT discovery config, so that sensors appear automatically.
+        #[cfg(feature = "mqtt")]
+        #[arg(long, requires = "mqtt_broker")]
+        mqtt_discovery: bool,
+
+        /// File to persist state across restarts into, like the highest temperature seen for each drive.
+        #[arg(long)]
+        state_file: Option<PathBuf>,
+
+        /// CSV file to append drive temperatures and spun down flags, hwmon temperatures and fan
+        /// speeds to at each iteration, ie. `/var/log/hddfancontrol/metrics.csv`.
+        /// Temperatures are in Celsius. When the file reaches --csv-log-max-size or its columns
+        /// change, it is renamed with a `.1` suffix, replacing the previous one.
+        #[arg(long, value_name = "FILE")]
+        csv_log: Option<PathBuf>,
+
+        /// Size of the --csv-log file before rotating it, ie. "10MB".
+        #[arg(long, default_value = "10MB", requires = "csv_log")]
+        csv_log_max_size: String,
+
+        /// Layout of log records.
+        /// `--log-datetime-format` only applies to the `bracketed` layout.
+        #[arg(long, value_enum, default_value_t = LogFormat::Bracketed)]
+        log_format: LogFormat,
     },
 
     /// Test PWM to find start/stop fan values
     PwmTest {
+        /// PWM filepath(s) (ie. `/sys/class/hwmon/hwmonX/device/pwmY`), or `auto` to test all
+        /// PWMs found in `/sys/class/hwmon/` that can be set to manual control.
+        #[arg(short, long, num_args = 1.., required = true)]
+        pwm: Vec<PathBuf>,
+
+        /// PWM filepath(s) where higher values are lower fan speeds, as for the 'daemon' command.
+        #[arg(long, value_name = "PWM_PATH", num_args = 1..)]
+        pwm_invert: Vec<PathBuf>,
+
+        /// PWM value for full speed, for PWMs not using the 0-255 range, as for the 'daemon'
+        /// command, as `PWM_PATH=MAX`.
+        #[arg(long, value_name = "PWM_PATH=MAX", num_args = 1..)]
+        pwm_max: Vec<PwmMax>,
+
+        /// Value written to `pwmY_enable` to take manual control of a PWM, as for the 'daemon'
+        /// command, as `PWM_PATH=VALUE`.
+        #[arg(long, value_name = "PWM_PATH=VALUE", num_args = 1..)]
+        pwm_enable_value: Vec<PwmEnableValue>,
+
+        /// File to write the fan start/stop values found into, for the daemon
+        /// --thresholds-file argument.
+        /// Values of other PWMs already in the file are kept.
+        #[arg(long, value_name = "FILE")]
+        thresholds_file: Option<PathBuf>,
+
+        /// Print a JSON object per line for each fan tested, with its PWM filepath, start/stop
+        /// values and RPM at full speed, or error.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Check that fan start/stop values found by 'pwm-test' are still accurate, ie. after fans aged.
+    /// PWM settings are restored afterwards.
+    VerifyThresholds {
+        /// PWM filepath(s) with values at which the fan start and stop moving, as for the
+        /// 'daemon' command.
+        /// Format is `PWM_PATH:STAT_VAL:STOP_VAL`
+        /// (ie. `/sys/class/hwmon/hwmonX/device/pwmY:200:75`)
+        #[arg(short, long, num_args = 1.., required = true)]
+        pwm: Vec<PwmSettings>,
+
+        /// PWM filepath(s) where higher values are lower fan speeds, as for the 'daemon' command.
+        #[arg(long, value_name = "PWM_PATH", num_args = 1..)]
+        pwm_invert: Vec<PathBuf>,
+
+        /// PWM value for full speed, for PWMs not using the 0-255 range, as for the 'daemon'
+        /// command, as `PWM_PATH=MAX`.
+        #[arg(long, value_name = "PWM_PATH=MAX", num_args = 1..)]
+        pwm_max: Vec<PwmMax>,
+
+        /// Value written to `pwmY_enable` to take manual control of a PWM, as for the 'daemon'
+        /// command, as `PWM_PATH=VALUE`.
+        #[arg(long, value_name = "PWM_PATH=VALUE", num_args = 1..)]
+        pwm_enable_value: Vec<PwmEnableValue>,
+    },
+
+    /// Program the automatic fan curve of PWMs whose driver supports one
+    /// (`pwmY_auto_pointZ_temp` and `pwmY_auto_pointZ_pwm` sysfs files) from a fan curve, and
+    /// leave the PWMs in automatic mode, so that fans keep being controlled even if the daemon
+    /// is not running.
+    /// The driver follows its own temperature sensor (see `pwmY_auto_channels_temp`), not the
+    /// drive temperatures.
+    #[command(group(clap::ArgGroup::new("curve_source").required(true).args(["drive_temp_curve", "curve_file"])))]
+    ProgramAutoCurve {
         /// PWM filepath(s) (ie. `/sys/class/hwmon/hwmonX/device/pwmY`).
         #[arg(short, long, num_args = 1.., required = true)]
         pwm: Vec<PathBuf>,
+
+        /// Fan curve, as for daemon --drive-temp-curve.
+        /// Speeds are PWM value percentages, ignoring fan start/stop values.
+        #[arg(long, value_name = "CURVE")]
+        drive_temp_curve: Option<Curve>,
+
+        /// Read the fan curve from a file, as for daemon --curve-file.
+        #[arg(long, value_name = "PATH")]
+        curve_file: Option<PathBuf>,
+
+        /// Unit of curve temperatures.
+        #[arg(long, value_enum, default_value_t = TempUnit::Celsius)]
+        temp_unit: TempUnit,
+
+        /// PWM filepath(s) where higher values are lower fan speeds, as for the 'daemon' command.
+        #[arg(long, value_name = "PWM_PATH", num_args = 1..)]
+        pwm_invert: Vec<PathBuf>,
+
+        /// PWM value for full speed, for PWMs not using the 0-255 range, as for the 'daemon'
+        /// command, as `PWM_PATH=MAX`.
+        #[arg(long, value_name = "PWM_PATH=MAX", num_args = 1..)]
+        pwm_max: Vec<PwmMax>,
+
+        /// Value written to `pwmY_enable` to hand control to the automatic fan curve, for drivers
+        /// not using 2, as `PWM_PATH=VALUE`.
+        #[arg(long, value_name = "PWM_PATH=VALUE", num_args = 1..)]
+        pwm_auto_enable_value: Vec<PwmEnableValue>,
+    },
+
+    /// Convert a lm-sensors `fancontrol` configuration into daemon arguments,
+    /// printed as a systemd environment file (see `/etc/conf.d/hddfancontrol`)
+    ImportFancontrol {
+        /// `fancontrol` configuration filepath
+        #[arg(default_value = "/etc/fancontrol")]
+        path: PathBuf,
+
+        /// Write to this file instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// List drives with the temperature probing method the daemon would use for them
+    List {
+        /// Drive path(s) or interface type selector(s), as for the 'daemon' command.
+        /// If not set, `/dev/sdX` and `/dev/nvmeXnY` drives are listed.
+        #[arg(short, long, num_args = 1..)]
+        drives: Vec<DriveSelector>,
+
+        /// hddtemp daemon TCP port [default: 7634].
+        /// Drives with no other probing method wait for the daemon if it is given.
+        #[arg(long)]
+        hddtemp_daemon_port: Option<u16>,
+
+        /// hddtemp daemon address as `HOST:PORT`, to query a remote daemon instead of the local
+        /// one on --hddtemp-daemon-port.
+        /// Can be repeated, daemons are queried in order until one reports the drive.
+        #[arg(long, value_name = "HOST:PORT", value_parser = socket_addr, conflicts_with = "hddtemp_daemon_port")]
+        hddtemp_daemon: Vec<SocketAddr>,
+
+        /// Shell command printing a drive temperature in Celsius, tried before other probing
+        /// methods, ie. `/usr/local/bin/backplane-temp %DRIVE%`.
+        /// `%DRIVE%` is replaced by the drive path. A non-zero exit status, an output that is not
+        /// a number or a timeout are probing failures.
+        /// It is also run while drives are spun down, so it must not wake them up.
+        #[arg(long, value_name = "COMMAND")]
+        temp_command: Option<String>,
+
+        /// Duration after which --temp-command is killed, and probing fails.
+        #[arg(long, default_value = "10s", requires = "temp_command")]
+        temp_command_timeout: humantime::Duration,
+    },
+
+    /// Print the last drive temperatures, fan speeds and drive states seen by a running daemon,
+    /// as JSON
+    Status {
+        /// Daemon --control-socket filepath
+        #[arg(long, default_value = "/run/hddfancontrol.sock")]
+        control_socket: PathBuf,
+
+        /// Print the readings of the last iterations instead, oldest first, see daemon
+        /// --history-size.
+        #[arg(long)]
+        history: bool,
+    },
+
+    /// Print the fan speed computed for drive temperatures, without touching any hardware, to see
+    /// what a temperature range or curve will do before running the daemon with it
+    #[command(group(clap::ArgGroup::new("input").required(true).args(["temps", "from", "temps_csv"])))]
+    Simulate {
+        /// Temperatures at which the fan(s) will be set to minimum/maximum speed,
+        /// in --temp-unit, 30 50 °C if not set, see daemon -t/--drive-temp-range.
+        #[arg(short = 't', long, value_name = "TEMP", num_args = 2)]
+        drive_temp_range: Vec<DriveTempRange>,
+
+        /// Custom fan curve, instead of -t/--drive-temp-range, see daemon --drive-temp-curve.
+        #[arg(long, value_name = "CURVE", conflicts_with = "drive_temp_range")]
+        drive_temp_curve: Option<Curve>,
+
+        /// Read the fan curve from a file, see daemon --curve-file.
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["drive_temp_range", "drive_temp_curve"])]
+        curve_file: Option<PathBuf>,
+
+        /// Shape of the fan speed ramp in -t/--drive-temp-range, see daemon --curve-shape.
+        #[arg(long, value_enum, default_value_t = CurveShape::Linear)]
+        curve_shape: CurveShape,
+
+        /// Minimum percentage of full fan speed, see daemon -m/--min-fan-speed-prct.
+        #[arg(short, long, default_value_t = 20, value_parser=percentage)]
+        min_fan_speed_prct: Percentage,
+
+        /// Unit of all temperatures, input and output.
+        #[arg(long, value_enum, default_value_t = TempUnit::Celsius)]
+        temp_unit: TempUnit,
+
+        /// Input temperatures.
+        #[arg(long, value_name = "TEMP", num_args = 1..)]
+        temps: Vec<Temp>,
+
+        /// First temperature of an input ramp, up or down to --to.
+        #[arg(long, value_name = "TEMP", requires = "to")]
+        from: Option<Temp>,
+
+        /// Last temperature of the input ramp, included.
+        #[arg(long, value_name = "TEMP", requires = "from")]
+        to: Option<Temp>,
+
+        /// Temperature step of the input ramp.
+        #[arg(long, value_name = "TEMP", default_value_t = 1.0, requires = "from")]
+        step: Temp,
+
+        /// CSV file of input temperatures, ie. a daemon --csv-log file.
+        /// Temperatures are taken from the first column, or from --csv-column, empty values
+        /// being skipped.
+        #[arg(long, value_name = "FILE")]
+        temps_csv: Option<PathBuf>,
+
+        /// Name of the --temps-csv column to take temperatures from, as found in its header
+        /// line (ie. `/dev/sda temp`).
+        #[arg(long, value_name = "NAME", requires = "temps_csv")]
+        csv_column: Option<String>,
+    },
+
+    /// Print shell completion script, ie.
+    /// `hddfancontrol completions bash > /usr/share/bash-completion/completions/hddfancontrol`
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use float_cmp::approx_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_temp_unit() {
+        assert!(approx_eq!(f64, TempUnit::Celsius.to_celsius(40.0), 40.0));
+        assert!(approx_eq!(
+            f64,
+            TempUnit::Fahrenheit.to_celsius(104.0),
+            40.0
+        ));
+        assert!(approx_eq!(
+            f64,
+            TempUnit::Fahrenheit.delta_to_celsius(9.0),
+            5.0
+        ));
+
+        assert_eq!(TempUnit::Celsius.display(38.5).to_string(), "38.5°C");
+        assert_eq!(TempUnit::Fahrenheit.display(38.5).to_string(), "101.3°F");
+        assert_eq!(
+            format!("{:.1}", TempUnit::Fahrenheit.display(40.0)),
+            "104.0°F"
+        );
+
+        let Ok(DriveTempRange::Drive(_, range)) = "/dev/sda=95:122"
+            .parse::<DriveTempRange>()
+            .map(|r| r.into_celsius(TempUnit::Fahrenheit))
+        else {
+            panic!();
+        };
+        assert!(approx_eq!(f64, range.start, 35.0));
+        assert!(approx_eq!(f64, range.end, 50.0));
+    }
+
+    #[test]
+    fn test_pwm_enable_value() {
+        let value: PwmEnableValue = "/sys/class/hwmon/hwmon1/pwm2=0".parse().unwrap();
+        assert_eq!(value.filepath, Path::new("/sys/class/hwmon/hwmon1/pwm2"));
+        assert_eq!(value.value, 0);
+        let values = [value];
+        assert_eq!(
+            PwmEnableValue::find(&values, Path::new("/sys/class/hwmon/hwmon1/pwm2")),
+            Some(0)
+        );
+        assert_eq!(
+            PwmEnableValue::find(&values, Path::new("/sys/class/hwmon/hwmon1/pwm3")),
+            None
+        );
+        assert!(
+            "/sys/class/hwmon/hwmon1/pwm2"
+                .parse::<PwmEnableValue>()
+                .is_err()
+        );
+        assert!("=1".parse::<PwmEnableValue>().is_err());
+        assert!(
+            "/sys/class/hwmon/hwmon1/pwm2=manual"
+                .parse::<PwmEnableValue>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_pwm_max() {
+        let pwm_max: PwmMax = "/sys/class/hwmon/hwmon1/pwm2=100".parse().unwrap();
+        assert_eq!(pwm_max.filepath, Path::new("/sys/class/hwmon/hwmon1/pwm2"));
+        assert_eq!(pwm_max.max, 100);
+        let pwm_maxs = [pwm_max];
+        assert_eq!(
+            PwmMax::find(&pwm_maxs, Path::new("/sys/class/hwmon/hwmon1/pwm2")),
+            Some(100)
+        );
+        assert_eq!(
+            PwmMax::find(&pwm_maxs, Path::new("/sys/class/hwmon/hwmon1/pwm3")),
+            None
+        );
+        assert!("/sys/class/hwmon/hwmon1/pwm2".parse::<PwmMax>().is_err());
+        assert!("/sys/class/hwmon/hwmon1/pwm2=0".parse::<PwmMax>().is_err());
+        assert!(
+            "/sys/class/hwmon/hwmon1/pwm2=256"
+                .parse::<PwmMax>()
+                .is_err()
+        );
+        assert!("=100".parse::<PwmMax>().is_err());
+    }
+
+    #[test]
+    fn test_drive_selector() {
+        let dev_dir = tempfile::TempDir::new().unwrap();
+        let by_id_dir = tempfile::TempDir::new().unwrap();
+        for (dev, link) in [
+            ("sda", "ata-WDC_WD40EFRX-68N32N0_WD-WCC7K1234567"),
+            ("sda1", "ata-WDC_WD40EFRX-68N32N0_WD-WCC7K1234567-part1"),
+            ("sda", "wwn-0x50014ee2b5c6d7e8"),
+            ("sdb", "ata-WDC_WD40EFRX-68N32N0_WD-WCC7K7654321"),
+            ("sdc", "ata-ST4000VN008-2DR166_ZGY12345"),
+            ("nvme0n1", "nvme-Samsung_SSD_970_EVO_1TB_S467NX0M123456"),
+        ] {
+            let dev_path = dev_dir.path().join(dev);
+            fs::write(&dev_path, "").unwrap();
+            std::os::unix::fs::symlink(&dev_path, by_id_dir.path().join(link)).unwrap();
+        }
+        let dev = |n: &str| dev_dir.path().join(n).canonicalize().unwrap();
+        let resolve = |s: &str| {
+            s.parse::<DriveSelector>()
+                .unwrap()
+                .to_drive_paths_in(by_id_dir.path())
+        };
+
+        assert_eq!(resolve("serial:WD-WCC7K7654321").unwrap(), vec![dev("sdb")]);
+        let mut wdc = resolve("model:WDC*").unwrap();
+        wdc.sort_unstable();
+        assert_eq!(wdc, vec![dev("sda"), dev("sdb")]);
+        assert_eq!(resolve("model:*970 EVO*").unwrap(), vec![dev("nvme0n1")]);
+        assert_eq!(
+            resolve("model:ST4000VN008-2DR166").unwrap(),
+            vec![dev("sdc")]
+        );
+        assert_eq!(resolve("ata").unwrap().len(), 3);
+
+        let err = resolve("serial:WD-WCC7K").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("ata-ST4000VN008-2DR166_ZGY12345"));
+        assert!(resolve("model:WDC").is_err());
+
+        assert_eq!(
+            "serial:ZGY12345"
+                .parse::<DriveSelector>()
+                .unwrap()
+                .to_string(),
+            "serial:ZGY12345"
+        );
+        assert!("serial:".parse::<DriveSelector>().is_err());
+        assert!("model:".parse::<DriveSelector>().is_err());
+    }
+
+    #[test]
+    fn test_aggregation() {
+        let temps = [40.0, 35.0, 48.0];
+        assert!(approx_eq!(
+            f64,
+            Aggregation::Max.aggregate(&temps).unwrap(),
+            48.0
+        ));
+        assert!(approx_eq!(
+            f64,
+            Aggregation::Mean.aggregate(&temps).unwrap(),
+            41.0
+        ));
+        assert!(approx_eq!(
+            f64,
+            Aggregation::P90.aggregate(&temps).unwrap(),
+            48.0
+        ));
+        let many: Vec<f64> = (1..=20).map(f64::from).collect();
+        assert!(approx_eq!(
+            f64,
+            Aggregation::P90.aggregate(&many).unwrap(),
+            18.0
+        ));
+        assert!(approx_eq!(
+            f64,
+            Aggregation::P90.aggregate(&[42.0]).unwrap(),
+            42.0
+        ));
+        for aggregation in [Aggregation::Max, Aggregation::Mean, Aggregation::P90] {
+            assert!(aggregation.aggregate(&[]).is_none());
+        }
+    }
+
+    #[test]
+    fn test_window_stat() {
+        let values = [40.0, 35.0, 50.0, 38.0];
+        assert_eq!("max".parse::<WindowStat>().unwrap(), WindowStat::Max);
+        assert_eq!("mean".parse::<WindowStat>().unwrap(), WindowStat::Mean);
+        assert_eq!(
+            "p95".parse::<WindowStat>().unwrap(),
+            WindowStat::Percentile(95)
+        );
+        for invalid in ["p0", "p101", "p", "95", "median"] {
+            assert!(invalid.parse::<WindowStat>().is_err());
+        }
+        assert!(approx_eq!(
+            f64,
+            WindowStat::Max.compute(&values).unwrap(),
+            50.0
+        ));
+        assert!(approx_eq!(
+            f64,
+            WindowStat::Mean.compute(&values).unwrap(),
+            40.75
+        ));
+        assert!(approx_eq!(
+            f64,
+            WindowStat::Percentile(50).compute(&values).unwrap(),
+            38.0
+        ));
+        assert!(approx_eq!(
+            f64,
+            WindowStat::Percentile(75).compute(&values).unwrap(),
+            40.0
+        ));
+        assert!(approx_eq!(
+            f64,
+            WindowStat::Percentile(95).compute(&values).unwrap(),
+            50.0
+        ));
+        assert!(WindowStat::Percentile(95).compute(&[]).is_none());
+        assert_eq!(WindowStat::Percentile(95).to_string(), "p95");
+    }
+
+    #[test]
+    fn test_hwmon_settings() {
+        let path: HwmonSettings = "/sys/class/hwmon/hwmon1/temp1_input:40:60".parse().unwrap();
+        assert_eq!(
+            path.filepath,
+            Path::new("/sys/class/hwmon/hwmon1/temp1_input")
+        );
+        assert_eq!(path.temp, Some(40.0..60.0));
+
+        let labeled: HwmonSettings = "nct6775:SYSTIN:40:60".parse().unwrap();
+        assert_eq!(labeled.filepath, Path::new("nct6775:SYSTIN"));
+        assert_eq!(labeled.temp, Some(40.0..60.0));
+        assert!(labeled.is_selected_by(&"nct6775:SYSTIN".parse().unwrap()));
+        assert!(!labeled.is_selected_by(&"nct6775:CPUTIN".parse().unwrap()));
+        let no_range: HwmonSettings = "nct6775:SYSTIN".parse().unwrap();
+        assert!(no_range.temp.is_none());
+
+        assert!("nct6775".parse::<HwmonSettings>().is_err());
+        assert!("nct6775:SYSTIN:40:60:80".parse::<HwmonSettings>().is_err());
+        // Inverted, empty or infinite range
+        assert!("nct6775:SYSTIN:60:40".parse::<HwmonSettings>().is_err());
+        assert!("nct6775:SYSTIN:40:40".parse::<HwmonSettings>().is_err());
+        assert!("nct6775:SYSTIN:40:inf".parse::<HwmonSettings>().is_err());
+    }
+
+    #[test]
+    fn test_temp_range_validation() {
+        let split = |values: &[&str]| {
+            DriveTempRange::split(values.iter().map(|v| v.parse().unwrap()).collect())
+        };
+        assert_eq!(split(&["35", "45"]).unwrap().0, 35.0..45.0);
+        assert_eq!(split(&[]).unwrap().0, DEFAULT_DRIVE_TEMP_RANGE);
+        // Inverted
+        assert!(split(&["50", "40"]).is_err());
+        // Equal
+        assert!(split(&["40", "40"]).is_err());
+        // Out of bounds
+        assert!(split(&["-20", "40"]).is_err());
+        assert!(split(&["40", "150"]).is_err());
+        assert!(split(&["35", "45", "/dev/sda=30:130"]).is_err());
+        assert!(split(&["-10", "120"]).is_ok());
+        assert!("inf".parse::<DriveTempRange>().is_err());
+        assert!("/dev/sda=50:40".parse::<DriveTempRange>().is_err());
+        assert!("/dev/sda=40:40".parse::<DriveTempRange>().is_err());
+        assert!("/dev/sda=40:NaN".parse::<DriveTempRange>().is_err());
+
+        // Bounds are in Celsius, whatever the unit of the values
+        let fahrenheit = |values: &[&str]| {
+            DriveTempRange::split(
+                values
+                    .iter()
+                    .map(|v| v.parse::<DriveTempRange>().unwrap())
+                    .map(|r| r.into_celsius(TempUnit::Fahrenheit))
+                    .collect(),
+            )
+        };
+        assert!(fahrenheit(&["95", "122"]).is_ok());
+        assert!(fahrenheit(&["95", "260"]).is_err());
+
+        assert!(check_temp_range(&(40.0..60.0)).is_ok());
+        assert!(check_temp_range(&(60.0..40.0)).is_err());
+        assert_eq!(
+            check_temp(130.0).unwrap_err().to_string(),
+            "Temperature 130°C is out of the -10°C to 120°C range"
+        );
+    }
+
+    #[test]
+    fn test_drive_weight() {
+        let weight: DriveWeight = "/dev/sda=2.5".parse().unwrap();
+        assert!(
+            matches!(&weight.selector, DriveSelector::DrivePath(p) if p == Path::new("/dev/sda"))
+        );
+        assert!(approx_eq!(f64, weight.weight, 2.5));
+
+        assert!("/dev/sda".parse::<DriveWeight>().is_err());
+        assert!("=1".parse::<DriveWeight>().is_err());
+        assert!("/dev/sda=0".parse::<DriveWeight>().is_err());
+        assert!("/dev/sda=-1".parse::<DriveWeight>().is_err());
+        assert!("/dev/sda=inf".parse::<DriveWeight>().is_err());
+    }
+
+    #[test]
+    fn test_nvme_sensor() {
+        let values: Vec<DriveNvmeSensor> = ["composite", "/dev/nvme1n1=2"]
+            .iter()
+            .map(|v| v.parse().unwrap())
+            .collect();
+        let (sensor, selector_sensors) = DriveNvmeSensor::split(&values).unwrap();
+        assert_eq!(sensor, NvmeSensor::Composite);
+        assert_eq!(selector_sensors.len(), 1);
+        assert_eq!(selector_sensors[0].1, NvmeSensor::Sensor(2));
+        assert_eq!(
+            DriveNvmeSensor::split(&[]).unwrap().0,
+            NvmeSensor::default()
+        );
+        assert!(DriveNvmeSensor::split(&[values[0].clone(), values[0].clone()]).is_err());
+
+        assert_eq!("max".parse::<NvmeSensor>().unwrap().to_string(), "max");
+        assert!("0".parse::<NvmeSensor>().is_err());
+        assert!("hottest".parse::<NvmeSensor>().is_err());
+        assert!("/dev/nvme0n1=".parse::<DriveNvmeSensor>().is_err());
+    }
+
+    #[test]
+    fn test_hwmon_temp_scale() {
+        let hwmons: Vec<HwmonSettings> = vec![
+            "/sys/class/hwmon/hwmon1/temp1_input".parse().unwrap(),
+            "nct6775:SYSTIN:40:60".parse().unwrap(),
+        ];
+        let scale: HwmonTempScale = "nct6775:SYSTIN=degree".parse().unwrap();
+        assert_eq!(scale.scale, TempScale::Degree);
+        assert_eq!(scale.hwmon_index(&hwmons), Some(1));
+        let path_scale: HwmonTempScale =
+            "/sys/class/hwmon/hwmon1/temp1_input=milli".parse().unwrap();
+        assert_eq!(path_scale.hwmon_index(&hwmons), Some(0));
+
+        assert!(approx_eq!(f64, TempScale::Milli.to_celsius(45500), 45.5));
+        assert!(approx_eq!(f64, TempScale::Milli.to_celsius(-2000), -2.0));
+        assert!(approx_eq!(f64, TempScale::Degree.to_celsius(45), 45.0));
+
+        assert!("nct6775:SYSTIN".parse::<HwmonTempScale>().is_err());
+        assert!("=degree".parse::<HwmonTempScale>().is_err());
+        assert!("nct6775:SYSTIN=kelvin".parse::<HwmonTempScale>().is_err());
+    }
+
+    #[test]
+    fn test_temp_offset() {
+        let offset: TempOffset = "/dev/sda=-4".parse().unwrap();
+        assert!(matches!(&offset.input, DriveSelector::DrivePath(p) if p == Path::new("/dev/sda")));
+        assert!(approx_eq!(f64, offset.offset, -4.0));
+        assert!(approx_eq!(
+            f64,
+            offset.into_celsius(TempUnit::Fahrenheit).offset,
+            -4.0 / 1.8
+        ));
+
+        let hwmons: Vec<HwmonSettings> = vec![
+            "/sys/class/hwmon/hwmon1/temp1_input".parse().unwrap(),
+            "/sys/class/hwmon/hwmon2/temp1_input".parse().unwrap(),
+        ];
+        let hwmon_offset: TempOffset = "/sys/class/hwmon/hwmon2/temp1_input=2.5".parse().unwrap();
+        assert_eq!(hwmon_offset.hwmon_index(&hwmons), Some(1));
+        let interface_offset: TempOffset = "ata=1".parse().unwrap();
+        assert_eq!(interface_offset.hwmon_index(&hwmons), None);
+
+        assert!("/dev/sda".parse::<TempOffset>().is_err());
+        assert!("=1".parse::<TempOffset>().is_err());
+        assert!("/dev/sda=x".parse::<TempOffset>().is_err());
+        assert!("/dev/sda=inf".parse::<TempOffset>().is_err());
+    }
+
+    #[test]
+    fn test_log_rotate() {
+        assert!(matches!(
+            LogRotate::default().criterion(1024),
+            flexi_logger::Criterion::Size(1024)
+        ));
+        assert!(matches!(
+            LogRotate::Daily.criterion(1024),
+            flexi_logger::Criterion::AgeOrSize(flexi_logger::Age::Day, 1024)
+        ));
+        assert!(matches!(
+            LogRotate::Hourly.criterion(1024),
+            flexi_logger::Criterion::AgeOrSize(flexi_logger::Age::Hour, 1024)
+        ));
+    }
+
+    #[test]
+    fn test_min_fan_speed_raw() {
+        use clap::Parser as _;
+
+        let daemon_args = |extra: &[&str]| {
+            Args::try_parse_from(
+                [
+                    "hddfancontrol",
+                    "daemon",
+                    "-d",
+                    "/dev/sda",
+                    "-p",
+                    "/sys/pwm1:200:75",
+                ]
+                .iter()
+                .chain(extra),
+            )
+        };
+        let Command::Daemon {
+            min_fan_speed_raw, ..
+        } = daemon_args(&["--min-fan-speed-raw", "60"]).unwrap().command
+        else {
+            panic!();
+        };
+        assert_eq!(min_fan_speed_raw, Some(60));
+        assert!(daemon_args(&["--min-fan-speed-raw", "256"]).is_err());
+        assert!(daemon_args(&["-m", "10", "--min-fan-speed-raw", "60"]).is_err());
+    }
+
+    #[test]
+    fn test_program_auto_curve() {
+        use clap::Parser as _;
+
+        let args = |extra: &[&str]| {
+            Args::try_parse_from(
+                ["hddfancontrol", "program-auto-curve", "-p", "/sys/pwm1"]
+                    .iter()
+                    .chain(extra),
+            )
+        };
+        assert!(args(&["--drive-temp-curve", "35:0,50:100"]).is_ok());
+        assert!(args(&["--curve-file", "/etc/curve.csv"]).is_ok());
+        assert!(args(&[]).is_err());
+        assert!(
+            args(&[
+                "--drive-temp-curve",
+                "35:0,50:100",
+                "--curve-file",
+                "/etc/curve.csv"
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_stdout_logging() {
+        use clap::Parser as _;
+
+        let daemon_args = |extra: &[&str]| {
+            Args::try_parse_from(
+                [
+                    "hddfancontrol",
+                    "daemon",
+                    "-d",
+                    "/dev/sda",
+                    "-p",
+                    "/sys/pwm1:200:75",
+                ]
+                .iter()
+                .chain(extra),
+            )
+        };
+        let Command::Daemon {
+            no_stdout,
+            stdout_only,
+            ..
+        } = daemon_args(&["--no-stdout"]).unwrap().command
+        else {
+            panic!();
+        };
+        assert!(no_stdout);
+        assert!(!stdout_only);
+        assert!(daemon_args(&["--stdout-only"]).is_ok());
+        assert!(daemon_args(&["--no-stdout", "--stdout-only"]).is_err());
+    }
+
+    #[test]
+    fn test_temp_sources() {
+        use clap::Parser as _;
+
+        let daemon_args = |extra: &[&str]| {
+            Args::try_parse_from(
+                ["hddfancontrol", "daemon", "-p", "/sys/pwm1:200:75"]
+                    .iter()
+                    .chain(extra),
+            )
+        };
+        assert!(daemon_args(&["-d", "/dev/sda"]).is_ok());
+        let Command::Daemon { drives, hwmons, .. } = daemon_args(&["-w", "/sys/temp1_input:40:60"])
+            .unwrap()
+            .command
+        else {
+            panic!();
+        };
+        assert!(drives.is_empty());
+        assert_eq!(hwmons.len(), 1);
+        assert!(daemon_args(&[]).is_err());
+    }
+
+    #[test]
+    fn test_hddtemp_daemon() {
+        use clap::Parser as _;
+
+        let Command::List { hddtemp_daemon, .. } = Args::try_parse_from([
+            "hddfancontrol",
+            "list",
+            "--hddtemp-daemon",
+            "127.0.0.1:7634",
+            "--hddtemp-daemon",
+            "localhost:7635",
+        ])
+        .unwrap()
+        .command
+        else {
+            panic!();
+        };
+        assert_eq!(hddtemp_daemon.len(), 2);
+        assert_eq!(hddtemp_daemon[0], SocketAddr::from(([127, 0, 0, 1], 7634)));
+        assert_eq!(hddtemp_daemon[1].port(), 7635);
+        assert!(socket_addr("127.0.0.1").is_err());
+        assert!(
+            Args::try_parse_from([
+                "hddfancontrol",
+                "list",
+                "--hddtemp-daemon",
+                "127.0.0.1:7634",
+                "--hddtemp-daemon-port",
+                "7635",
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_simulate() {
+        use clap::Parser as _;
+
+        let args = Args::try_parse_from([
+            "hddfancontrol",
+            "simulate",
+            "-t",
+            "35",
+            "45",
+            "--from",
+            "30",
+            "--to",
+            "50",
+        ])
+        .unwrap();
+        assert!(matches!(
+            args.command,
+            Command::Simulate { drive_temp_range, from: Some(_), to: Some(_), .. }
+                if drive_temp_range.len() == 2
+        ));
+
+        // Exactly one input
+        assert!(Args::try_parse_from(["hddfancontrol", "simulate"]).is_err());
+        assert!(
+            Args::try_parse_from(["hddfancontrol", "simulate", "--temps", "30", "--from", "30"])
+                .is_err()
+        );
+        assert!(Args::try_parse_from(["hddfancontrol", "simulate", "--from", "30"]).is_err());
+        assert!(Args::try_parse_from(["hddfancontrol", "simulate", "--temps", "30", "40"]).is_ok());
+    }
+
+    #[test]
+    fn test_completions() {
+        use clap::{CommandFactory as _, Parser as _};
+
+        let args = Args::try_parse_from(["hddfancontrol", "completions", "zsh"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Command::Completions {
+                shell: clap_complete::Shell::Zsh
+            }
+        ));
+
+        let mut cmd = Args::command();
+        let mut script = Vec::new();
+        clap_complete::generate(
+            clap_complete::Shell::Bash,
+            &mut cmd,
+            "hddfancontrol",
+            &mut script,
+        );
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("daemon"));
+        assert!(script.contains("--pwm"));
+    }
+}
@@ -0,0 +1,273 @@
+//! TOML configuration file for the daemon
+//!
+//! Keys are the daemon argument long names with `_` instead of `-` (ie. `min_fan_speed_prct`),
+//! and values use the same syntax as on the command line, so that they are validated and parsed
+//! by clap like command line arguments.
+
+use std::{ffi::OsString, fs, iter, path::Path};
+
+use anyhow::Context as _;
+use clap::{
+    ArgMatches, CommandFactory as _, FromArgMatches as _, Parser as _, parser::ValueSource,
+};
+
+use crate::cl;
+
+/// Name of the daemon subcommand
+const DAEMON_COMMAND: &str = "daemon";
+
+/// Parse command line arguments, completed by the configuration file if any
+pub(crate) fn parse_args(argv: Vec<OsString>) -> anyhow::Result<cl::Args> {
+    let matches = cl::Args::command().try_get_matches_from(&argv)?;
+    let parsed = cl::Args::from_arg_matches(&matches)?;
+    if let cl::Command::Daemon {
+        config: Some(config_path),
+        ..
+    } = &parsed.command
+    {
+        let full_argv = extend_args(config_path, argv, &matches)?;
+        Ok(cl::Args::try_parse_from(full_argv)?)
+    } else {
+        Ok(parsed)
+    }
+}
+
+/// Append arguments from a configuration file to the command line arguments `argv`,
+/// for those not explicitly set in `matches`
+pub(crate) fn extend_args(
+    path: &Path,
+    mut argv: Vec<OsString>,
+    matches: &ArgMatches,
+) -> anyhow::Result<Vec<OsString>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    let table: toml::Table = content
+        .parse()
+        .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+    let mut cmd = cl::Args::command();
+    cmd.build();
+    let daemon_cmd = cmd
+        .find_subcommand(DAEMON_COMMAND)
+        .ok_or_else(|| anyhow::anyhow!("No {DAEMON_COMMAND} command"))?;
+    let daemon_matches = matches
+        .subcommand_matches(DAEMON_COMMAND)
+        .ok_or_else(|| anyhow::anyhow!("Config file is only supported by the daemon command"))?;
+    let config_args = table_args(&cmd, daemon_cmd, daemon_matches, &table)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+    argv.extend(config_args);
+    Ok(argv)
+}
+
+/// Is an argument set explicitly by the user
+fn is_explicit(matches: &ArgMatches, id: &str) -> bool {
+    matches!(
+        matches.value_source(id),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    )
+}
+
+/// Build daemon command line arguments from configuration keys not explicitly set in `matches`
+fn table_args(
+    root_cmd: &clap::Command,
+    cmd: &clap::Command,
+    matches: &ArgMatches,
+    table: &toml::Table,
+) -> anyhow::Result<Vec<OsString>> {
+    let mut args = Vec::new();
+    for (key, value) in table {
+        let (arg, long) = cmd
+            .get_arguments()
+            .filter(|a| a.get_id() != "config")
+            .filter(|a| !matches!(a.get_action(), clap::ArgAction::Help))
+            .find_map(|a| {
+                a.get_long()
+                    .filter(|l| l.replace('-', "_") == *key)
+                    .map(|l| (a, l))
+            })
+            .ok_or_else(|| anyhow::anyhow!("Unknown key {key:?}"))?;
+        let values: Vec<String> = match value {
+            toml::Value::Array(values) => values
+                .iter()
+                .map(|v| {
+                    scalar_to_string(v)
+                        .ok_or_else(|| anyhow::anyhow!("Invalid value type for key {key:?}"))
+                })
+                .collect::<anyhow::Result<_>>()?,
+            v => vec![
+                scalar_to_string(v)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid value type for key {key:?}"))?,
+            ],
+        };
+
+        if is_explicit(matches, arg.get_id().as_str())
+            || cmd
+                .get_arguments()
+                .filter(|a| is_explicit(matches, a.get_id().as_str()))
+                .any(|a| {
+                    // Conflicts can be declared on either argument
+                    cmd.get_arg_conflicts_with(a)
+                        .iter()
+                        .chain(cmd.get_arg_conflicts_with(arg).iter())
+                        .any(|c| c.get_id() == a.get_id() || c.get_id() == arg.get_id())
+                })
+        {
+            log::debug!("Ignoring config key {key:?} set on command line");
+            continue;
+        }
+
+        if !arg.get_action().takes_values() {
+            match value {
+                toml::Value::Boolean(true) => args.push(format!("--{long}").into()),
+                toml::Value::Boolean(false) => {}
+                _ => anyhow::bail!("Invalid value for key {key:?}, expected a boolean"),
+            }
+            continue;
+        }
+        anyhow::ensure!(!values.is_empty(), "Empty value for key {key:?}");
+        let multiple_values = arg.get_num_args().is_some_and(|n| n.max_values() > 1);
+        let key_args: Vec<OsString> = if multiple_values {
+            iter::once(format!("--{long}").into())
+                .chain(values.into_iter().map(OsString::from))
+                .collect()
+        } else {
+            values
+                .into_iter()
+                .map(|v| format!("--{long}={v}").into())
+                .collect()
+        };
+
+        // Validate values alone, to report errors for this key
+        // (--config satisfies required arguments)
+        root_cmd
+            .clone()
+            .try_get_matches_from(
+                [root_cmd.get_name(), DAEMON_COMMAND, "--config", "-"]
+                    .into_iter()
+                    .map(OsString::from)
+                    .chain(key_args.iter().cloned()),
+            )
+            .map_err(|e| {
+                let rendered = e.render().to_string();
+                anyhow::anyhow!(
+                    "Invalid value for key {key:?}: {}",
+                    rendered
+                        .lines()
+                        .next()
+                        .unwrap_or_default()
+                        .trim_start_matches("error: ")
+                )
+            })?;
+        args.extend(key_args);
+    }
+    Ok(args)
+}
+
+/// Command line representation of a TOML scalar value
+fn scalar_to_string(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.to_owned()),
+        toml::Value::Integer(i) => Some(i.to_string()),
+        toml::Value::Float(f) => Some(f.to_string()),
+        toml::Value::Boolean(b) => Some(b.to_string()),
+        toml::Value::Datetime(_) | toml::Value::Array(_) | toml::Value::Table(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write as _;
+
+    use super::*;
+
+    /// Parse arguments, completed by a configuration file
+    fn parse(argv: &[&str], config: &str) -> anyhow::Result<cl::Args> {
+        let mut config_file = tempfile::NamedTempFile::new().unwrap();
+        config_file.write_all(config.as_bytes()).unwrap();
+        let config_path = config_file.path().to_str().unwrap().to_owned();
+        let argv: Vec<OsString> = ["hddfancontrol", "daemon", "--config", &config_path]
+            .into_iter()
+            .chain(argv.iter().copied())
+            .map(OsString::from)
+            .collect();
+        parse_args(argv)
+    }
+
+    #[test]
+    fn test_extend_args() {
+        let config = r#"
+drives = ["/dev/sda", "/dev/sdb"]
+pwm = ["/sys/class/hwmon/hwmon1/device/pwm2:200:75"]
+drive_temp_range = [35, 45.5]
+min_fan_speed_prct = 10
+interval = "1min"
+restore_fan_settings = true
+zone = ["/dev/sda=/sys/class/hwmon/hwmon1/device/pwm2"]
+"#;
+        let args = parse(&[], config).unwrap();
+        let cl::Command::Daemon {
+            drives,
+            pwm,
+            drive_temp_range,
+            min_fan_speed_prct,
+            interval,
+            restore_fan_settings,
+            zones,
+            ..
+        } = args.command
+        else {
+            panic!();
+        };
+        assert_eq!(drives.len(), 2);
+        assert_eq!(pwm.len(), 1);
+        let (drive_temp_range, drive_selector_temp_ranges) =
+            cl::DriveTempRange::split(drive_temp_range).unwrap();
+        assert_eq!(drive_temp_range, 35.0..45.5);
+        assert!(drive_selector_temp_ranges.is_empty());
+        assert_eq!(min_fan_speed_prct, 10);
+        assert_eq!(*interval, std::time::Duration::from_secs(60));
+        assert!(restore_fan_settings);
+        assert_eq!(zones.len(), 1);
+
+        // Command line takes precedence
+        let cl_args = parse(
+            &[
+                "-m",
+                "30",
+                "--drive-temp-curve",
+                "35:0,50:100",
+                "-d",
+                "/dev/sdc",
+            ],
+            config,
+        )
+        .unwrap();
+        let cl::Command::Daemon {
+            drives: cl_drives,
+            min_fan_speed_prct: cl_min_fan_speed_prct,
+            drive_temp_curve,
+            ..
+        } = cl_args.command
+        else {
+            panic!();
+        };
+        assert_eq!(cl_drives.len(), 1);
+        assert_eq!(cl_min_fan_speed_prct, 30);
+        assert!(drive_temp_curve.is_some());
+    }
+
+    #[test]
+    fn test_extend_args_errors() {
+        let base =
+            "drives = [\"/dev/sda\"]\npwm = [\"/sys/class/hwmon/hwmon1/device/pwm2:200:75\"]\n";
+        assert!(parse(&[], &format!("{base}foo = 1\n")).is_err());
+        assert!(parse(&[], &format!("{base}min_fan_speed_prct = 101\n")).is_err());
+        assert!(parse(&[], &format!("{base}restore_fan_settings = 1\n")).is_err());
+        assert!(parse(&[], &format!("{base}interval = [[]]\n")).is_err());
+        assert!(parse(&[], "drives = \n").is_err());
+
+        let matches = cl::Args::command()
+            .try_get_matches_from(["hddfancontrol", "daemon", "--config", "/nonexistent"])
+            .unwrap();
+        assert!(extend_args(Path::new("/nonexistent"), vec![], &matches).is_err());
+    }
+}
@@ -0,0 +1,596 @@
+//! Control socket, to interact with a running daemon
+
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, BufRead as _, BufReader, Write as _},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        Arc, Mutex, PoisonError, RwLock,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+
+use crate::{cl::TempUnit, fan::Speed, probe::Temp, snapshot::Snapshot, state::State};
+
+/// Timeout for reading a control socket response
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Duration after which an idle control socket client is disconnected
+const CLIENT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Temporary fan speed override
+#[derive(Clone, Debug)]
+pub(crate) struct Boost {
+    /// Speed to force fans to
+    pub speed: Speed,
+    /// When the boost expires
+    pub until: Instant,
+    /// Fans to boost, identified by name or PWM path, or all fans if empty
+    pub fans: Vec<String>,
+}
+
+impl Boost {
+    /// Does this boost apply to a fan
+    pub(crate) fn applies_to(&self, fan_name: &str, pwm_path: &Path) -> bool {
+        self.fans.is_empty()
+            || self
+                .fans
+                .iter()
+                .any(|f| f == fan_name || Path::new(f) == pwm_path)
+    }
+
+    /// Boosted speed of a fan with computed speed `target`, if this boost applies to it and
+    /// raises its speed, boosts never slowing fans down
+    pub(crate) fn speed_for(
+        &self,
+        fan_name: &str,
+        pwm_path: &Path,
+        target: Speed,
+    ) -> Option<Speed> {
+        (self.speed > target && self.applies_to(fan_name, pwm_path)).then_some(self.speed)
+    }
+}
+
+/// State shared between the control loop and the socket thread
+#[derive(Default)]
+pub(crate) struct Shared {
+    /// Currently active boost
+    boost: Mutex<Option<Boost>>,
+    /// Persisted state
+    state: Mutex<State>,
+    /// Has persisted state changed since last saved
+    state_dirty: AtomicBool,
+    /// New PID setpoint to apply, in Celsius
+    pid_setpoint: Mutex<Option<Temp>>,
+    /// Unit of temperatures in requests
+    temp_unit: TempUnit,
+    /// Readings of the last control loop iteration
+    snapshot: RwLock<Option<Snapshot>>,
+    /// Readings of the last control loop iterations, oldest first
+    history: Mutex<VecDeque<Snapshot>>,
+    /// Maximum number of readings kept in history
+    history_size: usize,
+}
+
+impl Shared {
+    /// Build shared state with initial persisted state, keeping the last `history_size`
+    /// readings, with request temperatures in `temp_unit`
+    pub(crate) fn new(state: State, history_size: usize, temp_unit: TempUnit) -> Self {
+        Self {
+            state: Mutex::new(state),
+            history: Mutex::new(VecDeque::with_capacity(history_size)),
+            history_size,
+            temp_unit,
+            ..Self::default()
+        }
+    }
+
+    /// Record a drive temperature, returns true if it is a new maximum
+    pub(crate) fn update_max_temp(&self, drive: &Path, temp: Temp) -> bool {
+        let new_max = self
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .update_max_temp(drive, temp);
+        if new_max {
+            self.state_dirty.store(true, Ordering::SeqCst);
+        }
+        new_max
+    }
+
+    /// Get persisted state if it has changed since last call
+    pub(crate) fn take_dirty_state(&self) -> Option<State> {
+        self.state_dirty.swap(false, Ordering::SeqCst).then(|| {
+            self.state
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone()
+        })
+    }
+
+    /// Get active boost if any, and clear it if it has expired
+    pub(crate) fn active_boost(&self) -> Option<Boost> {
+        let mut boost = self.boost.lock().unwrap_or_else(PoisonError::into_inner);
+        if boost.as_ref().is_some_and(|b| b.until <= Instant::now()) {
+            log::info!("Fan boost expired, resuming normal control");
+            *boost = None;
+        }
+        boost.clone()
+    }
+
+    /// Publish readings of a control loop iteration
+    pub(crate) fn set_snapshot(&self, snapshot: Snapshot) {
+        if self.history_size > 0 {
+            let mut history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+            if history.len() >= self.history_size {
+                history.pop_front();
+            }
+            history.push_back(snapshot.clone());
+        }
+        *self
+            .snapshot
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(snapshot);
+    }
+
+    /// Get readings of the last control loop iteration, if any
+    pub(crate) fn snapshot(&self) -> Option<Snapshot> {
+        self.snapshot
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Get readings of the last control loop iterations, oldest first
+    pub(crate) fn history(&self) -> Vec<Snapshot> {
+        self.history
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Get new PID setpoint in Celsius if it was changed since last call
+    pub(crate) fn take_pid_setpoint(&self) -> Option<Temp> {
+        self.pid_setpoint
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+
+    /// Set new boost, replacing any previous one
+    fn set_boost(&self, boost: Boost) {
+        *self.boost.lock().unwrap_or_else(PoisonError::into_inner) = Some(boost);
+    }
+}
+
+/// Control socket request
+#[derive(Debug)]
+enum Request {
+    /// Force fans to a speed for a duration: `boost <PRCT> <DURATION> [FAN ...]`
+    Boost {
+        /// Speed to force fans to
+        speed: Speed,
+        /// Boost duration
+        duration: Duration,
+        /// Fans to boost, or all if empty
+        fans: Vec<String>,
+    },
+    /// Get last readings: `status`
+    Status,
+    /// Get readings of the last iterations: `history`
+    History,
+    /// Get highest temperature seen for each drive: `max-temps`
+    MaxTemps,
+    /// Reset highest temperature seen: `reset-max-temps [DRIVE ...]`
+    ResetMaxTemps {
+        /// Drives to reset, or all if empty
+        drives: Vec<PathBuf>,
+    },
+    /// Change PID setpoint: `pid-setpoint <TEMP>`
+    PidSetpoint {
+        /// New setpoint, in --temp-unit
+        setpoint: Temp,
+    },
+}
+
+impl FromStr for Request {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = s.split_ascii_whitespace();
+        match tokens.next() {
+            Some("boost") => {
+                let prct: u8 = tokens
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("Missing boost speed percentage"))?
+                    .trim_end_matches('%')
+                    .parse()
+                    .context("Invalid boost speed percentage")?;
+                anyhow::ensure!(prct <= 100, "Invalid boost speed percentage {prct}");
+                let speed = Speed::try_from(f64::from(prct) / 100.0)?;
+                let duration = humantime::parse_duration(
+                    tokens
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("Missing boost duration"))?,
+                )
+                .context("Invalid boost duration")?;
+                Ok(Self::Boost {
+                    speed,
+                    duration,
+                    fans: tokens.map(ToOwned::to_owned).collect(),
+                })
+            }
+            Some("status") => Ok(Self::Status),
+            Some("history") => Ok(Self::History),
+            Some("max-temps") => Ok(Self::MaxTemps),
+            Some("reset-max-temps") => Ok(Self::ResetMaxTemps {
+                drives: tokens.map(PathBuf::from).collect(),
+            }),
+            Some("pid-setpoint") => {
+                let setpoint: Temp = tokens
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("Missing setpoint temperature"))?
+                    .parse()
+                    .context("Invalid setpoint temperature")?;
+                anyhow::ensure!(
+                    setpoint.is_finite(),
+                    "Invalid setpoint temperature {setpoint}"
+                );
+                Ok(Self::PidSetpoint { setpoint })
+            }
+            Some(cmd) => anyhow::bail!("Unknown command {cmd:?}"),
+            None => anyhow::bail!("Empty command"),
+        }
+    }
+}
+
+/// Listening control socket, removed when dropped
+pub(crate) struct ControlSocket {
+    /// Socket filepath
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    /// Bind socket and serve requests from a background thread
+    ///
+    /// `wake_tx` is used to interrupt the control loop sleep so that requests take effect immediately.
+    pub(crate) fn spawn(
+        path: &Path,
+        shared: Arc<Shared>,
+        wake_tx: mpsc::Sender<()>,
+    ) -> anyhow::Result<Self> {
+        if path.exists() {
+            // Stale socket from a previous run
+            fs::remove_file(path)
+                .with_context(|| format!("Failed to remove old socket {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to bind control socket {}", path.display()))?;
+        log::info!("Listening for commands on {}", path.display());
+        thread::Builder::new()
+            .name("control".to_owned())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        // Serve each client from its own thread, so that an idle one does not
+                        // block the others
+                        Ok(stream) => {
+                            let shared = Arc::clone(&shared);
+                            let wake_tx = wake_tx.clone();
+                            if let Err(e) = thread::Builder::new()
+                                .name("control-client".to_owned())
+                                .spawn(move || {
+                                    if let Err(e) = Self::serve(stream, &shared, &wake_tx) {
+                                        log::warn!("Control socket client error: {e}");
+                                    }
+                                })
+                            {
+                                log::warn!("Failed to start control socket client thread: {e}");
+                            }
+                        }
+                        Err(e) => log::warn!("Failed to accept control socket connection: {e}"),
+                    }
+                }
+            })
+            .context("Failed to start control socket thread")?;
+        Ok(Self {
+            path: path.to_owned(),
+        })
+    }
+
+    /// Handle a client connection, one request per line, one JSON response per line
+    fn serve(stream: UnixStream, shared: &Shared, wake_tx: &mpsc::Sender<()>) -> io::Result<()> {
+        stream.set_read_timeout(Some(CLIENT_IDLE_TIMEOUT))?;
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            log::debug!("Control socket request: {line:?}");
+            let response = match line.parse::<Request>() {
+                Ok(Request::Boost {
+                    speed,
+                    duration,
+                    fans,
+                }) => {
+                    if fans.is_empty() {
+                        log::info!("Boosting all fans to {speed} for {duration:?}");
+                    } else {
+                        log::info!("Boosting fans {fans:?} to {speed} for {duration:?}");
+                    }
+                    shared.set_boost(Boost {
+                        speed,
+                        until: Instant::now() + duration,
+                        fans,
+                    });
+                    let _ = wake_tx.send(());
+                    serde_json::json!({ "ok": true })
+                }
+                Ok(Request::Status) => match shared.snapshot() {
+                    Some(snapshot) => serde_json::json!({ "status": snapshot }),
+                    None => serde_json::json!({ "error": "No readings yet" }),
+                },
+                Ok(Request::History) => serde_json::json!({ "history": shared.history() }),
+                Ok(Request::MaxTemps) => {
+                    let state = shared.state.lock().unwrap_or_else(PoisonError::into_inner);
+                    serde_json::json!({ "max_temps": state.max_temps })
+                }
+                Ok(Request::ResetMaxTemps { drives }) => {
+                    log::info!("Resetting maximum drive temperatures");
+                    shared
+                        .state
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .reset_max_temps(&drives);
+                    shared.state_dirty.store(true, Ordering::SeqCst);
+                    let _ = wake_tx.send(());
+                    serde_json::json!({ "ok": true })
+                }
+                Ok(Request::PidSetpoint { setpoint }) => {
+                    let setpoint = shared.temp_unit.to_celsius(setpoint);
+                    log::info!(
+                        "Changing PID setpoint to {}",
+                        shared.temp_unit.display(setpoint)
+                    );
+                    *shared
+                        .pid_setpoint
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner) = Some(setpoint);
+                    let _ = wake_tx.send(());
+                    serde_json::json!({ "ok": true })
+                }
+                Err(e) => serde_json::json!({ "error": format!("{e:#}") }),
+            };
+            writeln!(writer, "{response}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Send a request to a daemon control socket, and return the value of its response
+pub(crate) fn query(path: &Path, request: &str) -> anyhow::Result<serde_json::Value> {
+    let stream = UnixStream::connect(path)
+        .with_context(|| format!("Failed to connect to control socket {}", path.display()))?;
+    stream.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    writeln!(&stream, "{request}")?;
+    let mut line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut line)
+        .context("Failed to read control socket response")?;
+    let mut response: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(&line).context("Invalid control socket response")?;
+    if let Some(error) = response.remove("error") {
+        anyhow::bail!("Daemon error: {}", error.as_str().unwrap_or_default());
+    }
+    let request_name = request.split_ascii_whitespace().next().unwrap_or_default();
+    Ok(response
+        .remove(&request_name.replace('-', "_"))
+        .unwrap_or(serde_json::Value::Object(response)))
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[expect(clippy::shadow_unrelated)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let Request::Boost {
+            speed,
+            duration,
+            fans,
+        } = "boost 80 10min".parse().unwrap()
+        else {
+            panic!();
+        };
+        assert_eq!(speed, Speed::try_from(0.8).unwrap());
+        assert_eq!(duration, Duration::from_secs(600));
+        assert!(fans.is_empty());
+
+        let Request::Boost { speed, fans, .. } =
+            "boost 100% 30s hwmon1/2 /sys/class/hwmon/hwmon1/pwm3"
+                .parse()
+                .unwrap()
+        else {
+            panic!();
+        };
+        assert_eq!(speed, Speed::try_from(1.0).unwrap());
+        assert_eq!(fans, vec!["hwmon1/2", "/sys/class/hwmon/hwmon1/pwm3"]);
+
+        assert!("boost".parse::<Request>().is_err());
+        assert!("boost 101 10s".parse::<Request>().is_err());
+        assert!("boost 50".parse::<Request>().is_err());
+        assert!("boost 50 nope".parse::<Request>().is_err());
+        assert!(matches!("status".parse().unwrap(), Request::Status));
+        assert!(matches!("history".parse().unwrap(), Request::History));
+        assert!(matches!("max-temps".parse().unwrap(), Request::MaxTemps));
+        let Request::ResetMaxTemps { drives } = "reset-max-temps /dev/sda".parse().unwrap() else {
+            panic!();
+        };
+        assert_eq!(drives, vec![PathBuf::from("/dev/sda")]);
+        assert!(matches!(
+            "pid-setpoint 42.5".parse().unwrap(),
+            Request::PidSetpoint { setpoint } if (setpoint - 42.5).abs() < f64::EPSILON
+        ));
+        assert!("pid-setpoint".parse::<Request>().is_err());
+        assert!("pid-setpoint nan".parse::<Request>().is_err());
+
+        assert!("hello".parse::<Request>().is_err());
+        assert!("".parse::<Request>().is_err());
+    }
+
+    #[test]
+    fn test_boost_applies_to() {
+        let mut boost = Boost {
+            speed: Speed::try_from(1.0).unwrap(),
+            until: Instant::now(),
+            fans: vec![],
+        };
+        assert!(boost.applies_to("hwmon1/2", Path::new("/sys/class/hwmon/hwmon1/pwm2")));
+        boost.fans = vec!["/sys/class/hwmon/hwmon1/pwm2".to_owned()];
+        assert!(boost.applies_to("hwmon1/2", Path::new("/sys/class/hwmon/hwmon1/pwm2")));
+        assert!(!boost.applies_to("hwmon1/3", Path::new("/sys/class/hwmon/hwmon1/pwm3")));
+        boost.fans = vec!["hwmon1/3".to_owned()];
+        assert!(boost.applies_to("hwmon1/3", Path::new("/sys/class/hwmon/hwmon1/pwm3")));
+    }
+
+    #[test]
+    fn test_boost_speed_for() {
+        let boost = Boost {
+            speed: Speed::try_from(0.3).unwrap(),
+            until: Instant::now(),
+            fans: vec!["hwmon1/2".to_owned()],
+        };
+        let pwm_path = Path::new("/sys/class/hwmon/hwmon1/pwm2");
+        assert_eq!(
+            boost.speed_for("hwmon1/2", pwm_path, Speed::try_from(0.2).unwrap()),
+            Some(Speed::try_from(0.3).unwrap())
+        );
+        // Boost below the computed speed does not slow the fan down
+        assert_eq!(
+            boost.speed_for("hwmon1/2", pwm_path, Speed::try_from(0.8).unwrap()),
+            None
+        );
+        assert_eq!(
+            boost.speed_for("hwmon1/2", pwm_path, Speed::try_from(1.0).unwrap()),
+            None
+        );
+        assert_eq!(
+            boost.speed_for(
+                "hwmon1/3",
+                Path::new("/sys/class/hwmon/hwmon1/pwm3"),
+                Speed::try_from(0.2).unwrap()
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_active_boost_expires() {
+        let shared = Shared::default();
+        assert!(shared.active_boost().is_none());
+        shared.set_boost(Boost {
+            speed: Speed::try_from(1.0).unwrap(),
+            until: Instant::now() + Duration::from_secs(60),
+            fans: vec![],
+        });
+        assert!(shared.active_boost().is_some());
+        shared.set_boost(Boost {
+            speed: Speed::try_from(1.0).unwrap(),
+            until: Instant::now(),
+            fans: vec![],
+        });
+        assert!(shared.active_boost().is_none());
+    }
+
+    #[test]
+    fn test_query() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("control.sock");
+        let shared = Arc::new(Shared::new(State::default(), 2, TempUnit::Celsius));
+        let (wake_tx, _wake_rx) = mpsc::channel();
+        let _socket = ControlSocket::spawn(&path, Arc::clone(&shared), wake_tx).unwrap();
+
+        assert!(query(&path, "status").is_err());
+        assert!(
+            query(&path, "history")
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .is_empty()
+        );
+        for _ in 0..3 {
+            shared.set_snapshot(Snapshot {
+                time: chrono::Local::now(),
+                drives: vec![],
+                hwmons: vec![],
+                fans: vec![],
+            });
+        }
+        let status = query(&path, "status").unwrap();
+        assert!(status.get("drives").unwrap().as_array().unwrap().is_empty());
+        assert_eq!(
+            query(&path, "history").unwrap().as_array().unwrap().len(),
+            2
+        );
+        assert!(query(&path, "max-temps").unwrap().is_object());
+        assert!(query(&path, "hello").is_err());
+        assert!(query(&dir.path().join("nope.sock"), "status").is_err());
+    }
+
+    #[test]
+    fn test_query_idle_client() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("control.sock");
+        let shared = Arc::new(Shared::new(State::default(), 2, TempUnit::Celsius));
+        let (wake_tx, _wake_rx) = mpsc::channel();
+        let _socket = ControlSocket::spawn(&path, Arc::clone(&shared), wake_tx).unwrap();
+
+        // Connected client never sending anything
+        let _idle = UnixStream::connect(&path).unwrap();
+        assert!(query(&path, "history").is_ok());
+    }
+
+    #[test]
+    fn test_pid_setpoint_unit() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("control.sock");
+        let shared = Arc::new(Shared::new(State::default(), 0, TempUnit::Fahrenheit));
+        let (wake_tx, _wake_rx) = mpsc::channel();
+        let _socket = ControlSocket::spawn(&path, Arc::clone(&shared), wake_tx).unwrap();
+
+        assert!(shared.take_pid_setpoint().is_none());
+        query(&path, "pid-setpoint 104").unwrap();
+        let setpoint = shared.take_pid_setpoint().unwrap();
+        assert!((setpoint - 40.0).abs() < 1e-9);
+        assert!(shared.take_pid_setpoint().is_none());
+    }
+
+    #[test]
+    fn test_dirty_state() {
+        let shared = Shared::new(State::default(), 0, TempUnit::Celsius);
+        assert!(shared.take_dirty_state().is_none());
+        assert!(shared.update_max_temp(Path::new("/dev/sda"), 40.0));
+        assert!(!shared.update_max_temp(Path::new("/dev/sda"), 39.0));
+        let state = shared.take_dirty_state().unwrap();
+        assert_eq!(state.max_temps.get(Path::new("/dev/sda")), Some(&40.0));
+        assert!(shared.take_dirty_state().is_none());
+    }
+}
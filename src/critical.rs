@@ -0,0 +1,170 @@
+//! User command run when a drive temperature becomes critical
+
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+use crate::{cl::TempUnit, probe::Temp};
+
+/// Environment variable with the filepath of the critical drive
+const DRIVE_ENV_VAR: &str = "HDDFANCONTROL_DRIVE";
+
+/// Environment variable with the critical drive temperature in Celsius
+const TEMP_ENV_VAR: &str = "HDDFANCONTROL_TEMP";
+
+/// Command run once each time a drive temperature reaches the critical temperature
+#[derive(Debug)]
+pub(crate) struct CriticalCommand {
+    /// Shell command
+    command: String,
+    /// Critical temperature
+    critical_temp: Temp,
+    /// Has the command been run since temperatures were last below the critical temperature
+    triggered: bool,
+    /// Unit to log temperatures in
+    unit: TempUnit,
+}
+
+impl CriticalCommand {
+    /// New command, not triggered
+    pub(crate) fn new(command: String, critical_temp: Temp, unit: TempUnit) -> Self {
+        Self {
+            command,
+            critical_temp,
+            triggered: false,
+            unit,
+        }
+    }
+
+    /// Update with drive temperatures, and run the command if one just became critical
+    ///
+    /// The command is run again only after all probed temperatures got below the critical
+    /// temperature. Iterations without any probed temperature (ie. drives spun down) keep the
+    /// current state. Returns true if the command was run.
+    pub(crate) fn update(&mut self, drive_temps: &[(&Path, Option<Temp>)]) -> bool {
+        let mut probed = false;
+        let mut hottest: Option<(&Path, Temp)> = None;
+        for (drive, temp) in drive_temps {
+            let Some(temp) = *temp else {
+                continue;
+            };
+            probed = true;
+            if temp >= self.critical_temp && hottest.is_none_or(|(_, t)| temp > t) {
+                hottest = Some((drive, temp));
+            }
+        }
+        match hottest {
+            Some((drive, temp)) if !self.triggered => {
+                self.triggered = true;
+                self.run(drive, temp);
+                true
+            }
+            Some(_) => false,
+            None => {
+                if probed && self.triggered {
+                    log::info!("Drive temperatures are below critical temperature again");
+                    self.triggered = false;
+                }
+                false
+            }
+        }
+    }
+
+    /// Run the command in the background, logging errors
+    fn run(&self, drive: &Path, temp: Temp) {
+        log::warn!(
+            "Drive {} temperature {} is critical, running {:?}",
+            drive.display(),
+            self.unit.display(temp),
+            self.command
+        );
+        let spawned = Command::new("sh")
+            .args(["-c", &self.command])
+            .env(DRIVE_ENV_VAR, drive)
+            .env(TEMP_ENV_VAR, temp.to_string())
+            .stdin(Stdio::null())
+            .spawn();
+        match spawned {
+            Ok(mut child) => {
+                let command = self.command.clone();
+                let waited = thread::Builder::new()
+                    .name("critical command".to_owned())
+                    .spawn(move || match child.wait() {
+                        Ok(status) if status.success() => {
+                            log::info!("Critical temperature command {command:?} succeeded");
+                        }
+                        Ok(status) => {
+                            log::error!(
+                                "Critical temperature command {command:?} failed: {status}"
+                            );
+                        }
+                        Err(e) => {
+                            log::error!(
+                                "Failed to wait for critical temperature command {command:?}: {e}"
+                            );
+                        }
+                    });
+                if let Err(e) = waited {
+                    log::error!("Failed to start critical temperature command thread: {e}");
+                }
+            }
+            Err(e) => {
+                log::error!(
+                    "Failed to run critical temperature command {:?}: {e}",
+                    self.command
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread::sleep, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn test_update() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let out_path = dir.path().join("out");
+        let mut command = CriticalCommand::new(
+            format!(
+                "echo \"${DRIVE_ENV_VAR} ${TEMP_ENV_VAR}\" >> {}",
+                out_path.display()
+            ),
+            55.0,
+            TempUnit::Celsius,
+        );
+        let sda = Path::new("/dev/sda");
+        let sdb = Path::new("/dev/sdb");
+
+        assert!(!command.update(&[(sda, Some(50.0)), (sdb, None)]));
+        assert!(command.update(&[(sda, Some(55.0)), (sdb, Some(56.0))]));
+        assert!(!command.update(&[(sda, Some(57.0)), (sdb, Some(56.0))]));
+        // Not probed
+        assert!(!command.update(&[(sda, None), (sdb, None)]));
+        assert!(!command.update(&[(sda, Some(54.0)), (sdb, None)]));
+        assert!(command.update(&[(sda, Some(55.5)), (sdb, None)]));
+
+        for _ in 0..100 {
+            if fs::read_to_string(&out_path).is_ok_and(|s| s.lines().count() == 2) {
+                break;
+            }
+            sleep(Duration::from_millis(10));
+        }
+        let mut lines: Vec<String> = fs::read_to_string(&out_path)
+            .unwrap()
+            .lines()
+            .map(ToOwned::to_owned)
+            .collect();
+        lines.sort_unstable();
+        assert_eq!(lines, vec!["/dev/sda 55.5", "/dev/sdb 56"]);
+
+        // Command failure is not fatal
+        let mut failing = CriticalCommand::new("exit 1".to_owned(), 55.0, TempUnit::Celsius);
+        assert!(failing.update(&[(sda, Some(60.0))]));
+    }
+}
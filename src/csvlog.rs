@@ -0,0 +1,216 @@
+//! CSV log of the control loop readings, for analysis in a spreadsheet
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead as _, BufReader, ErrorKind, Write as _},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+
+use crate::snapshot::Snapshot;
+
+/// CSV file with one row per control loop iteration
+///
+/// Temperatures are in Celsius, and fan speeds in percent. When the file reaches its maximum
+/// size or its columns change (ie. drives reloaded), it is renamed with a `.1` suffix, replacing
+/// the previous one, and a new file is started.
+#[derive(Debug)]
+pub(crate) struct CsvLog {
+    /// File path
+    path: PathBuf,
+    /// Size in bytes at which the file is rotated
+    max_size: u64,
+    /// Open file, and its header line
+    file: Option<(File, String)>,
+}
+
+impl CsvLog {
+    /// New log, the file is opened on first append
+    pub(crate) fn new(path: PathBuf, max_size: u64) -> Self {
+        Self {
+            path,
+            max_size,
+            file: None,
+        }
+    }
+
+    /// Append a row for a snapshot
+    pub(crate) fn append(&mut self, snapshot: &Snapshot) -> anyhow::Result<()> {
+        let header = header(snapshot);
+        if self.file.as_ref().is_some_and(|(_, h)| *h != header) {
+            self.rotate()?;
+        }
+        let (file, _) = if let Some(file) = self.file.as_mut() {
+            file
+        } else {
+            let file = self.open(&header)?;
+            self.file.insert((file, header))
+        };
+        writeln!(file, "{}", row(snapshot))
+            .with_context(|| format!("Failed to write CSV log {}", self.path.display()))?;
+        if file.metadata()?.len() >= self.max_size {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// Open file for appending, rotating it first if it has other columns
+    fn open(&self, header: &str) -> anyhow::Result<File> {
+        let current_header = match File::open(&self.path) {
+            Ok(f) => BufReader::new(f).lines().next().transpose()?,
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => {
+                return Err(anyhow::Error::new(e)
+                    .context(format!("Failed to read CSV log {}", self.path.display())));
+            }
+        };
+        if current_header.as_deref().is_some_and(|h| h != header) {
+            self.rename()?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open CSV log {}", self.path.display()))?;
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "{header}")
+                .with_context(|| format!("Failed to write CSV log {}", self.path.display()))?;
+        }
+        Ok(file)
+    }
+
+    /// Close and rename file, so that the next row starts a new one
+    fn rotate(&mut self) -> anyhow::Result<()> {
+        self.file = None;
+        self.rename()
+    }
+
+    /// Rename file with the rotated suffix
+    fn rename(&self) -> anyhow::Result<()> {
+        let mut rotated_path = self.path.as_os_str().to_owned();
+        rotated_path.push(".1");
+        fs::rename(&self.path, Path::new(&rotated_path))
+            .with_context(|| format!("Failed to rotate CSV log {}", self.path.display()))
+    }
+}
+
+/// Header line, with column names derived from drive paths, hwmon names and fan PWM paths
+fn header(snapshot: &Snapshot) -> String {
+    let columns = ["time".to_owned()]
+        .into_iter()
+        .chain(snapshot.drives.iter().flat_map(|d| {
+            [
+                format!("{} temp", d.path.display()),
+                format!("{} spun down", d.path.display()),
+            ]
+        }))
+        .chain(snapshot.hwmons.iter().map(|h| format!("{} temp", h.name)))
+        .chain(
+            snapshot
+                .fans
+                .iter()
+                .map(|f| format!("{} speed", f.pwm.display())),
+        );
+    columns.map(|c| field(&c)).collect::<Vec<_>>().join(",")
+}
+
+/// Row line, drives not probed having an empty temperature
+fn row(snapshot: &Snapshot) -> String {
+    let values = [snapshot.time.to_rfc3339()]
+        .into_iter()
+        .chain(snapshot.drives.iter().flat_map(|d| {
+            [
+                d.temp.map(|t| format!("{t:.1}")).unwrap_or_default(),
+                u8::from(d.spun_down).to_string(),
+            ]
+        }))
+        .chain(snapshot.hwmons.iter().map(|h| format!("{:.1}", h.temp)))
+        .chain(snapshot.fans.iter().map(|f| format!("{:.1}", f.speed_prct)));
+    values.collect::<Vec<_>>().join(",")
+}
+
+/// Quote a CSV field if needed
+fn field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+
+    use super::*;
+    use crate::snapshot::{DriveReading, FanReading, HwmonReading};
+
+    fn snapshot(drives: &[&str]) -> Snapshot {
+        Snapshot {
+            time: Local::now(),
+            drives: drives
+                .iter()
+                .map(|d| DriveReading {
+                    name: (*d).to_owned(),
+                    path: Path::new("/dev").join(d),
+                    state: "active/idle".to_owned(),
+                    spun_down: false,
+                    temp: Some(38.25),
+                })
+                .collect(),
+            hwmons: vec![HwmonReading {
+                name: "coretemp.0/1".to_owned(),
+                temp: 45.0,
+            }],
+            fans: vec![FanReading {
+                name: "pwm2".to_owned(),
+                pwm: "/sys/class/hwmon/hwmon1/pwm2".into(),
+                speed_prct: 40.0,
+                boosted: false,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_append() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("metrics.csv");
+        let rotated_path = dir.path().join("metrics.csv.1");
+        let mut log = CsvLog::new(path.clone(), 1024);
+
+        log.append(&snapshot(&["sda"])).unwrap();
+        log.append(&snapshot(&["sda"])).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "time,/dev/sda temp,/dev/sda spun down,coretemp.0/1 temp,/sys/class/hwmon/hwmon1/pwm2 speed"
+        );
+        assert!(lines[1].ends_with(",38.2,0,45.0,40.0"), "{}", lines[1]);
+
+        // Same columns, keep appending after restart
+        let mut restarted = CsvLog::new(path.clone(), 1024);
+        restarted.append(&snapshot(&["sda"])).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 4);
+        assert!(!rotated_path.exists());
+
+        // Columns change
+        restarted.append(&snapshot(&["sda", "sdb"])).unwrap();
+        assert_eq!(
+            fs::read_to_string(&rotated_path).unwrap().lines().count(),
+            4
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        // Size limit
+        for _ in 0..20 {
+            restarted.append(&snapshot(&["sda", "sdb"])).unwrap();
+        }
+        assert!(fs::metadata(&path).unwrap().len() < 1024);
+        assert!(fs::metadata(&rotated_path).unwrap().len() >= 1024);
+
+        assert_eq!(field("a,b"), "\"a,b\"");
+    }
+}
@@ -2,15 +2,15 @@
 // Replaced simple_logger in tests with env_logger
 
 use std::{
-    fmt,
-    io::BufRead as _,
+    fmt, fs,
+    io::{self, BufRead as _},
     os::unix::prelude::FileTypeExt as _,
     path::{Path, PathBuf},
     process::{Command, Stdio},
 };
 
 /// Drive runtime state
-#[derive(strum::EnumString, strum::Display)]
+#[derive(Clone, Copy, Debug, strum::EnumString, strum::Display)]
 #[strum(serialize_all = "lowercase")]
 pub(crate) enum State {
     /// Active/idle
@@ -32,11 +32,13 @@ enum StateProbingMethod {
     Hdparm,
     /// Use `sdparm`
     Sdparm,
+    /// NVMe drives have no spun down state
+    Nvme,
 }
 
 impl State {
     /// Is drive currently spun down
-    pub(crate) fn is_spun_down(&self) -> bool {
+    pub(crate) fn is_spun_down(self) -> bool {
         match self {
             State::Standby | State::Sleeping => true,
             State::ActiveIdle | State::Unknown => false,
@@ -48,6 +50,8 @@ impl State {
 pub(crate) struct Drive {
     /// Normalized (under /dev) device filepath
     pub dev_path: PathBuf,
+    /// NVMe controller name (ie. `nvme0` for `/dev/nvme0n1`), if this is a NVMe drive
+    pub nvme_controller: Option<String>,
     /// Pretty name for display
     name: String,
     /// How to probe for state
@@ -64,19 +68,23 @@ impl Drive {
     /// Build a drive from its device path
     pub(crate) fn new(path: &Path) -> anyhow::Result<Self> {
         let dev_path = path.canonicalize()?;
+        let dev_name = dev_path
+            .file_name()
+            .and_then(|p| p.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Invalid drive path"))?;
+        let nvme_controller = Self::nvme_controller(dev_name);
+        let file_type = dev_path.metadata()?.file_type();
+        // NVMe drives can also be given by their controller character device (ie. `/dev/nvme0`)
         anyhow::ensure!(
-            dev_path.metadata()?.file_type().is_block_device(),
-            "Path {dev_path:?} is not a block device",
+            file_type.is_block_device()
+                || (nvme_controller.as_deref() == Some(dev_name) && file_type.is_char_device()),
+            "Path {} is not a block device",
+            dev_path.display()
         );
-        let name = format!(
-            "{} {}",
-            dev_path
-                .file_name()
-                .and_then(|p| p.to_str())
-                .ok_or_else(|| anyhow::anyhow!("Invalid drive path"))?,
-            Self::model(&dev_path)?,
-        );
-        let state_probing = if Self::state_hdparm(&dev_path).is_ok() {
+        let name = format!("{dev_name} {}", Self::model(&dev_path)?);
+        let state_probing = if nvme_controller.is_some() {
+            StateProbingMethod::Nvme
+        } else if Self::state_hdparm(&dev_path).is_ok() {
             StateProbingMethod::Hdparm
         } else if Self::state_sdparm(&dev_path).is_ok() {
             StateProbingMethod::Sdparm
@@ -86,11 +94,41 @@ impl Drive {
         log::debug!("{name}: Will use {state_probing} state probing method");
         Ok(Self {
             dev_path,
+            nvme_controller,
             name,
             state_probing_method: state_probing,
         })
     }
 
+    /// Find drive device paths in `dev_dir` (`/dev/sdX` and `/dev/nvmeXnY`, without partitions)
+    pub(crate) fn discover(dev_dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dev_dir)?
+            .collect::<io::Result<Vec<_>>>()?
+            .into_iter()
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name().and_then(|f| f.to_str()).is_some_and(|f| {
+                    f.strip_prefix("sd")
+                        .is_some_and(|l| !l.is_empty() && l.chars().all(|c| c.is_ascii_lowercase()))
+                        || Self::nvme_controller(f).is_some_and(|c| c != f)
+                })
+            })
+            .collect();
+        paths.sort_unstable();
+        Ok(paths)
+    }
+
+    /// Get NVMe controller name from a namespace (ie. `nvme0n1`) or controller (ie. `nvme0`)
+    /// device name, or `None` if this is not a NVMe device
+    fn nvme_controller(dev_name: &str) -> Option<String> {
+        let ctrl_num = dev_name.strip_prefix("nvme")?;
+        let (ctrl_num, namespace) = ctrl_num
+            .split_once('n')
+            .map_or((ctrl_num, None), |(c, n)| (c, Some(n)));
+        let is_num = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+        (is_num(ctrl_num) && namespace.is_none_or(is_num)).then(|| format!("nvme{ctrl_num}"))
+    }
+
     /// Get drive model name
     fn model(path: &Path) -> anyhow::Result<String> {
         let dev = path
@@ -118,7 +156,7 @@ impl Drive {
                 return Ok(line.split_once(':').unwrap().1.trim().to_owned());
             }
         }
-        anyhow::bail!("Unable to get drive {path:?} model name");
+        anyhow::bail!("Unable to get drive {} model name", path.display());
     }
 
     /// Get drive runtime state using `hdparm`
@@ -197,11 +235,42 @@ impl Drive {
         Ok(state)
     }
 
+    /// Find the `drivetemp` kernel module hwmon temperature input of the drive, if any
+    pub(crate) fn drivetemp_input(&self) -> io::Result<Option<PathBuf>> {
+        #[expect(clippy::unwrap_used)] // At this point we already checked it is a valid device
+        let dev_name = self.dev_path.file_name().unwrap();
+        Self::drivetemp_input_in(&Path::new("/sys/block").join(dev_name))
+    }
+
+    /// Find the `drivetemp` hwmon temperature input under the SCSI device of a sysfs block
+    /// device dir (ie. `/sys/block/sda/device/hwmon/hwmonN/temp1_input`)
+    fn drivetemp_input_in(block_dir: &Path) -> io::Result<Option<PathBuf>> {
+        let hwmon_dir = block_dir.join("device/hwmon");
+        if !hwmon_dir.is_dir() {
+            return Ok(None);
+        }
+        let mut hwmon_subdirs: Vec<PathBuf> = fs::read_dir(&hwmon_dir)?
+            .map_while(Result::ok)
+            .map(|e| e.path())
+            .filter(|p| p.is_dir())
+            .collect();
+        hwmon_subdirs.sort_unstable();
+        for hwmon_subdir in hwmon_subdirs {
+            let name = fs::read_to_string(hwmon_subdir.join("name"))?;
+            let input_path = hwmon_subdir.join("temp1_input");
+            if name.trim_end() == "drivetemp" && input_path.is_file() {
+                return Ok(Some(input_path));
+            }
+        }
+        Ok(None)
+    }
+
     /// Get drive runtime state
     pub(crate) fn state(&self) -> anyhow::Result<State> {
         match self.state_probing_method {
             StateProbingMethod::Hdparm => Self::state_hdparm(&self.dev_path),
             StateProbingMethod::Sdparm => Self::state_sdparm(&self.dev_path),
+            StateProbingMethod::Nvme => Ok(State::ActiveIdle),
         }
     }
 }
@@ -210,15 +279,36 @@ impl Drive {
 #[expect(clippy::shadow_unrelated)]
 mod tests {
     use super::*;
-    use std::io::Write;
     use crate::tests::BinaryMock;
 
+    #[test]
+    fn test_discover() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for name in [
+            "sda",
+            "sda1",
+            "sdab",
+            "nvme0",
+            "nvme0n1",
+            "nvme0n1p1",
+            "tty0",
+            "sg0",
+        ] {
+            fs::File::create(dir.path().join(name)).unwrap();
+        }
+        assert_eq!(
+            Drive::discover(dir.path()).unwrap(),
+            ["nvme0n1", "sda", "sdab"]
+                .iter()
+                .map(|n| dir.path().join(n))
+                .collect::<Vec<_>>()
+        );
+    }
+
     #[serial_test::serial]
     #[test]
     fn test_model_hdd() {
-        let _ = flexi_logger::Logger::try_with_str("debug")
-            .unwrap()
-            .start();
+        let _ = flexi_logger::Logger::try_with_str("debug").unwrap().start();
 
         let _hdparm_mock = BinaryMock::new("hdparm", "\n/dev/_sdX:\n\nATA device, with non-removable media\n\tModel Number:       WDC WD4003FZEX-00Z4SA0                  \n\tSerial Number:      WD-WMC5D0D4YY1K\n\tFirmware Revision:  01.01A01\n\tTransport:          Serial, SATA 1.0a, SATA II Extensions, SATA Rev 2.5, SATA Rev 2.6, SATA Rev 3.0\nStandards:\n\tSupported: 9 8 7 6 5 \n\tLikely used: 9\nConfiguration:\n\tLogical\t\tmax\tcurrent\n\tcylinders\t16383\t16383\n\theads\t\t16\t16\n\tsectors/track\t63\t63\n\t--\n\tCHS current addressable sectors:   16514064\n\tLBA    user addressable sectors:  268435455\n\tLBA48  user addressable sectors: 7814037168\n\tLogical  Sector size:                   512 bytes\n\tPhysical Sector size:                  4096 bytes\n\tLogical Sector-0 offset:                  0 bytes\n\tdevice size with M = 1024*1024:     3815447 MBytes\n\tdevice size with M = 1000*1000:     4000787 MBytes (4000 GB)\n\tcache/buffer size  = unknown\n\tNominal Media Rotation Rate: 7200\nCapabilities:\n\tLBA, IORDY(can be disabled)\n\tQueue depth: 32\n\tStandby timer values: spec'd by Standard, with device specific minimum\n\tR/W multiple sector transfer: Max = 16\tCurrent = 0\n\tDMA: mdma0 mdma1 mdma2 udma0 udma1 udma2 udma3 udma4 udma5 *udma6 \n\t     Cycle time: min=120ns recommended=120ns\n\tPIO: pio0 pio1 pio2 pio3 pio4 \n\t     Cycle time: no flow control=120ns  IORDY flow control=120ns\nCommands/features:\n\tEnabled\tSupported:\n\t   *\tSMART feature set\n\t    \tSecurity Mode feature set\n\t   *\tPower Management feature set\n\t   *\tWrite cache\n\t   *\tLook-ahead\n\t   *\tHost Protected Area feature set\n\t   *\tWRITE_BUFFER command\n\t   *\tREAD_BUFFER command\n\t   *\tNOP cmd\n\t   *\tDOWNLOAD_MICROCODE\n\t    \tPower-Up In Standby feature set\n\t   *\tSET_FEATURES required to spinup after power up\n\t    \tSET_MAX security extension\n\t   *\t48-bit Address feature set\n\t   *\tMandatory FLUSH_CACHE\n\t   *\tFLUSH_CACHE_EXT\n\t   *\tSMART error logging\n\t   *\tSMART self-test\n\t   *\tGeneral Purpose Logging feature set\n\t   *\t64-bit World wide name\n\t   *\t{READ,WRITE}_DMA_EXT_GPL commands\n\t   *\tSegmented DOWNLOAD_MICROCODE\n\t   *\tGen1 signaling speed (1.5Gb/s)\n\t   *\tGen2 signaling speed (3.0Gb/s)\n\t   *\tGen3 signaling speed (6.0Gb/s)\n\t   *\tNative Command Queueing (NCQ)\n\t   *\tHost-initiated interface power management\n\t   *\tPhy event counters\n\t   *\tNCQ priority information\n\t   *\tREAD_LOG_DMA_EXT equivalent to READ_LOG_EXT\n\t   *\tDMA Setup Auto-Activate optimization\n\t   *\tSoftware settings preservation\n\t   *\tSMART Command Transport (SCT) feature set\n\t   *\tSCT Write Same (AC2)\n\t   *\tSCT Features Control (AC4)\n\t   *\tSCT Data Tables (AC5)\n\t    \tunknown 206[12] (vendor specific)\n\t    \tunknown 206[13] (vendor specific)\n\t    \tunknown 206[14] (vendor specific)\nSecurity: \n\tMaster password revision code = 65534\n\t\tsupported\n\tnot\tenabled\n\tnot\tlocked\n\tnot\tfrozen\n\tnot\texpired: security count\n\t\tsupported: enhanced erase\n\t424min for SECURITY ERASE UNIT. 424min for ENHANCED SECURITY ERASE UNIT. \nLogical Unit WWN Device Identifier: 50014ee0593d4632\n\tNAA\t\t: 5\n\tIEEE OUI\t: 0014ee\n\tUnique ID\t: 0593d4632\nChecksum: correct\n".as_bytes(), &[], 0).unwrap();
         let _smartctl_mock = BinaryMock::new("smartctl", &[], &[], 1).unwrap();
@@ -231,9 +321,7 @@ mod tests {
     #[serial_test::serial]
     #[test]
     fn test_model_ssd() {
-        let _ = flexi_logger::Logger::try_with_str("debug")
-            .unwrap()
-            .start();
+        let _ = flexi_logger::Logger::try_with_str("debug").unwrap().start();
 
         let _hdparm_mock = BinaryMock::new("hdparm", "\n/dev/_sdX:".as_bytes(), &[], 0).unwrap();
         let _smartctl_mock = BinaryMock::new("smartctl", "smartctl 7.3 2022-02-28 r5338 [x86_64-linux-6.1.53-1-lts] (local build)\nCopyright (C) 2002-22, Bruce Allen, Christian Franke, www.smartmontools.org\n\n=== START OF INFORMATION SECTION ===\nModel Number:                       WD_BLACK SN850 2TB\nFirmware Version:\n                   611100WD\nPCI Vendor/Subsystem ID:            0x15b7\nIEEE OUI Identifier:                0x001b44\nTotal NVM Capacity:                 2 000 398 934 016 [2,00 TB]\nUnallocated NVM Capacity:           0\nController ID:                      8224\nNVMe Version:                       1.4\nNumber of Namespaces:               1\nNamespace 1 Size/Capacity:          2 000 398 934 016 [2,00 TB]\nNamespace 1 Formatted LBA Size:     512\nNamespace 1 IEEE EUI-64:            001b44 8b492d482c\n\n".as_bytes(), &[], 0).unwrap();
@@ -243,12 +331,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_drivetemp_input_in() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let block_dir = dir.path().join("sda");
+        assert!(Drive::drivetemp_input_in(&block_dir).unwrap().is_none());
+
+        let other_hwmon = block_dir.join("device/hwmon/hwmon2");
+        fs::create_dir_all(&other_hwmon).unwrap();
+        fs::write(other_hwmon.join("name"), "other\n").unwrap();
+        fs::write(other_hwmon.join("temp1_input"), "30000\n").unwrap();
+        assert!(Drive::drivetemp_input_in(&block_dir).unwrap().is_none());
+
+        let drivetemp_hwmon = block_dir.join("device/hwmon/hwmon3");
+        fs::create_dir_all(&drivetemp_hwmon).unwrap();
+        fs::write(drivetemp_hwmon.join("name"), "drivetemp\n").unwrap();
+        fs::write(drivetemp_hwmon.join("temp1_input"), "38000\n").unwrap();
+        assert_eq!(
+            Drive::drivetemp_input_in(&block_dir).unwrap(),
+            Some(drivetemp_hwmon.join("temp1_input"))
+        );
+    }
+
+    #[test]
+    fn test_nvme_controller() {
+        assert_eq!(Drive::nvme_controller("nvme0n1").as_deref(), Some("nvme0"));
+        assert_eq!(
+            Drive::nvme_controller("nvme12n3").as_deref(),
+            Some("nvme12")
+        );
+        assert_eq!(Drive::nvme_controller("nvme1").as_deref(), Some("nvme1"));
+        assert_eq!(Drive::nvme_controller("nvme0n1p2"), None);
+        assert_eq!(Drive::nvme_controller("nvme"), None);
+        assert_eq!(Drive::nvme_controller("nvmen1"), None);
+        assert_eq!(Drive::nvme_controller("sda"), None);
+    }
+
     #[serial_test::serial]
     #[test]
     fn test_state_hdparm() {
-        let _ = flexi_logger::Logger::try_with_str("debug")
-            .unwrap()
-            .start();
+        let _ = flexi_logger::Logger::try_with_str("debug").unwrap().start();
 
         let _hdparm_mock = BinaryMock::new(
             "hdparm",
@@ -331,9 +453,7 @@ mod tests {
     #[serial_test::serial]
     #[test]
     fn test_state_sdparm() {
-        let _ = flexi_logger::Logger::try_with_str("debug")
-            .unwrap()
-            .start();
+        let _ = flexi_logger::Logger::try_with_str("debug").unwrap().start();
 
         let _sdparm_mock = BinaryMock::new(
             "sdparm",
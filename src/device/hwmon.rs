@@ -2,7 +2,6 @@
 
 use core::fmt;
 use std::{
-    cmp::{max, min},
     fs,
     ops::Range,
     path::{Path, PathBuf},
@@ -11,10 +10,14 @@ use std::{
 use anyhow::Context as _;
 
 use crate::{
+    cl::TempScale,
     probe::{DeviceTempProber, Temp},
     sysfs::{ensure_sysfs_dir, ensure_sysfs_file},
 };
 
+/// Sysfs directory of hwmon chips
+const HWMON_CLASS_DIR: &str = "/sys/class/hwmon";
+
 /// A linux whmon temp probe
 pub(crate) struct Hwmon {
     /// Sysfs temperature probe path
@@ -23,6 +26,8 @@ pub(crate) struct Hwmon {
     device: String,
     /// Probe index
     num: usize,
+    /// Scale of sysfs values
+    scale: TempScale,
 }
 
 impl fmt::Display for Hwmon {
@@ -32,42 +37,61 @@ impl fmt::Display for Hwmon {
 }
 
 impl Hwmon {
-    /// Build a new prober
-    pub(crate) fn new(input_path: &Path) -> anyhow::Result<Self> {
+    /// Build a new prober, from a sysfs temperature input path or a `chip:label` sensor
+    pub(crate) fn new(input: &Path) -> anyhow::Result<Self> {
+        let input_path = match input
+            .to_str()
+            .filter(|s| !s.contains(std::path::MAIN_SEPARATOR))
+            .and_then(|s| s.split_once(':'))
+        {
+            Some((chip, label)) => {
+                let input_path = find_labeled_input(Path::new(HWMON_CLASS_DIR), chip, label)?;
+                log::debug!("Hwmon {chip}:{label} input: {}", input_path.display());
+                input_path
+            }
+            None => input.to_owned(),
+        };
         let device = ensure_sysfs_dir(&input_path.with_file_name("device"))
             .or_else(|_| ensure_sysfs_dir(&input_path.with_file_name("driver")))
             .context("Failed to get path for device/driver")?
             .file_name()
             .and_then(|f| f.to_str())
-            .ok_or_else(|| anyhow::anyhow!("Invalid device path for {input_path:?}"))?
+            .ok_or_else(|| anyhow::anyhow!("Invalid device path for {}", input_path.display()))?
             .to_owned();
-        let num = ensure_sysfs_file(input_path)?
+        let num = ensure_sysfs_file(&input_path)?
             .file_name()
             .and_then(|f| f.to_str())
-            .ok_or_else(|| anyhow::anyhow!("Invalid device path for {input_path:?}"))?
+            .ok_or_else(|| anyhow::anyhow!("Invalid device path for {}", input_path.display()))?
             .chars()
             .filter(char::is_ascii_digit)
             .collect::<String>()
             .parse::<usize>()
-            .with_context(|| format!("Failed to extract number from {input_path:?}"))?;
+            .with_context(|| format!("Failed to extract number from {}", input_path.display()))?;
         Ok(Self {
-            input_path: input_path.to_owned(),
+            input_path,
             device,
             num,
+            scale: TempScale::default(),
         })
     }
 
+    /// Set scale of sysfs values, if the driver does not report millidegrees
+    pub(crate) fn with_scale(mut self, scale: TempScale) -> Self {
+        self.scale = scale;
+        self
+    }
+
     /// Get default temperature range
     pub(crate) fn default_range(&self) -> anyhow::Result<Range<Temp>> {
         let sysfs_dir = self
             .input_path
             .parent()
-            .ok_or_else(|| anyhow::anyhow!("Invalid probe path {:?}", self.input_path))?;
+            .ok_or_else(|| anyhow::anyhow!("Invalid probe path {}", self.input_path.display()))?;
         let sensor_num: u32 = self
             .input_path
             .file_name()
             .and_then(|f| f.to_str())
-            .ok_or_else(|| anyhow::anyhow!("Invalid probe path {:?}", self.input_path))?
+            .ok_or_else(|| anyhow::anyhow!("Invalid probe path {}", self.input_path.display()))?
             .chars()
             .skip_while(|c| !c.is_ascii_digit())
             .take_while(char::is_ascii_digit)
@@ -75,15 +99,15 @@ impl Hwmon {
             .parse()?;
         // Try to get crit and max temp
         let crit_filepath = sysfs_dir.join(format!("temp{sensor_num}_crit"));
-        let crit_temp_milli = Self::read_sysfs_temp_milli(&crit_filepath)?;
+        let sysfs_crit_temp = self.read_sysfs_temp(&crit_filepath)?;
         let max_filepath = sysfs_dir.join(format!("temp{sensor_num}_max"));
-        let max_temp_milli = Self::read_sysfs_temp_milli(&max_filepath).unwrap_or_else(|_| {
-            // Default to crit - 20 if we have no max temp
-            crit_temp_milli - 1000 * 20
-        });
+        // Default to crit - 20 if we have no max temp
+        let sysfs_max_temp = self
+            .read_sysfs_temp(&max_filepath)
+            .unwrap_or(sysfs_crit_temp - 20.0);
         // Ensure they are in the correct order...
-        let max_temp = f64::from(min(max_temp_milli, crit_temp_milli)) / 1000.0;
-        let crit_temp = f64::from(max(max_temp_milli, crit_temp_milli)) / 1000.0;
+        let max_temp = sysfs_max_temp.min(sysfs_crit_temp);
+        let crit_temp = sysfs_max_temp.max(sysfs_crit_temp);
         // Set range max as max minus a security margin, which is the difference between max and crit
         // The rationale is that this gap will be larger for devices with a large operating range, and vice versa
         Ok(Range {
@@ -92,19 +116,160 @@ impl Hwmon {
         })
     }
 
-    /// Read a sysfs temp probe
-    fn read_sysfs_temp(path: &Path) -> anyhow::Result<Temp> {
-        Ok(f64::from(Self::read_sysfs_temp_milli(path)?) / 1000.0)
+    /// Read a sysfs temp file in Celsius, values being signed and in the hwmon scale
+    fn read_sysfs_temp(&self, path: &Path) -> anyhow::Result<Temp> {
+        let raw: i64 = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .trim_end()
+            .parse()
+            .with_context(|| format!("Invalid temperature in {}", path.display()))?;
+        Ok(self.scale.to_celsius(raw))
     }
+}
 
-    /// Read a sysfs temp probe
-    fn read_sysfs_temp_milli(path: &Path) -> anyhow::Result<u32> {
-        Ok(fs::read_to_string(path)?.trim_end().parse()?)
+/// Find the temperature input of the chip named `chip` with label `label`, in `class_dir`
+fn find_labeled_input(class_dir: &Path, chip: &str, label: &str) -> anyhow::Result<PathBuf> {
+    let mut chip_dirs: Vec<PathBuf> = fs::read_dir(class_dir)
+        .with_context(|| format!("Failed to list {}", class_dir.display()))?
+        .map_while(Result::ok)
+        .map(|e| e.path())
+        .filter(|d| fs::read_to_string(d.join("name")).is_ok_and(|n| n.trim_end() == chip))
+        .collect();
+    anyhow::ensure!(!chip_dirs.is_empty(), "No hwmon chip named {chip:?}");
+    chip_dirs.sort_unstable();
+    let mut labels: Vec<String> = Vec::new();
+    let mut matches: Vec<PathBuf> = Vec::new();
+    for chip_dir in &chip_dirs {
+        let mut label_paths: Vec<PathBuf> = fs::read_dir(chip_dir)
+            .with_context(|| format!("Failed to list {}", chip_dir.display()))?
+            .map_while(Result::ok)
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|f| f.to_str())
+                    .is_some_and(|f| f.starts_with("temp") && f.ends_with("_label"))
+            })
+            .collect();
+        label_paths.sort_unstable();
+        for label_path in label_paths {
+            let Ok(sensor_label) = fs::read_to_string(&label_path) else {
+                continue;
+            };
+            let sensor_label = sensor_label.trim_end();
+            if sensor_label == label {
+                let input_name = label_path
+                    .file_name()
+                    .and_then(|f| f.to_str())
+                    .map(|f| f.replace("_label", "_input"))
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Invalid label path {}", label_path.display())
+                    })?;
+                matches.push(chip_dir.join(input_name));
+            }
+            labels.push(sensor_label.to_owned());
+        }
+    }
+    match matches.as_slice() {
+        [input_path] => Ok(input_path.to_owned()),
+        [] => anyhow::bail!(
+            "No temperature sensor labeled {label:?} for hwmon chip {chip:?}, available labels: {}",
+            labels.join(", ")
+        ),
+        _ => anyhow::bail!(
+            "Several temperature sensors labeled {label:?} for hwmon chip {chip:?}: {}",
+            matches
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
     }
 }
 
 impl DeviceTempProber for Hwmon {
     fn probe_temp(&mut self) -> anyhow::Result<Temp> {
-        Self::read_sysfs_temp(&self.input_path)
+        self.read_sysfs_temp(&self.input_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+
+    use super::*;
+
+    /// Hwmon with sysfs files in `dir`
+    fn fake_hwmon(dir: &Path, input: &str, max: Option<&str>, crit: &str) -> Hwmon {
+        fs::write(dir.join("temp1_input"), format!("{input}\n")).unwrap();
+        if let Some(max) = max {
+            fs::write(dir.join("temp1_max"), format!("{max}\n")).unwrap();
+        }
+        fs::write(dir.join("temp1_crit"), format!("{crit}\n")).unwrap();
+        Hwmon {
+            input_path: dir.join("temp1_input"),
+            device: "fake".to_owned(),
+            num: 1,
+            scale: TempScale::default(),
+        }
+    }
+
+    #[test]
+    fn test_probe_temp_milli() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut hwmon = fake_hwmon(dir.path(), "45500", Some("80000"), "100000");
+        assert!(approx_eq!(f64, hwmon.probe_temp().unwrap(), 45.5));
+        assert_eq!(hwmon.default_range().unwrap(), 30.0..60.0);
+
+        // Below zero
+        fs::write(dir.path().join("temp1_input"), "-2000\n").unwrap();
+        assert!(approx_eq!(f64, hwmon.probe_temp().unwrap(), -2.0));
+
+        // No max temp
+        fs::remove_file(dir.path().join("temp1_max")).unwrap();
+        assert_eq!(hwmon.default_range().unwrap(), 30.0..60.0);
+    }
+
+    #[test]
+    fn test_probe_temp_degree() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut hwmon =
+            fake_hwmon(dir.path(), "46", Some("85"), "95").with_scale(TempScale::Degree);
+        assert!(approx_eq!(f64, hwmon.probe_temp().unwrap(), 46.0));
+        assert_eq!(hwmon.default_range().unwrap(), 30.0..75.0);
+
+        fs::write(dir.path().join("temp1_input"), "garbage\n").unwrap();
+        assert!(hwmon.probe_temp().is_err());
+    }
+
+    #[test]
+    fn test_find_labeled_input() {
+        let class_dir = tempfile::TempDir::new().unwrap();
+        for (hwmon, name, labels) in [
+            ("hwmon0", "acpitz", vec![]),
+            ("hwmon1", "nct6775", vec!["SYSTIN", "CPUTIN", "AUXTIN0"]),
+            ("hwmon2", "coretemp", vec!["Core 0", "Core 0"]),
+        ] {
+            let dir = class_dir.path().join(hwmon);
+            fs::create_dir(&dir).unwrap();
+            fs::write(dir.join("name"), format!("{name}\n")).unwrap();
+            for (i, label) in labels.iter().enumerate() {
+                fs::write(
+                    dir.join(format!("temp{}_label", i + 1)),
+                    format!("{label}\n"),
+                )
+                .unwrap();
+            }
+        }
+
+        assert_eq!(
+            find_labeled_input(class_dir.path(), "nct6775", "CPUTIN").unwrap(),
+            class_dir.path().join("hwmon1/temp2_input")
+        );
+        let missing = find_labeled_input(class_dir.path(), "nct6775", "PECI")
+            .unwrap_err()
+            .to_string();
+        assert!(missing.contains("SYSTIN, CPUTIN, AUXTIN0"), "{missing}");
+        assert!(find_labeled_input(class_dir.path(), "coretemp", "Core 0").is_err());
+        assert!(find_labeled_input(class_dir.path(), "it87", "SYSTIN").is_err());
     }
 }
@@ -1,16 +1,33 @@
 //! Exit hook to set PWM config
 
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
 use crate::pwm;
 
+/// Default total duration of the restore on exit
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Restore PWM config when dropped
-pub(crate) struct ExitHook<T> {
+pub(crate) struct ExitHook<T: Send + 'static> {
     /// Pwm and their config to restore
     pwms: Vec<(pwm::Pwm<T>, pwm::State)>,
+    /// Maximum total duration of the restore
+    timeout: Duration,
 }
 
-impl<T> ExitHook<T> {
+impl<T: Send + 'static> ExitHook<T> {
     /// Build hook to restore current state on drop, or set max value
-    pub(crate) fn new(pwms: Vec<pwm::Pwm<T>>, restore: bool) -> anyhow::Result<Self> {
+    ///
+    /// With `restore_mode`, the current control mode is restored even if the value is not.
+    pub(crate) fn new(
+        pwms: Vec<pwm::Pwm<T>>,
+        restore: bool,
+        restore_mode: bool,
+    ) -> anyhow::Result<Self> {
         Ok(Self {
             pwms: pwms
                 .into_iter()
@@ -18,22 +35,119 @@ impl<T> ExitHook<T> {
                     let state = if restore {
                         p.get_state()?
                     } else {
+                        let mode = p.get_mode()?;
                         pwm::State {
-                            value: pwm::Value::MAX,
-                            mode: p.get_mode()?.map(|_| pwm::ControlMode::Software),
+                            value: p.max(),
+                            mode: if restore_mode {
+                                mode
+                            } else {
+                                mode.map(|_| p.manual_mode())
+                            },
                         }
                     };
                     Ok((p, state))
                 })
                 .collect::<anyhow::Result<Vec<_>>>()?,
+            timeout: DEFAULT_TIMEOUT,
         })
     }
+
+    /// Set maximum total duration of the restore
+    pub(crate) fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Leave PWMs as they are when dropped
+    pub(crate) fn disarm(&mut self) {
+        self.pwms.clear();
+    }
 }
 
-impl<T> Drop for ExitHook<T> {
+impl<T: Send + 'static> Drop for ExitHook<T> {
+    /// Restore each PWM from a thread, so that a hung sysfs write (ie. wedged driver) does not
+    /// block the others
+    ///
+    /// Each PWM gets an equal share of the remaining time, after which its thread is abandoned.
     fn drop(&mut self) {
-        for (pwm, state) in &mut self.pwms {
-            let _ = pwm.set_state(state);
+        let deadline = Instant::now() + self.timeout;
+        let count = self.pwms.len();
+        for (i, (pwm, state)) in self.pwms.drain(..).enumerate() {
+            let name = pwm.to_string();
+            let (result_tx, result_rx) = mpsc::channel();
+            if let Err(e) = thread::Builder::new()
+                .name(format!("restore {name}"))
+                .spawn(move || {
+                    let _ = result_tx.send(pwm.set_state(&state));
+                })
+            {
+                log::error!("Failed to start restore of PWM {name}: {e}");
+                continue;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let timeout = remaining / u32::try_from(count - i).unwrap_or(u32::MAX);
+            match result_rx.recv_timeout(timeout) {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => log::error!("Failed to restore PWM {name}: {e:#}"),
+                Err(_) => log::error!("Timed out restoring PWM {name} after {timeout:?}"),
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read as _, Write as _};
+
+    use nix::{sys::stat, unistd::mkfifo};
+
+    use super::*;
+    use crate::pwm::tests::{FakePwm, assert_file_content};
+
+    #[test]
+    fn test_restore_mode() {
+        let mut fake_pwm = FakePwm::new();
+        let pwm = pwm::Pwm::new(&fake_pwm.pwm_path).unwrap();
+        fake_pwm.mode_file_write.write_all(b"2\n").unwrap();
+        drop(ExitHook::new(vec![pwm.clone()], false, true).unwrap());
+        assert_file_content(&mut fake_pwm.val_file_read, "255\n");
+        assert_file_content(&mut fake_pwm.mode_file_read, "2\n");
+
+        fake_pwm.mode_file_write.write_all(b"2\n").unwrap();
+        drop(ExitHook::new(vec![pwm], false, false).unwrap());
+        assert_file_content(&mut fake_pwm.val_file_read, "255\n");
+        assert_file_content(&mut fake_pwm.mode_file_read, "1\n");
+    }
+
+    #[test]
+    fn test_disarm() {
+        let mut fake_pwm = FakePwm::new();
+        let pwm = pwm::Pwm::new(&fake_pwm.pwm_path).unwrap();
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        let mut exit_hook = ExitHook::new(vec![pwm], false, false).unwrap();
+        exit_hook.disarm();
+        drop(exit_hook);
+        let mut buf = [0; 16];
+        assert!(fake_pwm.val_file_read.read(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_restore_timeout() {
+        let mut fake_pwm = FakePwm::new();
+        let pwm = pwm::Pwm::new(&fake_pwm.pwm_path).unwrap();
+        // Writes to a FIFO without reader block forever
+        let hung_pwm_path = fake_pwm.pwm_path.with_file_name("pwm3");
+        mkfifo(&hung_pwm_path, stat::Mode::from_bits(0o600).unwrap()).unwrap();
+        let hung_pwm = pwm::Pwm::new(&hung_pwm_path).unwrap();
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+
+        let start = Instant::now();
+        drop(
+            ExitHook::new(vec![hung_pwm, pwm], false, false)
+                .unwrap()
+                .with_timeout(Duration::from_millis(200)),
+        );
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_file_content(&mut fake_pwm.val_file_read, "255\n");
+    }
+}
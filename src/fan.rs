@@ -2,9 +2,10 @@
 
 use std::{
     cmp::{Ordering, max},
-    fmt,
+    fmt, fs,
     ops::Range,
     path::{Path, PathBuf},
+    str::FromStr,
     thread::sleep,
     time::{Duration, Instant},
 };
@@ -12,16 +13,16 @@ use std::{
 use anyhow::Context as _;
 
 use crate::{
-    cl::PwmSettings,
+    cl::{self, CurveShape, PwmSettings, TempUnit},
     probe::Temp,
-    pwm::{self, ControlMode, Pwm},
+    pwm::{self, Pwm},
 };
 
 /// Minimum duration to apply fan startup boost
 const STARTUP_DELAY: Duration = Duration::from_secs(20);
 
 /// Fan characteristics
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
 pub(crate) struct Thresholds {
     /// Minimum value at which the fan starts moving when it was stopped
     pub min_start: pwm::Value,
@@ -35,6 +36,16 @@ impl fmt::Display for Thresholds {
     }
 }
 
+/// Result of a fan test
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct TestResult {
+    /// Start/stop thresholds found
+    #[serde(flatten)]
+    pub thresholds: Thresholds,
+    /// RPM measured at full speed
+    pub max_rpm: u32,
+}
+
 /// Stateful fan
 pub(crate) struct Fan<T> {
     /// Fan pwm
@@ -43,8 +54,22 @@ pub(crate) struct Fan<T> {
     thresholds: Thresholds,
     /// Current speed
     speed: Option<Speed>,
+    /// Sysfs PWM value written for current speed
+    pwm_value: Option<pwm::Value>,
     /// Startup ts
     startup: Option<Instant>,
+    /// Only log PWM writes instead of doing them
+    dry_run: bool,
+    /// Consecutive RPM checks where the fan was not moving while it should
+    stall_count: u32,
+    /// Full speed duration when starting a stopped fan below its start value
+    spinup_kick: Duration,
+    /// Interval to check the PWM is still in manual control mode while speed is unchanged
+    reassert_interval: Option<Duration>,
+    /// Last time the PWM control mode was checked
+    mode_checked: Option<Instant>,
+    /// Has the PWM control mode been changed by something else
+    mode_conflict: bool,
 }
 
 impl<T> fmt::Display for Fan<T> {
@@ -62,13 +87,36 @@ impl Speed {
     pub(crate) fn is_zero(self) -> bool {
         self.0.is_positive_zero()
     }
+
+    /// Test if speed is full speed
+    pub(crate) fn is_max(self) -> bool {
+        self.0.get() >= 1.0
+    }
+
+    /// Add a (possibly negative) value, clamping result to [0-1]
+    pub(crate) fn saturating_add(self, delta: f64) -> Self {
+        Self::from_clamped(self.0.get() + delta)
+    }
+
+    /// Build speed from any value, clamping it to [0-1], and NaN being full speed
+    pub(crate) fn from_clamped(value: f64) -> Self {
+        let value = if value.is_nan() {
+            1.0
+        } else {
+            value.clamp(0.0, 1.0)
+        };
+        #[expect(clippy::unwrap_used)]
+        Self::try_from(value).unwrap()
+    }
 }
 
-#[expect(clippy::missing_docs_in_private_items)]
+/// Speed conversion error
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum SpeedConversionError {
+    /// Value out of range
     #[error("Value not in range [0.0; 1.0]")]
     Range,
+    /// Not a valid number
     #[error("Invalid value: {0}")]
     InvalidNumber(typed_floats::InvalidNumber),
 }
@@ -88,6 +136,12 @@ impl TryFrom<f64> for Speed {
     }
 }
 
+impl From<Speed> for f64 {
+    fn from(speed: Speed) -> Self {
+        speed.0.get()
+    }
+}
+
 impl fmt::Display for Speed {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         write!(f, "{:.1}%", self.0.get() * 100.0)
@@ -106,15 +160,69 @@ enum SpeedChange {
 impl Fan<()> {
     /// Build a new fan from PWM settings
     pub(crate) fn new(pwm_info: &PwmSettings) -> anyhow::Result<Self> {
-        let pwm = Pwm::new(&pwm_info.filepath)?;
+        let pwm = Pwm::new(&pwm_info.filepath)?.with_inverted(pwm_info.inverted);
         Ok(Self {
             pwm,
-            thresholds: pwm_info.thresholds.clone(),
+            thresholds: pwm_info.thresholds.clone().unwrap_or_default(),
             speed: None,
+            pwm_value: None,
             startup: None,
+            dry_run: false,
+            stall_count: 0,
+            spinup_kick: Duration::ZERO,
+            reassert_interval: None,
+            mode_checked: None,
+            mode_conflict: false,
         })
     }
 
+    /// Set dry run mode, where PWM writes are logged but not done
+    pub(crate) fn with_dry_run(self, dry_run: bool) -> Self {
+        Self { dry_run, ..self }
+    }
+
+    /// Set duration to run the fan at full speed when starting it from stopped to a PWM value below
+    /// its start value, zero to disable
+    pub(crate) fn with_spinup_kick(self, spinup_kick: Duration) -> Self {
+        Self {
+            spinup_kick,
+            ..self
+        }
+    }
+
+    /// Check the PWM is still in manual control mode at this interval while speed is
+    /// unchanged, if set, and set it back if not
+    pub(crate) fn with_reassert_interval(self, reassert_interval: Option<Duration>) -> Self {
+        Self {
+            reassert_interval,
+            ..self
+        }
+    }
+
+    /// Read back PWM values written, logging if they differ by more than `tolerance`, if set
+    pub(crate) fn with_verify_writes(self, tolerance: Option<pwm::Value>) -> Self {
+        Self {
+            pwm: self.pwm.with_verify_writes(tolerance),
+            ..self
+        }
+    }
+
+    /// Set PWM value for full speed if set, instead of the one detected from sysfs
+    pub(crate) fn with_pwm_max(self, max: Option<pwm::Value>) -> Self {
+        Self {
+            pwm: self.pwm.with_max(max),
+            ..self
+        }
+    }
+
+    /// Set `pwmX_enable` value for manual control if set, instead of 1
+    pub(crate) fn with_pwm_enable_value(self, value: Option<u8>) -> Self {
+        Self {
+            pwm: self.pwm.with_manual_mode(value),
+            ..self
+        }
+    }
+
     /// Find RPM filepath for the current fan
     pub(crate) fn resolve_rpm_path(&self) -> anyhow::Result<PathBuf> {
         /// Delay to wait for between PWM speed control, and RPM feedback to ensure both are correlated
@@ -123,7 +231,7 @@ impl Fan<()> {
         let dir = self.pwm.sysfs_dir();
         let candidates: Vec<_> = dir
             .read_dir()
-            .with_context(|| format!("Failed to read directory {dir:?}"))?
+            .with_context(|| format!("Failed to read directory {}", dir.display()))?
             .flatten()
             .filter(|e| {
                 e.file_name()
@@ -157,17 +265,19 @@ impl Fan<()> {
                         sleep(RPM_CORRELATION_DELAY);
                         if pwm.get_rpm()? > 0 {
                             log::debug!(
-                                "RPM file {candidate:?} has positive value with PWM at minimum value, excluding"
+                                "RPM file {} has positive value with PWM at minimum value, excluding",
+                                candidate.display()
                             );
                             skip = true;
                             break;
                         }
 
-                        pwm.set(pwm::Value::MAX)?;
+                        pwm.set(pwm.max())?;
                         sleep(RPM_CORRELATION_DELAY);
                         if pwm.get_rpm()? == 0 {
                             log::debug!(
-                                "RPM file {candidate:?} has null value with PWM at maximum value, excluding"
+                                "RPM file {} has null value with PWM at maximum value, excluding",
+                                candidate.display()
                             );
                             skip = true;
                             break;
@@ -178,7 +288,11 @@ impl Fan<()> {
                         continue;
                     }
 
-                    log::info!("RPM file for {} resolved to {:?}", self.pwm, candidate);
+                    log::info!(
+                        "RPM file for {} resolved to {}",
+                        self.pwm,
+                        candidate.display()
+                    );
                     return Ok(candidate);
                 }
                 Err(anyhow::anyhow!("Unable to resolve fan RPM sysfs path"))
@@ -192,31 +306,156 @@ impl Fan<()> {
             pwm: self.pwm.with_rpm_file(path)?,
             thresholds: self.thresholds,
             speed: self.speed,
+            pwm_value: self.pwm_value,
             startup: self.startup,
+            dry_run: self.dry_run,
+            stall_count: self.stall_count,
+            spinup_kick: self.spinup_kick,
+            reassert_interval: self.reassert_interval,
+            mode_checked: self.mode_checked,
+            mode_conflict: self.mode_conflict,
+        })
+    }
+
+    /// Build a new instance with PWM RPM file optionally set
+    pub(crate) fn with_optional_rpm_file(
+        self,
+        path: Option<&Path>,
+    ) -> anyhow::Result<Fan<Option<PathBuf>>> {
+        Ok(Fan {
+            pwm: self.pwm.with_optional_rpm_file(path)?,
+            thresholds: self.thresholds,
+            speed: self.speed,
+            pwm_value: self.pwm_value,
+            startup: self.startup,
+            dry_run: self.dry_run,
+            stall_count: self.stall_count,
+            spinup_kick: self.spinup_kick,
+            reassert_interval: self.reassert_interval,
+            mode_checked: self.mode_checked,
+            mode_conflict: self.mode_conflict,
         })
     }
 }
 
 impl<T> Fan<T> {
+    /// Check that the PWM files of the fan can be written
+    pub(crate) fn check_writable(&self) -> anyhow::Result<()> {
+        self.pwm.check_writable()
+    }
+
     /// Compute PWM target value from speed and fan thresholds
+    ///
+    /// Non zero speeds are never set at the stop threshold value, at which the fan may stall.
     #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
     fn speed_to_pwm_val(&self, speed: Speed) -> pwm::Value {
         if speed.is_zero() {
             pwm::Value::MIN
         } else {
-            self.thresholds.max_stop
-                + (f64::from(pwm::Value::MAX - self.thresholds.max_stop) * speed.0.get())
-                    as pwm::Value
+            let max = self.pwm.max();
+            let max_stop = self.thresholds.max_stop.min(max);
+            let value = max_stop + (f64::from(max - max_stop) * speed.0.get()) as pwm::Value;
+            if max_stop < max {
+                value.max(max_stop + 1)
+            } else {
+                value
+            }
+        }
+    }
+
+    /// Speed the fan can actually run at: speeds closer to the stop threshold than to the
+    /// lowest value above it stop the fan
+    fn sustainable_speed(&self, speed: Speed) -> Speed {
+        let max = self.pwm.max();
+        let max_stop = self.thresholds.max_stop.min(max);
+        if max_stop < max && f64::from(max - max_stop) * speed.0.get() < 0.5 {
+            Speed::from_clamped(0.0)
+        } else {
+            speed
+        }
+    }
+
+    /// Lowest speed set with at least PWM `value`, zero if the fan is stopped at this value
+    pub(crate) fn min_speed_for_pwm_val(&self, value: pwm::Value) -> Speed {
+        let max = self.pwm.max();
+        let max_stop = self.thresholds.max_stop.min(max);
+        if value <= max_stop {
+            Speed::from_clamped(0.0)
+        } else {
+            // Half a value above, so that truncation in `speed_to_pwm_val` gives back `value`
+            Speed::from_clamped((f64::from(value - max_stop) + 0.5) / f64::from(max - max_stop))
         }
     }
 
+    /// Lowest speed at which the fan keeps running, one PWM value above its stop threshold
+    pub(crate) fn min_running_speed(&self) -> Speed {
+        let max = self.pwm.max();
+        self.min_speed_for_pwm_val(self.thresholds.max_stop.saturating_add(1).min(max))
+    }
+
+    /// Speed matching the current PWM value, ie. set by the firmware before the daemon started
+    pub(crate) fn current_speed(&self) -> anyhow::Result<Speed> {
+        let value = self.pwm.get()?;
+        let max = self.pwm.max();
+        let max_stop = self.thresholds.max_stop.min(max);
+        Ok(if value <= max_stop || max == max_stop {
+            Speed::from_clamped(0.0)
+        } else {
+            Speed::from_clamped(f64::from(value - max_stop) / f64::from(max - max_stop))
+        })
+    }
+
+    /// Last speed set, if any
+    pub(crate) fn speed(&self) -> Option<Speed> {
+        self.speed
+    }
+
+    /// Sysfs PWM value written for the last speed set, if any, inverted for inverted PWMs
+    pub(crate) fn pwm_value(&self) -> Option<pwm::Value> {
+        self.pwm_value
+    }
+
+    /// Get speed closest to target, changing current speed by at most `max_step`
+    pub(crate) fn ramped_speed(&self, target: Speed, max_step: f64) -> Speed {
+        self.speed.map_or(target, |current| {
+            let current = f64::from(current);
+            Speed::from_clamped(f64::from(target).clamp(current - max_step, current + max_step))
+        })
+    }
+
+    /// Current speed, if `target` differs from it by less than `deadband`, so that it is held
+    pub(crate) fn held_speed(&self, target: Speed, deadband: f64) -> Option<Speed> {
+        self.speed.filter(|current| {
+            *current != target && (f64::from(target) - f64::from(*current)).abs() < deadband
+        })
+    }
+
     /// Set fan speed
     pub(crate) fn set_speed(&mut self, speed: Speed) -> anyhow::Result<()> {
+        let speed = self.sustainable_speed(speed);
         if self.speed == Some(speed) {
-            log::trace!("Fan {self} speed unchanged: {speed}");
+            log::trace!(
+                "Fan {self} speed unchanged: {speed}{}",
+                self.pwm_value
+                    .map(|v| format!(" (raw {v}/{})", self.pwm.max()))
+                    .unwrap_or_default()
+            );
+            if !self.dry_run
+                && self.reassert_interval.is_some_and(|interval| {
+                    self.mode_checked.is_none_or(|c| c.elapsed() >= interval)
+                })
+            {
+                self.reassert_mode(speed)?;
+            }
         } else {
-            if let Some(prev_mode) = self.pwm.get_mode()? {
-                let new_mode = ControlMode::Software;
+            let prev_mode = if self.dry_run {
+                None
+            } else {
+                self.mode_checked = Some(Instant::now());
+                self.pwm.get_mode()?
+            };
+            if let Some(prev_mode) = prev_mode {
+                let new_mode = self.pwm.manual_mode();
                 if prev_mode != new_mode {
                     self.pwm.set_mode(new_mode)?;
                     log::info!(
@@ -227,34 +466,117 @@ impl<T> Fan<T> {
                     );
                 }
             }
-            let pwm_value = self.speed_to_pwm_val(speed);
-            let pwm_value = if self.speed.is_some_and(Speed::is_zero) {
-                log::info!("Fan {self} startup");
-                self.startup = Some(Instant::now());
-                max(pwm_value, self.thresholds.min_start)
-            } else if self
-                .startup
-                .is_some_and(|s| Instant::now().duration_since(s) < STARTUP_DELAY)
-            {
-                max(pwm_value, self.thresholds.min_start)
-            } else {
-                pwm_value
-            };
+            self.write_speed(speed)?;
+        }
+        Ok(())
+    }
+
+    /// Write PWM value for a speed, handling fan startup
+    fn write_speed(&mut self, speed: Speed) -> anyhow::Result<()> {
+        let pwm_value = self.speed_to_pwm_val(speed);
+        let pwm_value = if self.speed.is_some_and(Speed::is_zero) {
+            log::info!("Fan {self} startup");
+            if !self.spinup_kick.is_zero() && pwm_value < self.thresholds.min_start {
+                self.kick()?;
+            }
+            self.startup = Some(Instant::now());
+            max(pwm_value, self.thresholds.min_start)
+        } else if self
+            .startup
+            .is_some_and(|s| Instant::now().duration_since(s) < STARTUP_DELAY)
+        {
+            max(pwm_value, self.thresholds.min_start)
+        } else {
+            pwm_value
+        };
+        let raw_value = self.pwm.raw_value(pwm_value);
+        let max = self.pwm.max();
+        if self.dry_run {
+            log::info!("Fan {self} speed would be set to {speed} (raw {raw_value}/{max})");
+        } else {
             self.pwm.set(pwm_value)?;
-            log::info!("Fan {self} speed set to {speed}");
-            self.speed = Some(speed);
+            log::info!(
+                fan:% = self, speed:% = speed, pwm_value = raw_value;
+                "Fan {self} speed set to {speed} (raw {raw_value}/{max})"
+            );
+        }
+        self.speed = Some(speed);
+        self.pwm_value = Some(raw_value);
+        Ok(())
+    }
+
+    /// Set PWM back to manual control mode and current speed if something else changed its mode
+    fn reassert_mode(&mut self, speed: Speed) -> anyhow::Result<()> {
+        self.mode_checked = Some(Instant::now());
+        let mode = match self.pwm.get_mode()? {
+            Some(mode) if mode != self.pwm.manual_mode() => mode,
+            _ => return Ok(()),
+        };
+        if self.mode_conflict {
+            log::info!(
+                "PWM {} mode was changed to {mode} again, setting it back",
+                self.pwm
+            );
+        } else {
+            log::warn!(
+                "PWM {} mode was changed to {mode}, the firmware or another program may be managing this fan, setting it back",
+                self.pwm
+            );
+            self.mode_conflict = true;
+        }
+        self.pwm.set_mode(self.pwm.manual_mode())?;
+        self.write_speed(speed)
+    }
+
+    /// Run fan at full speed for the spin-up kick duration
+    fn kick(&self) -> anyhow::Result<()> {
+        if self.dry_run {
+            log::info!(
+                "Fan {self} would be kicked at full speed for {:?}",
+                self.spinup_kick
+            );
+        } else {
+            log::debug!("Fan {self} kicked at full speed for {:?}", self.spinup_kick);
+            self.pwm.set(self.pwm.max())?;
+            sleep(self.spinup_kick);
         }
         Ok(())
     }
 }
 
+impl Fan<Option<PathBuf>> {
+    /// Check if the fan moves when it was set to at least its start value, and return the
+    /// number of consecutive checks it did not, always 0 without RPM file
+    pub(crate) fn check_stalled(&mut self) -> anyhow::Result<u32> {
+        let should_move = self.speed.is_some_and(|speed| {
+            !speed.is_zero()
+                && (self.speed_to_pwm_val(speed) >= self.thresholds.min_start
+                    || self
+                        .startup
+                        .is_some_and(|s| Instant::now().duration_since(s) < STARTUP_DELAY))
+        });
+        let stalled = should_move && self.pwm.get_rpm()? == Some(0);
+        self.stall_count = if stalled {
+            self.stall_count.saturating_add(1)
+        } else {
+            0
+        };
+        Ok(self.stall_count)
+    }
+
+    /// Measured RPM, if RPM file is set
+    pub(crate) fn rpm(&self) -> anyhow::Result<Option<u32>> {
+        self.pwm.get_rpm()
+    }
+}
+
 impl Fan<PathBuf> {
     /// Wait until fan speed stop increasing or decreasing
     fn wait_stable(&self, change: SpeedChange) -> anyhow::Result<()> {
         /// Maximum duration to wait for the fan to be stabilized
         const STABILIZE_TIMEOUT: Duration = Duration::from_secs(30);
         /// Probe interval
-        const STABILIZE_PROBE_DELAY: Duration = Duration::from_millis(2000);
+        const STABILIZE_PROBE_DELAY: Duration = Duration::from_secs(2);
 
         let mut time_waited = Duration::from_secs(0);
         let mut prev_rpm = self.pwm.get_rpm()?;
@@ -294,14 +616,15 @@ impl Fan<PathBuf> {
     }
 
     /// Dynamically test fan to find its thresholds
-    pub(crate) fn test(&mut self) -> anyhow::Result<Thresholds> {
+    pub(crate) fn test(&mut self) -> anyhow::Result<TestResult> {
         self.set_speed(1.0.try_into()?)?;
         self.wait_stable(SpeedChange::Increasing)?;
-        anyhow::ensure!(self.is_moving()?, "Fan is not moving at maximum speed");
+        let max_rpm = self.pwm.get_rpm()?;
+        anyhow::ensure!(max_rpm > 0, "Fan is not moving at maximum speed");
 
         let mut max_stop = 0;
-        for pwm_val in (0..=pwm::Value::MAX).rev().step_by(5) {
-            self.set_speed((f64::from(pwm_val) / f64::from(pwm::Value::MAX)).try_into()?)?;
+        for pwm_val in (0..=self.pwm.max()).rev().step_by(5) {
+            self.set_pwm_value(pwm_val)?;
             self.wait_stable(SpeedChange::Decreasing)?;
             if !self.is_moving()? {
                 max_stop = pwm_val;
@@ -311,8 +634,8 @@ impl Fan<PathBuf> {
         anyhow::ensure!(!self.is_moving()?, "Fan still moves at minimum speed");
 
         let mut min_start = 0;
-        for pwm_val in (0..=u8::MAX).step_by(5) {
-            self.set_speed((f64::from(pwm_val) / f64::from(pwm::Value::MAX)).try_into()?)?;
+        for pwm_val in (0..=self.pwm.max()).step_by(5) {
+            self.set_pwm_value(pwm_val)?;
             self.wait_stable(SpeedChange::Increasing)?;
             if self.is_moving()? {
                 min_start = pwm_val;
@@ -321,19 +644,402 @@ impl Fan<PathBuf> {
         }
         anyhow::ensure!(self.is_moving()?, "Fan is not moving at maximum speed");
 
-        Ok(Thresholds {
-            min_start,
-            max_stop,
+        Ok(TestResult {
+            thresholds: Thresholds {
+                min_start,
+                max_stop,
+            },
+            max_rpm,
         })
     }
+
+    /// Check that the fan still stops and starts at known thresholds, returns the issues found
+    ///
+    /// The fan is expected to keep moving slightly above its stop threshold when slowing down,
+    /// to stop at its stop threshold, and to start again at its start threshold.
+    pub(crate) fn verify(&mut self, thresholds: &Thresholds) -> anyhow::Result<Vec<&'static str>> {
+        /// PWM value margin above stop threshold, same as the `test` step
+        const STOP_MARGIN: pwm::Value = 5;
+
+        let mut issues = Vec::new();
+
+        self.set_speed(1.0.try_into()?)?;
+        self.wait_stable(SpeedChange::Increasing)?;
+        anyhow::ensure!(self.is_moving()?, "Fan is not moving at maximum speed");
+
+        self.set_pwm_value(thresholds.max_stop.saturating_add(STOP_MARGIN))?;
+        self.wait_stable(SpeedChange::Decreasing)?;
+        if !self.is_moving()? {
+            issues.push("fan stops above its stop threshold, and may stall when running slowly");
+        }
+
+        self.set_pwm_value(thresholds.max_stop)?;
+        if self.is_moving()? {
+            self.wait_stable(SpeedChange::Decreasing)?;
+        }
+        if self.is_moving()? {
+            issues.push("fan still moves at its stop threshold");
+        }
+
+        self.set_pwm_value(thresholds.min_start)?;
+        self.wait_stable(SpeedChange::Increasing)?;
+        if !self.is_moving()? {
+            issues.push("fan does not start at its start threshold");
+        }
+
+        Ok(issues)
+    }
+
+    /// Set raw PWM value, for fans built with default thresholds
+    fn set_pwm_value(&mut self, value: pwm::Value) -> anyhow::Result<()> {
+        self.set_speed(Speed::from_clamped(
+            f64::from(value) / f64::from(self.pwm.max()),
+        ))
+    }
+}
+
+/// Curve point of a JSON curve file
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CurveFilePoint {
+    /// Temperature
+    temp: Temp,
+    /// Speed percentage
+    speed: f64,
+}
+
+/// Fan curve, as control points sorted by strictly increasing temperature
+#[derive(Clone, Debug)]
+pub(crate) struct Curve(Vec<(Temp, Speed)>);
+
+impl Curve {
+    /// Convert point temperatures from `unit` to Celsius
+    pub(crate) fn into_celsius(self, unit: TempUnit) -> Self {
+        Self(
+            self.0
+                .into_iter()
+                .map(|(temp, speed)| (unit.to_celsius(temp), speed))
+                .collect(),
+        )
+    }
+
+    /// Control points
+    pub(crate) fn points(&self) -> &[(Temp, Speed)] {
+        &self.0
+    }
+
+    /// Check that point temperatures in Celsius are within [`cl::TEMP_BOUNDS`]
+    pub(crate) fn check(&self) -> anyhow::Result<()> {
+        for (temp, _) in &self.0 {
+            cl::check_temp(*temp).context("Invalid curve point")?;
+        }
+        Ok(())
+    }
+
+    /// Read from a file: a JSON array of `{"temp": TEMP, "speed": PRCT}` objects if it has a
+    /// `.json` extension, `TEMP,PRCT` CSV lines otherwise
+    pub(crate) fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read curve file {}", path.display()))?;
+        let json = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("json"));
+        Self::parse_file(&content, json)
+            .with_context(|| format!("Invalid curve file {}", path.display()))
+    }
+
+    /// Parse curve file content, see [`Curve::from_file`]
+    fn parse_file(content: &str, json: bool) -> anyhow::Result<Self> {
+        // Points with their location in the file
+        let points: Vec<(String, Temp, f64)> = if json {
+            serde_json::from_str::<Vec<CurveFilePoint>>(content)
+                .context("Invalid JSON")?
+                .into_iter()
+                .enumerate()
+                .map(|(i, p)| (format!("point {}", i + 1), p.temp, p.speed))
+                .collect()
+        } else {
+            let mut points = Vec::new();
+            for (i, line) in content.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let location = format!("line {}", i + 1);
+                let values = line
+                    .split_once(',')
+                    .and_then(|(t, s)| Some((t.trim().parse().ok()?, s.trim().parse().ok()?)));
+                match values {
+                    Some((temp, speed)) => points.push((location, temp, speed)),
+                    // Header
+                    None if points.is_empty() && i == 0 => {}
+                    None => anyhow::bail!("Invalid point at {location}, expected `TEMP,PRCT`"),
+                }
+            }
+            points
+        };
+        let mut curve = Vec::with_capacity(points.len());
+        for (location, temp, prct) in points {
+            anyhow::ensure!(temp.is_finite(), "Invalid temperature at {location}");
+            anyhow::ensure!(
+                (0.0..=100.0).contains(&prct),
+                "Speed at {location} is not in [0-100]"
+            );
+            if let Some((prev_temp, _)) = curve.last() {
+                anyhow::ensure!(
+                    temp > *prev_temp,
+                    "Temperature at {location} is not above the previous point one"
+                );
+            }
+            curve.push((temp, Speed::from_clamped(prct / 100.0)));
+        }
+        anyhow::ensure!(!curve.is_empty(), "Empty curve");
+        Ok(Self(curve))
+    }
+}
+
+impl FromStr for Curve {
+    type Err = &'static str;
+
+    /// Parse from `TEMP:PRCT,TEMP:PRCT,...`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let points = s
+            .split(',')
+            .map(|p| {
+                let (temp, prct) = p.split_once(':').ok_or("Missing point speed")?;
+                let temp: Temp = temp
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|t: &Temp| t.is_finite())
+                    .ok_or("Invalid point temperature")?;
+                let prct: u8 = prct.trim().parse().map_err(|_| "Invalid point speed")?;
+                if prct > 100 {
+                    return Err("Invalid point speed");
+                }
+                let speed =
+                    Speed::try_from(f64::from(prct) / 100.0).map_err(|_| "Invalid point speed")?;
+                Ok((temp, speed))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if points.is_empty() {
+            return Err("Empty curve");
+        }
+        if !points.windows(2).all(|w| matches!(w, [a, b] if a.0 < b.0)) {
+            return Err("Curve points must have strictly increasing temperatures");
+        }
+        Ok(Self(points))
+    }
+}
+
+/// Compute target fan speed for the given temp and curve, linearly interpolated between
+/// control points
+pub(crate) fn target_speed_curve(temp: Temp, curve: &Curve, min_speed: Speed) -> Speed {
+    let speed = match curve.0.iter().position(|(t, _)| *t > temp) {
+        // Below first point
+        Some(0) => curve.0.first().map(|(_, s)| *s),
+        // Between 2 points
+        Some(i) => curve.0.get(i.saturating_sub(1)..=i).and_then(|w| match w {
+            [(t1, s1), (t2, s2)] => {
+                let (s1, s2) = (f64::from(*s1), f64::from(*s2));
+                Some(Speed::from_clamped(
+                    s1 + (s2 - s1) * (temp - t1) / (t2 - t1),
+                ))
+            }
+            _ => None,
+        }),
+        // Above last point
+        None => curve.0.last().map(|(_, s)| *s),
+    };
+    speed.map_or(min_speed, |s| max(min_speed, s))
+}
+
+/// Temperature hysteresis, to avoid fan speed oscillating when temperature hovers around a
+/// value
+#[derive(Debug)]
+pub(crate) struct SpeedSmoother {
+    /// Minimum temperature change to take into account
+    hysteresis: Temp,
+    /// Temperature speed was last computed for
+    last_temp: Option<Temp>,
+    /// Unit to log temperatures in
+    unit: TempUnit,
+}
+
+impl SpeedSmoother {
+    /// New smoother with no history
+    pub(crate) fn new(hysteresis: Temp, unit: TempUnit) -> Self {
+        Self {
+            hysteresis,
+            last_temp: None,
+            unit,
+        }
+    }
+
+    /// Get temperature to compute speed for, which only changes when the measured temperature
+    /// moves more than the hysteresis from it
+    ///
+    /// No temperature (ie. drives spun down) resets the history.
+    pub(crate) fn temp(&mut self, temp: Option<Temp>) -> Option<Temp> {
+        match (temp, self.last_temp) {
+            (Some(temp), Some(last_temp)) if (temp - last_temp).abs() <= self.hysteresis => {
+                if (temp - last_temp).abs() > f64::EPSILON {
+                    log::debug!(
+                        "Temperature change {} -> {} within hysteresis, ignoring",
+                        self.unit.display(last_temp),
+                        self.unit.display(temp)
+                    );
+                }
+            }
+            _ => self.last_temp = temp,
+        }
+        self.last_temp
+    }
+}
+
+/// Turns fans off below a temperature, with a hysteresis band above it so that fans do not
+/// toggle on and off when the temperature hovers around the threshold
+#[derive(Debug)]
+pub(crate) struct OffSwitch {
+    /// Temperature below which fans are turned off
+    threshold: Temp,
+    /// Temperature above the threshold to reach before turning fans back on
+    hysteresis: Temp,
+    /// Are fans off
+    off: bool,
+    /// Unit to log temperatures in
+    unit: TempUnit,
+}
+
+impl OffSwitch {
+    /// New switch, fans on
+    pub(crate) fn new(threshold: Temp, hysteresis: Temp, unit: TempUnit) -> Self {
+        Self {
+            threshold,
+            hysteresis,
+            off: false,
+            unit,
+        }
+    }
+
+    /// Update with the controlling temperature, and get whether fans should be off
+    ///
+    /// No temperature (ie. drives spun down) keeps the current state.
+    pub(crate) fn update(&mut self, temp: Option<Temp>) -> bool {
+        if let Some(temp) = temp {
+            if self.off && temp >= self.threshold + self.hysteresis {
+                log::info!(
+                    "Temperature {} is above fan off threshold, turning fans on",
+                    self.unit.display(temp)
+                );
+                self.off = false;
+            } else if !self.off && temp < self.threshold {
+                log::info!(
+                    "Temperature {} is below fan off threshold, turning fans off",
+                    self.unit.display(temp)
+                );
+                self.off = true;
+            }
+        }
+        self.off
+    }
+}
+
+/// Keeps fans running or stopped for minimum durations, to prevent short cycling
+#[derive(Debug)]
+pub(crate) struct CycleGuard {
+    /// Minimum duration to keep a started fan running
+    min_runtime: Duration,
+    /// Minimum duration to keep a stopped fan stopped
+    min_offtime: Duration,
+    /// Last running state and speed, and when the state last changed
+    last: Option<(Speed, Instant)>,
+}
+
+impl CycleGuard {
+    /// New guard, the first speed starting a cycle
+    pub(crate) fn new(min_runtime: Duration, min_offtime: Duration) -> Self {
+        Self {
+            min_runtime,
+            min_offtime,
+            last: None,
+        }
+    }
+
+    /// Get speed to set for a target speed, keeping the current running state if it changed
+    /// too recently
+    ///
+    /// If `emergency`, a stopped fan is started regardless of its minimum off time.
+    pub(crate) fn apply(&mut self, target: Speed, now: Instant, emergency: bool) -> Speed {
+        let Some((last_speed, changed)) = self.last else {
+            self.last = Some((target, now));
+            return target;
+        };
+        if last_speed.is_zero() == target.is_zero() {
+            self.last = Some((target, changed));
+            return target;
+        }
+        let elapsed = now.saturating_duration_since(changed);
+        let (min_duration, state) = if last_speed.is_zero() {
+            (self.min_offtime, "stopped")
+        } else {
+            (self.min_runtime, "started")
+        };
+        if elapsed < min_duration && !(emergency && last_speed.is_zero()) {
+            log::debug!("Fan {state} {elapsed:?} ago, keeping it so for at least {min_duration:?}");
+            return last_speed;
+        }
+        self.last = Some((target, now));
+        target
+    }
+}
+
+/// Gradual fan speed increase after the daemon started, to avoid a loud burst
+#[derive(Debug)]
+pub(crate) struct StartupRamp {
+    /// Speed at the start of the ramp
+    from: Speed,
+    /// Start of the ramp
+    start: Instant,
+    /// Ramp duration
+    duration: Duration,
 }
 
-/// Compute target fan speed for the given temp and parameters
-pub(crate) fn target_speed(temp: Temp, temp_range: &Range<Temp>, min_speed: Speed) -> Speed {
+impl StartupRamp {
+    /// New ramp from `from` speed, starting at `start`
+    pub(crate) fn new(from: Speed, start: Instant, duration: Duration) -> Self {
+        Self {
+            from,
+            start,
+            duration,
+        }
+    }
+
+    /// Get speed to set for a target speed, a target above the ramp start speed being reached
+    /// progressively until the end of the ramp
+    pub(crate) fn apply(&self, target: Speed, now: Instant) -> Speed {
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed >= self.duration || target <= self.from {
+            return target;
+        }
+        let progress = elapsed.as_secs_f64() / self.duration.as_secs_f64();
+        let from = f64::from(self.from);
+        Speed::from_clamped((f64::from(target) - from).mul_add(progress, from))
+    }
+}
+
+/// Compute target fan speed for the given temp and parameters, ramping up with `shape` in the
+/// range
+pub(crate) fn target_speed(
+    temp: Temp,
+    temp_range: &Range<Temp>,
+    min_speed: Speed,
+    shape: CurveShape,
+) -> Speed {
     if temp_range.contains(&temp) {
+        let position = (temp - temp_range.start) / (temp_range.end - temp_range.start);
         #[expect(clippy::unwrap_used)]
-        let s = Speed::try_from((temp - temp_range.start) / (temp_range.end - temp_range.start))
-            .unwrap();
+        let s = Speed::try_from(shape.apply(position)).unwrap();
         max(min_speed, s)
     } else if temp < temp_range.start {
         min_speed
@@ -343,10 +1049,39 @@ pub(crate) fn target_speed(temp: Temp, temp_range: &Range<Temp>, min_speed: Spee
     }
 }
 
+/// Weighted average of drive and hwmon speeds, or the one available if the other is not
+pub(crate) fn blend_speeds(
+    drive_speed: Option<Speed>,
+    hwmon_speed: Option<Speed>,
+    drive_weight: f64,
+    hwmon_weight: f64,
+) -> Option<Speed> {
+    match (drive_speed, hwmon_speed) {
+        (Some(drive_speed), Some(hwmon_speed)) => Some(Speed::from_clamped(
+            f64::from(drive_speed).mul_add(drive_weight, f64::from(hwmon_speed) * hwmon_weight)
+                / (drive_weight + hwmon_weight),
+        )),
+        (speed, None) | (None, speed) => speed,
+    }
+}
+
+/// Interpolate interval between the end of `intervals` at min speed, and its start at full speed
+pub(crate) fn interval_for_speed(speed: Speed, intervals: &Range<Duration>) -> Duration {
+    intervals.end.saturating_sub(
+        intervals
+            .end
+            .saturating_sub(intervals.start)
+            .mul_f64(speed.0.get()),
+    )
+}
+
+#[expect(clippy::shadow_unrelated)]
 #[cfg(test)]
 mod tests {
 
-    use std::io::Write as _;
+    use std::io::{Read as _, Write as _};
+
+    use float_cmp::approx_eq;
 
     use self::pwm::tests::{FakePwm, assert_file_content};
     use super::*;
@@ -360,7 +1095,8 @@ mod tests {
                     start: 40.0,
                     end: 50.0
                 },
-                Speed::try_from(0.2).unwrap()
+                Speed::try_from(0.2).unwrap(),
+                CurveShape::Linear
             ),
             Speed::try_from(0.5).unwrap()
         );
@@ -371,7 +1107,8 @@ mod tests {
                     start: 40.0,
                     end: 50.0
                 },
-                Speed::try_from(0.2).unwrap()
+                Speed::try_from(0.2).unwrap(),
+                CurveShape::Linear
             ),
             Speed::try_from(0.2).unwrap()
         );
@@ -382,7 +1119,8 @@ mod tests {
                     start: 40.0,
                     end: 50.0
                 },
-                Speed::try_from(0.2).unwrap()
+                Speed::try_from(0.2).unwrap(),
+                CurveShape::Linear
             ),
             Speed::try_from(0.2).unwrap()
         );
@@ -393,7 +1131,8 @@ mod tests {
                     start: 40.0,
                     end: 50.0
                 },
-                Speed::try_from(0.0).unwrap()
+                Speed::try_from(0.0).unwrap(),
+                CurveShape::Linear
             ),
             Speed::try_from(0.0).unwrap()
         );
@@ -404,7 +1143,8 @@ mod tests {
                     start: 40.0,
                     end: 50.0
                 },
-                Speed::try_from(0.0).unwrap()
+                Speed::try_from(0.0).unwrap(),
+                CurveShape::Linear
             ),
             Speed::try_from(0.0).unwrap()
         );
@@ -415,7 +1155,8 @@ mod tests {
                     start: 40.0,
                     end: 50.0
                 },
-                Speed::try_from(0.2).unwrap()
+                Speed::try_from(0.2).unwrap(),
+                CurveShape::Linear
             ),
             Speed::try_from(1.0).unwrap()
         );
@@ -426,21 +1167,483 @@ mod tests {
                     start: 40.0,
                     end: 50.0
                 },
-                Speed::try_from(0.2).unwrap()
+                Speed::try_from(0.2).unwrap(),
+                CurveShape::Linear
             ),
             Speed::try_from(1.0).unwrap()
         );
     }
 
+    #[test]
+    fn test_target_speed_shapes() {
+        let range = Range {
+            start: 40.0,
+            end: 50.0,
+        };
+        let min_speed = Speed::try_from(0.0).unwrap();
+        for shape in [
+            CurveShape::Linear,
+            CurveShape::Quadratic,
+            CurveShape::Cubic,
+            CurveShape::Exp,
+        ] {
+            assert_eq!(target_speed(40.0, &range, min_speed, shape), min_speed);
+            assert_eq!(
+                target_speed(50.0, &range, min_speed, shape),
+                Speed::try_from(1.0).unwrap()
+            );
+            let speeds: Vec<Speed> = (0..=100)
+                .map(|i| target_speed(40.0 + f64::from(i) / 10.0, &range, min_speed, shape))
+                .collect();
+            assert!(speeds.windows(2).all(|w| w[0] <= w[1]), "{shape:?}");
+            // Non linear shapes stay below the linear ramp
+            assert!(
+                target_speed(45.0, &range, min_speed, shape) <= Speed::try_from(0.5).unwrap(),
+                "{shape:?}"
+            );
+        }
+        assert_eq!(
+            target_speed(45.0, &range, min_speed, CurveShape::Quadratic),
+            Speed::try_from(0.25).unwrap()
+        );
+        assert_eq!(
+            target_speed(45.0, &range, min_speed, CurveShape::Cubic),
+            Speed::try_from(0.125).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_curve() {
+        assert_eq!("35:0,45:50,50:100".parse::<Curve>().unwrap().0.len(), 3);
+        assert_eq!("40:20".parse::<Curve>().unwrap().0.len(), 1);
+        assert!("".parse::<Curve>().is_err());
+        assert!("35".parse::<Curve>().is_err());
+        assert!("35:101".parse::<Curve>().is_err());
+        assert!("a:10".parse::<Curve>().is_err());
+        assert!("45:50,35:0".parse::<Curve>().is_err());
+        assert!("35:0,35:50".parse::<Curve>().is_err());
+        assert!("inf:50".parse::<Curve>().is_err());
+        assert!("NaN:50".parse::<Curve>().is_err());
+
+        assert!("35:0,45:50".parse::<Curve>().unwrap().check().is_ok());
+        assert!("-20:0,45:50".parse::<Curve>().unwrap().check().is_err());
+        assert!("35:0,130:50".parse::<Curve>().unwrap().check().is_err());
+        // 250°F is above the bounds, 100°F is not
+        assert!(
+            "100:0,250:100"
+                .parse::<Curve>()
+                .unwrap()
+                .into_celsius(TempUnit::Fahrenheit)
+                .check()
+                .is_err()
+        );
+        assert!(
+            "100:0,200:100"
+                .parse::<Curve>()
+                .unwrap()
+                .into_celsius(TempUnit::Fahrenheit)
+                .check()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_parse_curve_file() {
+        let curve = Curve::parse_file(
+            "temp,speed\n35,0\n\n# Quiet below 45\n45,50\n50,100\n",
+            false,
+        )
+        .unwrap();
+        assert_eq!(curve.0.len(), 3);
+        assert!(approx_eq!(f64, curve.0[1].0, 45.0));
+        assert!(approx_eq!(f64, f64::from(curve.0[1].1), 0.5));
+        assert!(Curve::parse_file("35,0\n45,12.5\n", false).is_ok());
+        assert!(Curve::parse_file("", false).is_err());
+        assert!(Curve::parse_file("temp,speed\n", false).is_err());
+
+        let err = Curve::parse_file("35,0\n45\n", false).unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{err}");
+        let err = Curve::parse_file("35,0\n45,50\n40,100\n", false).unwrap_err();
+        assert!(err.to_string().contains("line 3"), "{err}");
+        let err = Curve::parse_file("35,0\n45,101\n", false).unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{err}");
+        assert!(Curve::parse_file("35,-1\n", false).is_err());
+        assert!(Curve::parse_file("inf,50\n", false).is_err());
+
+        let curve = Curve::parse_file(
+            r#"[{"temp": 35, "speed": 0}, {"temp": 45.5, "speed": 50}]"#,
+            true,
+        )
+        .unwrap();
+        assert_eq!(curve.0.len(), 2);
+        assert!(approx_eq!(f64, curve.0[1].0, 45.5));
+        let err = Curve::parse_file(
+            r#"[{"temp": 35, "speed": 0}, {"temp": 30, "speed": 50}]"#,
+            true,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("point 2"), "{err}");
+        assert!(Curve::parse_file("[]", true).is_err());
+        assert!(Curve::parse_file(r#"[{"temp": 35}]"#, true).is_err());
+        assert!(Curve::parse_file("35,0\n", true).is_err());
+    }
+
+    #[test]
+    fn test_target_speed_curve() {
+        let curve: Curve = "35:0,45:50,50:100".parse().unwrap();
+        let min_speed = Speed::try_from(0.1).unwrap();
+        assert_eq!(target_speed_curve(30.0, &curve, min_speed), min_speed);
+        assert_eq!(target_speed_curve(35.0, &curve, min_speed), min_speed);
+        assert_eq!(
+            target_speed_curve(40.0, &curve, min_speed),
+            Speed::try_from(0.25).unwrap()
+        );
+        assert_eq!(
+            target_speed_curve(45.0, &curve, min_speed),
+            Speed::try_from(0.5).unwrap()
+        );
+        assert_eq!(
+            target_speed_curve(48.0, &curve, min_speed),
+            Speed::try_from(0.8).unwrap()
+        );
+        assert_eq!(
+            target_speed_curve(60.0, &curve, min_speed),
+            Speed::try_from(1.0).unwrap()
+        );
+
+        let single_point: Curve = "40:30".parse().unwrap();
+        assert_eq!(
+            target_speed_curve(30.0, &single_point, min_speed),
+            Speed::try_from(0.3).unwrap()
+        );
+        assert_eq!(
+            target_speed_curve(50.0, &single_point, min_speed),
+            Speed::try_from(0.3).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_speed_smoother() {
+        let mut smoother = SpeedSmoother::new(1.0, TempUnit::Celsius);
+        assert_eq!(smoother.temp(Some(40.0)), Some(40.0));
+        assert_eq!(smoother.temp(Some(41.0)), Some(40.0));
+        assert_eq!(smoother.temp(Some(39.0)), Some(40.0));
+        assert_eq!(smoother.temp(Some(41.5)), Some(41.5));
+        assert_eq!(smoother.temp(Some(40.5)), Some(41.5));
+        assert_eq!(smoother.temp(None), None);
+        assert_eq!(smoother.temp(Some(40.5)), Some(40.5));
+
+        let mut disabled = SpeedSmoother::new(0.0, TempUnit::Celsius);
+        assert_eq!(disabled.temp(Some(40.0)), Some(40.0));
+        assert_eq!(disabled.temp(Some(41.0)), Some(41.0));
+    }
+
+    #[test]
+    fn test_serialize_test_result() {
+        let result = TestResult {
+            thresholds: Thresholds {
+                min_start: 200,
+                max_stop: 75,
+            },
+            max_rpm: 1500,
+        };
+        assert_eq!(
+            serde_json::to_value(&result).unwrap(),
+            serde_json::json!({ "min_start": 200, "max_stop": 75, "max_rpm": 1500 })
+        );
+    }
+
+    #[test]
+    fn test_blend_speeds() {
+        let drive_speed = Speed::try_from(0.8).unwrap();
+        let hwmon_speed = Speed::try_from(0.2).unwrap();
+        assert!(approx_eq!(
+            f64,
+            f64::from(blend_speeds(Some(drive_speed), Some(hwmon_speed), 0.7, 0.3).unwrap()),
+            0.62
+        ));
+        assert!(approx_eq!(
+            f64,
+            f64::from(blend_speeds(Some(drive_speed), Some(hwmon_speed), 1.0, 1.0).unwrap()),
+            0.5
+        ));
+        assert_eq!(
+            blend_speeds(Some(drive_speed), None, 0.7, 0.3),
+            Some(drive_speed)
+        );
+        assert_eq!(
+            blend_speeds(None, Some(hwmon_speed), 0.7, 0.3),
+            Some(hwmon_speed)
+        );
+        assert_eq!(blend_speeds(None, None, 0.7, 0.3), None);
+    }
+
+    #[test]
+    fn test_interval_for_speed() {
+        let intervals = Duration::from_secs(5)..Duration::from_secs(65);
+        assert_eq!(
+            interval_for_speed(Speed::from_clamped(0.0), &intervals),
+            Duration::from_secs(65)
+        );
+        assert_eq!(
+            interval_for_speed(Speed::from_clamped(0.5), &intervals),
+            Duration::from_secs(35)
+        );
+        assert_eq!(
+            interval_for_speed(Speed::from_clamped(1.0), &intervals),
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    fn test_off_switch() {
+        let mut switch = OffSwitch::new(38.0, 2.0, TempUnit::Celsius);
+        assert!(!switch.update(Some(40.0)));
+        assert!(!switch.update(Some(38.0)));
+        assert!(switch.update(Some(37.5)));
+        assert!(switch.update(None));
+        assert!(switch.update(Some(38.5)));
+        assert!(switch.update(Some(39.9)));
+        assert!(!switch.update(Some(40.0)));
+        assert!(!switch.update(None));
+        assert!(!switch.update(Some(38.5)));
+    }
+
+    #[test]
+    fn test_startup_ramp() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let ramp = StartupRamp::new(
+            Speed::try_from(0.2).unwrap(),
+            start,
+            Duration::from_secs(60),
+        );
+        let target = Speed::try_from(0.8).unwrap();
+        assert_eq!(ramp.apply(target, at(0)), Speed::try_from(0.2).unwrap());
+        assert!(approx_eq!(f64, f64::from(ramp.apply(target, at(30))), 0.5));
+        assert_eq!(ramp.apply(target, at(60)), target);
+        assert_eq!(ramp.apply(target, at(90)), target);
+        // Lower target is set immediately
+        assert_eq!(
+            ramp.apply(Speed::try_from(0.1).unwrap(), at(10)),
+            Speed::try_from(0.1).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_min_speed_for_pwm_val() {
+        let fake_pwm = FakePwm::new();
+        let fan = Fan::new(&PwmSettings {
+            filepath: fake_pwm.pwm_path.clone(),
+            thresholds: Some(Thresholds {
+                min_start: 200,
+                max_stop: 50,
+            }),
+            inverted: false,
+        })
+        .unwrap();
+        assert!(fan.min_speed_for_pwm_val(50).is_zero());
+        assert!(fan.min_speed_for_pwm_val(30).is_zero());
+        for value in [51, 60, 127, 254] {
+            let speed = fan.min_speed_for_pwm_val(value);
+            assert_eq!(fan.speed_to_pwm_val(speed), value);
+        }
+        assert!(fan.min_speed_for_pwm_val(255).is_max());
+        assert_eq!(fan.speed_to_pwm_val(fan.min_running_speed()), 51);
+        assert!(!fan.min_running_speed().is_zero());
+
+        let fan = fan.with_pwm_max(Some(100));
+        assert_eq!(fan.speed_to_pwm_val(fan.min_speed_for_pwm_val(60)), 60);
+        assert!(fan.min_speed_for_pwm_val(120).is_max());
+    }
+
+    #[test]
+    fn test_set_speed_stop_threshold() {
+        let mut fake_pwm = FakePwm::new();
+        let mut fan = Fan::new(&PwmSettings {
+            filepath: fake_pwm.pwm_path.clone(),
+            thresholds: Some(Thresholds {
+                min_start: 200,
+                max_stop: 55,
+            }),
+            inverted: false,
+        })
+        .unwrap();
+        fan.startup = None;
+        fan.speed = Some(1.0.try_into().unwrap());
+
+        // Less than half a PWM value above the stop threshold
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(0.002.try_into().unwrap()).unwrap();
+        assert!(fan.speed.is_some_and(Speed::is_zero));
+        assert_file_content(&mut fake_pwm.val_file_read, "0\n");
+
+        // Stopped fan starting again
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(0.003.try_into().unwrap()).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "200\n");
+        fan.startup = None;
+
+        // Between half a value and a value above the stop threshold
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(0.004.try_into().unwrap()).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "56\n");
+
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(0.005.try_into().unwrap()).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "56\n");
+
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(0.01.try_into().unwrap()).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "57\n");
+    }
+
+    #[test]
+    fn test_current_speed() {
+        let mut fake_pwm = FakePwm::new();
+        let fan = Fan::new(&PwmSettings {
+            filepath: fake_pwm.pwm_path.clone(),
+            thresholds: Some(Thresholds {
+                min_start: 200,
+                max_stop: 55,
+            }),
+            inverted: false,
+        })
+        .unwrap();
+        fake_pwm.val_file_write.write_all(b"155\n").unwrap();
+        assert!(approx_eq!(
+            f64,
+            f64::from(fan.current_speed().unwrap()),
+            0.5
+        ));
+        fake_pwm.val_file_write.write_all(b"40\n").unwrap();
+        assert!(fan.current_speed().unwrap().is_zero());
+    }
+
+    #[test]
+    fn test_cycle_guard() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let stopped = Speed::try_from(0.0).unwrap();
+        let slow = Speed::try_from(0.3).unwrap();
+        let fast = Speed::try_from(0.6).unwrap();
+        let mut guard = CycleGuard::new(Duration::from_secs(60), Duration::from_secs(120));
+
+        assert_eq!(guard.apply(slow, at(0), false), slow);
+        assert_eq!(guard.apply(fast, at(10), false), fast);
+        // Running for less than min runtime
+        assert_eq!(guard.apply(stopped, at(50), false), fast);
+        assert_eq!(guard.apply(stopped, at(60), false), stopped);
+        // Stopped for less than min off time
+        assert_eq!(guard.apply(slow, at(100), false), stopped);
+        assert_eq!(guard.apply(slow, at(180), false), slow);
+        assert_eq!(guard.apply(stopped, at(240), false), stopped);
+        // Emergency overrides min off time
+        assert_eq!(
+            guard.apply(Speed::try_from(1.0).unwrap(), at(250), true),
+            Speed::try_from(1.0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_set_speed_reassert_mode() {
+        let mut fake_pwm = FakePwm::new();
+        let mut fan = Fan::new(&PwmSettings {
+            filepath: fake_pwm.pwm_path.clone(),
+            thresholds: Some(Thresholds {
+                min_start: 200,
+                max_stop: 100,
+            }),
+            inverted: false,
+        })
+        .unwrap()
+        .with_reassert_interval(Some(Duration::ZERO));
+        let speed = Speed::try_from(0.5).unwrap();
+
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(speed).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "177\n");
+
+        // Mode unchanged
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(speed).unwrap();
+        assert!(!fan.mode_conflict);
+
+        // Mode changed by something else
+        fake_pwm.mode_file_write.write_all(b"2\n").unwrap();
+        fan.set_speed(speed).unwrap();
+        assert!(fan.mode_conflict);
+        assert_file_content(&mut fake_pwm.mode_file_read, "1\n");
+        assert_file_content(&mut fake_pwm.val_file_read, "177\n");
+    }
+
+    #[test]
+    fn test_ramped_speed() {
+        let fake_pwm = FakePwm::new();
+        let mut fan = Fan::new(&PwmSettings {
+            filepath: fake_pwm.pwm_path.clone(),
+            thresholds: Some(Thresholds {
+                min_start: 200,
+                max_stop: 75,
+            }),
+            inverted: false,
+        })
+        .unwrap();
+        assert_eq!(
+            fan.ramped_speed(Speed::try_from(1.0).unwrap(), 0.1),
+            Speed::try_from(1.0).unwrap()
+        );
+        fan.speed = Some(Speed::try_from(0.5).unwrap());
+        assert_eq!(
+            fan.ramped_speed(Speed::try_from(1.0).unwrap(), 0.25),
+            Speed::try_from(0.75).unwrap()
+        );
+        assert_eq!(
+            fan.ramped_speed(Speed::try_from(0.0).unwrap(), 0.25),
+            Speed::try_from(0.25).unwrap()
+        );
+        assert_eq!(
+            fan.ramped_speed(Speed::try_from(0.625).unwrap(), 0.25),
+            Speed::try_from(0.625).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_held_speed() {
+        let fake_pwm = FakePwm::new();
+        let mut fan = Fan::new(&PwmSettings {
+            filepath: fake_pwm.pwm_path.clone(),
+            thresholds: None,
+            inverted: false,
+        })
+        .unwrap();
+        // Speed not set yet
+        assert_eq!(fan.held_speed(Speed::try_from(0.5).unwrap(), 0.05), None);
+
+        fan.speed = Some(Speed::try_from(0.5).unwrap());
+        assert_eq!(
+            fan.held_speed(Speed::try_from(0.52).unwrap(), 0.05),
+            Some(Speed::try_from(0.5).unwrap())
+        );
+        assert_eq!(
+            fan.held_speed(Speed::try_from(0.47).unwrap(), 0.05),
+            Some(Speed::try_from(0.5).unwrap())
+        );
+        assert_eq!(fan.held_speed(Speed::try_from(0.5).unwrap(), 0.05), None);
+        assert_eq!(fan.held_speed(Speed::try_from(0.56).unwrap(), 0.05), None);
+        assert_eq!(fan.held_speed(Speed::try_from(0.4).unwrap(), 0.05), None);
+    }
+
     #[test]
     fn test_set_speed() {
         let mut fake_pwm = FakePwm::new();
         let mut fan = Fan::new(&PwmSettings {
             filepath: fake_pwm.pwm_path.clone(),
-            thresholds: Thresholds {
+            thresholds: Some(Thresholds {
                 min_start: 200,
                 max_stop: 100,
-            },
+            }),
+            inverted: false,
         })
         .unwrap();
 
@@ -458,11 +1661,13 @@ mod tests {
         fan.set_speed(0.5.try_into().unwrap()).unwrap();
         assert!(fan.startup.is_some());
         assert_file_content(&mut fake_pwm.val_file_read, "200\n");
+        assert_eq!(fan.pwm_value(), Some(200));
 
         fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
         fan.set_speed(0.9.try_into().unwrap()).unwrap();
         assert!(fan.startup.is_some());
         assert_file_content(&mut fake_pwm.val_file_read, "239\n");
+        assert_eq!(fan.pwm_value(), Some(239));
 
         fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
         fan.set_speed(1.0.try_into().unwrap()).unwrap();
@@ -491,4 +1696,168 @@ mod tests {
         assert!(fan.startup.is_some());
         assert_file_content(&mut fake_pwm.val_file_read, "200\n");
     }
+
+    #[test]
+    fn test_set_speed_pwm_max() {
+        let mut fake_pwm = FakePwm::new();
+        let mut fan = Fan::new(&PwmSettings {
+            filepath: fake_pwm.pwm_path.clone(),
+            thresholds: Some(Thresholds {
+                min_start: 60,
+                max_stop: 40,
+            }),
+            inverted: false,
+        })
+        .unwrap()
+        .with_pwm_max(Some(100));
+
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(1.0.try_into().unwrap()).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "100\n");
+
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(0.5.try_into().unwrap()).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "70\n");
+    }
+
+    #[test]
+    fn test_set_speed_pwm_enable_value() {
+        let mut fake_pwm = FakePwm::new();
+        let mut fan = Fan::new(&PwmSettings {
+            filepath: fake_pwm.pwm_path.clone(),
+            thresholds: None,
+            inverted: false,
+        })
+        .unwrap()
+        .with_pwm_enable_value(Some(0));
+
+        fake_pwm.mode_file_write.write_all(b"2\n").unwrap();
+        fan.set_speed(1.0.try_into().unwrap()).unwrap();
+        assert_file_content(&mut fake_pwm.mode_file_read, "0\n");
+        assert_file_content(&mut fake_pwm.val_file_read, "255\n");
+
+        // Already in manual mode
+        fake_pwm.mode_file_write.write_all(b"0\n").unwrap();
+        fan.set_speed(0.5.try_into().unwrap()).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "127\n");
+        let mut buf = [0; 16];
+        assert!(fake_pwm.mode_file_read.read(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_set_speed_spinup_kick() {
+        let mut fake_pwm = FakePwm::new();
+        let mut fan = Fan::new(&PwmSettings {
+            filepath: fake_pwm.pwm_path.clone(),
+            thresholds: Some(Thresholds {
+                min_start: 200,
+                max_stop: 100,
+            }),
+            inverted: false,
+        })
+        .unwrap()
+        .with_spinup_kick(Duration::from_millis(1));
+
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(0.0.try_into().unwrap()).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "0\n");
+
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(0.01.try_into().unwrap()).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "255\n200\n");
+
+        // Already spinning
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(0.02.try_into().unwrap()).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "200\n");
+
+        // Start value reached without kick
+        fan.startup = None;
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(0.0.try_into().unwrap()).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "0\n");
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(0.9.try_into().unwrap()).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "239\n");
+    }
+
+    #[test]
+    fn test_set_speed_dry_run() {
+        let mut fake_pwm = FakePwm::new();
+        let mut fan = Fan::new(&PwmSettings {
+            filepath: fake_pwm.pwm_path.clone(),
+            thresholds: Some(Thresholds {
+                min_start: 200,
+                max_stop: 100,
+            }),
+            inverted: false,
+        })
+        .unwrap()
+        .with_dry_run(true);
+
+        fan.set_speed(0.0.try_into().unwrap()).unwrap();
+        fan.set_speed(0.5.try_into().unwrap()).unwrap();
+        assert!(fan.startup.is_some());
+        assert_eq!(fan.speed, Some(0.5.try_into().unwrap()));
+        let mut buf = [0; 16];
+        assert!(fake_pwm.val_file_read.read(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_check_stalled() {
+        let mut fake_pwm = FakePwm::new();
+        let mut fan = Fan::new(&PwmSettings {
+            filepath: fake_pwm.pwm_path.clone(),
+            thresholds: Some(Thresholds {
+                min_start: 200,
+                max_stop: 100,
+            }),
+            inverted: false,
+        })
+        .unwrap()
+        .with_optional_rpm_file(Some(&fake_pwm.rpm_path))
+        .unwrap();
+
+        // Speed not set yet
+        assert_eq!(fan.check_stalled().unwrap(), 0);
+
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(1.0.try_into().unwrap()).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "255\n");
+        fake_pwm.rpm_file_write.write_all(b"0\n").unwrap();
+        assert_eq!(fan.check_stalled().unwrap(), 1);
+        fake_pwm.rpm_file_write.write_all(b"0\n").unwrap();
+        assert_eq!(fan.check_stalled().unwrap(), 2);
+        fake_pwm.rpm_file_write.write_all(b"1200\n").unwrap();
+        assert_eq!(fan.check_stalled().unwrap(), 0);
+
+        // Fan is expected to be stopped
+        fake_pwm.mode_file_write.write_all(b"1\n").unwrap();
+        fan.set_speed(0.0.try_into().unwrap()).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "0\n");
+        assert_eq!(fan.check_stalled().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_rpm() {
+        let mut fake_pwm = FakePwm::new();
+        let settings = PwmSettings {
+            filepath: fake_pwm.pwm_path.clone(),
+            thresholds: None,
+            inverted: false,
+        };
+
+        let fan_without_rpm = Fan::new(&settings)
+            .unwrap()
+            .with_optional_rpm_file(None)
+            .unwrap();
+        assert_eq!(fan_without_rpm.rpm().unwrap(), None);
+
+        let fan_with_rpm = Fan::new(&settings)
+            .unwrap()
+            .with_optional_rpm_file(Some(&fake_pwm.rpm_path))
+            .unwrap();
+        fake_pwm.rpm_file_write.write_all(b"1350\n").unwrap();
+        assert_eq!(fan_with_rpm.rpm().unwrap(), Some(1350));
+    }
 }
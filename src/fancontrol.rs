@@ -0,0 +1,356 @@
+//! Import of lm-sensors `fancontrol` configuration
+//! See <https://github.com/lm-sensors/lm-sensors/blob/master/prog/pwm/fancontrol.8>
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::Context as _;
+
+use crate::{probe::Temp, pwm};
+
+/// Directory `fancontrol` paths are relative to
+const HWMON_DIR: &str = "/sys/class/hwmon";
+
+/// Settings for a single PWM, as found in a `fancontrol` configuration
+#[derive(Debug, Default)]
+struct FanConfig {
+    /// Temperature sensor input files (`FCTEMPS`)
+    temps: Vec<PathBuf>,
+    /// Temperature at or below which the fan runs at `min_pwm` (`MINTEMP`)
+    min_temp: Option<Temp>,
+    /// Temperature at or above which the fan runs at `max_pwm` (`MAXTEMP`)
+    max_temp: Option<Temp>,
+    /// PWM value at which the fan starts (`MINSTART`)
+    min_start: Option<pwm::Value>,
+    /// PWM value at which the fan stops (`MINSTOP`)
+    min_stop: Option<pwm::Value>,
+    /// PWM value below `min_temp` (`MINPWM`)
+    min_pwm: Option<pwm::Value>,
+    /// PWM value above `max_temp` (`MAXPWM`)
+    max_pwm: Option<pwm::Value>,
+}
+
+/// Parsed `fancontrol` configuration
+#[derive(Debug, Default)]
+pub(crate) struct Config {
+    /// Interval in seconds
+    interval: Option<u64>,
+    /// Fans, by PWM path
+    fans: BTreeMap<PathBuf, FanConfig>,
+    /// Problems found while parsing
+    warnings: Vec<String>,
+}
+
+/// Configuration converted for this daemon
+pub(crate) struct Import {
+    /// `daemon` command line arguments
+    pub args: Vec<String>,
+    /// Settings that could not be converted exactly
+    pub warnings: Vec<String>,
+}
+
+impl Import {
+    /// Render as a systemd environment file, like `/etc/conf.d/hddfancontrol`
+    pub(crate) fn to_env_file(&self, source: &Path) -> String {
+        let mut s = format!(
+            "#\n# HDD fan control configuration, imported from {} by 'hddfancontrol import-fancontrol'.\n#\n\n",
+            source.display()
+        );
+        for warning in &self.warnings {
+            let _ = writeln!(s, "# WARNING: {warning}");
+        }
+        if !self.warnings.is_empty() {
+            s.push('\n');
+        }
+        let _ = writeln!(
+            s,
+            "# Daemon startup arguments, run 'hddfancontrol daemon -h' to see help about all available arguments.\n\
+             # Drives to monitor need to be added with '-d', ie: '-d /dev/sda /dev/sdb'"
+        );
+        let _ = writeln!(s, "HDDFANCONTROL_DAEMON_ARGS=\"{}\"", self.args.join(" "));
+        s
+    }
+}
+
+/// Resolve a `fancontrol` path, relative to the hwmon class directory
+fn resolve_path(s: &str) -> PathBuf {
+    Path::new(HWMON_DIR).join(s)
+}
+
+/// Parse a list of space separated `PWM=VALUE` pairs
+fn parse_pairs<'a>(key: &str, value: &'a str) -> anyhow::Result<Vec<(PathBuf, &'a str)>> {
+    value
+        .split_ascii_whitespace()
+        .map(|pair| {
+            let (pwm, val) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Invalid {key} entry {pair:?}"))?;
+            Ok((resolve_path(pwm), val))
+        })
+        .collect()
+}
+
+/// Parse a single value
+fn parse_value<T>(key: &str, pwm: &Path, val: &str) -> anyhow::Result<T>
+where
+    T: FromStr,
+{
+    val.parse()
+        .map_err(|_| anyhow::anyhow!("Invalid {key} value {val:?} for PWM {}", pwm.display()))
+}
+
+impl FromStr for Config {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cfg = Self::default();
+        for (line_num, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Invalid line {}: {line:?}", line_num + 1))?;
+            let key = key.trim();
+            let value = value.trim().trim_matches('"');
+            match key {
+                "INTERVAL" => {
+                    cfg.interval = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("Invalid INTERVAL value {value:?}"))?,
+                    );
+                }
+                "FCTEMPS" => {
+                    for (pwm, temps) in parse_pairs(key, value)? {
+                        cfg.fans.entry(pwm).or_default().temps =
+                            temps.split('+').map(resolve_path).collect();
+                    }
+                }
+                "MINTEMP" | "MAXTEMP" => {
+                    for (pwm, val) in parse_pairs(key, value)? {
+                        let temp = parse_value(key, &pwm, val)?;
+                        let fan = cfg.fans.entry(pwm).or_default();
+                        if key == "MINTEMP" {
+                            fan.min_temp = Some(temp);
+                        } else {
+                            fan.max_temp = Some(temp);
+                        }
+                    }
+                }
+                "MINSTART" | "MINSTOP" | "MINPWM" | "MAXPWM" => {
+                    for (pwm, val) in parse_pairs(key, value)? {
+                        let pwm_val = parse_value(key, &pwm, val)?;
+                        let fan = cfg.fans.entry(pwm).or_default();
+                        let field = match key {
+                            "MINSTART" => &mut fan.min_start,
+                            "MINSTOP" => &mut fan.min_stop,
+                            "MINPWM" => &mut fan.min_pwm,
+                            _ => &mut fan.max_pwm,
+                        };
+                        *field = Some(pwm_val);
+                    }
+                }
+                "FCFANS" => {
+                    // Fan RPM inputs, only used by fancontrol for sanity checks
+                }
+                "DEVPATH" | "DEVNAME" => {
+                    cfg.warnings.push(format!(
+                        "{key} is ignored, make sure the hwmon numbering in paths is stable across reboots"
+                    ));
+                }
+                _ => cfg
+                    .warnings
+                    .push(format!("Unknown setting {key} is ignored")),
+            }
+        }
+        cfg.warnings.dedup();
+        Ok(cfg)
+    }
+}
+
+impl Config {
+    /// Read and parse a `fancontrol` configuration file
+    pub(crate) fn from_file(path: &Path) -> anyhow::Result<Self> {
+        std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .parse()
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Convert to equivalent daemon settings
+    pub(crate) fn import(self) -> anyhow::Result<Import> {
+        let mut args = Vec::new();
+        let mut warnings = self.warnings;
+
+        let mut pwm_args = Vec::new();
+        let mut hwmon_args: Vec<String> = Vec::new();
+        let mut min_speed_prcts = Vec::new();
+        for (pwm_path, fan) in &self.fans {
+            let pwm_name = pwm_path.display();
+            let (Some(min_start), Some(min_stop)) = (fan.min_start, fan.min_stop) else {
+                warnings.push(format!(
+                    "PWM {pwm_name} is missing MINSTART or MINSTOP, skipping it"
+                ));
+                continue;
+            };
+            pwm_args.push(format!("{pwm_name}:{min_start}:{min_stop}"));
+
+            if fan.temps.is_empty() {
+                warnings.push(format!("PWM {pwm_name} has no FCTEMPS sensor"));
+            }
+            for temp in &fan.temps {
+                let arg = if let (Some(min_temp), Some(max_temp)) = (fan.min_temp, fan.max_temp) {
+                    format!("{}:{min_temp}:{max_temp}", temp.display())
+                } else {
+                    warnings.push(format!(
+                        "PWM {pwm_name} is missing MINTEMP or MAXTEMP, default range will be used for {}",
+                        temp.display()
+                    ));
+                    temp.display().to_string()
+                };
+                if !hwmon_args.contains(&arg) {
+                    hwmon_args.push(arg);
+                }
+            }
+
+            // Below MINTEMP fancontrol sets MINPWM, which we translate into a minimum speed
+            let min_pwm = fan.min_pwm.unwrap_or(pwm::Value::MIN);
+            let min_speed_prct = if min_pwm <= min_stop {
+                0
+            } else {
+                #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let prct = (f64::from(min_pwm - min_stop) * 100.0
+                    / f64::from(pwm::Value::MAX - min_stop))
+                .round() as u8;
+                prct
+            };
+            min_speed_prcts.push(min_speed_prct);
+
+            if fan.max_pwm.is_some_and(|v| v < pwm::Value::MAX) {
+                warnings.push(format!(
+                    "MAXPWM for PWM {pwm_name} is not supported, fan will reach full speed"
+                ));
+            }
+        }
+        anyhow::ensure!(!pwm_args.is_empty(), "No usable PWM found");
+
+        if self
+            .fans
+            .values()
+            .map(|f| (&f.temps, f.min_temp, f.max_temp))
+            .collect::<Vec<_>>()
+            .windows(2)
+            .any(|w| w.first() != w.last())
+        {
+            warnings.push(
+                "PWMs use different sensors or temperature ranges, all fans will be driven by all sensors"
+                    .to_owned(),
+            );
+        }
+
+        args.push("-p".to_owned());
+        args.extend(pwm_args);
+        if !hwmon_args.is_empty() {
+            args.push("-w".to_owned());
+            args.extend(hwmon_args);
+        }
+        let min_speed_prct = min_speed_prcts.iter().copied().max().unwrap_or(0);
+        if min_speed_prcts.iter().any(|p| *p != min_speed_prct) {
+            warnings.push(format!(
+                "PWMs have different MINPWM values, using the highest minimum speed of {min_speed_prct}%"
+            ));
+        }
+        args.push(format!("--min-fan-speed-prct={min_speed_prct}"));
+        if let Some(interval) = self.interval {
+            args.push(format!("--interval={interval}s"));
+        }
+
+        Ok(Import { args, warnings })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import() {
+        let cfg: Config = "# Configuration file generated by pwmconfig
+INTERVAL=10
+DEVPATH=hwmon1=devices/platform/nct6775.656
+DEVNAME=hwmon1=nct6775
+FCTEMPS=hwmon1/pwm2=hwmon1/temp1_input hwmon1/pwm3=hwmon1/temp1_input
+FCFANS=hwmon1/pwm2=hwmon1/fan2_input hwmon1/pwm3=hwmon1/fan3_input
+MINTEMP=hwmon1/pwm2=35 hwmon1/pwm3=35
+MAXTEMP=hwmon1/pwm2=55 hwmon1/pwm3=55
+MINSTART=hwmon1/pwm2=150 hwmon1/pwm3=120
+MINSTOP=hwmon1/pwm2=75 hwmon1/pwm3=60
+MINPWM=hwmon1/pwm2=0 hwmon1/pwm3=0
+"
+        .parse()
+        .unwrap();
+        let import = cfg.import().unwrap();
+        assert_eq!(
+            import.args,
+            vec![
+                "-p",
+                "/sys/class/hwmon/hwmon1/pwm2:150:75",
+                "/sys/class/hwmon/hwmon1/pwm3:120:60",
+                "-w",
+                "/sys/class/hwmon/hwmon1/temp1_input:35:55",
+                "--min-fan-speed-prct=0",
+                "--interval=10s"
+            ]
+        );
+        assert_eq!(import.warnings.len(), 2);
+    }
+
+    #[test]
+    fn test_import_warnings() {
+        let cfg: Config = "INTERVAL=10
+FCTEMPS=hwmon0/device/pwm1=hwmon0/device/temp1_input+hwmon1/temp2_input hwmon0/device/pwm2=hwmon0/device/temp2_input
+MINTEMP=hwmon0/device/pwm1=40 hwmon0/device/pwm2=30
+MAXTEMP=hwmon0/device/pwm1=60 hwmon0/device/pwm2=50
+MINSTART=hwmon0/device/pwm1=200 hwmon0/device/pwm2=150
+MINSTOP=hwmon0/device/pwm1=100 hwmon0/device/pwm2=50
+MINPWM=hwmon0/device/pwm1=155
+MAXPWM=hwmon0/device/pwm1=200
+FOO=bar
+"
+        .parse()
+        .unwrap();
+        let import = cfg.import().unwrap();
+        assert_eq!(
+            import.args,
+            vec![
+                "-p",
+                "/sys/class/hwmon/hwmon0/device/pwm1:200:100",
+                "/sys/class/hwmon/hwmon0/device/pwm2:150:50",
+                "-w",
+                "/sys/class/hwmon/hwmon0/device/temp1_input:40:60",
+                "/sys/class/hwmon/hwmon1/temp2_input:40:60",
+                "/sys/class/hwmon/hwmon0/device/temp2_input:30:50",
+                "--min-fan-speed-prct=35",
+                "--interval=10s"
+            ]
+        );
+        assert_eq!(import.warnings.len(), 4);
+        assert!(import.to_env_file(Path::new("/etc/fancontrol")).contains(
+            "\nHDDFANCONTROL_DAEMON_ARGS=\"-p /sys/class/hwmon/hwmon0/device/pwm1:200:100 "
+        ));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!("MINSTART=hwmon1/pwm2=abc".parse::<Config>().is_err());
+        assert!("MINSTART=hwmon1/pwm2".parse::<Config>().is_err());
+        assert!("INTERVAL".parse::<Config>().is_err());
+        assert!("INTERVAL=10".parse::<Config>().unwrap().import().is_err());
+    }
+}
@@ -0,0 +1,162 @@
+//! InfluxDB line protocol output over UDP, ie. to a Telegraf `socket_listener` or InfluxDB UDP
+//! input
+//! See <https://docs.influxdata.com/influxdb/v1/write_protocols/line_protocol_reference/>
+
+use std::{
+    fmt::Write as _,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs as _, UdpSocket},
+};
+
+use anyhow::Context as _;
+
+use crate::snapshot::Snapshot;
+
+/// Measurement name
+const MEASUREMENT: &str = "hddfancontrol";
+
+/// Fire and forget sender of the control loop readings
+pub(crate) struct InfluxSender {
+    /// Socket connected to the destination
+    socket: UdpSocket,
+}
+
+impl InfluxSender {
+    /// Resolve `host:port` destination address, and setup socket
+    pub(crate) fn new(addr: &str) -> anyhow::Result<Self> {
+        let dest = addr
+            .to_socket_addrs()
+            .with_context(|| format!("Invalid InfluxDB UDP address {addr:?}"))?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("InfluxDB UDP address {addr:?} resolves to nothing"))?;
+        let local: SocketAddr = if dest.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(local).context("Failed to bind InfluxDB UDP socket")?;
+        socket
+            .connect(dest)
+            .with_context(|| format!("Failed to set InfluxDB UDP destination {dest}"))?;
+        log::info!("Sending InfluxDB measurements to udp://{dest}");
+        Ok(Self { socket })
+    }
+
+    /// Send measurements for a snapshot, in a single datagram
+    pub(crate) fn send(&self, snapshot: &Snapshot) -> anyhow::Result<()> {
+        self.socket
+            .send(render(snapshot).as_bytes())
+            .context("Failed to send InfluxDB measurements")?;
+        Ok(())
+    }
+}
+
+/// Escape a tag value
+fn escape_tag(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+/// Render snapshot as line protocol, with one line per drive, hwmon and fan
+fn render(snapshot: &Snapshot) -> String {
+    let timestamp = snapshot
+        .time
+        .timestamp_nanos_opt()
+        .map(|t| format!(" {t}"))
+        .unwrap_or_default();
+    let mut out = String::new();
+    for drive in &snapshot.drives {
+        let _ = write!(
+            out,
+            "{MEASUREMENT},drive={} spun_down={}",
+            escape_tag(&drive.path.to_string_lossy()),
+            drive.spun_down
+        );
+        if let Some(temp) = drive.temp {
+            let _ = write!(out, ",temp={temp}");
+        }
+        let _ = writeln!(out, "{timestamp}");
+    }
+    for hwmon in &snapshot.hwmons {
+        let _ = writeln!(
+            out,
+            "{MEASUREMENT},hwmon={} temp={}{timestamp}",
+            escape_tag(&hwmon.name),
+            hwmon.temp
+        );
+    }
+    for fan in &snapshot.fans {
+        let _ = writeln!(
+            out,
+            "{MEASUREMENT},pwm={} speed_prct={},boosted={}{timestamp}",
+            escape_tag(&fan.pwm.to_string_lossy()),
+            fan.speed_prct,
+            fan.boosted
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use chrono::{Local, TimeZone as _};
+
+    use super::*;
+    use crate::snapshot::{DriveReading, FanReading, HwmonReading};
+
+    #[test]
+    fn test_send() {
+        let snapshot = Snapshot {
+            time: Local.timestamp_opt(1_700_000_000, 0).unwrap(),
+            drives: vec![
+                DriveReading {
+                    name: "sda".to_owned(),
+                    path: Path::new("/dev/sda").to_owned(),
+                    state: "active/idle".to_owned(),
+                    spun_down: false,
+                    temp: Some(42.5),
+                },
+                DriveReading {
+                    name: "sdb".to_owned(),
+                    path: Path::new("/dev/sdb").to_owned(),
+                    state: "standby".to_owned(),
+                    spun_down: true,
+                    temp: None,
+                },
+            ],
+            hwmons: vec![HwmonReading {
+                name: "coretemp.0/Package id 0".to_owned(),
+                temp: 45.0,
+            }],
+            fans: vec![FanReading {
+                name: "pwm2".to_owned(),
+                pwm: "/sys/class/hwmon/hwmon1/pwm2".into(),
+                speed_prct: 40.0,
+                boosted: false,
+            }],
+        };
+
+        let receiver = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let sender = InfluxSender::new(&receiver.local_addr().unwrap().to_string()).unwrap();
+        sender.send(&snapshot).unwrap();
+        let mut buf = vec![0; 4096];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(
+            str::from_utf8(&buf[..len]).unwrap(),
+            "hddfancontrol,drive=/dev/sda spun_down=false,temp=42.5 1700000000000000000\n\
+             hddfancontrol,drive=/dev/sdb spun_down=true 1700000000000000000\n\
+             hddfancontrol,hwmon=coretemp.0/Package\\ id\\ 0 temp=45 1700000000000000000\n\
+             hddfancontrol,pwm=/sys/class/hwmon/hwmon1/pwm2 speed_prct=40,boosted=false 1700000000000000000\n"
+        );
+
+        assert!(InfluxSender::new("localhost").is_err());
+        assert!(InfluxSender::new("127.0.0.1:port").is_err());
+    }
+}
@@ -0,0 +1,168 @@
+//! systemd journal logging backend, using the native journal protocol
+//! See <https://systemd.io/JOURNAL_NATIVE_PROTOCOL/>
+
+use std::{io, os::unix::net::UnixDatagram};
+
+use anyhow::Context as _;
+use flexi_logger::{DeferredNow, writers::LogWriter};
+
+/// Journal daemon socket path
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Syslog identifier of the entries
+const SYSLOG_IDENTIFIER: &str = "hddfancontrol";
+
+/// Log writer sending records to the journal, with their key-values as structured fields
+/// (ie. `DRIVE`, `TEMP`, `SPEED`)
+pub(crate) struct JournalWriter {
+    /// Socket connected to the journal daemon
+    socket: UnixDatagram,
+}
+
+impl JournalWriter {
+    /// Connect to the journal daemon
+    pub(crate) fn new() -> anyhow::Result<Self> {
+        let socket = UnixDatagram::unbound().context("Failed to create journal socket")?;
+        socket
+            .connect(JOURNAL_SOCKET)
+            .with_context(|| format!("Failed to connect to journal socket {JOURNAL_SOCKET}"))?;
+        Ok(Self { socket })
+    }
+}
+
+impl LogWriter for JournalWriter {
+    fn write(&self, _now: &mut DeferredNow, record: &log::Record) -> io::Result<()> {
+        self.socket.send(&entry(record)).map(|_| ())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Syslog priority of a log level
+fn priority(level: log::Level) -> u8 {
+    match level {
+        log::Level::Error => 3,
+        log::Level::Warn => 4,
+        log::Level::Info => 6,
+        log::Level::Debug | log::Level::Trace => 7,
+    }
+}
+
+/// Journal field name for a record key, only made of uppercase letters, digits and underscores,
+/// and not starting with an underscore or digit, or `None` if there is no valid character
+fn field_name(key: &str) -> Option<String> {
+    let name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .skip_while(|c| *c == '_' || c.is_ascii_digit())
+        .collect();
+    (!name.is_empty()).then_some(name)
+}
+
+/// Append a field to a journal entry, values with line breaks being length prefixed
+fn append_field(entry: &mut Vec<u8>, name: &str, value: &str) {
+    entry.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
+
+/// Collect record key-values as journal fields
+struct FieldVisitor<'a> {
+    /// Journal entry
+    entry: &'a mut Vec<u8>,
+}
+
+impl<'kvs> log::kv::VisitSource<'kvs> for FieldVisitor<'_> {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        if let Some(name) = field_name(key.as_str()) {
+            append_field(self.entry, &name, &value.to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Build journal entry for a record
+fn entry(record: &log::Record) -> Vec<u8> {
+    let mut entry = Vec::new();
+    append_field(&mut entry, "MESSAGE", &record.args().to_string());
+    append_field(
+        &mut entry,
+        "PRIORITY",
+        &priority(record.level()).to_string(),
+    );
+    append_field(&mut entry, "SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER);
+    append_field(&mut entry, "TARGET", record.target());
+    if let Some(module) = record.module_path() {
+        append_field(&mut entry, "CODE_MODULE", module);
+    }
+    if let Some(file) = record.file() {
+        append_field(&mut entry, "CODE_FILE", file);
+    }
+    if let Some(line) = record.line() {
+        append_field(&mut entry, "CODE_LINE", &line.to_string());
+    }
+    let _ = record
+        .key_values()
+        .visit(&mut FieldVisitor { entry: &mut entry });
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_name() {
+        assert_eq!(field_name("drive").as_deref(), Some("DRIVE"));
+        assert_eq!(field_name("speed.prct").as_deref(), Some("SPEED_PRCT"));
+        assert_eq!(field_name("_1temp").as_deref(), Some("TEMP"));
+        assert_eq!(field_name("_"), None);
+    }
+
+    #[test]
+    fn test_write() {
+        let (socket, journal) = UnixDatagram::pair().unwrap();
+        let writer = JournalWriter { socket };
+        let kvs: [(&str, &str); 2] = [("drive", "/dev/sda"), ("temp", "38.5")];
+        writer
+            .write(
+                &mut DeferredNow::new(),
+                &log::Record::builder()
+                    .args(format_args!("line1\nline2"))
+                    .level(log::Level::Warn)
+                    .target("hddfancontrol")
+                    .key_values(&kvs)
+                    .build(),
+            )
+            .unwrap();
+        let mut buf = vec![0; 4096];
+        let len = journal.recv(&mut buf).unwrap();
+        buf.truncate(len);
+
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&11_u64.to_le_bytes());
+        expected.extend_from_slice(b"line1\nline2\n");
+        expected.extend_from_slice(
+            b"PRIORITY=4\nSYSLOG_IDENTIFIER=hddfancontrol\nTARGET=hddfancontrol\nDRIVE=/dev/sda\nTEMP=38.5\n",
+        );
+        assert_eq!(buf, expected);
+    }
+}
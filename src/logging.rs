@@ -0,0 +1,161 @@
+//! Log record formatting and log files
+
+use std::{
+    fs,
+    io::{self, Write},
+    os::unix::fs::symlink,
+    path::Path,
+};
+
+use anyhow::Context as _;
+use flexi_logger::{DeferredNow, FormatFunction};
+use once_cell::sync::OnceCell;
+
+use crate::cl::LogFormat;
+
+/// Timestamp format used when none was set
+const DEFAULT_DATETIME_FORMAT: &str = "%d.%m.%Y %H:%M:%S";
+
+/// Timestamp format for the bracketed log format
+static DATETIME_FORMAT: OnceCell<String> = OnceCell::new();
+
+/// Set global timestamp format, used by the bracketed log format
+pub(crate) fn set_datetime_format(format: String) -> anyhow::Result<()> {
+    DATETIME_FORMAT
+        .set(format)
+        .map_err(|f| anyhow::anyhow!("Log timestamp format already set to {f:?}"))
+}
+
+/// Get formatting function for a log format
+pub(crate) fn format_function(format: LogFormat) -> FormatFunction {
+    match format {
+        LogFormat::Bracketed => bracketed,
+        LogFormat::Rfc3339 => rfc3339,
+        LogFormat::Logfmt => logfmt,
+    }
+}
+
+/// Point a stable symlink to the current log file, replacing any previous link or file
+pub(crate) fn link_current_log(link_path: &Path, target_path: &Path) -> anyhow::Result<()> {
+    // Dangling links do not exist for `Path::exists`
+    if link_path.symlink_metadata().is_ok() {
+        fs::remove_file(link_path)
+            .with_context(|| format!("Failed to remove old symlink {}", link_path.display()))?;
+    }
+    symlink(target_path, link_path).with_context(|| {
+        format!(
+            "Failed to create symlink {} -> {}",
+            link_path.display(),
+            target_path.display()
+        )
+    })
+}
+
+/// `[datetime] [LEVEL] message` with a custom datetime format
+fn bracketed(
+    writer: &mut dyn Write,
+    now: &mut DeferredNow,
+    record: &log::Record,
+) -> io::Result<()> {
+    write!(
+        writer,
+        "[{}] [{}] {}",
+        now.format(
+            DATETIME_FORMAT
+                .get()
+                .map_or(DEFAULT_DATETIME_FORMAT, String::as_str)
+        ),
+        record.level(),
+        record.args()
+    )
+}
+
+/// `2024-01-31T13:14:15.678+01:00 LEVEL message`
+fn rfc3339(writer: &mut dyn Write, now: &mut DeferredNow, record: &log::Record) -> io::Result<()> {
+    write!(
+        writer,
+        "{} {:<5} {}",
+        now.format_rfc3339(),
+        record.level(),
+        record.args()
+    )
+}
+
+/// `ts=2024-01-31T13:14:15.678+01:00 level=info msg="message"`
+fn logfmt(writer: &mut dyn Write, now: &mut DeferredNow, record: &log::Record) -> io::Result<()> {
+    write!(
+        writer,
+        "ts={} level={} target={} msg={}",
+        now.format_rfc3339(),
+        record.level().as_str().to_ascii_lowercase(),
+        logfmt_value(record.target()),
+        logfmt_value(&record.args().to_string())
+    )
+}
+
+/// Quote and escape a logfmt value if needed
+fn logfmt_value(s: &str) -> String {
+    if !s.is_empty()
+        && !s
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '=' | '"' | '\\'))
+    {
+        return s.to_owned();
+    }
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.extend(c.escape_unicode()),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_current_log() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let link_path = dir.path().join("hddfancontrol.log");
+        let target_path = dir.path().join("logs/log_rCURRENT.log");
+
+        // Dangling link of a previous run
+        symlink(dir.path().join("old.log"), &link_path).unwrap();
+        link_current_log(&link_path, &target_path).unwrap();
+        assert_eq!(fs::read_link(&link_path).unwrap(), target_path);
+
+        fs::remove_file(&link_path).unwrap();
+        fs::write(&link_path, "stale").unwrap();
+        link_current_log(&link_path, &target_path).unwrap();
+        assert_eq!(fs::read_link(&link_path).unwrap(), target_path);
+
+        assert!(link_current_log(&dir.path().join("missing/link.log"), &target_path).is_err());
+    }
+
+    #[test]
+    fn test_logfmt_value() {
+        assert_eq!(logfmt_value("hddfancontrol"), "hddfancontrol");
+        assert_eq!(logfmt_value(""), "\"\"");
+        assert_eq!(
+            logfmt_value("Max drive temperature: 40°C"),
+            "\"Max drive temperature: 40°C\""
+        );
+        assert_eq!(logfmt_value("a=b"), "\"a=b\"");
+        assert_eq!(
+            logfmt_value("path \"/dev/sda\"\nnext"),
+            "\"path \\\"/dev/sda\\\"\\nnext\""
+        );
+        assert_eq!(logfmt_value("C:\\x"), "\"C:\\\\x\"");
+        assert_eq!(logfmt_value("\u{1b}[1mbold\0"), "\"\\u{1b}[1mbold\\u{0}\"");
+    }
+}
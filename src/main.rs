@@ -6,48 +6,109 @@
 )]
 
 use std::{
+    cmp::{max, min},
+    collections::HashMap,
+    env,
+    ffi::OsString,
     ops::Range,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{
         Arc,
         atomic::{AtomicBool, Ordering},
         mpsc,
     },
+    thread,
     time::{Duration, Instant},
 };
 
-use std::io::Write;
 use std::fs;
-use std::os::unix::fs::symlink;
 
 use anyhow::Context as _;
 use byte_unit::Byte;
 use chrono::Local;
-use clap::Parser as _;
+use control::ControlSocket;
 use device::Hwmon;
 use exit::ExitHook;
 use fan::Speed;
-use flexi_logger::{Logger, FileSpec, Criterion, Naming, Cleanup, Duplicate};
-use once_cell::sync::OnceCell;
+use flexi_logger::{Cleanup, Duplicate, FileSpec, Logger, Naming};
 use probe::Temp;
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM},
+    iterator::Signals,
+};
 
-static FORMAT_STRING: OnceCell<String> = OnceCell::new();
-
+mod autocurve;
 mod cl;
+mod config;
+mod control;
+mod critical;
+mod csvlog;
 mod device;
 mod exit;
 mod fan;
+mod fancontrol;
+#[cfg(feature = "influx")]
+mod influx;
+#[cfg(feature = "journald")]
+mod journald;
+mod logging;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod monitor;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod pid;
 mod probe;
+mod profile;
 mod pwm;
+mod simulate;
+mod snapshot;
+mod state;
+mod summary;
 mod sysfs;
+#[cfg(feature = "systemd")]
+mod systemd;
 #[cfg(test)]
 mod tests;
+mod thresholds;
+mod trend;
+mod zone;
 
-use crate::{device::Drive, fan::Fan, probe::DeviceTempProber};
+use crate::{device::DriveState, fan::Fan, probe::DeviceTempProber as _, trend::RateTracker};
+
+/// Parse drive related settings again, from command line and configuration file
+fn reload_drive_settings(argv: Vec<OsString>) -> anyhow::Result<monitor::DriveSettings> {
+    match config::parse_args(argv)?.command {
+        cl::Command::Daemon {
+            drives,
+            drive_temp_range,
+            zones,
+            temp_offsets,
+            drive_weights,
+            never_wake,
+            temp_unit,
+            ..
+        } => Ok(monitor::DriveSettings {
+            selectors: drives,
+            temp_ranges: drive_temp_range
+                .into_iter()
+                .map(|r| r.into_celsius(temp_unit))
+                .collect(),
+            zones,
+            temp_offsets: temp_offsets
+                .into_iter()
+                .map(|o| o.into_celsius(temp_unit))
+                .collect(),
+            weights: drive_weights,
+            never_wake,
+        }),
+        _ => anyhow::bail!("Not a daemon command"),
+    }
+}
 
 /// Interruptible sleep
-fn sleep(dur: Duration, exit_rx: &mpsc::Receiver<()>) {
-    let _ = exit_rx.recv_timeout(dur);
+fn sleep(dur: Duration, wake_rx: &mpsc::Receiver<()>) {
+    let _ = wake_rx.recv_timeout(dur);
 }
 
 #[cfg(feature = "gen-man-pages")]
@@ -65,23 +126,76 @@ fn main() -> anyhow::Result<()> {
 #[expect(clippy::too_many_lines)]
 fn main() -> anyhow::Result<()> {
     // Parse cl args
-    let args = cl::Args::parse();
+    let cl_argv: Vec<OsString> = env::args_os().collect();
+    let args = match config::parse_args(cl_argv.clone()) {
+        Ok(args) => args,
+        Err(e) => match e.downcast::<clap::Error>() {
+            Ok(e) => e.exit(),
+            Err(e) => return Err(e),
+        },
+    };
 
-   // Validate the provided datetime format
+    // Validate the provided datetime format
     if let Err(e) = std::panic::catch_unwind(|| {
         let _ = Local::now().format(&args.log_datetime_format).to_string();
     }) {
-        anyhow::bail!("Invalid datetime format string '{}': {e:?}", args.log_datetime_format);
+        anyhow::bail!(
+            "Invalid datetime format string '{}': {e:?}",
+            args.log_datetime_format
+        );
+    }
+
+    let log_spec = args
+        .log_filter
+        .clone()
+        .unwrap_or_else(|| args.verbosity.to_string());
+
+    if !matches!(args.command, cl::Command::Daemon { .. }) {
+        Logger::try_with_str(&log_spec)
+            .with_context(|| format!("Invalid log filter {log_spec:?}"))?
+            .log_to_stderr()
+            .start()?;
     }
 
+    anyhow::ensure!(
+        !args.dry_run
+            || !matches!(
+                args.command,
+                cl::Command::PwmTest { .. } | cl::Command::VerifyThresholds { .. }
+            ),
+        "Dry run is not supported by fan test commands"
+    );
+
     match args.command {
-        cl::Command::PwmTest { pwm } => {
+        cl::Command::PwmTest {
+            pwm,
+            pwm_invert,
+            pwm_max,
+            pwm_enable_value,
+            thresholds_file,
+            json,
+        } => {
+            let mut thresholds_cache = thresholds_file
+                .as_deref()
+                .map(thresholds::ThresholdsCache::load)
+                .transpose()?;
+            let pwm = if pwm.iter().any(|p| p == Path::new(cl::PWM_AUTO)) {
+                pwm::discover(false, |p| {
+                    cl::PwmEnableValue::find(&pwm_enable_value, p)
+                        .unwrap_or_else(|| pwm::ControlMode::Software.into())
+                })?
+            } else {
+                pwm
+            };
             for pwm_path in &pwm {
                 let fan = Fan::new(&cl::PwmSettings {
                     filepath: pwm_path.to_owned(),
-                    thresholds: fan::Thresholds::default(),
+                    thresholds: None,
+                    inverted: pwm_invert.contains(pwm_path),
                 })
-                .context("Failed to setup fan")?;
+                .context("Failed to setup fan")?
+                .with_pwm_max(cl::PwmMax::find(&pwm_max, pwm_path))
+                .with_pwm_enable_value(cl::PwmEnableValue::find(&pwm_enable_value, pwm_path));
                 let rpm_path = fan
                     .resolve_rpm_path()
                     .context("Failed to resolve fan rpm filepath")?;
@@ -90,144 +204,667 @@ fn main() -> anyhow::Result<()> {
                     .context("Failed to setup fan with rpm filepath")?;
                 log::info!("Testing fan {fan}, this may take a long time");
                 match fan.test() {
-                    Ok(t) => {
-                        log::info!("Fan {fan}] start/stop thresholds: {t}");
+                    Ok(result) => {
+                        log::info!(
+                            "Fan {fan} start/stop thresholds: {}, RPM at full speed: {}",
+                            result.thresholds,
+                            result.max_rpm
+                        );
+                        if json {
+                            let mut output = serde_json::to_value(&result)?;
+                            if let Some(fields) = output.as_object_mut() {
+                                fields.insert("pwm".to_owned(), serde_json::json!(pwm_path));
+                            }
+                            println!("{output}");
+                        }
+                        if let (Some(cache), Some(path)) =
+                            (thresholds_cache.as_mut(), thresholds_file.as_deref())
+                        {
+                            cache.insert(pwm_path, result.thresholds);
+                            cache.save(path)?;
+                        }
                     }
                     Err(e) => {
                         log::error!("Fan {fan} test failed: {e}");
+                        if json {
+                            println!(
+                                "{}",
+                                serde_json::json!({ "pwm": pwm_path, "error": format!("{e:#}") })
+                            );
+                        }
                     }
                 }
             }
         }
+        cl::Command::VerifyThresholds {
+            pwm,
+            pwm_invert,
+            pwm_max,
+            pwm_enable_value,
+        } => {
+            let _exit_hook = ExitHook::new(
+                pwm.iter()
+                    .map(|p| {
+                        pwm::Pwm::new(&p.filepath).map(|w| {
+                            w.with_inverted(pwm_invert.contains(&p.filepath))
+                                .with_max(cl::PwmMax::find(&pwm_max, &p.filepath))
+                                .with_manual_mode(cl::PwmEnableValue::find(
+                                    &pwm_enable_value,
+                                    &p.filepath,
+                                ))
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()
+                    .context("Failed to setup PWMs for exit hook")?,
+                true,
+                true,
+            )?;
+            let mut failed = 0_usize;
+            for pwm_settings in &pwm {
+                let thresholds = pwm_settings.thresholds.as_ref().ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Missing start/stop values for PWM {}",
+                        pwm_settings.filepath.display()
+                    )
+                })?;
+                let fan = Fan::new(&cl::PwmSettings {
+                    filepath: pwm_settings.filepath.clone(),
+                    thresholds: None,
+                    inverted: pwm_invert.contains(&pwm_settings.filepath),
+                })
+                .context("Failed to setup fan")?
+                .with_pwm_max(cl::PwmMax::find(&pwm_max, &pwm_settings.filepath))
+                .with_pwm_enable_value(cl::PwmEnableValue::find(
+                    &pwm_enable_value,
+                    &pwm_settings.filepath,
+                ));
+                let rpm_path = fan
+                    .resolve_rpm_path()
+                    .context("Failed to resolve fan rpm filepath")?;
+                let mut fan = fan
+                    .with_rpm_file(&rpm_path)
+                    .context("Failed to setup fan with rpm filepath")?;
+                log::info!(
+                    "Verifying fan {fan} thresholds {thresholds}, this may take a long time"
+                );
+                match fan.verify(thresholds) {
+                    Ok(issues) if issues.is_empty() => {
+                        log::info!("Fan {fan}: PASS");
+                    }
+                    Ok(issues) => {
+                        failed += 1;
+                        log::error!(
+                            "Fan {fan}: FAIL, {}, run 'pwm-test' again to find new thresholds",
+                            issues.join(", ")
+                        );
+                    }
+                    Err(e) => {
+                        failed += 1;
+                        log::error!("Fan {fan}: FAIL, {e}");
+                    }
+                }
+            }
+            anyhow::ensure!(failed == 0, "{failed} fan(s) failed verification");
+        }
+        cl::Command::ProgramAutoCurve {
+            pwm: pwm_paths,
+            drive_temp_curve,
+            curve_file,
+            temp_unit,
+            pwm_invert,
+            pwm_max,
+            pwm_auto_enable_value,
+        } => {
+            let curve = match curve_file {
+                Some(path) => fan::Curve::from_file(&path)?,
+                None => drive_temp_curve.context("Missing fan curve")?,
+            }
+            .into_celsius(temp_unit);
+            curve.check().context("Invalid drive temperature curve")?;
+            for pwm_path in &pwm_paths {
+                let pwm = pwm::Pwm::new(pwm_path)
+                    .context("Failed to setup PWM")?
+                    .with_inverted(pwm_invert.contains(pwm_path))
+                    .with_max(cl::PwmMax::find(&pwm_max, pwm_path));
+                autocurve::program(
+                    &pwm,
+                    &curve,
+                    cl::PwmEnableValue::find(&pwm_auto_enable_value, pwm_path)
+                        .unwrap_or(pwm::AUTO_CURVE_MODE),
+                    args.dry_run,
+                )?;
+            }
+        }
+        cl::Command::ImportFancontrol { path, output } => {
+            let import = fancontrol::Config::from_file(&path)?.import()?;
+            for warning in &import.warnings {
+                log::warn!("{warning}");
+            }
+            let env_file = import.to_env_file(&path);
+            if let Some(output) = output {
+                fs::write(&output, env_file)
+                    .with_context(|| format!("Failed to write {}", output.display()))?;
+            } else {
+                print!("{env_file}");
+            }
+        }
+        cl::Command::List {
+            drives: drive_selectors,
+            hddtemp_daemon_port,
+            hddtemp_daemon,
+            temp_command,
+            temp_command_timeout,
+        } => {
+            let hddtemp_daemons = probe::HddtempDaemons::new(&hddtemp_daemon, hddtemp_daemon_port);
+            let temp_command = temp_command.map(|command| probe::CommandMethod {
+                command,
+                timeout: *temp_command_timeout,
+            });
+            let drive_paths: Vec<PathBuf> = if drive_selectors.is_empty() {
+                device::Drive::discover(Path::new("/dev")).context("Failed to list drives")?
+            } else {
+                drive_selectors
+                    .iter()
+                    .map(|s| {
+                        s.to_drive_paths()
+                            .with_context(|| format!("Failed to match drives for selector {s}"))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?
+                    .into_iter()
+                    .flatten()
+                    .collect()
+            };
+            let mut rows = vec![[
+                "DRIVE".to_owned(),
+                "NAME".to_owned(),
+                "PROBING METHOD".to_owned(),
+                "PROBES ASLEEP".to_owned(),
+            ]];
+            let mut failed = 0_usize;
+            for drive_path in &drive_paths {
+                let row = match monitor::setup_drive(
+                    drive_path,
+                    &hddtemp_daemons,
+                    temp_command.as_ref(),
+                    cl::NvmeSensor::default(),
+                ) {
+                    Ok((drive, Some(selected))) if selected.pending => {
+                        failed += 1;
+                        [
+                            drive.dev_path.display().to_string(),
+                            drive.to_string(),
+                            format!("{} (pending)", selected.method),
+                            "-".to_owned(),
+                        ]
+                    }
+                    Ok((drive, Some(selected))) => [
+                        drive.dev_path.display().to_string(),
+                        drive.to_string(),
+                        selected.method,
+                        if selected.supports_probing_sleeping {
+                            "yes"
+                        } else {
+                            "no"
+                        }
+                        .to_owned(),
+                    ],
+                    Ok((drive, None)) => {
+                        failed += 1;
+                        [
+                            drive.dev_path.display().to_string(),
+                            drive.to_string(),
+                            "none".to_owned(),
+                            "-".to_owned(),
+                        ]
+                    }
+                    Err(e) => {
+                        failed += 1;
+                        [
+                            drive_path.display().to_string(),
+                            format!("{e:#}"),
+                            "none".to_owned(),
+                            "-".to_owned(),
+                        ]
+                    }
+                };
+                rows.push(row);
+            }
+            let widths: Vec<usize> = (0..4)
+                .map(|col| {
+                    rows.iter()
+                        .filter_map(|r| r.get(col))
+                        .map(String::len)
+                        .max()
+                        .unwrap_or(0)
+                })
+                .collect();
+            for row in &rows {
+                let line: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{cell:width$}"))
+                    .collect();
+                println!("{}", line.join("  ").trim_end());
+            }
+            anyhow::ensure!(
+                failed == 0,
+                "{failed} drive(s) have no usable probing method"
+            );
+        }
+        cl::Command::Status {
+            control_socket,
+            history,
+        } => {
+            let status =
+                control::query(&control_socket, if history { "history" } else { "status" })?;
+            println!("{}", serde_json::to_string_pretty(&status)?);
+        }
+        cl::Command::Simulate {
+            drive_temp_range,
+            drive_temp_curve,
+            curve_file,
+            curve_shape,
+            min_fan_speed_prct,
+            temp_unit,
+            temps,
+            from,
+            to,
+            step,
+            temps_csv,
+            csv_column,
+        } => {
+            let drive_temp_curve = match curve_file {
+                Some(path) => Some(fan::Curve::from_file(&path)?),
+                None => drive_temp_curve,
+            };
+            // Temperatures are in Celsius from here
+            let model = if let Some(curve) = drive_temp_curve {
+                let curve = curve.into_celsius(temp_unit);
+                curve.check().context("Invalid drive temperature curve")?;
+                simulate::Model::Curve(curve)
+            } else {
+                let (range, selector_ranges) = cl::DriveTempRange::split(
+                    drive_temp_range
+                        .into_iter()
+                        .map(|r| r.into_celsius(temp_unit))
+                        .collect(),
+                )?;
+                anyhow::ensure!(
+                    selector_ranges.is_empty(),
+                    "Drive specific temperature ranges can not be simulated"
+                );
+                simulate::Model::Range {
+                    range,
+                    shape: curve_shape,
+                }
+            };
+            let min_speed = Speed::try_from(f64::from(min_fan_speed_prct) / 100.0)
+                .with_context(|| format!("Invalid speed {min_fan_speed_prct}%"))?;
+            let temps = if let Some(temps_csv) = temps_csv {
+                simulate::read_csv_file(&temps_csv, csv_column.as_deref())?
+            } else if let (Some(from), Some(to)) = (from, to) {
+                simulate::ramp(from, to, step)?
+            } else {
+                temps
+            };
+            for temp in temps.into_iter().map(|t| temp_unit.to_celsius(t)) {
+                println!(
+                    "{:.1}\t{}",
+                    temp_unit.display(temp),
+                    model.speed(temp, min_speed)
+                );
+            }
+        }
+        cl::Command::Completions { shell } => {
+            use clap::CommandFactory as _;
+            let mut cmd = cl::Args::command();
+            let name = cmd.get_name().to_owned();
+            clap_complete::generate(shell, &mut cmd, name, &mut std::io::stdout());
+        }
         cl::Command::Daemon {
             drives: drive_selectors,
             hddtemp_daemon_port,
+            hddtemp_daemon,
+            temp_command,
+            temp_command_timeout,
+            nvme_sensors,
             pwm,
+            pwm_invert,
+            pwm_max,
+            pwm_enable_value,
+            thresholds_file,
             drive_temp_range,
+            drive_temp_curve,
+            curve_file,
+            curve_shape,
+            profile,
+            profile_temp_range,
+            profile_temp_curve,
+            profile_min_fan_speed_prct,
+            hysteresis_temp,
+            temp_smoothing_alpha,
+            temp_window,
+            window_stat,
+            temp_decay,
             min_fan_speed_prct,
+            min_fan_speed_raw,
+            auto_min_speed,
+            max_speed_step_prct,
+            no_ramp_emergency,
+            speed_deadband_prct,
             interval,
+            interval_min,
+            interval_max,
+            startup_poll,
+            drive_state_cache_ttl,
+            skip_idle_sleeping,
+            control_mode,
+            pid_setpoint,
+            pid_gains,
+            pid_kp,
+            pid_ki,
+            pid_kd,
+            derivative_gain,
+            blend_drive_weight,
+            blend_hwmon_weight,
+            post_wake_ignored_samples,
+            sleep_temp_hold,
+            sleep_temp_decay,
+            aggregation,
+            spun_down_behavior,
+            fan_off_below,
+            fan_off_hysteresis,
+            startup_ramp,
+            spinup_kick_ms,
+            reassert_interval,
+            drive_rescan_iterations,
+            verify_writes,
+            verify_writes_tolerance,
+            min_fan_runtime,
+            min_fan_offtime,
             hwmons,
+            temp_scales,
+            zones: zone_settings,
+            temp_offsets,
+            drive_weights,
+            never_wake,
             restore_fan_settings,
+            restore_enable_mode,
+            print_config,
+            once,
+            once_restore,
+            shutdown_timeout,
+            max_consecutive_errors,
+            on_probe_error,
+            probe_error_threshold,
+            probe_timeout,
+            probe_retries,
+            probe_retry_delay,
+            drive_critical_temp,
+            critical_command,
+            respect_drive_thresholds,
+            monitor_rpm,
+            log_rpm,
+            rpm_fail_threshold,
+            rpm_fail_max_speed,
+            temp_unit,
+            log_backend,
             log_dir,
+            log_subdir,
+            no_log_subdir,
+            log_basename,
+            no_log_symlink,
+            no_stdout,
+            stdout_only,
             log_max_size,
             log_retain,
+            log_rotate,
             log_datetime_format,
+            log_format,
+            control_socket,
+            history_size,
+            #[cfg(feature = "metrics")]
+            metrics_listen,
+            #[cfg(feature = "influx")]
+            influx_udp,
+            #[cfg(feature = "mqtt")]
+            mqtt_broker,
+            #[cfg(feature = "mqtt")]
+            mqtt_topic_prefix,
+            #[cfg(feature = "mqtt")]
+            mqtt_discovery,
+            state_file,
+            csv_log,
+            csv_log_max_size,
+            ..
         } => {
             // Configure logging
-            let log_max_size_bytes = Byte::parse_str(&log_max_size, true)
-                .with_context(|| format!("Invalid value for --log-max-size: {}", log_max_size))?
-                .as_u64();
-
-            // Set global timestamp format
-            FORMAT_STRING.set(log_datetime_format.clone()).unwrap();
-
-            // Custom log formatter
-            fn my_format(
-                writer: &mut dyn Write,
-                now: &mut flexi_logger::DeferredNow,
-                record: &log::Record,
-            ) -> std::io::Result<()> {
-                write!(
-                    writer,
-                    "[{}] [{}] {}",
-                    now.format(&FORMAT_STRING.get().unwrap()),
-                    record.level(),
-                    record.args()
-                )
-            }
+            match log_backend {
+                #[cfg(feature = "journald")]
+                cl::LogBackend::Journald => {
+                    anyhow::ensure!(
+                        !no_stdout && !stdout_only,
+                        "--no-stdout and --stdout-only do not apply to the journald log backend"
+                    );
+                    Logger::try_with_str(&log_spec)
+                        .with_context(|| format!("Invalid log filter {log_spec:?}"))?
+                        .log_to_writer(Box::new(journald::JournalWriter::new()?))
+                        .start()?;
+                }
+                cl::LogBackend::File => {
+                    let log_max_size_bytes = Byte::parse_str(&log_max_size, true)
+                        .with_context(|| {
+                            format!("Invalid value for --log-max-size: {log_max_size}")
+                        })?
+                        .as_u64();
 
-            // Ensure `logs/` subdirectory exists
-            let logs_dir = log_dir.join("logs");
-            fs::create_dir_all(&logs_dir)
-                .with_context(|| format!("Failed to create logs directory {}", logs_dir.display()))?;
-
-            // Prepare FileSpec for rotated files in `logs/`
-            let file_spec = FileSpec::default()
-                .directory(&logs_dir)
-                .basename("log")      // produces files like log_rCURRENT.log, log_r00001.log, ...
-                .suffix("log")
-                .suppress_timestamp();
-
-            // Build and start the logger:
-            //    - write into `logs/` with rotation
-            //    - duplicate all levels to stdout
-            //    - create a stable symlink in the parent dir
-            let logger = Logger::try_with_str(args.verbosity.to_string())?
-                .format(my_format)
-                .log_to_file(file_spec)
-                .rotate(
-                    Criterion::Size(log_max_size_bytes),
-                    Naming::Numbers,
-                    Cleanup::KeepLogFiles(log_retain),
-                )
-                .duplicate_to_stdout(Duplicate::All);
+                    logging::set_datetime_format(log_datetime_format)?;
 
-            // start the logger
-            logger.start()?;
+                    if stdout_only {
+                        Logger::try_with_str(&log_spec)
+                            .with_context(|| format!("Invalid log filter {log_spec:?}"))?
+                            .format(logging::format_function(log_format))
+                            .log_to_stdout()
+                            .start()?;
+                    } else {
+                        let logs_dir = if no_log_subdir {
+                            log_dir.clone()
+                        } else {
+                            log_dir.join(&log_subdir)
+                        };
+                        fs::create_dir_all(&logs_dir).with_context(|| {
+                            format!("Failed to create logs directory {}", logs_dir.display())
+                        })?;
 
-            // use the local `log_dir` you destructured above
-            let link_path   = log_dir.join("hddfancontrol.log");
-            // target_path: /var/log/hddfancontrol/logs/log_rCURRENT.log
-            let target_path = logs_dir.join("log_rCURRENT.log");
+                        // Produces files like log_rCURRENT.log, log_r00001.log, ...
+                        let file_spec = FileSpec::default()
+                            .directory(&logs_dir)
+                            .basename(&log_basename)
+                            .suffix("log")
+                            .suppress_timestamp();
 
-            // remove existing link or stale file if present
-            if link_path.exists() {
-                fs::remove_file(&link_path)
-                    .with_context(|| format!("Failed to remove old symlink {}", link_path.display()))?;
-            }
+                        // Write into the logs directory with rotation, and duplicate all levels to
+                        // stdout unless disabled
+                        Logger::try_with_str(&log_spec)
+                            .with_context(|| format!("Invalid log filter {log_spec:?}"))?
+                            .format(logging::format_function(log_format))
+                            .log_to_file(file_spec)
+                            .rotate(
+                                log_rotate.criterion(log_max_size_bytes),
+                                Naming::Numbers,
+                                Cleanup::KeepLogFiles(log_retain),
+                            )
+                            .duplicate_to_stdout(if no_stdout {
+                                Duplicate::None
+                            } else {
+                                Duplicate::All
+                            })
+                            .start()?;
 
-            // create a new symlink `hddfancontrol.log` → `logs/log_rCURRENT.log`
-            symlink(&target_path, &link_path)
-                .with_context(|| {
-                    format!(
-                        "Failed to create symlink {} -> {}",
-                        link_path.display(),
-                        target_path.display()
-                    )
-                })?;
+                        if !no_log_symlink {
+                            // With numbered naming the active file keeps this name whatever the
+                            // rotation criterion
+                            let target_path = logs_dir.join(format!("{log_basename}_rCURRENT.log"));
+                            if let Err(e) = logging::link_current_log(
+                                &log_dir.join("hddfancontrol.log"),
+                                &target_path,
+                            ) {
+                                log::warn!("{e:#}, pass --no-log-symlink to not create it");
+                            }
+                        }
+                    }
+                }
+            }
 
-            // DEBUG: confirm symlink creation
-            println!("Symlink created: {} -> {}", link_path.display(), target_path.display());
+            let intervals = interval_min.zip(interval_max).map(|(min, max)| *min..*max);
+            if let Some(intervals) = intervals.as_ref() {
+                anyhow::ensure!(
+                    intervals.start <= intervals.end,
+                    "--interval-min must not be longer than --interval-max"
+                );
+            }
+            // Longest interval, for checks depending on it
+            let interval = intervals.as_ref().map_or(*interval, |r| r.end);
 
-            #[expect(clippy::indexing_slicing)] // guaranteed by clap's numl_args
-            let drive_temp_range = Range {
-                start: drive_temp_range[0],
-                end: drive_temp_range[1],
+            let drive_temp_curve = match curve_file {
+                Some(path) => Some(fan::Curve::from_file(&path)?),
+                None => drive_temp_curve,
             };
-            let drive_paths: Vec<PathBuf> = drive_selectors
+            // Temperatures are in Celsius from here
+            let drive_temp_range: Vec<_> = drive_temp_range
                 .into_iter()
-                .map(|s| {
-                    s.to_drive_paths()
-                        .with_context(|| format!("Failed to match drives for selector {s}"))
-                })
-                .collect::<anyhow::Result<Vec<_>>>()?
+                .map(|r| r.into_celsius(temp_unit))
+                .collect();
+            let drive_temp_curve = drive_temp_curve.map(|c| c.into_celsius(temp_unit));
+            if let Some(drive_temp_curve) = drive_temp_curve.as_ref() {
+                drive_temp_curve
+                    .check()
+                    .context("Invalid drive temperature curve")?;
+            }
+            let profiles = profile::profiles(
+                profile,
+                profile_temp_range,
+                profile_temp_curve,
+                &profile_min_fan_speed_prct,
+                temp_unit,
+            )?;
+            let mut active_profile_name: Option<String> = None;
+            let hysteresis_temp = temp_unit.delta_to_celsius(hysteresis_temp);
+            let hwmons: Vec<_> = hwmons
                 .into_iter()
-                .flatten()
+                .map(|h| h.into_celsius(temp_unit))
                 .collect();
-            anyhow::ensure!(!drive_paths.is_empty(), "No drive match");
-            let drives: Vec<Drive> = drive_paths
-                .iter()
-                .map(|path| Drive::new(path))
-                .collect::<anyhow::Result<_>>()
-                .context("Failed to setup drives")?;
-            let mut drive_probers: Vec<(Box<dyn DeviceTempProber>, bool)> = drives
+            for hwmon in &hwmons {
+                if let Some(range) = hwmon.temp.as_ref() {
+                    cl::check_temp_range(range).with_context(|| {
+                        format!(
+                            "Invalid hwmon {} temperature range",
+                            hwmon.filepath.display()
+                        )
+                    })?;
+                }
+            }
+            let drive_critical_temp = drive_critical_temp.map(|t| temp_unit.to_celsius(t));
+            let pid_setpoint =
+                pid_setpoint.map_or(cl::DEFAULT_PID_SETPOINT, |t| temp_unit.to_celsius(t));
+            let mut critical_command = critical_command
+                .zip(drive_critical_temp)
+                .map(|(c, t)| critical::CriticalCommand::new(c, t, temp_unit));
+            let fan_off_below = fan_off_below.map(|t| temp_unit.to_celsius(t));
+            let fan_off_hysteresis = temp_unit.delta_to_celsius(fan_off_hysteresis);
+            let temp_offsets: Vec<_> = temp_offsets
+                .into_iter()
+                .map(|o| o.into_celsius(temp_unit))
+                .collect();
+            let hwmon_offsets: Vec<Temp> = (0..hwmons.len())
+                .map(|hwmon_idx| {
+                    temp_offsets
+                        .iter()
+                        .find(|o| o.hwmon_index(&hwmons) == Some(hwmon_idx))
+                        .map_or(0.0, |o| o.offset)
+                })
+                .collect();
+
+            let thresholds_cache = thresholds_file
+                .as_deref()
+                .map(thresholds::ThresholdsCache::load)
+                .transpose()?
+                .unwrap_or_default();
+            let pwm: Vec<cl::PwmSettings> = if pwm.iter().any(cl::PwmSettings::is_auto) {
+                pwm::discover(args.dry_run, |p| {
+                    cl::PwmEnableValue::find(&pwm_enable_value, p)
+                        .unwrap_or_else(|| pwm::ControlMode::Software.into())
+                })?
+                .into_iter()
+                .map(|filepath| cl::PwmSettings {
+                    filepath,
+                    thresholds: None,
+                    inverted: false,
+                })
+                .collect()
+            } else {
+                pwm
+            };
+            // Thresholds given or cached, not defaults
+            let measured_thresholds: Vec<bool> = pwm
                 .iter()
-                .zip(drive_paths.iter())
-                .map(|(drive, path)| {
-                    probe::prober(drive, hddtemp_daemon_port)
-                        .with_context(|| format!("Failed to setup prober for drive {drive}"))?
-                        .ok_or_else(|| {
-                            anyhow::anyhow!("No probing method found for drive {path:?}")
-                        })
+                .map(|p| p.thresholds.is_some() || thresholds_cache.contains(&p.filepath))
+                .collect();
+            let pwm: Vec<_> = pwm
+                .into_iter()
+                .map(|p| cl::PwmSettings {
+                    inverted: pwm_invert.contains(&p.filepath),
+                    ..thresholds_cache.resolve(p)
                 })
-                .collect::<anyhow::Result<_>>()
-                .context("Failed to setup drive probers")?;
+                .collect();
 
+            let drive_options = monitor::DriveOptions {
+                hddtemp_daemons: probe::HddtempDaemons::new(&hddtemp_daemon, hddtemp_daemon_port),
+                temp_command: temp_command.map(|command| probe::CommandMethod {
+                    command,
+                    timeout: *temp_command_timeout,
+                }),
+                temp_smoothing_alpha,
+                hysteresis_temp,
+                temp_unit,
+                sleep_temp_hold: sleep_temp_hold.map_or(Duration::ZERO, Into::into),
+                sleep_temp_decay: temp_unit.delta_to_celsius(sleep_temp_decay),
+                temp_window: temp_window.map_or(Duration::ZERO, Into::into),
+                window_stat,
+                temp_decay: temp_decay.map(|rate| temp_unit.delta_to_celsius(rate)),
+                nvme_sensors,
+                respect_drive_thresholds,
+            };
+            let mut drive_settings = monitor::DriveSettings {
+                selectors: drive_selectors,
+                temp_ranges: drive_temp_range,
+                zones: zone_settings,
+                temp_offsets,
+                weights: drive_weights,
+                never_wake,
+            };
+            let mut monitored_drives =
+                monitor::MonitoredDrives::new(&drive_settings, &drive_options, &hwmons, &pwm)?;
+
+            for temp_scale in &temp_scales {
+                anyhow::ensure!(
+                    temp_scale.hwmon_index(&hwmons).is_some(),
+                    "Temperature scale input {} is not a hwmon",
+                    temp_scale.input
+                );
+            }
             let mut hwmon_and_range: Vec<(Hwmon, Range<Temp>)> = hwmons
                 .iter()
-                .map(|h| {
+                .enumerate()
+                .map(|(hwmon_idx, h)| {
+                    let scale = temp_scales
+                        .iter()
+                        .find(|s| s.hwmon_index(&hwmons) == Some(hwmon_idx))
+                        .map(|s| s.scale)
+                        .unwrap_or_default();
                     let hwm = Hwmon::new(&h.filepath)
-                        .with_context(|| format!("Failed to setup hwmon {:?}", h.filepath))?;
+                        .with_context(|| format!("Failed to setup hwmon {}", h.filepath.display()))?
+                        .with_scale(scale);
                     let range = h.temp.as_ref().map_or_else(
                         || -> anyhow::Result<_> {
                             // Default range
@@ -237,9 +874,9 @@ fn main() -> anyhow::Result<()> {
                                 )
                             })?;
                             log::info!(
-                                "Device temperature range set to {}-{}°C",
-                                range.start,
-                                range.end
+                                "Device temperature range set to {}-{}",
+                                temp_unit.display(range.start),
+                                temp_unit.display(range.end)
                             );
                             Ok(range)
                         },
@@ -249,95 +886,1203 @@ fn main() -> anyhow::Result<()> {
                 })
                 .collect::<anyhow::Result<_>>()?;
 
+            // With a minimum PWM value, the minimum speed is per fan
+            let min_fan_speed_prct = if min_fan_speed_raw.is_some() {
+                0
+            } else {
+                min_fan_speed_prct
+            };
             let min_fan_speed = Speed::try_from(f64::from(min_fan_speed_prct) / 100.0)
                 .with_context(|| format!("Invalid speed {min_fan_speed_prct}%"))?;
+            let blend_weights = blend_drive_weight.zip(blend_hwmon_weight);
+            let effective_config = summary::EffectiveConfig {
+                drives: monitored_drives
+                    .drives
+                    .iter()
+                    .map(|d| summary::DriveSummary {
+                        name: d.drive.to_string(),
+                        path: d.drive.dev_path.clone(),
+                        method: d.method.clone(),
+                        temp_range: d.temp_range.clone(),
+                        temp_offset: d.temp_offset,
+                        weight: d.weight,
+                    })
+                    .collect(),
+                hwmons: hwmon_and_range
+                    .iter()
+                    .map(|(hwm, range)| summary::HwmonSummary {
+                        name: hwm.to_string(),
+                        temp_range: range.clone(),
+                    })
+                    .collect(),
+                fans: pwm
+                    .iter()
+                    .map(|p| summary::FanSummary {
+                        path: p.filepath.clone(),
+                        thresholds: p.thresholds.clone(),
+                        inverted: p.inverted,
+                    })
+                    .collect(),
+                zones: monitored_drives
+                    .zones
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+                curve: drive_temp_curve.clone(),
+                curve_shape,
+                control_mode,
+                interval,
+                intervals: intervals.clone(),
+                min_fan_speed,
+                min_fan_speed_raw,
+                blend_weights,
+                temp_unit,
+            };
+            if print_config {
+                for line in effective_config.lines() {
+                    println!("{line}");
+                }
+                return Ok(());
+            }
+            log::info!("Effective configuration:");
+            for line in effective_config.lines() {
+                log::info!("  {line}");
+            }
+            anyhow::ensure!(
+                !(args.dry_run && (monitor_rpm || log_rpm)),
+                "RPM monitoring is not supported in dry run"
+            );
             let mut fans: Vec<_> = pwm
                 .iter()
-                .map(Fan::new)
+                .map(|p| {
+                    let fan = Fan::new(p)?
+                        .with_pwm_max(cl::PwmMax::find(&pwm_max, &p.filepath))
+                        .with_pwm_enable_value(cl::PwmEnableValue::find(
+                            &pwm_enable_value,
+                            &p.filepath,
+                        ))
+                        .with_dry_run(args.dry_run)
+                        .with_spinup_kick(Duration::from_millis(spinup_kick_ms))
+                        .with_verify_writes(verify_writes.then_some(verify_writes_tolerance))
+                        .with_reassert_interval(Some(*reassert_interval));
+                    if !args.dry_run {
+                        fan.check_writable()?;
+                    }
+                    let rpm_path = if monitor_rpm {
+                        Some(fan.resolve_rpm_path().with_context(|| {
+                            format!("Failed to resolve fan {fan} rpm filepath")
+                        })?)
+                    } else if log_rpm {
+                        fan.resolve_rpm_path()
+                            .inspect_err(|e| {
+                                log::warn!(
+                                    "Failed to resolve fan {fan} rpm filepath, its RPM will not be logged: {e:#}"
+                                );
+                            })
+                            .ok()
+                    } else {
+                        None
+                    };
+                    fan.with_optional_rpm_file(rpm_path.as_deref())
+                })
                 .collect::<anyhow::Result<_>>()
                 .context("Failed to setup fans")?;
 
-            let _exit_hook = ExitHook::new(
-                pwm.iter()
-                    .map(|p| pwm::Pwm::new(&p.filepath))
-                    .collect::<anyhow::Result<_>>()
-                    .context("Failed to setup PWMs for exit hook")?,
-                restore_fan_settings,
-            )?;
+            let mut exit_hook = if args.dry_run {
+                log::warn!("Dry run, PWM values will not be written");
+                None
+            } else {
+                Some(
+                    ExitHook::new(
+                        pwm.iter()
+                            .map(|p| {
+                                pwm::Pwm::new(&p.filepath).map(|w| {
+                                    w.with_inverted(p.inverted)
+                                        .with_max(cl::PwmMax::find(&pwm_max, &p.filepath))
+                                        .with_manual_mode(cl::PwmEnableValue::find(
+                                            &pwm_enable_value,
+                                            &p.filepath,
+                                        ))
+                                })
+                            })
+                            .collect::<anyhow::Result<_>>()
+                            .context("Failed to setup PWMs for exit hook")?,
+                        restore_fan_settings,
+                        restore_enable_mode,
+                    )?
+                    .with_timeout(shutdown_timeout.into()),
+                )
+            };
 
             // Signal handling
             let exit_requested = Arc::new(AtomicBool::new(false));
-            let (exit_tx, exit_rx) = mpsc::channel::<()>();
+            let (wake_tx, wake_rx) = mpsc::channel::<()>();
+            let (reload_tx, reload_rx) = mpsc::channel::<()>();
             {
                 let exit_requested = Arc::clone(&exit_requested);
-                ctrlc::set_handler(move || {
-                    exit_requested.store(true, Ordering::SeqCst);
-                    let _ = exit_tx.send(());
-                })
-                .context("Failed to setup SIGINT handler")?;
+                let wake_tx = wake_tx.clone();
+                let mut signals = Signals::new([SIGINT, SIGTERM, SIGQUIT, SIGHUP])
+                    .context("Failed to setup signal handlers")?;
+                thread::Builder::new()
+                    .name("signals".to_owned())
+                    .spawn(move || {
+                        for signal in signals.forever() {
+                            if signal == SIGHUP {
+                                let _ = reload_tx.send(());
+                            } else {
+                                log::info!("Got signal {signal}, exiting");
+                                exit_requested.store(true, Ordering::SeqCst);
+                            }
+                            let _ = wake_tx.send(());
+                        }
+                    })
+                    .context("Failed to start signal thread")?;
             }
 
-            while !exit_requested.load(Ordering::SeqCst) {
-                let start = Instant::now();
+            let initial_state = state_file
+                .as_deref()
+                .map(state::State::load)
+                .transpose()?
+                .unwrap_or_default();
+            for (drive, max_temp) in &initial_state.max_temps {
+                log::info!(
+                    "Drive {} maximum temperature seen: {}",
+                    drive.display(),
+                    temp_unit.display(*max_temp)
+                );
+            }
+            let control_shared =
+                Arc::new(control::Shared::new(initial_state, history_size, temp_unit));
+            let _control_socket = control_socket
+                .as_deref()
+                .map(|p| ControlSocket::spawn(p, Arc::clone(&control_shared), wake_tx))
+                .transpose()
+                .context("Failed to setup control socket")?;
+            #[cfg(feature = "metrics")]
+            let _metrics_server = metrics_listen
+                .map(|a| metrics::MetricsServer::spawn(a, Arc::clone(&control_shared)))
+                .transpose()
+                .context("Failed to setup metrics server")?;
+            #[cfg(feature = "influx")]
+            let influx_sender = influx_udp
+                .as_deref()
+                .map(influx::InfluxSender::new)
+                .transpose()
+                .context("Failed to setup InfluxDB output")?;
+            #[cfg(feature = "mqtt")]
+            let mqtt_publisher = mqtt_broker
+                .map(|broker| {
+                    mqtt::MqttPublisher::spawn(mqtt::MqttSettings {
+                        broker,
+                        topic_prefix: mqtt_topic_prefix,
+                        discovery: mqtt_discovery,
+                    })
+                })
+                .transpose()
+                .context("Failed to setup MQTT publishing")?;
+
+            let mut csv_log = csv_log
+                .map(|path| -> anyhow::Result<_> {
+                    let max_size = Byte::parse_str(&csv_log_max_size, true)
+                        .with_context(|| {
+                            format!("Invalid value for --csv-log-max-size: {csv_log_max_size}")
+                        })?
+                        .as_u64();
+                    Ok(csvlog::CsvLog::new(path, max_size))
+                })
+                .transpose()?;
+
+            // Drives last confirmed to be spun up, and when
+            let mut spun_up_drives: HashMap<PathBuf, Instant> = HashMap::new();
+            // Drive sectors read and written at the previous iteration
+            let mut drive_io_sectors: HashMap<PathBuf, u64> = HashMap::new();
+            // Drives that recently woke up, and how many readings are still to be ignored
+            let mut post_wake_samples: HashMap<PathBuf, u32> = HashMap::new();
+
+            let pid_gains: Vec<pid::Gains> = pid_gains
+                .into_iter()
+                .map(|g| pid::Gains {
+                    kp: pid_kp.unwrap_or(g.kp),
+                    ki: pid_ki.unwrap_or(g.ki),
+                    kd: pid_kd.unwrap_or(g.kd),
+                })
+                .collect();
+            let mut pids: Vec<pid::Pid> = match control_mode {
+                cl::ControlMode::Curve => Vec::new(),
+                cl::ControlMode::Pid => match pid_gains.as_slice() {
+                    [gains] => fans
+                        .iter()
+                        .map(|_| pid::Pid::new(*gains, pid_setpoint))
+                        .collect(),
+                    gains if gains.len() == fans.len() => gains
+                        .iter()
+                        .map(|g| pid::Pid::new(*g, pid_setpoint))
+                        .collect(),
+                    gains => anyhow::bail!(
+                        "Got {} PID gains for {} fans, expected a single value or one per fan",
+                        gains.len(),
+                        fans.len()
+                    ),
+                },
+            };
+
+            let mut fan_off_switches: Vec<fan::OffSwitch> = fan_off_below
+                .map(|threshold| {
+                    fans.iter()
+                        .map(|_| fan::OffSwitch::new(threshold, fan_off_hysteresis, temp_unit))
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let fan_min_speeds: Vec<Speed> = fans
+                .iter()
+                .zip(measured_thresholds.iter())
+                .map(|(fan, measured)| {
+                    let min_speed = min_fan_speed_raw.map_or(min_fan_speed, |value| {
+                        let speed = fan.min_speed_for_pwm_val(value);
+                        log::info!("Fan {fan} minimum speed for PWM value {value}: {speed}");
+                        speed
+                    });
+                    if !auto_min_speed {
+                        min_speed
+                    } else if *measured {
+                        let auto_speed = fan.min_running_speed();
+                        log::info!(
+                            "Fan {fan} automatic minimum speed above its stop value: {auto_speed}"
+                        );
+                        max(min_speed, auto_speed)
+                    } else {
+                        log::warn!(
+                            "Fan {fan} start/stop values are not measured, no automatic minimum speed"
+                        );
+                        min_speed
+                    }
+                })
+                .collect();
 
-                let max_drive_temp = drive_probers
-                    .iter_mut()
-                    .zip(drives.iter())
-                    .map(|((prober, supports_probing_sleeping), drive)| {
-                        let state = drive
-                            .state()
-                            .with_context(|| format!("Failed to get drive {drive} state"))?;
-                        log::debug!("Drive {drive} state: {state}");
-                        let temp = if state.is_spun_down() && !*supports_probing_sleeping {
-                            log::debug!("Drive {drive} is sleeping");
-                            None
+            let startup_ramps: Vec<Option<fan::StartupRamp>> = startup_ramp
+                .map(|duration| {
+                    let start = Instant::now();
+                    fans.iter()
+                        .map(|fan| {
+                            let from = fan.current_speed().unwrap_or_else(|e| {
+                                log::warn!("Failed to get fan {fan} current speed: {e:#}");
+                                min_fan_speed
+                            });
+                            log::info!("Fan {fan} speed ramps up from {from} over {duration}");
+                            Some(fan::StartupRamp::new(from, start, duration.into()))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let mut cycle_guards: Vec<fan::CycleGuard> = fans
+                .iter()
+                .map(|_| fan::CycleGuard::new(*min_fan_runtime, *min_fan_offtime))
+                .collect();
+
+            // Temperature rate of change for each hwmon
+            let mut hwmon_rates: Vec<RateTracker> = hwmon_and_range
+                .iter()
+                .map(|_| RateTracker::default())
+                .collect();
+            let rate_max_gap = interval * 2;
+
+            let mut hwmon_smoothers: Vec<fan::SpeedSmoother> = hwmon_and_range
+                .iter()
+                .map(|_| fan::SpeedSmoother::new(hysteresis_temp, temp_unit))
+                .collect();
+
+            let mut run_iteration =
+                |monitored: &mut monitor::MonitoredDrives| -> anyhow::Result<Speed> {
+                    let active_profile = profile::active(&profiles, Local::now().time());
+                    if active_profile.map(|p| &p.name) != active_profile_name.as_ref() {
+                        if let Some(active_profile) = active_profile {
+                            log::info!("Fan profile {active_profile} is now active");
                         } else {
-                            let temp = prober
+                            log::info!("No fan profile active, using default settings");
+                        }
+                        active_profile_name = active_profile.map(|p| p.name.clone());
+                    }
+                    let min_fan_speed = active_profile
+                        .and_then(|p| p.min_speed)
+                        .unwrap_or(min_fan_speed);
+                    let (profile_drive_range, drive_temp_curve) = match active_profile {
+                        Some(profile::Profile {
+                            temp_range: Some(range),
+                            ..
+                        }) => (Some(range), None),
+                        Some(profile::Profile {
+                            curve: Some(curve), ..
+                        }) => (None, Some(curve)),
+                        _ => (None, drive_temp_curve.as_ref()),
+                    };
+                    // Drives of zones not due for an update keep their last reading
+                    let due = monitored.due_drives().to_vec();
+                    let drive_states: Vec<(DriveState, bool)> = monitored
+                        .drives
+                        .iter()
+                        .zip(due.iter())
+                        .map(|(monitored_drive, due)| {
+                            let drive = &monitored_drive.drive;
+                            if let (false, Some(last_reading)) =
+                                (*due, monitored_drive.last_reading)
+                            {
+                                log::trace!("Drive {drive} zones are not due for an update");
+                                return Ok((last_reading.state, false));
+                            }
+                            // A drive probed with a method waking it up must not be probed if it
+                            // spun down since its state was cached
+                            let state = if monitored_drive.probes_sleeping()
+                                && spun_up_drives
+                                    .get(&drive.dev_path)
+                                    .is_some_and(|t| t.elapsed() < *drive_state_cache_ttl)
+                            {
+                                log::trace!("Drive {drive} state cached");
+                                DriveState::ActiveIdle
+                            } else {
+                                let state = match drive.state() {
+                                    Ok(state) => state,
+                                    // Dropped at the next hotplug check
+                                    Err(e) if monitored_drive.device_gone() => {
+                                        log::warn!(
+                                            "Failed to get drive {drive} state: {e:#}, its device node disappeared, skipping it"
+                                        );
+                                        DriveState::Unknown
+                                    }
+                                    Err(e) => {
+                                        return Err(
+                                            e.context(format!("Failed to get drive {drive} state"))
+                                        );
+                                    }
+                                };
+                                if matches!(state, DriveState::ActiveIdle) {
+                                    spun_up_drives.insert(drive.dev_path.clone(), Instant::now());
+                                } else {
+                                    spun_up_drives.remove(&drive.dev_path);
+                                }
+                                state
+                            };
+                            log::debug!("Drive {drive} state: {state}");
+                            let mut post_wake = false;
+                            if state.is_spun_down() {
+                                if post_wake_ignored_samples > 0 {
+                                    post_wake_samples
+                                        .insert(drive.dev_path.clone(), post_wake_ignored_samples);
+                                }
+                            } else if let Some(left) = post_wake_samples.get_mut(&drive.dev_path) {
+                                post_wake = true;
+                                *left = left.saturating_sub(1);
+                                if *left == 0 {
+                                    post_wake_samples.remove(&drive.dev_path);
+                                }
+                            }
+                            Ok((state, post_wake))
+                        })
+                        .collect::<anyhow::Result<_>>()
+                        .context("Failed to get drive states")?;
+
+                    // Drives with no I/O since the previous iteration
+                    let diskstats = if skip_idle_sleeping && !monitored.drives.is_empty() {
+                        sysfs::read_diskstats()
+                            .map_err(|e| log::warn!("{e:#}"))
+                            .ok()
+                    } else {
+                        None
+                    };
+                    let io_idle: Vec<bool> = monitored
+                        .drives
+                        .iter()
+                        .zip(due.iter())
+                        .map(|(monitored_drive, due)| {
+                            if !due {
+                                return false;
+                            }
+                            let dev_path = &monitored_drive.drive.dev_path;
+                            let Some(sectors) = diskstats.as_ref().and_then(|stats| {
+                                stats.get(dev_path.file_name()?.to_str()?).copied()
+                            }) else {
+                                return false;
+                            };
+                            drive_io_sectors.insert(dev_path.clone(), sectors) == Some(sectors)
+                        })
+                        .collect();
+
+                    // Probe all drives concurrently
+                    let to_probe: Vec<bool> = monitored
+                        .drives
+                        .iter()
+                        .zip(drive_states.iter())
+                        .zip(io_idle.iter())
+                        .map(|((monitored_drive, (state, _)), idle)| {
+                            let probe = !state.is_spun_down()
+                                || (monitored_drive.probes_sleeping() && !idle);
+                            if !probe {
+                                if *idle && monitored_drive.probes_sleeping() {
+                                    log::debug!(
+                                        "Drive {} is sleeping with no I/O activity, not probing it",
+                                        monitored_drive.drive
+                                    );
+                                } else if monitored_drive.never_wake {
+                                    log::debug!(
+                                        "Drive {} is sleeping, not probing it because of --never-wake",
+                                        monitored_drive.drive
+                                    );
+                                } else {
+                                    log::debug!("Drive {} is sleeping", monitored_drive.drive);
+                                }
+                            }
+                            probe
+                        })
+                        .zip(due.iter())
+                        .map(|(probe, due)| probe && *due)
+                        .collect();
+                    let probe_results = monitored.probe_temps(
+                        &to_probe,
+                        *probe_timeout,
+                        probe::ProbeRetry {
+                            retries: probe_retries,
+                            delay: *probe_retry_delay,
+                        },
+                    );
+
+                    let (drive_states, drive_temps): (Vec<DriveState>, Vec<Option<Temp>>) = monitored
+                        .drives
+                        .iter_mut()
+                        .zip(drive_states)
+                        .zip(probe_results)
+                        .map(|((monitored_drive, (state, post_wake)), result)| {
+                            let drive = &monitored_drive.drive;
+                            let temp = match result.map(|r| {
+                                r.with_context(|| format!("Failed to get drive {drive} temp"))
+                            }) {
+                                None => None,
+                                Some(Ok(raw_temp)) => {
+                                    monitored_drive.probe_errors = 0;
+                                    let temp = raw_temp + monitored_drive.temp_offset;
+                                    if monitored_drive.temp_offset == 0.0 {
+                                        log::debug!(
+                                            drive:% = drive.dev_path.display(), temp;
+                                            "Drive {drive}: {}",
+                                            temp_unit.display(temp)
+                                        );
+                                    } else {
+                                        log::debug!(
+                                            drive:% = drive.dev_path.display(), temp;
+                                            "Drive {drive}: {} (raw {})",
+                                            temp_unit.display(temp),
+                                            temp_unit.display(raw_temp)
+                                        );
+                                    }
+                                    if post_wake {
+                                        log::info!(
+                                            "Drive {drive} just woke up, ignoring its {} temperature",
+                                            temp_unit.display(temp)
+                                        );
+                                        None
+                                    } else {
+                                        Some(temp)
+                                    }
+                                }
+                                Some(Err(e)) if monitored_drive.device_gone() => {
+                                    spun_up_drives.remove(&drive.dev_path);
+                                    log::warn!(
+                                        "{e:#}, its device node disappeared, skipping it"
+                                    );
+                                    None
+                                }
+                                Some(Err(e)) => {
+                                    // Query state again next time
+                                    spun_up_drives.remove(&drive.dev_path);
+                                    if on_probe_error == cl::ProbeErrorAction::Exit {
+                                        return Err(e);
+                                    }
+                                    monitored_drive.probe_errors =
+                                        monitored_drive.probe_errors.saturating_add(1);
+                                    log::error!(
+                                        "{e:#} ({} consecutive failure(s)), ignoring drive",
+                                        monitored_drive.probe_errors
+                                    );
+                                    None
+                                }
+                            };
+                            Ok((state, temp))
+                        })
+                        .collect::<anyhow::Result<Vec<_>>>()
+                        .context("Failed to get maximum drive temperature")?
+                        .into_iter()
+                        .unzip();
+                    let drive_temps: Vec<Option<Temp>> = drive_temps
+                        .into_iter()
+                        .zip(monitored.drives.iter())
+                        .zip(due.iter())
+                        .map(|((temp, monitored_drive), due)| {
+                            match (*due, monitored_drive.last_reading) {
+                                (false, Some(last_reading)) => last_reading.temp,
+                                _ => temp,
+                            }
+                        })
+                        .collect();
+                    for (monitored_drive, temp) in monitored.drives.iter().zip(drive_temps.iter()) {
+                        let drive = &monitored_drive.drive;
+                        if let Some(temp) = temp {
+                            if control_shared.update_max_temp(&drive.dev_path, *temp) {
+                                log::info!(
+                                    "Drive {drive} new maximum temperature: {}",
+                                    temp_unit.display(*temp)
+                                );
+                            }
+                        }
+                    }
+                    let now = Instant::now();
+                    // Spun down drives that were not probed keep their last temperature for a while
+                    let control_drive_temps: Vec<Option<Temp>> = monitored
+                        .drives
+                        .iter_mut()
+                        .zip(drive_temps.iter())
+                        .zip(to_probe.iter())
+                        .zip(due.iter())
+                        .map(|(((monitored_drive, temp), probed), due)| {
+                            if let (false, Some(last_reading)) =
+                                (*due, monitored_drive.last_reading)
+                            {
+                                return last_reading.control_temp;
+                            }
+                            if let Some(temp) = temp {
+                                monitored_drive.sleep_temp.set(*temp, now);
+                                return Some(*temp);
+                            }
+                            if *probed {
+                                return None;
+                            }
+                            let last_temp = monitored_drive
+                                .sleep_temp
+                                .get(now, monitored_drive.temp_range.start);
+                            if let Some(last_temp) = last_temp {
+                                log::debug!(
+                                    "Drive {} is sleeping, using its last temperature: {}",
+                                    monitored_drive.drive,
+                                    temp_unit.display(last_temp)
+                                );
+                            }
+                            last_temp
+                        })
+                        .collect();
+                    let smoothed_drive_temps: Vec<Option<Temp>> = monitored
+                        .drives
+                        .iter_mut()
+                        .zip(control_drive_temps.iter())
+                        .zip(due.iter())
+                        .map(|((monitored_drive, temp), due)| {
+                            if !due {
+                                return *temp;
+                            }
+                            let smoothed = monitored_drive.temp_smoother.smooth(*temp);
+                            if let (Some(smoothed), true) = (smoothed, temp_smoothing_alpha < 1.0) {
+                                log::debug!(
+                                    "Drive {} smoothed temperature: {:.1}",
+                                    monitored_drive.drive,
+                                    temp_unit.display(smoothed)
+                                );
+                            }
+                            monitored_drive.rate.update(now, smoothed, rate_max_gap);
+                            let windowed = monitored_drive.temp_window.update(now, smoothed);
+                            if let (Some(windowed), true) =
+                                (windowed, monitored_drive.temp_window.is_enabled())
+                            {
+                                log::debug!(
+                                    "Drive {} {window_stat} temperature over window: {:.1}",
+                                    monitored_drive.drive,
+                                    temp_unit.display(windowed)
+                                );
+                            }
+                            let held = monitored_drive.peak_hold.update(now, windowed);
+                            if let (Some(held), Some(windowed)) = (held, windowed) {
+                                if held > windowed {
+                                    log::debug!(
+                                        "Drive {} temperature held at {:.1} decaying from peak",
+                                        monitored_drive.drive,
+                                        temp_unit.display(held)
+                                    );
+                                }
+                            }
+                            held
+                        })
+                        .collect();
+                    for ((((monitored_drive, state), temp), control_temp), drive_due) in monitored
+                        .drives
+                        .iter_mut()
+                        .zip(drive_states.iter())
+                        .zip(drive_temps.iter())
+                        .zip(smoothed_drive_temps.iter())
+                        .zip(due.iter())
+                    {
+                        if *drive_due {
+                            monitored_drive.last_reading = Some(monitor::LastReading {
+                                state: *state,
+                                temp: *temp,
+                                control_temp: *control_temp,
+                            });
+                        }
+                    }
+                    let probed_drive_temps: Vec<Temp> =
+                        smoothed_drive_temps.iter().flatten().copied().collect();
+                    let aggregated_drive_temp = aggregation.aggregate(&probed_drive_temps);
+                    if aggregation != cl::Aggregation::Max && !probed_drive_temps.is_empty() {
+                        log::debug!(
+                            "Drive temperatures: {}",
+                            probed_drive_temps
+                                .iter()
+                                .map(|t| temp_unit.display(*t).to_string())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        );
+                    }
+
+                    let hwmon_temps: Vec<Temp> = hwmon_and_range
+                        .iter_mut()
+                        .zip(hwmon_offsets.iter())
+                        .map(|((hwm, _range), offset)| {
+                            let raw_temp = hwm
                                 .probe_temp()
-                                .with_context(|| format!("Failed to get drive {drive} temp"))?;
-                            log::debug!("Drive {drive}: {temp}°C");
-                            Some(temp)
+                                .with_context(|| format!("Failed to get hwmon {hwm} temp"))?;
+                            let temp = raw_temp + offset;
+                            if *offset == 0.0 {
+                                log::info!(
+                                    hwmon:% = hwm, temp;
+                                    "Hwmon {hwm} temperature: {}",
+                                    temp_unit.display(temp)
+                                );
+                            } else {
+                                log::info!(
+                                    hwmon:% = hwm, temp, offset = *offset;
+                                    "Hwmon {hwm} temperature: {} (raw {})",
+                                    temp_unit.display(temp),
+                                    temp_unit.display(raw_temp)
+                                );
+                            }
+                            Ok(temp)
+                        })
+                        .collect::<anyhow::Result<_>>()?;
+                    for (rate, temp) in hwmon_rates.iter_mut().zip(hwmon_temps.iter()) {
+                        rate.update(now, Some(*temp), rate_max_gap);
+                    }
+
+                    let probe_failsafe = on_probe_error == cl::ProbeErrorAction::MaxSpeed
+                        && monitored
+                            .drives
+                            .iter()
+                            .any(|d| d.probe_errors >= probe_error_threshold);
+                    if probe_failsafe {
+                        log::warn!(
+                            "Drive temperature probing failed {probe_error_threshold} consecutive times, setting fans to full speed"
+                        );
+                    }
+                    let critical_failsafe = drive_critical_temp.is_some_and(|critical| {
+                        drive_temps.iter().flatten().any(|temp| *temp >= critical)
+                    });
+                    if critical_failsafe {
+                        log::warn!("Drive temperature is critical, setting fans to full speed");
+                    }
+                    if let Some(critical_command) = critical_command.as_mut() {
+                        let critical_drive_temps: Vec<_> = monitored
+                            .drives
+                            .iter()
+                            .map(|d| d.drive.dev_path.as_path())
+                            .zip(drive_temps.iter().copied())
+                            .collect();
+                        critical_command.update(&critical_drive_temps);
+                    }
+                    let throttling_drives: Vec<bool> = monitored
+                        .drives
+                        .iter()
+                        .zip(drive_temps.iter())
+                        .map(|(monitored_drive, temp)| {
+                            let throttling = monitored_drive.warning_temp.zip(*temp).is_some_and(
+                                |(warning_temp, temp)| {
+                                    temp - monitored_drive.temp_offset >= warning_temp
+                                },
+                            );
+                            if throttling {
+                                log::warn!(
+                                    "Drive {} reached its warning temperature threshold, setting its fans to full speed",
+                                    monitored_drive.drive
+                                );
+                            }
+                            throttling
+                        })
+                        .collect();
+                    let mut rpm_failsafe = false;
+                    for fan in &mut fans {
+                        let stall_count = fan
+                            .check_stalled()
+                            .with_context(|| format!("Failed to check fan {fan} RPM"))?;
+                        if stall_count >= rpm_fail_threshold {
+                            log::error!(
+                                "Fan {fan} is not moving for {stall_count} intervals while it should, it may have failed"
+                            );
+                            rpm_failsafe |= rpm_fail_max_speed;
+                        }
+                    }
+                    if rpm_failsafe {
+                        log::warn!("A fan has failed, setting fans to full speed");
+                    }
+                    let failsafe = probe_failsafe || critical_failsafe || rpm_failsafe;
+
+                    let all_spun_down = !drive_states.is_empty()
+                        && drive_states.iter().copied().all(DriveState::is_spun_down);
+                    if let Some(aggregated_drive_temp) = aggregated_drive_temp {
+                        if aggregation == cl::Aggregation::Max {
+                            log::info!(
+                                temp = aggregated_drive_temp;
+                                "Max drive temperature: {}",
+                                temp_unit.display(aggregated_drive_temp)
+                            );
+                        } else {
+                            log::info!(
+                                temp = aggregated_drive_temp;
+                                "Drive temperature ({aggregation}): {}",
+                                temp_unit.display(aggregated_drive_temp)
+                            );
+                        }
+                    } else if !drive_states.is_empty() {
+                        log::info!("All drives are spun down");
+                    }
+                    let drive_speeds: Vec<Speed> = if control_mode == cl::ControlMode::Curve {
+                        monitored
+                            .drives
+                            .iter_mut()
+                            .zip(smoothed_drive_temps.iter())
+                            .map(|(monitored_drive, drive_temp)| {
+                                let drive_range =
+                                    profile_drive_range.unwrap_or(&monitored_drive.temp_range);
+                                monitored_drive.speed_smoother.temp(*drive_temp).map_or(
+                                    min_fan_speed,
+                                    |drive_temp| {
+                                        if let Some(curve) = drive_temp_curve {
+                                            fan::target_speed_curve(
+                                                drive_temp,
+                                                curve,
+                                                min_fan_speed,
+                                            )
+                                        } else {
+                                            fan::target_speed(
+                                                drive_temp,
+                                                drive_range,
+                                                min_fan_speed,
+                                                curve_shape,
+                                            )
+                                        }
+                                    },
+                                )
+                            })
+                            .collect()
+                    } else {
+                        vec![min_fan_speed; monitored.drives.len()]
+                    };
+                    let hwmon_speeds: Vec<Speed> = hwmon_temps
+                        .iter()
+                        .zip(hwmon_and_range.iter())
+                        .zip(hwmon_smoothers.iter_mut())
+                        .map(|((hwmon_temp, (_hwmon, hwmon_range)), smoother)| {
+                            smoother
+                                .temp(Some(*hwmon_temp))
+                                .map_or(min_fan_speed, |hwmon_temp| {
+                                    fan::target_speed(
+                                        hwmon_temp,
+                                        hwmon_range,
+                                        min_fan_speed,
+                                        curve_shape,
+                                    )
+                                })
+                        })
+                        .collect();
+
+                    let weights_by_drive: Vec<f64> =
+                        monitored.drives.iter().map(|d| d.weight).collect();
+                    let drive_rates: Vec<Option<f64>> =
+                        monitored.drives.iter().map(|d| d.rate.rate()).collect();
+                    let hwmon_rate_values: Vec<Option<f64>> =
+                        hwmon_rates.iter().map(RateTracker::rate).collect();
+
+                    // Speed and max drive temperature of each zone
+                    let zones = &monitored.zones;
+                    let zone_readings: Vec<(Speed, Option<Temp>)> = zones
+                        .iter()
+                        .map(|zone| {
+                            // Spun down drives without temperature are not aggregated, and speeds
+                            // are scaled by weight relative to the highest zone drive weight
+                            let zone_max_weight = zone
+                                .drive_values(&weights_by_drive)
+                                .copied()
+                                .fold(0.0, f64::max);
+                            let zone_drive_speeds: Vec<f64> = zone
+                                .drive_values(&drive_speeds)
+                                .zip(zone.drive_values(&smoothed_drive_temps))
+                                .zip(zone.drive_values(&weights_by_drive))
+                                .filter(|((_, temp), _)| temp.is_some())
+                                .map(|((speed, _), weight)| {
+                                    f64::from(*speed) * weight / zone_max_weight
+                                })
+                                .collect();
+                            let zone_drive_speed = aggregation
+                                .aggregate(&zone_drive_speeds)
+                                .map(Speed::from_clamped);
+                            let zone_hwmon_speed =
+                                zone.hwmon_values(&hwmon_speeds).copied().reduce(max);
+                            let zone_speed =
+                                if let Some((drive_weight, hwmon_weight)) = blend_weights {
+                                    fan::blend_speeds(
+                                        zone_drive_speed,
+                                        zone_hwmon_speed,
+                                        drive_weight,
+                                        hwmon_weight,
+                                    )
+                                } else {
+                                    zone_drive_speed
+                                        .into_iter()
+                                        .chain(zone_hwmon_speed)
+                                        .reduce(max)
+                                };
+                            let mut speed =
+                                zone_speed.map_or(min_fan_speed, |speed| max(speed, min_fan_speed));
+                            if derivative_gain > 0.0 {
+                                if let Some(rate) = zone
+                                    .drive_values(&drive_rates)
+                                    .chain(zone.hwmon_values(&hwmon_rate_values))
+                                    .flatten()
+                                    .copied()
+                                    .reduce(f64::max)
+                                {
+                                    let derivative_speed =
+                                        trend::derivative_speed(rate, derivative_gain);
+                                    log::debug!(
+                                        "Max temperature rate: {:.2}{}/min, adding {:.1}% speed",
+                                        temp_unit.delta_from_celsius(rate),
+                                        temp_unit.symbol(),
+                                        derivative_speed * 100.0
+                                    );
+                                    speed = speed.saturating_add(derivative_speed);
+                                }
+                            }
+                            if zone.drive_values(&throttling_drives).any(|t| *t) {
+                                speed = Speed::from_clamped(1.0);
+                            }
+                            let zone_temps: Vec<Temp> = zone
+                                .drive_values(&smoothed_drive_temps)
+                                .flatten()
+                                .copied()
+                                .collect();
+                            let zone_temp = aggregation.aggregate(&zone_temps);
+                            if zones.len() > 1 {
+                                log::debug!("Zone {zone}: speed {speed}");
+                            }
+                            (speed, zone_temp)
+                        })
+                        .collect();
+
+                    if let Some(setpoint) = control_shared.take_pid_setpoint() {
+                        if pids.is_empty() {
+                            log::warn!("Ignoring PID setpoint change, not in PID control mode");
+                        }
+                        for pid in &mut pids {
+                            pid.set_setpoint(setpoint);
+                        }
+                    }
+                    let fan_speeds: Vec<Speed> = (0..fans.len())
+                        .map(|fan_idx| {
+                            let min_fan_speed = max(
+                                min_fan_speed,
+                                fan_min_speeds
+                                    .get(fan_idx)
+                                    .copied()
+                                    .unwrap_or(min_fan_speed),
+                            );
+                            let (speed, temp) = zones
+                                .iter()
+                                .zip(zone_readings.iter())
+                                .filter(|(zone, _)| zone.has_fan(fan_idx))
+                                .fold((min_fan_speed, None), |(speed, temp), (_, reading)| {
+                                    (
+                                        max(speed, reading.0),
+                                        temp.into_iter().chain(reading.1).reduce(f64::max),
+                                    )
+                                });
+                            let speed = match pids.get_mut(fan_idx) {
+                                _ if failsafe => return Speed::from_clamped(1.0),
+                                Some(pid) => max(speed, pid.update(now, temp, min_fan_speed)),
+                                None => speed,
+                            };
+                            let off = fan_off_switches
+                                .get_mut(fan_idx)
+                                .is_some_and(|switch| switch.update(temp));
+                            // Only override the minimum speed, not one required by a hwmon
+                            if speed > min_fan_speed {
+                                return speed;
+                            }
+                            if off {
+                                return Speed::from_clamped(0.0);
+                            }
+                            if !all_spun_down {
+                                return speed;
+                            }
+                            match spun_down_behavior {
+                                cl::SpunDownBehavior::Min => speed,
+                                cl::SpunDownBehavior::Hold => {
+                                    fans.get(fan_idx).and_then(Fan::speed).unwrap_or(speed)
+                                }
+                                cl::SpunDownBehavior::Off => Speed::from_clamped(0.0),
+                            }
+                        })
+                        .collect();
+                    let boost = control_shared.active_boost();
+                    let mut fan_readings = Vec::with_capacity(fans.len());
+                    let mut max_fan_speed = Speed::from_clamped(0.0);
+                    for (fan_idx, (((fan, fan_settings), cycle_guard), target_speed)) in fans
+                        .iter_mut()
+                        .zip(pwm.iter())
+                        .zip(cycle_guards.iter_mut())
+                        .zip(fan_speeds)
+                        .enumerate()
+                    {
+                        // Boosts only raise speed, computed speed wins if it is higher
+                        let (fan_speed, boosted) = match boost.as_ref().and_then(|boost| {
+                            boost.speed_for(&fan.to_string(), &fan_settings.filepath, target_speed)
+                        }) {
+                            Some(boost_speed) => {
+                                log::debug!("Fan {fan} is boosted to {boost_speed}");
+                                (boost_speed, true)
+                            }
+                            None => (target_speed, false),
                         };
-                        Ok(temp)
-                    })
-                    .collect::<anyhow::Result<Vec<_>>>()
-                    .context("Failed to get maximum drive temperature")?
-                    .into_iter()
-                    .flatten()
-                    .reduce(f64::max);
-
-                let hwmon_temps: Vec<Temp> = hwmon_and_range
-                    .iter_mut()
-                    .map(|(hwm, _range)| {
-                        let temp = hwm
-                            .probe_temp()
-                            .with_context(|| format!("Failed to get hwmon {hwm} temp"))?;
-                        log::info!("Hwmon {hwm} temperature: {temp}°C");
-                        Ok(temp)
-                    })
-                    .collect::<anyhow::Result<_>>()?;
-
-                let mut speed = min_fan_speed;
-                if let Some(max_drive_temp) = max_drive_temp {
-                    log::info!("Max drive temperature: {max_drive_temp}°C");
-                    speed = fan::target_speed(max_drive_temp, &drive_temp_range, speed);
-                } else {
-                    log::info!("All drives are spun down");
+                        let fan_speed = match startup_ramps.get(fan_idx) {
+                            Some(Some(ramp)) if !failsafe && !boosted && !target_speed.is_max() => {
+                                ramp.apply(fan_speed, now)
+                            }
+                            _ => fan_speed,
+                        };
+                        let fan_speed = match max_speed_step_prct {
+                            Some(_) if failsafe || (no_ramp_emergency && target_speed.is_max()) => {
+                                fan_speed
+                            }
+                            Some(step) => fan.ramped_speed(fan_speed, f64::from(step) / 100.0),
+                            None => fan_speed,
+                        };
+                        let fan_speed =
+                            cycle_guard.apply(fan_speed, now, failsafe || target_speed.is_max());
+                        let held_speed = speed_deadband_prct
+                            .filter(|_| {
+                                !failsafe
+                                    && !boosted
+                                    && !target_speed.is_max()
+                                    && !fan_speed.is_max()
+                                    && !fan_speed.is_zero()
+                            })
+                            .and_then(|deadband| {
+                                fan.held_speed(fan_speed, f64::from(deadband) / 100.0)
+                            });
+                        let fan_speed = if let Some(held_speed) = held_speed {
+                            log::trace!(
+                                "Fan {fan} speed {fan_speed} is within deadband of current speed {held_speed}, keeping it"
+                            );
+                            held_speed
+                        } else {
+                            fan.set_speed(fan_speed)
+                                .with_context(|| format!("Failed to set fan {fan} speed"))?;
+                            fan_speed
+                        };
+                        if log_rpm {
+                            match fan.rpm() {
+                                Ok(Some(rpm)) => log::info!(
+                                    fan:% = fan, speed:% = fan_speed, rpm = rpm;
+                                    "Fan {fan} speed: {fan_speed}{}, RPM: {rpm}",
+                                    fan.pwm_value()
+                                        .map(|v| format!(" (raw {v})"))
+                                        .unwrap_or_default()
+                                ),
+                                Ok(None) => {}
+                                Err(e) => log::warn!("Failed to read fan {fan} RPM: {e:#}"),
+                            }
+                        }
+                        max_fan_speed = max(max_fan_speed, fan_speed);
+                        fan_readings.push(snapshot::FanReading {
+                            name: fan.to_string(),
+                            pwm: fan_settings.filepath.clone(),
+                            speed_prct: f64::from(fan_speed) * 100.0,
+                            boosted,
+                        });
+                    }
+
+                    let snapshot = snapshot::Snapshot {
+                        time: Local::now(),
+                        drives: monitored
+                            .drives
+                            .iter()
+                            .map(|m| &m.drive)
+                            .zip(drive_states)
+                            .zip(drive_temps)
+                            .map(|((drive, state), temp)| snapshot::DriveReading {
+                                name: drive.to_string(),
+                                path: drive.dev_path.clone(),
+                                state: state.to_string(),
+                                spun_down: state.is_spun_down(),
+                                temp,
+                            })
+                            .collect(),
+                        hwmons: hwmon_and_range
+                            .iter()
+                            .zip(hwmon_temps)
+                            .map(|((hwmon, _range), temp)| snapshot::HwmonReading {
+                                name: hwmon.to_string(),
+                                temp,
+                            })
+                            .collect(),
+                        fans: fan_readings,
+                    };
+                    if let Some(csv_log) = csv_log.as_mut() {
+                        if let Err(e) = csv_log.append(&snapshot) {
+                            log::warn!("{e:#}");
+                        }
+                    }
+                    #[cfg(feature = "influx")]
+                    if let Some(influx_sender) = influx_sender.as_ref() {
+                        if let Err(e) = influx_sender.send(&snapshot) {
+                            log::warn!("{e:#}");
+                        }
+                    }
+                    #[cfg(feature = "mqtt")]
+                    if let Some(mqtt_publisher) = mqtt_publisher.as_ref() {
+                        mqtt_publisher.publish(&snapshot);
+                    }
+                    control_shared.set_snapshot(snapshot);
+
+                    if let (Some(state_file), Some(dirty_state)) =
+                        (state_file.as_deref(), control_shared.take_dirty_state())
+                    {
+                        if let Err(e) = dirty_state.save(state_file) {
+                            log::warn!("{e:#}");
+                        }
+                    }
+
+                    Ok(max_fan_speed)
+                };
+
+            #[cfg(feature = "systemd")]
+            let watchdog_timeout = systemd::watchdog_timeout();
+            #[cfg(feature = "systemd")]
+            if let Some(watchdog_timeout) = watchdog_timeout {
+                log::info!("Service manager watchdog timeout: {watchdog_timeout:?}");
+                if watchdog_timeout <= interval {
+                    log::warn!(
+                        "Watchdog timeout is not longer than interval {interval:?}, the service will be restarted"
+                    );
                 }
-                for (hwmon_temp, (_hwmon, hwmon_range)) in
-                    hwmon_temps.into_iter().zip(hwmon_and_range.iter())
+            }
+            #[cfg(feature = "systemd")]
+            systemd::notify_ready();
+
+            let mut consecutive_errors: u32 = 0;
+            let mut fan_speed = Speed::from_clamped(1.0);
+            let mut iteration: u32 = 0;
+            let mut iteration_interval = interval;
+            let mut last_default_update: Option<Instant> = None;
+            // Should the next sleep be shortened by --startup-poll, and was the last one
+            let mut startup_poll_pending = true;
+            let mut startup_polled = false;
+            while !exit_requested.load(Ordering::SeqCst) {
+                let start = Instant::now();
+                iteration = iteration.wrapping_add(1);
+
+                if reload_rx.try_iter().count() > 0 {
+                    log::info!("Reloading drive settings");
+                    if let Err(e) = reload_drive_settings(cl_argv.clone()).and_then(|settings| {
+                        monitored_drives.reload(&settings, &drive_options, &hwmons, &pwm)?;
+                        drive_settings = settings;
+                        startup_poll_pending = true;
+                        Ok(())
+                    }) {
+                        log::error!("Failed to reload drive settings: {e:#}");
+                    }
+                }
+                if let Err(e) =
+                    monitored_drives.check_hotplug(&drive_settings, &drive_options, &hwmons, &pwm)
                 {
-                    speed = fan::target_speed(hwmon_temp, hwmon_range, speed);
+                    log::error!("Failed to update unplugged or replugged drives: {e:#}");
                 }
-                for fan in &mut fans {
-                    fan.set_speed(speed)
-                        .with_context(|| format!("Failed to set fan {fan} speed"))?;
+                if drive_rescan_iterations > 0 && iteration % drive_rescan_iterations == 0 {
+                    if let Err(e) =
+                        monitored_drives.rescan(&drive_settings, &drive_options, &hwmons, &pwm)
+                    {
+                        log::error!("Failed to rescan drives: {e:#}");
+                    }
                 }
 
-                let elapsed = Instant::now().duration_since(start);
-                let to_wait = interval.saturating_sub(elapsed);
+                // Without zone intervals, everything is updated at each iteration, otherwise zones
+                // without one are updated at the -i/--interval pace
+                let default_due = startup_polled
+                    || !monitored_drives.zones.iter().any(zone::Zone::has_interval)
+                    || last_default_update
+                        .is_none_or(|last| zone::is_elapsed(last, iteration_interval, start));
+                monitored_drives.schedule(start, default_due);
+                let result = run_iteration(&mut monitored_drives);
+                if default_due {
+                    last_default_update = Some(start);
+                }
+                if once {
+                    result?;
+                    if let (false, Some(exit_hook)) = (once_restore, exit_hook.as_mut()) {
+                        log::info!("Single iteration done, keeping fan speeds");
+                        exit_hook.disarm();
+                    }
+                    break;
+                }
+                match result {
+                    Ok(speed) => {
+                        consecutive_errors = 0;
+                        fan_speed = speed;
+                    }
+                    Err(e) => {
+                        consecutive_errors = consecutive_errors.saturating_add(1);
+                        if consecutive_errors >= max_consecutive_errors {
+                            // Fans are set to full speed or restored by the exit hook
+                            return Err(e.context(format!(
+                                "Giving up after {consecutive_errors} consecutive errors"
+                            )));
+                        }
+                        log::error!(
+                            "{e:#} ({consecutive_errors}/{max_consecutive_errors} consecutive errors tolerated)"
+                        );
+                    }
+                }
+
+                #[cfg(feature = "systemd")]
+                if watchdog_timeout.is_some() {
+                    systemd::ping_watchdog();
+                }
+
+                iteration_interval = intervals
+                    .as_ref()
+                    .map_or(interval, |r| fan::interval_for_speed(fan_speed, r));
+                let next_update = monitored_drives
+                    .zones
+                    .iter()
+                    .filter_map(zone::Zone::next_update)
+                    .fold(
+                        last_default_update.map_or(start, |last| last + iteration_interval),
+                        min,
+                    );
+                let mut to_wait = next_update.saturating_duration_since(Instant::now());
+                startup_polled = false;
+                if let Some(startup_poll) = startup_poll.filter(|_| startup_poll_pending) {
+                    startup_poll_pending = false;
+                    // The adaptive interval may already be shorter
+                    if *startup_poll < to_wait {
+                        to_wait = *startup_poll;
+                        startup_polled = true;
+                    }
+                }
                 log::debug!("Will sleep at most {to_wait:?}");
-                sleep(to_wait, &exit_rx);
+                sleep(to_wait, &wake_rx);
             }
         }
     }
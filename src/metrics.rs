@@ -0,0 +1,233 @@
+//! Prometheus metrics HTTP endpoint
+
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead as _, BufReader, Write as _},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use anyhow::Context as _;
+
+use crate::{control::Shared, snapshot::Snapshot};
+
+/// Path metrics are served at
+const METRICS_PATH: &str = "/metrics";
+
+/// Timeout for reading a client request
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Metrics HTTP server
+pub(crate) struct MetricsServer;
+
+impl MetricsServer {
+    /// Bind address and serve metrics from the last snapshot in a background thread
+    pub(crate) fn spawn(addr: SocketAddr, shared: Arc<Shared>) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .with_context(|| format!("Failed to bind metrics address {addr}"))?;
+        log::info!("Serving metrics on http://{addr}{METRICS_PATH}");
+        thread::Builder::new()
+            .name("metrics".to_owned())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            if let Err(e) = Self::serve(stream, &shared) {
+                                log::warn!("Metrics client error: {e}");
+                            }
+                        }
+                        Err(e) => log::warn!("Failed to accept metrics connection: {e}"),
+                    }
+                }
+            })
+            .context("Failed to start metrics thread")?;
+        Ok(Self)
+    }
+
+    /// Handle a single HTTP request
+    fn serve(stream: TcpStream, shared: &Shared) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // Skip headers
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+            header.clear();
+        }
+        log::debug!("Metrics request: {:?}", request_line.trim_end());
+
+        let mut tokens = request_line.split_whitespace();
+        let (status, content_type, body) = match (tokens.next(), tokens.next()) {
+            (Some("GET"), Some(METRICS_PATH)) => (
+                "200 OK",
+                "text/plain; version=0.0.4",
+                shared.snapshot().map_or_else(String::new, |s| render(&s)),
+            ),
+            (Some("GET"), Some(_)) => ("404 Not Found", "text/plain", "Not found\n".to_owned()),
+            _ => (
+                "405 Method Not Allowed",
+                "text/plain",
+                "Method not allowed\n".to_owned(),
+            ),
+        };
+        write!(
+            writer,
+            "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )?;
+        writer.flush()
+    }
+}
+
+/// Escape a Prometheus label value
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Write help and type lines of a gauge metric
+fn gauge_header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} gauge");
+}
+
+/// Render snapshot in Prometheus text exposition format
+fn render(snapshot: &Snapshot) -> String {
+    let mut out = String::new();
+
+    gauge_header(
+        &mut out,
+        "hddfancontrol_drive_temp_celsius",
+        "Drive temperature, if it was probed",
+    );
+    for drive in &snapshot.drives {
+        if let Some(temp) = drive.temp {
+            let _ = writeln!(
+                out,
+                "hddfancontrol_drive_temp_celsius{{drive=\"{}\"}} {temp}",
+                escape_label(&drive.path.to_string_lossy())
+            );
+        }
+    }
+
+    gauge_header(
+        &mut out,
+        "hddfancontrol_drive_spun_down",
+        "Is the drive spun down",
+    );
+    for drive in &snapshot.drives {
+        let _ = writeln!(
+            out,
+            "hddfancontrol_drive_spun_down{{drive=\"{}\"}} {}",
+            escape_label(&drive.path.to_string_lossy()),
+            u8::from(drive.spun_down)
+        );
+    }
+
+    gauge_header(
+        &mut out,
+        "hddfancontrol_hwmon_temp_celsius",
+        "Hwmon temperature",
+    );
+    for hwmon in &snapshot.hwmons {
+        let _ = writeln!(
+            out,
+            "hddfancontrol_hwmon_temp_celsius{{hwmon=\"{}\"}} {}",
+            escape_label(&hwmon.name),
+            hwmon.temp
+        );
+    }
+
+    gauge_header(
+        &mut out,
+        "hddfancontrol_fan_speed_ratio",
+        "Fan speed set, from 0 to 1",
+    );
+    for fan in &snapshot.fans {
+        let _ = writeln!(
+            out,
+            "hddfancontrol_fan_speed_ratio{{pwm=\"{}\"}} {}",
+            escape_label(&fan.pwm.to_string_lossy()),
+            fan.speed_prct / 100.0
+        );
+    }
+
+    gauge_header(
+        &mut out,
+        "hddfancontrol_fan_boosted",
+        "Is the fan speed forced by a boost",
+    );
+    for fan in &snapshot.fans {
+        let _ = writeln!(
+            out,
+            "hddfancontrol_fan_boosted{{pwm=\"{}\"}} {}",
+            escape_label(&fan.pwm.to_string_lossy()),
+            u8::from(fan.boosted)
+        );
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::Local;
+
+    use super::*;
+    use crate::snapshot::{DriveReading, FanReading, HwmonReading};
+
+    #[test]
+    fn test_render() {
+        let snapshot = Snapshot {
+            time: Local::now(),
+            drives: vec![
+                DriveReading {
+                    name: "sda".to_owned(),
+                    path: PathBuf::from("/dev/sda"),
+                    state: "active/idle".to_owned(),
+                    spun_down: false,
+                    temp: Some(38.0),
+                },
+                DriveReading {
+                    name: "sdb".to_owned(),
+                    path: PathBuf::from("/dev/sdb"),
+                    state: "standby".to_owned(),
+                    spun_down: true,
+                    temp: None,
+                },
+            ],
+            hwmons: vec![HwmonReading {
+                name: "cpu \"0\"".to_owned(),
+                temp: 45.5,
+            }],
+            fans: vec![FanReading {
+                name: "hwmon1/2".to_owned(),
+                pwm: PathBuf::from("/sys/class/hwmon/hwmon1/pwm2"),
+                speed_prct: 50.0,
+                boosted: false,
+            }],
+        };
+        let metrics = render(&snapshot);
+        let lines: Vec<&str> = metrics.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "hddfancontrol_drive_temp_celsius{drive=\"/dev/sda\"} 38",
+                "hddfancontrol_drive_spun_down{drive=\"/dev/sda\"} 0",
+                "hddfancontrol_drive_spun_down{drive=\"/dev/sdb\"} 1",
+                "hddfancontrol_hwmon_temp_celsius{hwmon=\"cpu \\\"0\\\"\"} 45.5",
+                "hddfancontrol_fan_speed_ratio{pwm=\"/sys/class/hwmon/hwmon1/pwm2\"} 0.5",
+                "hddfancontrol_fan_boosted{pwm=\"/sys/class/hwmon/hwmon1/pwm2\"} 0",
+            ]
+        );
+        assert!(metrics.contains("# TYPE hddfancontrol_fan_speed_ratio gauge\n"));
+    }
+}
@@ -0,0 +1,745 @@
+//! Monitored drives and their control loop state, which can be reloaded while running
+
+use std::{
+    io, mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, TryLockError, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+
+use crate::{
+    cl::{
+        DriveNvmeSensor, DriveSelector, DriveTempRange, DriveWeight, HwmonSettings, NvmeSensor,
+        PwmSettings, TempOffset, TempUnit, WindowStat, ZoneSettings,
+    },
+    device::{Drive, DriveState},
+    fan::SpeedSmoother,
+    probe::{self, DeviceTempProber, ProbeRetry, Temp},
+    trend::{PeakHold, RateTracker, TempWindow},
+    zone::{self, Zone},
+};
+
+/// Drive related settings, that can be reloaded
+#[derive(Debug)]
+pub(crate) struct DriveSettings {
+    /// Drive selectors
+    pub selectors: Vec<DriveSelector>,
+    /// Drive temperature ranges
+    pub temp_ranges: Vec<DriveTempRange>,
+    /// Fan zones
+    pub zones: Vec<ZoneSettings>,
+    /// Drive and hwmon temperature offsets
+    pub temp_offsets: Vec<TempOffset>,
+    /// Drive weights
+    pub weights: Vec<DriveWeight>,
+    /// Drives to never probe while spun down
+    pub never_wake: Vec<DriveSelector>,
+}
+
+/// Settings used to setup each drive, that can not be reloaded
+#[derive(Debug)]
+pub(crate) struct DriveOptions {
+    /// hddtemp daemons
+    pub hddtemp_daemons: probe::HddtempDaemons,
+    /// User temperature command
+    pub temp_command: Option<probe::CommandMethod>,
+    /// Temperature smoothing factor
+    pub temp_smoothing_alpha: f64,
+    /// Temperature hysteresis
+    pub hysteresis_temp: Temp,
+    /// Temperature unit for logs
+    pub temp_unit: TempUnit,
+    /// Duration to use the last temperature of a spun down drive that can not be probed
+    pub sleep_temp_hold: Duration,
+    /// Decay rate of the last temperature of a spun down drive, in °C per minute
+    pub sleep_temp_decay: Temp,
+    /// Duration of the drive temperature window, zero to disable it
+    pub temp_window: Duration,
+    /// Statistic of the drive temperature window
+    pub window_stat: WindowStat,
+    /// Decay rate of the peak drive temperature, in °C per minute, if it is held
+    pub temp_decay: Option<Temp>,
+    /// NVMe temperature sensors
+    pub nvme_sensors: Vec<DriveNvmeSensor>,
+    /// Read drive warning temperature thresholds
+    pub respect_drive_thresholds: bool,
+}
+
+/// Monitored drive and its state
+pub(crate) struct MonitoredDrive {
+    /// Drive
+    pub drive: Drive,
+    /// Temperature prober, shared with the probing thread
+    prober: Arc<Mutex<Box<dyn DeviceTempProber>>>,
+    /// Can the prober probe a sleeping drive without waking it up
+    pub supports_probing_sleeping: bool,
+    /// Name of the temperature probing method
+    pub method: String,
+    /// Never probe the drive while it is spun down, even if the prober supports it
+    pub never_wake: bool,
+    /// Temperature range
+    pub temp_range: Range<Temp>,
+    /// Offset to add to probed temperatures
+    pub temp_offset: Temp,
+    /// Weight in the fan speed of its zones
+    pub weight: f64,
+    /// Temperature reported by the drive itself above which it may throttle, in Celsius
+    pub warning_temp: Option<Temp>,
+    /// Temperature moving average
+    pub temp_smoother: probe::Smoother,
+    /// Temperature hysteresis
+    pub speed_smoother: SpeedSmoother,
+    /// Temperature rate of change
+    pub rate: RateTracker,
+    /// Temperatures over a sliding time window
+    pub temp_window: TempWindow,
+    /// Peak temperature, decaying slowly
+    pub peak_hold: PeakHold,
+    /// Last temperature, used while the drive is spun down
+    pub sleep_temp: probe::SleepTempCache,
+    /// Consecutive temperature probing failures
+    pub probe_errors: u32,
+    /// Last reading, used while the zones of the drive are not due for an update
+    pub last_reading: Option<LastReading>,
+}
+
+/// Drive reading of the last update
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LastReading {
+    /// State
+    pub state: DriveState,
+    /// Temperature, if probed
+    pub temp: Option<Temp>,
+    /// Temperature used for fan speed decisions
+    pub control_temp: Option<Temp>,
+}
+
+impl MonitoredDrive {
+    /// Setup drive and its prober
+    fn new(
+        path: &Path,
+        temp_range: Range<Temp>,
+        nvme_sensor: NvmeSensor,
+        options: &DriveOptions,
+    ) -> anyhow::Result<Self> {
+        let (drive, selected) = setup_drive(
+            path,
+            &options.hddtemp_daemons,
+            options.temp_command.as_ref(),
+            nvme_sensor,
+        )?;
+        let selected = selected.ok_or_else(|| {
+            anyhow::anyhow!("No probing method found for drive {}", path.display())
+        })?;
+        let warning_temp = drive
+            .nvme_controller
+            .as_deref()
+            .filter(|_| options.respect_drive_thresholds)
+            .and_then(probe::nvme_warning_temp);
+        if let Some(warning_temp) = warning_temp {
+            log::info!(
+                "Drive {drive} warning temperature threshold: {}",
+                options.temp_unit.display(warning_temp)
+            );
+        }
+        Ok(Self {
+            drive,
+            prober: Arc::new(Mutex::new(selected.prober)),
+            supports_probing_sleeping: selected.supports_probing_sleeping,
+            method: selected.method,
+            never_wake: false,
+            temp_range,
+            temp_offset: 0.0,
+            weight: 1.0,
+            warning_temp,
+            temp_smoother: probe::Smoother::new(options.temp_smoothing_alpha),
+            speed_smoother: SpeedSmoother::new(options.hysteresis_temp, options.temp_unit),
+            rate: RateTracker::default(),
+            temp_window: TempWindow::new(options.temp_window, options.window_stat),
+            peak_hold: PeakHold::new(options.temp_decay),
+            sleep_temp: probe::SleepTempCache::new(
+                options.sleep_temp_hold,
+                options.sleep_temp_decay,
+            ),
+            probe_errors: 0,
+            last_reading: None,
+        })
+    }
+
+    /// Can the drive be probed while it is spun down
+    pub(crate) fn probes_sleeping(&self) -> bool {
+        self.supports_probing_sleeping && !self.never_wake
+    }
+
+    /// Has the device node of the drive disappeared (ie. hot unplugged) since the last hotplug
+    /// check
+    pub(crate) fn device_gone(&self) -> bool {
+        !self.drive.dev_path.exists()
+    }
+}
+
+/// Setup drive and find its prober, if any
+pub(crate) fn setup_drive(
+    path: &Path,
+    hddtemp_daemons: &probe::HddtempDaemons,
+    temp_command: Option<&probe::CommandMethod>,
+    nvme_sensor: NvmeSensor,
+) -> anyhow::Result<(Drive, Option<probe::SelectedProber>)> {
+    let drive = Drive::new(path).context("Failed to setup drive")?;
+    let selected = probe::prober(&drive, hddtemp_daemons, temp_command, nvme_sensor)
+        .with_context(|| format!("Failed to setup prober for drive {drive}"))?;
+    Ok((drive, selected))
+}
+
+/// Check that drive selectors match drives, and that there is at least one temperature source
+fn check_temp_sources(selectors: usize, drives: usize, hwmons: usize) -> anyhow::Result<()> {
+    anyhow::ensure!(selectors == 0 || drives > 0, "No drive match");
+    anyhow::ensure!(
+        drives > 0 || hwmons > 0,
+        "No drive or hwmon to get temperature from"
+    );
+    Ok(())
+}
+
+/// Canonical paths of the drives matched by a selector
+///
+/// If `lenient`, a selector matching no drive (ie. unplugged) resolves to no path.
+fn canonical_drive_paths(selector: &DriveSelector, lenient: bool) -> anyhow::Result<Vec<PathBuf>> {
+    let paths = match selector.to_drive_paths() {
+        Ok(paths) => paths,
+        Err(e) if lenient && e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => {
+            return Err(anyhow::Error::new(e)
+                .context(format!("Failed to match drives for selector {selector}")));
+        }
+    };
+    Ok(paths.iter().filter_map(|p| p.canonicalize().ok()).collect())
+}
+
+/// Canonical paths of drives matched by selectors, ignoring errors
+fn matched_drive_paths(selectors: &[DriveSelector]) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = selectors
+        .iter()
+        .filter_map(|s| s.to_drive_paths().ok())
+        .flatten()
+        .filter_map(|p| p.canonicalize().ok())
+        .collect();
+    paths.sort_unstable();
+    paths.dedup();
+    paths
+}
+
+/// Monitored drives and the fan zones referencing them
+#[derive(Default)]
+pub(crate) struct MonitoredDrives {
+    /// Drives
+    pub drives: Vec<MonitoredDrive>,
+    /// Fan zones
+    pub zones: Vec<Zone>,
+    /// Device paths of drives removed because their device node disappeared
+    missing: Vec<PathBuf>,
+    /// Canonical paths matched by the drive selectors at the last update
+    matched: Vec<PathBuf>,
+    /// Drives to update in the current iteration
+    due: Vec<bool>,
+}
+
+impl MonitoredDrives {
+    /// Setup drives, failing if any can not be set up
+    pub(crate) fn new(
+        settings: &DriveSettings,
+        options: &DriveOptions,
+        hwmons: &[HwmonSettings],
+        pwms: &[PwmSettings],
+    ) -> anyhow::Result<Self> {
+        let mut monitored = Self::default();
+        monitored.update(settings, options, hwmons, pwms, false)?;
+        Ok(monitored)
+    }
+
+    /// Update drives for new settings, keeping the state of the drives still monitored
+    ///
+    /// Drives that can not be set up anymore are dropped, and newly matched drives are added.
+    /// If this fails, current drives are left unchanged.
+    pub(crate) fn reload(
+        &mut self,
+        settings: &DriveSettings,
+        options: &DriveOptions,
+        hwmons: &[HwmonSettings],
+        pwms: &[PwmSettings],
+    ) -> anyhow::Result<()> {
+        self.update(settings, options, hwmons, pwms, true)
+    }
+
+    /// Reload drives if the device node of a drive disappeared (ie. hot unplugged), or if one
+    /// that disappeared is back
+    ///
+    /// Fans are then controlled from the remaining drives. If this fails, current drives are
+    /// left unchanged.
+    pub(crate) fn check_hotplug(
+        &mut self,
+        settings: &DriveSettings,
+        options: &DriveOptions,
+        hwmons: &[HwmonSettings],
+        pwms: &[PwmSettings],
+    ) -> anyhow::Result<()> {
+        let gone: Vec<PathBuf> = self
+            .drives
+            .iter()
+            .map(|d| &d.drive.dev_path)
+            .filter(|p| !p.exists())
+            .cloned()
+            .collect();
+        let back = self.missing.iter().any(|p| p.exists());
+        if gone.is_empty() && !back {
+            return Ok(());
+        }
+        for path in &gone {
+            log::warn!("Drive {} device node disappeared", path.display());
+        }
+        self.reload(settings, options, hwmons, pwms)?;
+        let mut missing = mem::take(&mut self.missing);
+        missing.extend(gone);
+        missing.retain(|p| !self.drives.iter().any(|d| d.drive.dev_path == *p));
+        missing.sort_unstable();
+        missing.dedup();
+        self.missing = missing;
+        Ok(())
+    }
+
+    /// Match drive selectors again, and reload drives if matches changed (ie. new drive attached)
+    ///
+    /// Drives that failed to be set up are only retried if matches changed.
+    pub(crate) fn rescan(
+        &mut self,
+        settings: &DriveSettings,
+        options: &DriveOptions,
+        hwmons: &[HwmonSettings],
+        pwms: &[PwmSettings],
+    ) -> anyhow::Result<()> {
+        if matched_drive_paths(&settings.selectors) == self.matched {
+            return Ok(());
+        }
+        log::info!("Drive selector matches changed");
+        self.reload(settings, options, hwmons, pwms)
+    }
+
+    /// Update drives for new settings, if `lenient` drives failing to be set up are skipped
+    #[expect(clippy::too_many_lines)]
+    fn update(
+        &mut self,
+        settings: &DriveSettings,
+        options: &DriveOptions,
+        hwmons: &[HwmonSettings],
+        pwms: &[PwmSettings],
+        lenient: bool,
+    ) -> anyhow::Result<()> {
+        let (default_temp_range, selector_temp_ranges) =
+            DriveTempRange::split(settings.temp_ranges.clone())?;
+        let selector_temp_ranges: Vec<(&DriveSelector, Vec<PathBuf>, &Range<Temp>)> =
+            selector_temp_ranges
+                .iter()
+                .map(|(selector, range)| {
+                    Ok((selector, canonical_drive_paths(selector, lenient)?, range))
+                })
+                .collect::<anyhow::Result<_>>()?;
+        let temp_range = |dev_path: &PathBuf| {
+            selector_temp_ranges
+                .iter()
+                .find(|(_, paths, _)| paths.contains(dev_path))
+                .map_or_else(|| default_temp_range.clone(), |(_, _, r)| (*r).clone())
+        };
+        let selector_temp_offsets: Vec<(&DriveSelector, Vec<PathBuf>, Temp)> = settings
+            .temp_offsets
+            .iter()
+            .filter(|o| o.hwmon_index(hwmons).is_none())
+            .map(|o| {
+                Ok((
+                    &o.input,
+                    canonical_drive_paths(&o.input, lenient)?,
+                    o.offset,
+                ))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let temp_offset = |dev_path: &PathBuf| {
+            selector_temp_offsets
+                .iter()
+                .find(|(_, paths, _)| paths.contains(dev_path))
+                .map_or(0.0, |(_, _, o)| *o)
+        };
+        let selector_weights: Vec<(&DriveSelector, Vec<PathBuf>, f64)> = settings
+            .weights
+            .iter()
+            .map(|w| {
+                Ok((
+                    &w.selector,
+                    canonical_drive_paths(&w.selector, lenient)?,
+                    w.weight,
+                ))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let weight = |dev_path: &PathBuf| {
+            selector_weights
+                .iter()
+                .find(|(_, paths, _)| paths.contains(dev_path))
+                .map_or(1.0, |(_, _, w)| *w)
+        };
+        let never_wake_paths: Vec<(&DriveSelector, Vec<PathBuf>)> = settings
+            .never_wake
+            .iter()
+            .map(|selector| Ok((selector, canonical_drive_paths(selector, lenient)?)))
+            .collect::<anyhow::Result<_>>()?;
+        let never_wake = |dev_path: &PathBuf| {
+            never_wake_paths
+                .iter()
+                .any(|(_, paths)| paths.contains(dev_path))
+        };
+        let (default_nvme_sensor, selector_nvme_sensors) =
+            DriveNvmeSensor::split(&options.nvme_sensors)?;
+        let selector_nvme_sensors: Vec<(DriveSelector, Vec<PathBuf>, NvmeSensor)> =
+            selector_nvme_sensors
+                .into_iter()
+                .map(|(selector, sensor)| {
+                    let paths = canonical_drive_paths(&selector, lenient)?;
+                    Ok((selector, paths, sensor))
+                })
+                .collect::<anyhow::Result<_>>()?;
+        let nvme_sensor = |dev_path: &PathBuf| {
+            selector_nvme_sensors
+                .iter()
+                .find(|(_, paths, _)| paths.contains(dev_path))
+                .map_or(default_nvme_sensor, |(_, _, s)| *s)
+        };
+
+        // Resolve drive paths, and setup new drives
+        let mut dev_paths: Vec<PathBuf> = Vec::new();
+        let mut new_drives: Vec<Option<MonitoredDrive>> = Vec::new();
+        for selector in &settings.selectors {
+            let paths = match selector.to_drive_paths() {
+                Ok(paths) => paths,
+                Err(e) if lenient && e.kind() == io::ErrorKind::NotFound => {
+                    log::warn!("Ignoring drive selector {selector}: {e}");
+                    continue;
+                }
+                Err(e) => {
+                    return Err(anyhow::Error::new(e)
+                        .context(format!("Failed to match drives for selector {selector}")));
+                }
+            };
+            for path in paths {
+                let dev_path = match path.canonicalize() {
+                    Ok(p) => p,
+                    Err(e) if lenient => {
+                        log::warn!("Ignoring drive {}: {e}", path.display());
+                        continue;
+                    }
+                    Err(e) => {
+                        return Err(anyhow::Error::new(e)
+                            .context(format!("Failed to setup drive {}", path.display())));
+                    }
+                };
+                if dev_paths.contains(&dev_path) {
+                    continue;
+                }
+                let new_drive = if self.drives.iter().any(|d| d.drive.dev_path == dev_path) {
+                    None
+                } else {
+                    match MonitoredDrive::new(
+                        &path,
+                        temp_range(&dev_path),
+                        nvme_sensor(&dev_path),
+                        options,
+                    ) {
+                        Ok(d) => Some(d),
+                        Err(e) if lenient => {
+                            log::warn!("Ignoring drive {}: {e:#}", path.display());
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    }
+                };
+                dev_paths.push(dev_path);
+                new_drives.push(new_drive);
+            }
+        }
+        check_temp_sources(settings.selectors.len(), dev_paths.len(), hwmons.len())?;
+        for (selector, paths, range) in &selector_temp_ranges {
+            if dev_paths.iter().any(|p| paths.contains(p)) {
+                log::info!(
+                    "Drive(s) {selector} temperature range set to {}-{}",
+                    options.temp_unit.display(range.start),
+                    options.temp_unit.display(range.end)
+                );
+            } else {
+                log::warn!("Temperature range selector {selector} matches no drive");
+            }
+        }
+        for (selector, paths, _) in &selector_temp_offsets {
+            if !dev_paths.iter().any(|p| paths.contains(p)) {
+                log::warn!("Temperature offset input {selector} matches no drive or hwmon");
+            }
+        }
+        for (selector, paths, _) in &selector_weights {
+            if !dev_paths.iter().any(|p| paths.contains(p)) {
+                log::warn!("Drive weight selector {selector} matches no drive");
+            }
+        }
+        for (selector, paths) in &never_wake_paths {
+            if !dev_paths.iter().any(|p| paths.contains(p)) {
+                log::warn!("Never wake selector {selector} matches no drive");
+            }
+        }
+        for (selector, paths, _) in &selector_nvme_sensors {
+            if !dev_paths.iter().any(|p| paths.contains(p)) {
+                log::warn!("NVMe sensor selector {selector} matches no drive");
+            }
+        }
+
+        let zones = zone::zones(&settings.zones, &dev_paths, hwmons, pwms, lenient)?;
+
+        // Nothing can fail from here, apply changes
+        let mut previous_drives = mem::take(&mut self.drives);
+        for (dev_path, new_drive) in dev_paths.into_iter().zip(new_drives) {
+            let mut drive = if let Some(new_drive) = new_drive {
+                if lenient {
+                    log::info!("Drive {} is now monitored", new_drive.drive);
+                }
+                new_drive
+            } else if let Some(i) = previous_drives
+                .iter()
+                .position(|d| d.drive.dev_path == dev_path)
+            {
+                let mut kept_drive = previous_drives.swap_remove(i);
+                kept_drive.temp_range = temp_range(&dev_path);
+                kept_drive
+            } else {
+                continue;
+            };
+            drive.temp_offset = temp_offset(&dev_path);
+            drive.weight = weight(&dev_path);
+            let drive_never_wake = never_wake(&dev_path);
+            if drive_never_wake && !drive.never_wake && drive.supports_probing_sleeping {
+                log::info!(
+                    "Drive {} will not be probed while spun down, its probing method supports it but it is selected by --never-wake",
+                    drive.drive
+                );
+            }
+            drive.never_wake = drive_never_wake;
+            self.drives.push(drive);
+        }
+        for drive in previous_drives {
+            log::warn!("Drive {} is not monitored anymore", drive.drive);
+        }
+        self.zones = zones;
+        self.matched = matched_drive_paths(&settings.selectors);
+        Ok(())
+    }
+
+    /// Select drives to update at `now`: drives of due zones, drives in no zone if
+    /// `default_due`, and drives never updated, and record the update of due zones
+    pub(crate) fn schedule(&mut self, now: Instant, default_due: bool) {
+        self.due = self
+            .drives
+            .iter()
+            .enumerate()
+            .map(|(drive_idx, monitored_drive)| {
+                let mut drive_zones = self
+                    .zones
+                    .iter()
+                    .filter(|z| z.has_drive(drive_idx))
+                    .peekable();
+                let zone_due = if drive_zones.peek().is_some() {
+                    drive_zones.any(|z| z.is_due(now, default_due))
+                } else {
+                    default_due
+                };
+                zone_due || monitored_drive.last_reading.is_none()
+            })
+            .collect();
+        for zone in &mut self.zones {
+            zone.set_updated(now, default_due);
+        }
+    }
+
+    /// Which drives are updated in this iteration, see [`Self::schedule`]
+    pub(crate) fn due_drives(&self) -> &[bool] {
+        &self.due
+    }
+
+    /// Probe temperature of drives flagged in `to_probe` concurrently, in background threads
+    ///
+    /// Results are in the same order as drives, `None` for drives not probed.
+    /// A probe not done after `timeout` is a failure, and its thread is left running, so that
+    /// probing the same drive fails until it finishes.
+    pub(crate) fn probe_temps(
+        &self,
+        to_probe: &[bool],
+        timeout: Duration,
+        retry: ProbeRetry,
+    ) -> Vec<Option<anyhow::Result<Temp>>> {
+        let deadline = Instant::now() + timeout;
+        let (result_tx, result_rx) = mpsc::channel();
+        let mut results: Vec<Option<anyhow::Result<Temp>>> =
+            self.drives.iter().map(|_| None).collect();
+        let mut pending = 0_usize;
+        for (idx, (monitored_drive, result)) in self.drives.iter().zip(&mut results).enumerate() {
+            if !to_probe.get(idx).copied().unwrap_or(false) {
+                continue;
+            }
+            let prober = Arc::clone(&monitored_drive.prober);
+            let result_tx = result_tx.clone();
+            let name = monitored_drive.drive.to_string();
+            let spawned = thread::Builder::new()
+                .name(format!("probe {name}"))
+                .spawn(move || {
+                    let temp = match prober.try_lock() {
+                        Ok(mut prober) => retry.probe_temp(prober.as_mut(), deadline, &name),
+                        Err(TryLockError::Poisoned(e)) => {
+                            retry.probe_temp(e.into_inner().as_mut(), deadline, &name)
+                        }
+                        Err(TryLockError::WouldBlock) => {
+                            Err(anyhow::anyhow!("Previous probe is still running"))
+                        }
+                    };
+                    let _ = result_tx.send((idx, temp));
+                });
+            match spawned {
+                Ok(_) => pending += 1,
+                Err(e) => {
+                    *result = Some(Err(
+                        anyhow::Error::new(e).context("Failed to start probing thread")
+                    ));
+                }
+            }
+        }
+        while pending > 0 {
+            let Ok((idx, temp)) =
+                result_rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            else {
+                break;
+            };
+            if let Some(result) = results.get_mut(idx) {
+                *result = Some(temp);
+            }
+            pending -= 1;
+        }
+        for (result, probe) in results.iter_mut().zip(to_probe) {
+            if *probe && result.is_none() {
+                *result = Some(Err(anyhow::anyhow!("Probe timed out after {timeout:?}")));
+            }
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use nix::sys::stat;
+
+    use super::*;
+    use crate::tests::BinaryMock;
+
+    // `Drive::new` only accepts block device nodes
+    #[ignore = "creating block device nodes needs CAP_MKNOD"]
+    #[serial_test::serial]
+    #[test]
+    fn test_check_hotplug() {
+        let dev_dir = tempfile::TempDir::new().unwrap();
+        let sdx = dev_dir.path().join("sdx");
+        let sdy = dev_dir.path().join("sdy");
+        let mknod = |path: &Path| {
+            stat::mknod(
+                path,
+                stat::SFlag::S_IFBLK,
+                stat::Mode::from_bits(0o600).unwrap(),
+                stat::makedev(7, 200),
+            )
+        };
+        mknod(&sdx).unwrap();
+        mknod(&sdy).unwrap();
+        let _hdparm = BinaryMock::new(
+            "hdparm",
+            "Model Number: TEST\n drive state is:  active/idle\n".as_bytes(),
+            &[],
+            0,
+        )
+        .unwrap();
+        let settings = DriveSettings {
+            selectors: vec![
+                sdx.to_str().unwrap().parse().unwrap(),
+                sdy.to_str().unwrap().parse().unwrap(),
+            ],
+            temp_ranges: Vec::new(),
+            zones: Vec::new(),
+            temp_offsets: Vec::new(),
+            weights: Vec::new(),
+            never_wake: Vec::new(),
+        };
+        let options = DriveOptions {
+            hddtemp_daemons: probe::HddtempDaemons::default(),
+            temp_command: Some(probe::CommandMethod {
+                command: "echo 40".to_owned(),
+                timeout: Duration::from_secs(5),
+            }),
+            temp_smoothing_alpha: 1.0,
+            hysteresis_temp: 0.0,
+            temp_unit: TempUnit::Celsius,
+            sleep_temp_hold: Duration::ZERO,
+            sleep_temp_decay: 0.0,
+            temp_window: Duration::ZERO,
+            window_stat: WindowStat::Max,
+            temp_decay: None,
+            nvme_sensors: Vec::new(),
+            respect_drive_thresholds: false,
+        };
+        let dev_paths = |monitored: &MonitoredDrives| -> Vec<PathBuf> {
+            monitored
+                .drives
+                .iter()
+                .map(|d| d.drive.dev_path.clone())
+                .collect()
+        };
+        let mut monitored = MonitoredDrives::new(&settings, &options, &[], &[]).unwrap();
+        assert_eq!(dev_paths(&monitored), vec![sdx.clone(), sdy.clone()]);
+
+        // Unplugged
+        fs::remove_file(&sdx).unwrap();
+        assert!(monitored.drives[0].device_gone());
+        monitored
+            .check_hotplug(&settings, &options, &[], &[])
+            .unwrap();
+        assert_eq!(dev_paths(&monitored), vec![sdy.clone()]);
+        assert_eq!(monitored.missing, vec![sdx.clone()]);
+        monitored
+            .check_hotplug(&settings, &options, &[], &[])
+            .unwrap();
+        assert_eq!(monitored.missing, vec![sdx.clone()]);
+
+        // Plugged back
+        mknod(&sdx).unwrap();
+        monitored
+            .check_hotplug(&settings, &options, &[], &[])
+            .unwrap();
+        assert_eq!(dev_paths(&monitored), vec![sdx, sdy]);
+        assert!(monitored.missing.is_empty());
+    }
+
+    #[test]
+    fn test_check_temp_sources() {
+        // Drives only
+        assert!(check_temp_sources(1, 2, 0).is_ok());
+        // Hwmons only
+        assert!(check_temp_sources(0, 0, 1).is_ok());
+        assert!(check_temp_sources(1, 2, 1).is_ok());
+        // Neither
+        assert!(check_temp_sources(0, 0, 0).is_err());
+        // Drive selectors matching nothing
+        assert!(check_temp_sources(1, 0, 1).is_err());
+    }
+}
@@ -0,0 +1,472 @@
+//! MQTT publishing of the control loop readings, with optional Home Assistant discovery
+//! See <https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/mqtt-v3.1.1.html>
+//! and <https://www.home-assistant.io/integrations/mqtt/#mqtt-discovery>
+
+use std::{
+    collections::HashSet,
+    io::{self, Read as _, Write as _},
+    net::{SocketAddr, TcpStream, ToSocketAddrs as _},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+
+use crate::snapshot::Snapshot;
+
+/// Broker port if not set
+const DEFAULT_PORT: u16 = 1883;
+
+/// Home Assistant discovery topic prefix
+const DISCOVERY_PREFIX: &str = "homeassistant";
+
+/// Timeout for connecting, reading from and writing to the broker
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Delay before connecting again after a failure
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// MQTT 3.1.1 protocol level
+const PROTOCOL_LEVEL: u8 = 4;
+
+/// Availability payload while the daemon runs
+const ONLINE: &str = "online";
+
+/// Availability payload once the daemon stopped or lost its connection
+const OFFLINE: &str = "offline";
+
+/// MQTT settings
+#[derive(Clone, Debug)]
+pub(crate) struct MqttSettings {
+    /// Broker `HOST[:PORT]`
+    pub broker: String,
+    /// Prefix of the topics, also used as client identifier
+    pub topic_prefix: String,
+    /// Publish Home Assistant discovery config
+    pub discovery: bool,
+}
+
+impl MqttSettings {
+    /// Topic of the daemon availability
+    fn status_topic(&self) -> String {
+        format!("{}/status", self.topic_prefix)
+    }
+}
+
+/// Publisher of the control loop readings, from a background thread so that a slow or
+/// unavailable broker never delays the control loop
+pub(crate) struct MqttPublisher {
+    /// Channel to the publishing thread
+    snapshot_tx: Option<mpsc::SyncSender<Snapshot>>,
+    /// Publishing thread
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl MqttPublisher {
+    /// Check broker address, and start publishing thread
+    pub(crate) fn spawn(settings: MqttSettings) -> anyhow::Result<Self> {
+        broker_addr(&settings.broker)?;
+        let (snapshot_tx, snapshot_rx) = mpsc::sync_channel(1);
+        let thread = thread::Builder::new()
+            .name("mqtt".to_owned())
+            .spawn(move || run(&settings, &snapshot_rx))
+            .context("Failed to start MQTT thread")?;
+        Ok(Self {
+            snapshot_tx: Some(snapshot_tx),
+            thread: Some(thread),
+        })
+    }
+
+    /// Queue readings for publishing, they are dropped if previous ones are still being published
+    pub(crate) fn publish(&self, snapshot: &Snapshot) {
+        if let Some(snapshot_tx) = self.snapshot_tx.as_ref() {
+            if let Err(mpsc::TrySendError::Full(_)) = snapshot_tx.try_send(snapshot.clone()) {
+                log::debug!("MQTT publishing still in progress, skipping readings");
+            }
+        }
+    }
+}
+
+impl Drop for MqttPublisher {
+    /// Stop publishing thread, which marks the daemon as offline and disconnects
+    fn drop(&mut self) {
+        self.snapshot_tx = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Publish received snapshots until the channel is closed, connecting again after failures
+fn run(settings: &MqttSettings, snapshot_rx: &mpsc::Receiver<Snapshot>) {
+    let mut connection: Option<Connection> = None;
+    let mut next_connect = Instant::now();
+    for snapshot in snapshot_rx {
+        if connection.is_none() && Instant::now() >= next_connect {
+            match Connection::connect(settings) {
+                Ok(c) => {
+                    log::info!("Connected to MQTT broker {}", settings.broker);
+                    connection = Some(c);
+                }
+                Err(e) => {
+                    log::warn!("{e:#}, retrying in {RECONNECT_DELAY:?}");
+                    next_connect = Instant::now() + RECONNECT_DELAY;
+                }
+            }
+        }
+        if let Some(c) = connection.as_mut() {
+            if let Err(e) = c.publish_snapshot(&snapshot) {
+                log::warn!("{e:#}, reconnecting");
+                connection = None;
+            }
+        }
+    }
+    if let Some(c) = connection {
+        if let Err(e) = c.disconnect() {
+            log::warn!("Failed to disconnect from MQTT broker: {e}");
+        }
+    }
+}
+
+/// Resolve broker address, with the default port if not set
+fn broker_addr(broker: &str) -> anyhow::Result<SocketAddr> {
+    let broker = if broker.contains(':') {
+        broker.to_owned()
+    } else {
+        format!("{broker}:{DEFAULT_PORT}")
+    };
+    broker
+        .to_socket_addrs()
+        .with_context(|| format!("Invalid MQTT broker address {broker:?}"))?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("MQTT broker address {broker:?} resolves to nothing"))
+}
+
+/// Append a length prefixed string
+fn encode_str(buf: &mut Vec<u8>, s: &str) -> io::Result<()> {
+    let len = u16::try_from(s.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "MQTT string too long"))?;
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+/// Build packet from its fixed header first byte and its content
+fn packet(header: u8, content: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut len = content.len();
+    loop {
+        #[expect(clippy::cast_possible_truncation)]
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(content);
+    packet
+}
+
+/// Sensor exposed to Home Assistant
+struct Entity {
+    /// Identifier, unique among the daemon sensors
+    id: String,
+    /// Display name
+    name: String,
+    /// Topic suffix of the state
+    topic: String,
+    /// State value
+    value: String,
+    /// Unit of the value
+    unit: &'static str,
+    /// Home Assistant device class
+    device_class: Option<&'static str>,
+}
+
+/// Topic and identifier safe version of a name
+fn sanitize(name: &str) -> String {
+    name.trim_start_matches('/')
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Sensors of a snapshot, drives not probed having none
+fn entities(snapshot: &Snapshot) -> Vec<Entity> {
+    let drives = snapshot.drives.iter().filter_map(|d| {
+        let path = d.path.to_string_lossy();
+        let id = sanitize(path.rsplit('/').next().unwrap_or_default());
+        d.temp.map(|temp| Entity {
+            name: format!("Drive {} temperature", d.name),
+            topic: format!("drive/{id}/temperature"),
+            id: format!("drive_{id}_temperature"),
+            value: format!("{temp:.1}"),
+            unit: "°C",
+            device_class: Some("temperature"),
+        })
+    });
+    let hwmons = snapshot.hwmons.iter().map(|h| {
+        let id = sanitize(&h.name);
+        Entity {
+            name: format!("Hwmon {} temperature", h.name),
+            topic: format!("hwmon/{id}/temperature"),
+            id: format!("hwmon_{id}_temperature"),
+            value: format!("{:.1}", h.temp),
+            unit: "°C",
+            device_class: Some("temperature"),
+        }
+    });
+    let fans = snapshot.fans.iter().map(|f| {
+        let id = sanitize(&f.name);
+        Entity {
+            name: format!("Fan {} speed", f.name),
+            topic: format!("fan/{id}/speed"),
+            id: format!("fan_{id}_speed"),
+            value: format!("{:.1}", f.speed_prct),
+            unit: "%",
+            device_class: None,
+        }
+    });
+    drives.chain(hwmons).chain(fans).collect()
+}
+
+/// Connection to the broker
+struct Connection<'a> {
+    /// Broker stream
+    stream: TcpStream,
+    /// Settings
+    settings: &'a MqttSettings,
+    /// Entities whose discovery config was published
+    discovered: HashSet<String>,
+}
+
+impl<'a> Connection<'a> {
+    /// Connect to the broker, with a last will marking the daemon offline, and mark it online
+    fn connect(settings: &'a MqttSettings) -> anyhow::Result<Self> {
+        let addr = broker_addr(&settings.broker)?;
+        let stream = TcpStream::connect_timeout(&addr, IO_TIMEOUT)
+            .with_context(|| format!("Failed to connect to MQTT broker {addr}"))?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let mut connection = Self {
+            stream,
+            settings,
+            discovered: HashSet::new(),
+        };
+
+        let mut content = Vec::new();
+        encode_str(&mut content, "MQTT")?;
+        // Clean session, and retained last will with QoS 0
+        content.extend_from_slice(&[PROTOCOL_LEVEL, 0b0010_0110]);
+        // No keep alive, readings are published at each iteration
+        content.extend_from_slice(&0_u16.to_be_bytes());
+        encode_str(&mut content, &sanitize(&settings.topic_prefix))?;
+        encode_str(&mut content, &settings.status_topic())?;
+        encode_str(&mut content, OFFLINE)?;
+        connection
+            .stream
+            .write_all(&packet(0x10, &content))
+            .context("Failed to send MQTT connect request")?;
+        let mut connack = [0; 4];
+        connection
+            .stream
+            .read_exact(&mut connack)
+            .context("Failed to read MQTT connect response")?;
+        anyhow::ensure!(
+            connack[..2] == [0x20, 0x02],
+            "Invalid MQTT connect response {connack:?}"
+        );
+        anyhow::ensure!(
+            connack[3] == 0,
+            "MQTT broker refused connection with return code {}",
+            connack[3]
+        );
+
+        connection.publish(&settings.status_topic(), ONLINE, true)?;
+        Ok(connection)
+    }
+
+    /// Publish a message, with at most once delivery
+    fn publish(&mut self, topic: &str, payload: &str, retain: bool) -> anyhow::Result<()> {
+        let mut content = Vec::new();
+        encode_str(&mut content, topic)?;
+        content.extend_from_slice(payload.as_bytes());
+        self.stream
+            .write_all(&packet(0x30 | u8::from(retain), &content))
+            .with_context(|| format!("Failed to publish MQTT topic {topic}"))
+    }
+
+    /// Publish snapshot values, and the discovery config of entities not published yet
+    fn publish_snapshot(&mut self, snapshot: &Snapshot) -> anyhow::Result<()> {
+        let prefix = self.settings.topic_prefix.clone();
+        for entity in entities(snapshot) {
+            let state_topic = format!("{prefix}/{}", entity.topic);
+            if self.settings.discovery && !self.discovered.contains(&entity.id) {
+                let unique_id = format!("{}_{}", sanitize(&prefix), entity.id);
+                let mut config = serde_json::json!({
+                    "name": entity.name,
+                    "unique_id": unique_id,
+                    "state_topic": state_topic,
+                    "unit_of_measurement": entity.unit,
+                    "state_class": "measurement",
+                    "availability_topic": self.settings.status_topic(),
+                    "device": {
+                        "identifiers": [sanitize(&prefix)],
+                        "name": prefix,
+                        "model": "hddfancontrol",
+                        "sw_version": env!("CARGO_PKG_VERSION"),
+                    },
+                });
+                if let (Some(device_class), Some(config)) =
+                    (entity.device_class, config.as_object_mut())
+                {
+                    config.insert("device_class".to_owned(), device_class.into());
+                }
+                self.publish(
+                    &format!("{DISCOVERY_PREFIX}/sensor/{unique_id}/config"),
+                    &config.to_string(),
+                    true,
+                )?;
+                self.discovered.insert(entity.id);
+            }
+            self.publish(&state_topic, &entity.value, false)?;
+        }
+        Ok(())
+    }
+
+    /// Mark daemon offline, and disconnect
+    fn disconnect(mut self) -> anyhow::Result<()> {
+        self.publish(&self.settings.status_topic(), OFFLINE, true)?;
+        self.stream
+            .write_all(&packet(0xE0, &[]))
+            .context("Failed to send MQTT disconnect request")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, path::Path};
+
+    use chrono::Local;
+
+    use super::*;
+    use crate::snapshot::{DriveReading, FanReading};
+
+    /// Read a packet, and return its fixed header first byte and its content
+    fn read_packet(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+        let mut header = [0; 1];
+        stream.read_exact(&mut header).ok()?;
+        let mut len = 0;
+        let mut shift = 0;
+        loop {
+            let mut byte = [0; 1];
+            stream.read_exact(&mut byte).unwrap();
+            len |= usize::from(byte[0] & 0x7F) << shift;
+            shift += 7;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut content = vec![0; len];
+        stream.read_exact(&mut content).unwrap();
+        Some((header[0], content))
+    }
+
+    #[test]
+    fn test_packet() {
+        assert_eq!(packet(0xE0, &[]), vec![0xE0, 0]);
+        let long = packet(0x30, &[0; 200]);
+        assert_eq!(long[..3], [0x30, 0xC8, 0x01]);
+        assert_eq!(long.len(), 203);
+    }
+
+    #[test]
+    fn test_publish() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let settings = MqttSettings {
+            broker: listener.local_addr().unwrap().to_string(),
+            topic_prefix: "nas/hddfancontrol".to_owned(),
+            discovery: true,
+        };
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let (connect_header, connect) = read_packet(&mut stream).unwrap();
+            assert_eq!(connect_header, 0x10);
+            assert_eq!(connect[..7], *b"\x00\x04MQTT\x04");
+            stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+            let mut publishes = Vec::new();
+            while let Some((header, content)) = read_packet(&mut stream) {
+                if header == 0xE0 {
+                    break;
+                }
+                let topic_len = usize::from(u16::from_be_bytes([content[0], content[1]]));
+                let topic = String::from_utf8(content[2..2 + topic_len].to_vec()).unwrap();
+                let payload = String::from_utf8(content[2 + topic_len..].to_vec()).unwrap();
+                publishes.push((header, topic, payload));
+            }
+            publishes
+        });
+
+        let snapshot = Snapshot {
+            time: Local::now(),
+            drives: vec![DriveReading {
+                name: "WDC WD40EFRX".to_owned(),
+                path: Path::new("/dev/sda").to_owned(),
+                state: "active/idle".to_owned(),
+                spun_down: false,
+                temp: Some(38.25),
+            }],
+            hwmons: vec![],
+            fans: vec![FanReading {
+                name: "nct6775.656/2".to_owned(),
+                pwm: "/sys/class/hwmon/hwmon1/pwm2".into(),
+                speed_prct: 40.0,
+                boosted: false,
+            }],
+        };
+        let mut connection = Connection::connect(&settings).unwrap();
+        connection.publish_snapshot(&snapshot).unwrap();
+        // Discovery config is only published once
+        connection.publish_snapshot(&snapshot).unwrap();
+        connection.disconnect().unwrap();
+
+        let publishes = broker.join().unwrap();
+        let topics: Vec<(u8, &str)> = publishes.iter().map(|(h, t, _)| (*h, t.as_str())).collect();
+        assert_eq!(
+            topics,
+            vec![
+                (0x31, "nas/hddfancontrol/status"),
+                (
+                    0x31,
+                    "homeassistant/sensor/nas_hddfancontrol_drive_sda_temperature/config"
+                ),
+                (0x30, "nas/hddfancontrol/drive/sda/temperature"),
+                (
+                    0x31,
+                    "homeassistant/sensor/nas_hddfancontrol_fan_nct6775_656_2_speed/config"
+                ),
+                (0x30, "nas/hddfancontrol/fan/nct6775_656_2/speed"),
+                (0x30, "nas/hddfancontrol/drive/sda/temperature"),
+                (0x30, "nas/hddfancontrol/fan/nct6775_656_2/speed"),
+                (0x31, "nas/hddfancontrol/status"),
+            ]
+        );
+        assert_eq!(publishes[0].2, "online");
+        assert_eq!(publishes[2].2, "38.2");
+        assert_eq!(publishes[7].2, "offline");
+        let config: serde_json::Value = serde_json::from_str(&publishes[1].2).unwrap();
+        assert_eq!(config["device_class"], "temperature");
+        assert_eq!(
+            config["state_topic"],
+            "nas/hddfancontrol/drive/sda/temperature"
+        );
+        assert_eq!(config["availability_topic"], "nas/hddfancontrol/status");
+
+        assert!(broker_addr("localhost:port").is_err());
+    }
+}
@@ -0,0 +1,287 @@
+//! PID fan speed controller
+
+use std::{str::FromStr, time::Instant};
+
+use crate::{fan::Speed, probe::Temp};
+
+/// Setpoint change above which the integral term is reset, because it was accumulated
+/// for a different operating point
+const SETPOINT_RESET_THRESHOLD: Temp = 2.0;
+
+/// PID gains, in fan speed percentage
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Gains {
+    /// Proportional gain, per °C above setpoint
+    pub kp: f64,
+    /// Integral gain, per °C·minute above setpoint
+    pub ki: f64,
+    /// Derivative gain, per °C per minute of increase
+    pub kd: f64,
+}
+
+impl FromStr for Gains {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = s.splitn(3, ':');
+        let kp = tokens
+            .next()
+            .ok_or("Missing Kp value")?
+            .parse()
+            .map_err(|_| "Invalid Kp value")?;
+        let ki = tokens
+            .next()
+            .ok_or("Missing Ki value")?
+            .parse()
+            .map_err(|_| "Invalid Ki value")?;
+        let kd = tokens
+            .next()
+            .ok_or("Missing Kd value")?
+            .parse()
+            .map_err(|_| "Invalid Kd value")?;
+        let gains = Self { kp, ki, kd };
+        if gains.is_valid() {
+            Ok(gains)
+        } else {
+            Err("Gains must be positive numbers")
+        }
+    }
+}
+
+/// Is a single gain a positive finite number
+pub(crate) fn is_valid_gain(gain: f64) -> bool {
+    gain.is_finite() && gain >= 0.0
+}
+
+impl Gains {
+    /// Are all gains positive finite numbers
+    pub(crate) fn is_valid(&self) -> bool {
+        [self.kp, self.ki, self.kd].into_iter().all(is_valid_gain)
+    }
+}
+
+/// PID controller holding a temperature to a setpoint
+#[derive(Debug)]
+pub(crate) struct Pid {
+    /// Gains
+    gains: Gains,
+    /// Target temperature
+    setpoint: Temp,
+    /// Integral of error, in °C·minute
+    integral: f64,
+    /// Previous error, and when it was computed
+    last: Option<(Instant, f64)>,
+}
+
+impl Pid {
+    /// New controller with no history
+    pub(crate) fn new(gains: Gains, setpoint: Temp) -> Self {
+        Self {
+            gains,
+            setpoint,
+            integral: 0.0,
+            last: None,
+        }
+    }
+
+    /// Change setpoint, resetting the controller history if the change is large
+    pub(crate) fn set_setpoint(&mut self, setpoint: Temp) {
+        if (setpoint - self.setpoint).abs() > SETPOINT_RESET_THRESHOLD {
+            self.reset();
+        }
+        self.setpoint = setpoint;
+    }
+
+    /// Forget controller history
+    fn reset(&mut self) {
+        self.integral = 0.0;
+        self.last = None;
+    }
+
+    /// Compute fan speed for a temperature, clamped to [`min_speed`-1]
+    ///
+    /// If there is no temperature (ie. all drives are spun down), the controller history is
+    /// reset and `min_speed` is returned.
+    pub(crate) fn update(&mut self, now: Instant, temp: Option<Temp>, min_speed: Speed) -> Speed {
+        let Some(temp) = temp else {
+            self.reset();
+            return min_speed;
+        };
+        let error = temp - self.setpoint;
+        let (elapsed_min, derivative) = match self.last {
+            Some((last_time, last_error)) => {
+                let elapsed_min = now.saturating_duration_since(last_time).as_secs_f64() / 60.0;
+                if elapsed_min > 0.0 {
+                    (elapsed_min, (error - last_error) / elapsed_min)
+                } else {
+                    (0.0, 0.0)
+                }
+            }
+            None => (0.0, 0.0),
+        };
+        self.last = Some((now, error));
+
+        let min = f64::from(min_speed);
+        let proportional_derivative = self.gains.kp * error + self.gains.kd * derivative;
+        let prev_output = self
+            .gains
+            .ki
+            .mul_add(self.integral, proportional_derivative)
+            / 100.0;
+        // Anti windup: stop integrating when the output is saturated, unless the error
+        // brings it back in range, and clamp the integral term to the speed range
+        if (min..=1.0).contains(&prev_output) || ((prev_output > 1.0) == (error < 0.0)) {
+            self.integral = error.mul_add(elapsed_min, self.integral);
+            if self.gains.ki > 0.0 {
+                self.integral = self.integral.clamp(0.0, 100.0 / self.gains.ki);
+            }
+        }
+        let output = self
+            .gains
+            .ki
+            .mul_add(self.integral, proportional_derivative)
+            / 100.0;
+        log::trace!(
+            "PID error: {error:.2}°C, integral: {:.2}°C·min, derivative: {derivative:.2}°C/min, output: {:.1}%",
+            self.integral,
+            output * 100.0
+        );
+        Speed::from_clamped(output.clamp(min, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    /// One minute
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_parse_gains() {
+        assert_eq!(
+            "5:0.5:1".parse::<Gains>().unwrap(),
+            Gains {
+                kp: 5.0,
+                ki: 0.5,
+                kd: 1.0
+            }
+        );
+        assert!("5:0.5".parse::<Gains>().is_err());
+        assert!("5:a:1".parse::<Gains>().is_err());
+        assert!("5:-1:1".parse::<Gains>().is_err());
+        assert!("inf:1:1".parse::<Gains>().is_err());
+    }
+
+    #[test]
+    fn test_is_valid_gain() {
+        assert!(is_valid_gain(0.0));
+        assert!(is_valid_gain(2.5));
+        assert!(!is_valid_gain(-0.1));
+        assert!(!is_valid_gain(f64::NAN));
+        assert!(!is_valid_gain(f64::INFINITY));
+    }
+
+    #[test]
+    fn test_update() {
+        let min_speed = Speed::try_from(0.2).unwrap();
+        let gains = Gains {
+            kp: 10.0,
+            ki: 5.0,
+            kd: 0.0,
+        };
+        let mut pid = Pid::new(gains, 40.0);
+        let start = Instant::now();
+
+        // Proportional only on first sample
+        assert_eq!(
+            pid.update(start, Some(43.0), min_speed),
+            Speed::try_from(0.3).unwrap()
+        );
+        // Integral accumulates while above setpoint
+        assert!(pid.update(start + MINUTE, Some(43.0), min_speed) > Speed::try_from(0.3).unwrap());
+        // Below setpoint, clamped to min speed
+        assert_eq!(
+            pid.update(start + MINUTE * 2, Some(30.0), min_speed),
+            min_speed
+        );
+
+        // Spun down resets history
+        assert_eq!(pid.update(start + MINUTE * 3, None, min_speed), min_speed);
+        assert_eq!(
+            pid.update(start + MINUTE * 4, Some(43.0), min_speed),
+            Speed::try_from(0.3).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_anti_windup() {
+        let min_speed = Speed::try_from(0.2).unwrap();
+        let gains = Gains {
+            kp: 10.0,
+            ki: 5.0,
+            kd: 0.0,
+        };
+        let mut pid = Pid::new(gains, 40.0);
+        let start = Instant::now();
+
+        // Saturated at full speed for a long time
+        for i in 0..100 {
+            assert!(
+                pid.update(start + MINUTE * i, Some(60.0), min_speed)
+                    .is_max()
+            );
+        }
+        // Recovers as soon as temperature gets back to setpoint
+        assert!(
+            !pid.update(start + MINUTE * 100, Some(40.0), min_speed)
+                .is_max()
+        );
+    }
+
+    #[test]
+    fn test_integral_clamp() {
+        let min_speed = Speed::try_from(0.0).unwrap();
+        let gains = Gains {
+            kp: 0.0,
+            ki: 10.0,
+            kd: 0.0,
+        };
+        let mut pid = Pid::new(gains, 40.0);
+        let start = Instant::now();
+        assert_eq!(pid.update(start, Some(60.0), min_speed), min_speed);
+        assert!(pid.update(start + MINUTE, Some(60.0), min_speed).is_max());
+        // Integral term was clamped to full speed, so it decreases immediately
+        assert!(
+            !pid.update(start + MINUTE * 2, Some(39.0), min_speed)
+                .is_max()
+        );
+    }
+
+    #[test]
+    fn test_set_setpoint() {
+        let min_speed = Speed::try_from(0.0).unwrap();
+        let gains = Gains {
+            kp: 10.0,
+            ki: 5.0,
+            kd: 0.0,
+        };
+        let mut pid = Pid::new(gains, 40.0);
+        let start = Instant::now();
+        pid.update(start, Some(42.0), min_speed);
+        pid.update(start + MINUTE, Some(42.0), min_speed);
+
+        // Small change keeps integral
+        pid.set_setpoint(41.0);
+        assert!(pid.update(start + MINUTE, Some(42.0), min_speed) > Speed::try_from(0.1).unwrap());
+
+        // Large change resets it
+        pid.set_setpoint(38.0);
+        assert_eq!(
+            pid.update(start + MINUTE * 2, Some(42.0), min_speed),
+            Speed::try_from(0.4).unwrap()
+        );
+    }
+}
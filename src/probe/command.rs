@@ -0,0 +1,120 @@
+//! User command temperature probing, for sensors not supported otherwise
+
+use std::{
+    fmt,
+    io::Read as _,
+    process::{Command, Stdio},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+
+use super::{DeviceTempProber, Drive, DriveTempProbeMethod, ProberError, Temp};
+
+/// Placeholder replaced by the drive path in the command
+const DRIVE_PLACEHOLDER: &str = "%DRIVE%";
+
+/// Interval to check if the command has exited
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// User command probing method
+#[derive(Clone, Debug)]
+pub(crate) struct Method {
+    /// Shell command printing a temperature in Celsius
+    pub command: String,
+    /// Duration after which the command is killed
+    pub timeout: Duration,
+}
+
+impl DriveTempProbeMethod for Method {
+    type Prober = Prober;
+
+    fn prober(&self, drive: &Drive) -> Result<Prober, ProberError> {
+        let mut prober = Prober {
+            command: self
+                .command
+                .replace(DRIVE_PLACEHOLDER, &drive.dev_path.to_string_lossy()),
+            timeout: self.timeout,
+        };
+        prober
+            .probe_temp()
+            .map_err(|e| ProberError::Unsupported(format!("{e:#}")))?;
+        Ok(prober)
+    }
+
+    fn supports_probing_sleeping(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "command")
+    }
+}
+
+/// User command temperature prober
+pub(crate) struct Prober {
+    /// Shell command for this drive
+    command: String,
+    /// Duration after which the command is killed
+    timeout: Duration,
+}
+
+impl DeviceTempProber for Prober {
+    fn probe_temp(&mut self) -> anyhow::Result<Temp> {
+        let mut child = Command::new("sh")
+            .args(["-c", &self.command])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to run {:?}", self.command))?;
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                anyhow::bail!("{:?} timed out after {:?}", self.command, self.timeout);
+            }
+            sleep(POLL_INTERVAL);
+        };
+        anyhow::ensure!(status.success(), "{:?} failed: {status}", self.command);
+        let mut output = String::new();
+        if let Some(mut stdout) = child.stdout.take() {
+            stdout.read_to_string(&mut output)?;
+        }
+        let temp = output
+            .trim()
+            .parse()
+            .with_context(|| format!("Failed to parse {:?} output {output:?}", self.command))?;
+        Ok(temp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_probe_temp() {
+        let prober = |command: &str, timeout_ms| Prober {
+            command: command.to_owned(),
+            timeout: Duration::from_millis(timeout_ms),
+        };
+        assert!(approx_eq!(
+            f64,
+            prober("echo ' 42.5'", 5000).probe_temp().unwrap(),
+            42.5
+        ));
+        assert!(prober("echo 42; exit 1", 5000).probe_temp().is_err());
+        assert!(prober("echo foo", 5000).probe_temp().is_err());
+        assert!(prober("sleep 5; echo 42", 100).probe_temp().is_err());
+    }
+}
@@ -1,10 +1,7 @@
 //! Drivetemp native kernel temperature probing
 //! See <https://docs.kernel.org/hwmon/drivetemp.html>
 
-use std::{
-    fmt, fs,
-    path::{Path, PathBuf},
-};
+use std::{fmt, fs, path::PathBuf};
 
 use super::{DeviceTempProber, Drive, DriveTempProbeMethod, ProberError, Temp};
 
@@ -15,40 +12,19 @@ impl DriveTempProbeMethod for Method {
     type Prober = Prober;
 
     fn prober(&self, drive: &Drive) -> Result<Prober, ProberError> {
-        #[expect(clippy::unwrap_used)] // At this point we already checked it is a valid device
-        let drive_name = drive.dev_path.file_name().unwrap();
-        let hwmon_dir = Path::new("/sys/block/")
-            .join(drive_name)
-            .join("../../hwmon");
-        if !hwmon_dir.is_dir() {
-            return Err(ProberError::Unsupported(format!(
-                "{hwmon_dir:?} does not exist"
-            )));
-        }
-        for hwmon_subdir_entry in fs::read_dir(&hwmon_dir)
+        let input_path = drive
+            .drivetemp_input()
             .map_err(|e| ProberError::Other(e.into()))?
-            .map_while(Result::ok)
-            .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
-        {
-            let hwmon_subdir = hwmon_subdir_entry.path();
-            let name_file = hwmon_subdir.join("name");
-            let name = fs::read_to_string(&name_file)
-                .map_err(|e| ProberError::Other(e.into()))?
-                .trim_end()
-                .to_owned();
-            if name == "drivetemp" {
-                let input_path = hwmon_subdir.join("temp1_input");
-                if !input_path.is_file() {
-                    return Err(ProberError::Other(anyhow::anyhow!(
-                        "{input_path:?} does not exist"
-                    )));
-                }
-                return Ok(Prober { input_path });
-            }
-        }
-        Err(ProberError::Unsupported(format!(
-            "No drivetemp hwmon found in {hwmon_dir:?}"
-        )))
+            .ok_or_else(|| {
+                ProberError::Unsupported(
+                    "No drivetemp hwmon found, is the drivetemp kernel module loaded?".to_owned(),
+                )
+            })?;
+        log::debug!(
+            "Drive '{drive}' drivetemp temperature input: {}",
+            input_path.display()
+        );
+        Ok(Prober { input_path })
     }
 
     fn supports_probing_sleeping(&self) -> bool {
@@ -68,6 +44,13 @@ pub(crate) struct Prober {
     input_path: PathBuf,
 }
 
+impl Prober {
+    /// Build a prober reading a hwmon `tempN_input` file
+    pub(super) fn new(input_path: PathBuf) -> Self {
+        Self { input_path }
+    }
+}
+
 impl DeviceTempProber for Prober {
     fn probe_temp(&mut self) -> anyhow::Result<Temp> {
         Ok(f64::from(
@@ -2,21 +2,37 @@
 
 use std::{
     fmt,
-    io::Read as _,
-    net::{SocketAddrV4, TcpStream},
+    io::{self, Read as _},
+    net::{SocketAddr, TcpStream},
     path::PathBuf,
     process::{Command, Stdio},
     str,
+    time::Duration,
 };
 
+use anyhow::Context as _;
 use itertools::Itertools as _;
 
 use super::{DeviceTempProber, Drive, DriveTempProbeMethod, ProberError, Temp};
 
+/// Timeout for connecting to, and reading from a hddtemp daemon, so that an unreachable remote
+/// host or a hung daemon does not stall probing
+const DAEMON_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Connect to a hddtemp daemon, with connect and read timeouts
+fn connect(addr: &SocketAddr) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(addr, DAEMON_TIMEOUT)?;
+    stream.set_read_timeout(Some(DAEMON_TIMEOUT))?;
+    Ok(stream)
+}
+
 /// Hddtemp daemon probing method
+#[derive(Clone)]
 pub(crate) struct DaemonMethod {
-    /// Daemon address
-    pub addr: SocketAddrV4,
+    /// Daemon addresses, queried in order
+    pub addrs: Vec<SocketAddr>,
+    /// Were the daemons explicitly configured
+    pub configured: bool,
 }
 
 impl DriveTempProbeMethod for DaemonMethod {
@@ -24,8 +40,9 @@ impl DriveTempProbeMethod for DaemonMethod {
 
     fn prober(&self, drive: &Drive) -> Result<DaemonProber, ProberError> {
         let mut prober = DaemonProber {
-            addr: self.addr,
+            addrs: self.addrs.clone(),
             device: drive.dev_path.clone(),
+            connected: None,
         };
         prober
             .probe_temp()
@@ -38,6 +55,28 @@ impl DriveTempProbeMethod for DaemonMethod {
     }
 }
 
+impl DaemonMethod {
+    /// Can any daemon be connected to
+    pub(crate) fn is_reachable(&self) -> bool {
+        self.addrs.iter().any(|addr| connect(addr).is_ok())
+    }
+
+    /// Should drives with no other probing method wait for the daemons, ie. they were
+    /// configured but none is reachable yet
+    pub(crate) fn is_pending(&self) -> bool {
+        self.configured && !self.is_reachable()
+    }
+
+    /// Prober for daemons not reachable yet, connecting again at each probe
+    pub(crate) fn pending_prober(&self, drive: &Drive) -> DaemonProber {
+        DaemonProber {
+            addrs: self.addrs.clone(),
+            device: drive.dev_path.clone(),
+            connected: Some(false),
+        }
+    }
+}
+
 impl fmt::Display for DaemonMethod {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         write!(f, "hddtemp daemon")
@@ -46,17 +85,28 @@ impl fmt::Display for DaemonMethod {
 
 /// Hddtemp daemon temperature prober
 pub(crate) struct DaemonProber {
-    /// Daemon address
-    addr: SocketAddrV4,
+    /// Daemon addresses, the first one reporting the drive temperature is used
+    addrs: Vec<SocketAddr>,
     /// Device path in /dev/
     device: PathBuf,
+    /// Was any daemon reachable at the last probe, if known
+    connected: Option<bool>,
 }
 
-impl DeviceTempProber for DaemonProber {
-    fn probe_temp(&mut self) -> anyhow::Result<Temp> {
-        let mut stream = TcpStream::connect(self.addr)?;
+impl DaemonProber {
+    /// Query a daemon for the drive temperature, `None` if it does not report the drive
+    fn query(&self, addr: &SocketAddr, stream: &mut TcpStream) -> anyhow::Result<Option<Temp>> {
         let mut buf = String::new();
-        stream.read_to_string(&mut buf)?;
+        let res = stream.read_to_string(&mut buf);
+        if let Err(e) = &res {
+            if matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) {
+                anyhow::bail!("Timed out reading from hddtemp daemon on {addr}");
+            }
+        }
+        res.with_context(|| format!("Failed to read from hddtemp daemon on {addr}"))?;
         let mut tokens = buf.split('|');
         while let Some(chunk) = tokens.next_array::<5>() {
             let dev = chunk[1];
@@ -72,9 +122,46 @@ impl DeviceTempProber for DaemonProber {
             } else if unit != "C" {
                 anyhow::bail!("Unexpected temp unit {unit:?}");
             }
-            return Ok(temp);
+            return Ok(Some(temp));
+        }
+        Ok(None)
+    }
+}
+
+impl DeviceTempProber for DaemonProber {
+    fn probe_temp(&mut self) -> anyhow::Result<Temp> {
+        let mut connect_err = None;
+        let mut connected = false;
+        let mut temp = None;
+        for addr in &self.addrs {
+            let mut stream = match connect(addr) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::debug!("Failed to connect to hddtemp daemon on {addr}: {e}");
+                    connect_err = Some(
+                        anyhow::Error::new(e)
+                            .context(format!("Failed to connect to hddtemp daemon on {addr}")),
+                    );
+                    continue;
+                }
+            };
+            connected = true;
+            temp = self.query(addr, &mut stream).transpose();
+            if temp.is_some() {
+                break;
+            }
+        }
+        match (self.connected, connected) {
+            (Some(false), true) => log::info!("Connection to hddtemp daemon established"),
+            (Some(true), false) => log::warn!("Connection to hddtemp daemon lost"),
+            _ => {}
+        }
+        self.connected = Some(connected);
+        match (temp, connect_err) {
+            (Some(temp), _) => temp,
+            (None, Some(e)) if !connected => Err(e),
+            (None, _) => anyhow::bail!("No temperature found for device {}", self.device.display()),
         }
-        anyhow::bail!("No temperature found for device {:?}", self.device);
     }
 }
 
@@ -142,7 +229,7 @@ impl DeviceTempProber for InvocationProber {
 mod tests {
     use std::{
         io::{ErrorKind, Write as _},
-        net::{Ipv4Addr, TcpListener},
+        net::{Ipv4Addr, SocketAddrV4, TcpListener},
         sync::mpsc,
         thread,
     };
@@ -152,7 +239,7 @@ mod tests {
     use super::*;
     use crate::tests::BinaryMock;
 
-    fn start_hddtemp_server() -> anyhow::Result<(SocketAddrV4, mpsc::Sender<Vec<u8>>)> {
+    fn start_hddtemp_server() -> anyhow::Result<(SocketAddr, mpsc::Sender<Vec<u8>>)> {
         let mut port = 1024;
         let (addr, listener) = loop {
             let addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, port);
@@ -175,15 +262,16 @@ mod tests {
                 }
             }
         });
-        Ok((addr, chan_tx))
+        Ok((addr.into(), chan_tx))
     }
 
     #[test]
     fn test_daemon_probe_temp() {
         let (addr, msg_tx) = start_hddtemp_server().unwrap();
         let mut prober = DaemonProber {
-            addr,
+            addrs: vec![addr],
             device: PathBuf::from("/dev/_sdz"),
+            connected: None,
         };
 
         msg_tx.send(b"|/dev/_sdz|DriveSDZ|30|C|".to_vec()).unwrap();
@@ -218,6 +306,80 @@ mod tests {
         assert!(approx_eq!(f64, prober.probe_temp().unwrap(), 30.0));
     }
 
+    /// Address nothing listens on
+    fn unreachable_addr() -> SocketAddr {
+        TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
+            .and_then(|l| l.local_addr())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_daemon_probe_temp_multiple_hosts() {
+        let (addr1, msg1_tx) = start_hddtemp_server().unwrap();
+        let (addr2, msg2_tx) = start_hddtemp_server().unwrap();
+        let mut prober = DaemonProber {
+            addrs: vec![unreachable_addr(), addr1, addr2],
+            device: PathBuf::from("/dev/_sdz"),
+            connected: None,
+        };
+
+        msg1_tx.send(b"|/dev/_sdz|DriveSDZ|30|C|".to_vec()).unwrap();
+        assert!(approx_eq!(f64, prober.probe_temp().unwrap(), 30.0));
+
+        msg1_tx.send(b"|/dev/_sdy|DriveSDY|31|C|".to_vec()).unwrap();
+        msg2_tx.send(b"|/dev/_sdz|DriveSDZ|32|C|".to_vec()).unwrap();
+        assert!(approx_eq!(f64, prober.probe_temp().unwrap(), 32.0));
+
+        msg1_tx.send(b"|/dev/_sdy|DriveSDY|31|C|".to_vec()).unwrap();
+        msg2_tx.send(b"|/dev/_sdx|DriveSDX|33|C|".to_vec()).unwrap();
+        assert!(prober.probe_temp().is_err());
+        assert_eq!(prober.connected, Some(true));
+    }
+
+    #[test]
+    fn test_daemon_probe_temp_timeout() {
+        // Connections are queued but never answered
+        let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut prober = DaemonProber {
+            addrs: vec![listener.local_addr().unwrap()],
+            device: PathBuf::from("/dev/_sdz"),
+            connected: None,
+        };
+        let start = std::time::Instant::now();
+        let err = prober.probe_temp().unwrap_err();
+        assert!(err.to_string().contains("Timed out"), "{err:#}");
+        assert!(start.elapsed() < DAEMON_TIMEOUT * 3);
+    }
+
+    #[test]
+    fn test_daemon_pending_prober() {
+        let method = DaemonMethod {
+            addrs: vec![unreachable_addr()],
+            configured: false,
+        };
+        assert!(!method.is_reachable());
+        // Default local daemon not running, drives have no probing method
+        assert!(!method.is_pending());
+        let method = DaemonMethod {
+            configured: true,
+            ..method
+        };
+        assert!(method.is_pending());
+        let mut prober = DaemonProber {
+            addrs: method.addrs,
+            device: PathBuf::from("/dev/_sdz"),
+            connected: Some(false),
+        };
+        assert!(prober.probe_temp().is_err());
+        assert_eq!(prober.connected, Some(false));
+
+        let (addr, msg_tx) = start_hddtemp_server().unwrap();
+        prober.addrs.push(addr);
+        msg_tx.send(b"|/dev/_sdz|DriveSDZ|30|C|".to_vec()).unwrap();
+        assert!(approx_eq!(f64, prober.probe_temp().unwrap(), 30.0));
+        assert_eq!(prober.connected, Some(true));
+    }
+
     #[serial_test::serial]
     #[test]
     fn test_invocation_probe_temp() {
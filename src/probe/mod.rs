@@ -1,16 +1,24 @@
 //! Temperature probing
 
+mod command;
 mod drivetemp;
 mod hddtemp;
 mod hdparm;
+mod nvme;
+mod sglogs;
 mod smartctl;
 
 use std::{
     fmt,
-    net::{Ipv4Addr, SocketAddrV4},
+    net::{Ipv4Addr, SocketAddr},
+    thread,
+    time::{Duration, Instant},
 };
 
-use crate::device::Drive;
+use crate::{cl::NvmeSensor, device::Drive};
+
+pub(crate) use command::Method as CommandMethod;
+pub(crate) use nvme::warning_temp as nvme_warning_temp;
 
 /// Error returned when
 #[derive(thiserror::Error, Debug)]
@@ -26,6 +34,76 @@ pub(crate) enum ProberError {
 /// Temperature in Celcius
 pub(crate) type Temp = f64;
 
+/// Exponential moving average of temperature readings
+#[derive(Debug)]
+pub(crate) struct Smoother {
+    /// Weight of the new reading, in ]0-1], 1 disabling smoothing
+    alpha: f64,
+    /// Last smoothed value
+    last: Option<Temp>,
+}
+
+impl Smoother {
+    /// New smoother with no history
+    pub(crate) fn new(alpha: f64) -> Self {
+        Self { alpha, last: None }
+    }
+
+    /// Add a reading, and get smoothed value
+    ///
+    /// No reading (ie. drive spun down) resets the history, so that a drive waking up
+    /// starts from its current temperature.
+    pub(crate) fn smooth(&mut self, temp: Option<Temp>) -> Option<Temp> {
+        self.last = match (temp, self.last) {
+            (Some(temp), Some(last)) => Some(self.alpha.mul_add(temp - last, last)),
+            (temp, _) => temp,
+        };
+        self.last
+    }
+}
+
+/// Last probed temperature of a drive, used while it is spun down and can not be probed
+#[derive(Debug)]
+pub(crate) struct SleepTempCache {
+    /// Duration to use the last temperature for, after it was probed
+    hold: Duration,
+    /// Decay rate toward the floor temperature, in °C per minute
+    decay: Temp,
+    /// Last probed temperature, and when
+    last: Option<(Temp, Instant)>,
+}
+
+impl SleepTempCache {
+    /// New empty cache, a zero `hold` disabling it
+    pub(crate) fn new(hold: Duration, decay: Temp) -> Self {
+        Self {
+            hold,
+            decay,
+            last: None,
+        }
+    }
+
+    /// Record a probed temperature
+    pub(crate) fn set(&mut self, temp: Temp, now: Instant) {
+        self.last = Some((temp, now));
+    }
+
+    /// Last probed temperature decayed toward `floor`, or `None` if it is older than the hold
+    /// duration
+    pub(crate) fn get(&self, now: Instant, floor: Temp) -> Option<Temp> {
+        let (temp, time) = self.last?;
+        let elapsed = now.saturating_duration_since(time);
+        if elapsed >= self.hold {
+            return None;
+        }
+        if temp <= floor {
+            return Some(temp);
+        }
+        let decayed = (self.decay * elapsed.as_secs_f64()).mul_add(-1.0 / 60.0, temp);
+        Some(decayed.max(floor))
+    }
+}
+
 /// A way to probe drive temperature
 pub(crate) trait DriveTempProbeMethod: fmt::Display {
     /// Prober generated by this method
@@ -38,18 +116,56 @@ pub(crate) trait DriveTempProbeMethod: fmt::Display {
     fn supports_probing_sleeping(&self) -> bool;
 }
 
-/// Device temperature prober
-pub(crate) trait DeviceTempProber {
+/// Device temperature prober, that can be moved to a probing thread
+pub(crate) trait DeviceTempProber: Send {
     /// Get current drive temperature
     fn probe_temp(&mut self) -> anyhow::Result<Temp>;
 }
 
+/// Retries of failed temperature probes, for transient errors
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ProbeRetry {
+    /// Maximum number of retries after the first failure
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each next one
+    pub delay: Duration,
+}
+
+impl ProbeRetry {
+    /// Probe temperature, retrying on failure until retries are exhausted, or the next retry
+    /// would happen after `deadline`
+    pub(crate) fn probe_temp(
+        &self,
+        prober: &mut dyn DeviceTempProber,
+        deadline: Instant,
+        name: &str,
+    ) -> anyhow::Result<Temp> {
+        let mut delay = self.delay;
+        let mut retry = 0;
+        loop {
+            match prober.probe_temp() {
+                Ok(temp) => return Ok(temp),
+                Err(e) if retry < self.retries && Instant::now() + delay < deadline => {
+                    retry += 1;
+                    log::debug!(
+                        "Probing {name} failed: {e:#}, retrying in {delay:?} ({retry}/{})",
+                        self.retries
+                    );
+                    thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
 /// Type erased version of `DriveTempProbeMethod`
 mod dyn_method {
     use std::fmt;
 
     use super::{DeviceTempProber, DriveTempProbeMethod, ProberError};
-    use crate::Drive;
+    use crate::device::Drive;
 
     /// Type erased version of `DriveTempProbeMethod`
     pub(super) trait DynDriveTempProbeMethod: fmt::Display {
@@ -76,33 +192,189 @@ mod dyn_method {
     }
 }
 
-/// Find first supported prober for a drive
+/// Prober found for a drive
+pub(crate) struct SelectedProber {
+    /// Prober
+    pub prober: Box<dyn DeviceTempProber>,
+    /// Name of the probing method
+    pub method: String,
+    /// Can the prober probe a sleeping drive without waking it up
+    pub supports_probing_sleeping: bool,
+    /// Is the prober waiting for a configured hddtemp daemon to be reachable
+    pub pending: bool,
+}
+
+/// Default local hddtemp daemon TCP port
+pub(crate) const HDDTEMP_DAEMON_DEFAULT_PORT: u16 = 7634;
+
+/// hddtemp daemons to query
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HddtempDaemons {
+    /// Daemon addresses, queried in order
+    pub addrs: Vec<SocketAddr>,
+    /// Were the daemons explicitly configured, in which case drives with no other probing method
+    /// wait for them to be reachable
+    pub configured: bool,
+}
+
+impl HddtempDaemons {
+    /// The given daemons, or the local one on `port` (default port if `None`) if none
+    pub(crate) fn new(daemons: &[SocketAddr], port: Option<u16>) -> Self {
+        let configured = !daemons.is_empty() || port.is_some();
+        let addrs = if daemons.is_empty() {
+            vec![SocketAddr::from((
+                Ipv4Addr::LOCALHOST,
+                port.unwrap_or(HDDTEMP_DAEMON_DEFAULT_PORT),
+            ))]
+        } else {
+            daemons.to_vec()
+        };
+        Self { addrs, configured }
+    }
+}
+
+/// Find first supported prober for a drive, trying the user command first if any
 pub(crate) fn prober(
     drive: &Drive,
-    hddtemp_daemon_port: u16,
-) -> anyhow::Result<Option<(Box<dyn DeviceTempProber>, bool)>> {
-    let methods: [Box<dyn dyn_method::DynDriveTempProbeMethod>; 6] = [
+    hddtemp_daemons: &HddtempDaemons,
+    temp_command: Option<&CommandMethod>,
+    nvme_sensor: NvmeSensor,
+) -> anyhow::Result<Option<SelectedProber>> {
+    let command_method = temp_command
+        .cloned()
+        .map(|m| Box::new(m) as Box<dyn dyn_method::DynDriveTempProbeMethod>);
+    let daemon_method = hddtemp::DaemonMethod {
+        addrs: hddtemp_daemons.addrs.clone(),
+        configured: hddtemp_daemons.configured,
+    };
+    let methods: [Box<dyn dyn_method::DynDriveTempProbeMethod>; 9] = [
         Box::new(drivetemp::Method),
+        Box::new(nvme::Method {
+            sensor: nvme_sensor,
+        }),
         Box::new(hdparm::Method),
         Box::new(smartctl::SctMethod),
-        Box::new(hddtemp::DaemonMethod {
-            addr: SocketAddrV4::new(Ipv4Addr::LOCALHOST, hddtemp_daemon_port),
-        }),
+        Box::new(daemon_method.clone()),
         Box::new(hddtemp::InvocationMethod),
+        Box::new(smartctl::JsonMethod),
         Box::new(smartctl::AttribMethod),
+        Box::new(sglogs::Method),
     ];
-    for method in methods {
+    for method in command_method.into_iter().chain(methods) {
         match method.prober(drive) {
             Ok(p) => {
                 log::info!("Using probing method '{method}' for drive '{drive}'");
-                let sqa = method.supports_probing_sleeping();
-                return Ok(Some((p, sqa)));
+                return Ok(Some(SelectedProber {
+                    prober: p,
+                    method: method.to_string(),
+                    supports_probing_sleeping: method.supports_probing_sleeping(),
+                    pending: false,
+                }));
             }
             Err(ProberError::Unsupported(e)) => {
-                log::info!("Drive '{drive}' does not support probing method '{method}': {e}",);
+                log::info!("Drive '{drive}' does not support probing method '{method}': {e}");
             }
             Err(ProberError::Other(e)) => return Err(e),
         }
     }
+    // A configured hddtemp daemon may start after us, so keep trying to connect to it if nothing
+    // else works
+    if daemon_method.is_pending() {
+        log::warn!(
+            "No probing method found for drive '{drive}', using '{daemon_method}' once it is reachable"
+        );
+        return Ok(Some(SelectedProber {
+            prober: Box::new(daemon_method.pending_prober(drive)),
+            method: daemon_method.to_string(),
+            supports_probing_sleeping: daemon_method.supports_probing_sleeping(),
+            pending: true,
+        }));
+    }
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_smoother() {
+        let mut smoother = Smoother::new(0.5);
+        assert!(approx_eq!(f64, smoother.smooth(Some(40.0)).unwrap(), 40.0));
+        assert!(approx_eq!(f64, smoother.smooth(Some(42.0)).unwrap(), 41.0));
+        assert!(approx_eq!(f64, smoother.smooth(Some(42.0)).unwrap(), 41.5));
+        assert!(smoother.smooth(None).is_none());
+        assert!(approx_eq!(f64, smoother.smooth(Some(30.0)).unwrap(), 30.0));
+
+        let mut disabled = Smoother::new(1.0);
+        assert!(approx_eq!(f64, disabled.smooth(Some(40.0)).unwrap(), 40.0));
+        assert!(approx_eq!(f64, disabled.smooth(Some(42.0)).unwrap(), 42.0));
+    }
+
+    /// Prober failing a number of times before succeeding
+    struct FlakyProber {
+        /// Remaining failures
+        failures: u32,
+    }
+
+    impl DeviceTempProber for FlakyProber {
+        fn probe_temp(&mut self) -> anyhow::Result<Temp> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                anyhow::bail!("Transient failure");
+            }
+            Ok(40.0)
+        }
+    }
+
+    #[test]
+    fn test_probe_retry() {
+        let retry = ProbeRetry {
+            retries: 2,
+            delay: Duration::from_millis(1),
+        };
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut prober = FlakyProber { failures: 2 };
+        assert!(approx_eq!(
+            f64,
+            retry.probe_temp(&mut prober, deadline, "sda").unwrap(),
+            40.0
+        ));
+        let mut failing_prober = FlakyProber { failures: 3 };
+        assert!(
+            retry
+                .probe_temp(&mut failing_prober, deadline, "sda")
+                .is_err()
+        );
+        assert_eq!(failing_prober.failures, 0);
+
+        // No retry past deadline
+        let mut late_prober = FlakyProber { failures: 1 };
+        assert!(
+            retry
+                .probe_temp(&mut late_prober, Instant::now(), "sda")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_sleep_temp_cache() {
+        let start = Instant::now();
+        let mut cache = SleepTempCache::new(Duration::from_secs(600), 0.5);
+        assert!(cache.get(start, 30.0).is_none());
+
+        cache.set(45.0, start);
+        assert!(approx_eq!(f64, cache.get(start, 30.0).unwrap(), 45.0));
+        let later = start + Duration::from_secs(120);
+        assert!(approx_eq!(f64, cache.get(later, 30.0).unwrap(), 44.0));
+        assert!(approx_eq!(f64, cache.get(later, 44.5).unwrap(), 44.5));
+        assert!(approx_eq!(f64, cache.get(later, 50.0).unwrap(), 45.0));
+        assert!(cache.get(start + Duration::from_secs(600), 30.0).is_none());
+
+        let mut disabled = SleepTempCache::new(Duration::ZERO, 0.0);
+        disabled.set(45.0, start);
+        assert!(disabled.get(start, 30.0).is_none());
+    }
+}
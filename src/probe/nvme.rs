@@ -0,0 +1,215 @@
+//! NVMe native kernel temperature probing
+//! See <https://docs.kernel.org/hwmon/nvme.html>
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use super::{DeviceTempProber, Drive, DriveTempProbeMethod, ProberError, Temp, drivetemp};
+use crate::cl::NvmeSensor;
+
+/// Label of the sensor reporting the overall controller temperature
+const COMPOSITE_LABEL: &str = "Composite";
+
+/// NVMe native kernel temperature probing method
+pub(crate) struct Method {
+    /// Sensor to probe
+    pub sensor: NvmeSensor,
+}
+
+impl DriveTempProbeMethod for Method {
+    type Prober = Prober;
+
+    fn prober(&self, drive: &Drive) -> Result<Self::Prober, ProberError> {
+        let controller = drive
+            .nvme_controller
+            .as_ref()
+            .ok_or_else(|| ProberError::Unsupported("Not a NVMe drive".to_owned()))?;
+        let input_paths = find_inputs(&Path::new("/sys/class/nvme").join(controller), self.sensor)?;
+        log::debug!(
+            "Drive '{drive}' NVMe {} sensor temperature input(s): {input_paths:?}",
+            self.sensor
+        );
+        Ok(Prober {
+            inputs: input_paths
+                .into_iter()
+                .map(drivetemp::Prober::new)
+                .collect(),
+        })
+    }
+
+    fn supports_probing_sleeping(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "native Linux NVMe hwmon")
+    }
+}
+
+/// NVMe kernel temperature prober, reporting the highest temperature of its inputs
+pub(crate) struct Prober {
+    /// Temperature input probers, not empty
+    inputs: Vec<drivetemp::Prober>,
+}
+
+impl DeviceTempProber for Prober {
+    fn probe_temp(&mut self) -> anyhow::Result<Temp> {
+        self.inputs
+            .iter_mut()
+            .try_fold(Temp::NEG_INFINITY, |max, input| {
+                Ok(max.max(input.probe_temp()?))
+            })
+    }
+}
+
+/// Warning temperature threshold of a NVMe controller, above which it may throttle
+pub(crate) fn warning_temp(controller: &str) -> Option<Temp> {
+    let inputs = find_inputs(
+        &Path::new("/sys/class/nvme").join(controller),
+        NvmeSensor::Composite,
+    )
+    .ok()?;
+    input_warning_temp(inputs.first()?)
+}
+
+/// Warning temperature threshold of a hwmon temperature input
+///
+/// The kernel reports the controller warning composite temperature threshold (WCTEMP), or a
+/// user set over temperature threshold, as the input max temperature.
+fn input_warning_temp(input: &Path) -> Option<Temp> {
+    let max_path = format!("{}_max", input.to_str()?.strip_suffix("_input")?);
+    let millis: i64 = fs::read_to_string(max_path).ok()?.trim_end().parse().ok()?;
+    #[expect(clippy::cast_precision_loss)]
+    let temp = millis as Temp / 1000.0;
+    // Controllers not reporting a threshold have a 0 Kelvin one
+    (temp > 0.0).then_some(temp)
+}
+
+/// Find temperature input files of a sensor for a NVMe controller sysfs directory
+///
+/// The composite sensor falls back to the first input if no input is labeled as such.
+fn find_inputs(controller_dir: &Path, sensor: NvmeSensor) -> Result<Vec<PathBuf>, ProberError> {
+    // Depending on kernel version, hwmon is a child of the controller or of its PCI device
+    let hwmon_dirs: Vec<PathBuf> = [controller_dir.join("device"), controller_dir.to_owned()]
+        .iter()
+        .filter_map(|d| fs::read_dir(d).ok())
+        .flat_map(|entries| entries.map_while(Result::ok))
+        .filter(|e| {
+            e.file_name()
+                .to_str()
+                .is_some_and(|f| f.starts_with("hwmon"))
+        })
+        .map(|e| e.path())
+        .collect();
+    let mut inputs: Vec<PathBuf> = hwmon_dirs
+        .iter()
+        .filter_map(|d| fs::read_dir(d).ok())
+        .flat_map(|entries| entries.map_while(Result::ok))
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|f| f.to_str())
+                .is_some_and(|f| f.starts_with("temp") && f.ends_with("_input"))
+        })
+        .collect();
+    inputs.sort();
+    let first = inputs.first().cloned().ok_or_else(|| {
+        ProberError::Unsupported(format!(
+            "No hwmon temperature input found for {}",
+            controller_dir.display()
+        ))
+    })?;
+    let labeled = |label: &str| {
+        inputs
+            .iter()
+            .find(|p| {
+                p.to_str()
+                    .and_then(|s| s.strip_suffix("_input"))
+                    .and_then(|s| fs::read_to_string(format!("{s}_label")).ok())
+                    .is_some_and(|l| l.trim_end() == label)
+            })
+            .cloned()
+    };
+    match sensor {
+        NvmeSensor::Composite => Ok(vec![labeled(COMPOSITE_LABEL).unwrap_or(first)]),
+        NvmeSensor::Max => Ok(inputs),
+        NvmeSensor::Sensor(n) => {
+            let label = format!("Sensor {n}");
+            labeled(&label).map(|p| vec![p]).ok_or_else(|| {
+                ProberError::Unsupported(format!(
+                    "No hwmon temperature input labeled {label:?} found for {}",
+                    controller_dir.display()
+                ))
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_input_warning_temp() {
+        let hwmon_dir = tempfile::TempDir::new().unwrap();
+        let input = hwmon_dir.path().join("temp1_input");
+        fs::write(&input, "40850\n").unwrap();
+        assert!(input_warning_temp(&input).is_none());
+
+        fs::write(hwmon_dir.path().join("temp1_max"), "84850\n").unwrap();
+        assert!(approx_eq!(f64, input_warning_temp(&input).unwrap(), 84.85));
+
+        fs::write(hwmon_dir.path().join("temp1_max"), "-273150\n").unwrap();
+        assert!(input_warning_temp(&input).is_none());
+    }
+
+    #[test]
+    fn test_find_inputs() {
+        let controller_dir = tempfile::TempDir::new().unwrap();
+        assert!(matches!(
+            find_inputs(controller_dir.path(), NvmeSensor::Max),
+            Err(ProberError::Unsupported(_))
+        ));
+
+        let hwmon_dir = controller_dir.path().join("device/hwmon3");
+        fs::create_dir_all(&hwmon_dir).unwrap();
+        fs::write(hwmon_dir.join("name"), "nvme\n").unwrap();
+        fs::write(hwmon_dir.join("temp2_input"), "40850\n").unwrap();
+        fs::write(hwmon_dir.join("temp2_label"), "Sensor 1\n").unwrap();
+        fs::write(hwmon_dir.join("temp3_input"), "45850\n").unwrap();
+        fs::write(hwmon_dir.join("temp3_label"), "Sensor 2\n").unwrap();
+        assert_eq!(
+            find_inputs(controller_dir.path(), NvmeSensor::Composite).unwrap(),
+            vec![hwmon_dir.join("temp2_input")]
+        );
+
+        fs::write(hwmon_dir.join("temp1_input"), "42850\n").unwrap();
+        fs::write(hwmon_dir.join("temp1_label"), "Composite\n").unwrap();
+        fs::rename(hwmon_dir.join("temp2_input"), hwmon_dir.join("temp0_input")).unwrap();
+        assert_eq!(
+            find_inputs(controller_dir.path(), NvmeSensor::Composite).unwrap(),
+            vec![hwmon_dir.join("temp1_input")]
+        );
+        assert_eq!(
+            find_inputs(controller_dir.path(), NvmeSensor::Sensor(2)).unwrap(),
+            vec![hwmon_dir.join("temp3_input")]
+        );
+        assert!(matches!(
+            find_inputs(controller_dir.path(), NvmeSensor::Sensor(3)),
+            Err(ProberError::Unsupported(_))
+        ));
+
+        let inputs = find_inputs(controller_dir.path(), NvmeSensor::Max).unwrap();
+        assert_eq!(inputs.len(), 3);
+        let mut prober = Prober {
+            inputs: inputs.into_iter().map(drivetemp::Prober::new).collect(),
+        };
+        assert!(approx_eq!(f64, prober.probe_temp().unwrap(), 45.85));
+    }
+}
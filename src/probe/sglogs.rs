@@ -0,0 +1,170 @@
+//! `sg_logs` (from `sg3_utils`) temperature probing, for SCSI/SAS drives
+
+use std::{
+    fmt, fs,
+    io::{self, BufRead as _},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::Context as _;
+
+use super::{DeviceTempProber, DriveTempProbeMethod, ProberError, Temp};
+use crate::device::Drive;
+
+/// Sysfs directory of block devices
+const SYS_CLASS_BLOCK_DIR: &str = "/sys/class/block";
+
+/// `sg_logs` temperature log page probing method
+pub(crate) struct Method;
+
+impl DriveTempProbeMethod for Method {
+    type Prober = Prober;
+
+    fn prober(&self, drive: &Drive) -> Result<Prober, ProberError> {
+        if drive.nvme_controller.is_some()
+            || !is_scsi_drive(Path::new(SYS_CLASS_BLOCK_DIR), &drive.dev_path)
+        {
+            return Err(ProberError::Unsupported("Not a SCSI/SAS drive".to_owned()));
+        }
+        let mut prober = Prober {
+            device: drive.dev_path.clone(),
+        };
+        prober
+            .probe_temp()
+            .map_err(|e| ProberError::Unsupported(format!("{e:#}")))?;
+        Ok(prober)
+    }
+
+    fn supports_probing_sleeping(&self) -> bool {
+        false
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "sg_logs")
+    }
+}
+
+/// Is a drive a SCSI drive, not an ATA one behind the kernel SCSI layer
+fn is_scsi_drive(sys_class_block_dir: &Path, dev_path: &Path) -> bool {
+    dev_path
+        .file_name()
+        .map(|name| sys_class_block_dir.join(name).join("device/vendor"))
+        .and_then(|vendor_path| fs::read_to_string(vendor_path).ok())
+        .is_some_and(|vendor| vendor.trim() != "ATA")
+}
+
+/// `sg_logs` temperature log page prober
+pub(crate) struct Prober {
+    /// Device path in /dev/
+    device: PathBuf,
+}
+
+impl DeviceTempProber for Prober {
+    fn probe_temp(&mut self) -> anyhow::Result<Temp> {
+        let output = Command::new("sg_logs")
+            .args([
+                "--temperature",
+                self.device
+                    .to_str()
+                    .ok_or_else(|| anyhow::anyhow!("Invalid device path"))?,
+            ])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .env("LANG", "C")
+            .output()
+            .map_err(|e| {
+                if e.kind() == io::ErrorKind::NotFound {
+                    anyhow::anyhow!("sg_logs not found, install sg3_utils")
+                } else {
+                    anyhow::Error::new(e).context("Failed to run sg_logs")
+                }
+            })?;
+        anyhow::ensure!(
+            output.status.success(),
+            "sg_logs failed with code {}",
+            output.status
+        );
+        let value = output
+            .stdout
+            .lines()
+            .map_while(Result::ok)
+            .find_map(|l| {
+                l.trim_start()
+                    .strip_prefix("Current temperature = ")
+                    .map(|v| v.trim().to_owned())
+            })
+            .ok_or_else(|| anyhow::anyhow!("Failed to parse sg_logs temp output"))?;
+        let temp = value
+            .strip_suffix('C')
+            .map(str::trim_end)
+            .ok_or_else(|| anyhow::anyhow!("Temperature not available: {value:?}"))?
+            .parse()
+            .with_context(|| format!("Invalid sg_logs temperature {value:?}"))?;
+        Ok(temp)
+    }
+}
+
+#[expect(clippy::shadow_unrelated)]
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::tests::BinaryMock;
+
+    #[serial_test::serial]
+    #[test]
+    fn test_sg_logs_probe_temp() {
+        let mut prober = Prober {
+            device: PathBuf::from("/dev/_sdX"),
+        };
+
+        let _sg_logs = BinaryMock::new(
+            "sg_logs",
+            "    SEAGATE   ST4000NM0023      0004\n\
+             Temperature log page  [0xd]\n  \
+               Current temperature = 34 C\n  \
+               Reference temperature = 68 C\n"
+                .as_bytes(),
+            &[],
+            0,
+        )
+        .unwrap();
+        assert!(approx_eq!(f64, prober.probe_temp().unwrap(), 34.0));
+
+        let _sg_logs = BinaryMock::new(
+            "sg_logs",
+            "Temperature log page  [0xd]\n  Current temperature = <not available>\n".as_bytes(),
+            &[],
+            0,
+        )
+        .unwrap();
+        assert!(prober.probe_temp().is_err());
+
+        let _sg_logs = BinaryMock::new(
+            "sg_logs",
+            &[],
+            "log_sense: transport: Host_status=0x01 [DID_NO_CONNECT]\n".as_bytes(),
+            99,
+        )
+        .unwrap();
+        assert!(prober.probe_temp().is_err());
+    }
+
+    #[test]
+    fn test_is_scsi_drive() {
+        let sys_dir = TempDir::new().unwrap();
+        for (name, vendor) in [("sda", "ATA     \n"), ("sdb", "SEAGATE \n")] {
+            let device_dir = sys_dir.path().join(name).join("device");
+            fs::create_dir_all(&device_dir).unwrap();
+            fs::write(device_dir.join("vendor"), vendor).unwrap();
+        }
+        assert!(!is_scsi_drive(sys_dir.path(), Path::new("/dev/sda")));
+        assert!(is_scsi_drive(sys_dir.path(), Path::new("/dev/sdb")));
+        assert!(!is_scsi_drive(sys_dir.path(), Path::new("/dev/sdc")));
+    }
+}
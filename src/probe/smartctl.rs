@@ -184,7 +184,7 @@ impl DeviceTempProber for AttribProber {
             .stdout
             .lines()
             .map_while(Result::ok)
-            .find_map(|l| l.parse::<SmartAttribLog>().ok().and_then(|a| a.temp()))
+            .find_map(|l| l.parse::<SmartAttribLog>().ok()?.temp())
             .ok_or_else(|| {
                 anyhow::anyhow!("Failed to parse smartctl attribute output, or no temp attribute")
             })?;
@@ -192,6 +192,133 @@ impl DeviceTempProber for AttribProber {
     }
 }
 
+/// Smartctl JSON output temperature probing method
+pub(crate) struct JsonMethod;
+
+impl DriveTempProbeMethod for JsonMethod {
+    type Prober = JsonProber;
+
+    fn prober(&self, drive: &Drive) -> Result<JsonProber, ProberError> {
+        let mut prober = JsonProber {
+            device: drive.dev_path.clone(),
+        };
+        prober
+            .probe_temp()
+            .map_err(|e| ProberError::Unsupported(e.to_string()))?;
+        Ok(prober)
+    }
+
+    fn supports_probing_sleeping(&self) -> bool {
+        false
+    }
+}
+
+impl fmt::Display for JsonMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "smartctl JSON")
+    }
+}
+
+/// Smartctl JSON output temperature prober
+pub(crate) struct JsonProber {
+    /// Device path in /dev/
+    device: PathBuf,
+}
+
+/// Relevant parts of `smartctl -A -j` output
+#[derive(serde::Deserialize)]
+struct JsonOutput {
+    /// Temperature summary, computed by smartctl from device specific data
+    temperature: Option<JsonTemperature>,
+    /// ATA SMART attributes
+    ata_smart_attributes: Option<JsonAttributes>,
+}
+
+/// `temperature` object of smartctl JSON output
+#[derive(serde::Deserialize)]
+struct JsonTemperature {
+    /// Current temperature
+    current: Option<Temp>,
+}
+
+/// `ata_smart_attributes` object of smartctl JSON output
+#[derive(serde::Deserialize)]
+struct JsonAttributes {
+    /// Attributes
+    table: Vec<JsonAttribute>,
+}
+
+/// SMART attribute of smartctl JSON output
+#[derive(serde::Deserialize)]
+struct JsonAttribute {
+    /// Attribute id
+    id: u16,
+    /// Attribute name
+    name: String,
+    /// Raw value
+    raw: JsonAttributeRaw,
+}
+
+/// SMART attribute raw value of smartctl JSON output
+#[derive(serde::Deserialize)]
+struct JsonAttributeRaw {
+    /// Raw value as displayed, ie. `35 (Min/Max 13/45)`
+    string: String,
+}
+
+impl JsonOutput {
+    /// Get temperature, from summary or attributes
+    fn temp(&self) -> Option<Temp> {
+        self.temperature
+            .as_ref()
+            .and_then(|t| t.current)
+            .or_else(|| {
+                self.ata_smart_attributes
+                    .as_ref()?
+                    .table
+                    .iter()
+                    .find_map(|a| {
+                        SmartAttribLog {
+                            id: a.id,
+                            name: a.name.clone(),
+                            value: a.raw.string.split_ascii_whitespace().next()?.parse().ok()?,
+                        }
+                        .temp()
+                    })
+            })
+    }
+}
+
+impl DeviceTempProber for JsonProber {
+    fn probe_temp(&mut self) -> anyhow::Result<Temp> {
+        let output = Command::new("smartctl")
+            .args([
+                "-A",
+                "-j",
+                self.device
+                    .to_str()
+                    .ok_or_else(|| anyhow::anyhow!("Invalid device path"))?,
+            ])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .env("LANG", "C")
+            .output()?;
+        // Only the 2 lowest exit status bits are fatal errors, others report drive health
+        anyhow::ensure!(
+            output
+                .status
+                .code()
+                .is_some_and(|c| c.trailing_zeros() >= 2),
+            "smartctl failed with code {}",
+            output.status
+        );
+        let json: JsonOutput = serde_json::from_slice(&output.stdout)
+            .map_err(|e| anyhow::anyhow!("Failed to parse smartctl JSON output: {e}"))?;
+        json.temp()
+            .ok_or_else(|| anyhow::anyhow!("No temperature in smartctl JSON output"))
+    }
+}
+
 #[expect(clippy::shadow_unrelated)]
 #[cfg(test)]
 mod tests {
@@ -308,4 +435,64 @@ ID# ATTRIBUTE_NAME          FLAG     VALUE WORST THRESH TYPE      UPDATED  WHEN_
         );
         assert!(approx_eq!(f64, prober.probe_temp().unwrap(), 44.0));
     }
+
+    #[serial_test::serial]
+    #[test]
+    fn test_json_probe_temp() {
+        let mut prober = JsonProber {
+            device: PathBuf::from("/dev/_sdX"),
+        };
+
+        let _smartctl = BinaryMock::new(
+            "smartctl",
+            r#"{
+  "json_format_version": [1, 0],
+  "smartctl": {"version": [7, 4], "exit_status": 4},
+  "device": {"name": "/dev/_sdX", "type": "sat"},
+  "ata_smart_attributes": {
+    "revision": 16,
+    "table": [
+      {"id": 1, "name": "Raw_Read_Error_Rate", "value": 100, "raw": {"value": 0, "string": "0"}},
+      {"id": 194, "name": "Temperature_Celsius", "value": 171, "raw": {"value": 193275232291, "string": "35 (Min/Max 13/45)"}}
+    ]
+  },
+  "temperature": {"current": 35}
+}
+"#
+            .as_bytes(),
+            &[],
+            4,
+        );
+        assert!(approx_eq!(f64, prober.probe_temp().unwrap(), 35.0));
+
+        let _smartctl = BinaryMock::new(
+            "smartctl",
+            r#"{
+  "ata_smart_attributes": {
+    "table": [
+      {"id": 190, "name": "Airflow_Temperature_Cel", "value": 56, "raw": {"value": 44, "string": "44 (0 56 56 12)"}}
+    ]
+  }
+}
+"#
+            .as_bytes(),
+            &[],
+            0,
+        );
+        assert!(approx_eq!(f64, prober.probe_temp().unwrap(), 44.0));
+
+        let _smartctl = BinaryMock::new(
+            "smartctl",
+            r#"{"ata_smart_attributes": {"table": []}}"#.as_bytes(),
+            &[],
+            0,
+        );
+        assert!(prober.probe_temp().is_err());
+
+        let _smartctl = BinaryMock::new("smartctl", "not json".as_bytes(), &[], 0);
+        assert!(prober.probe_temp().is_err());
+
+        let _smartctl = BinaryMock::new("smartctl", "{}".as_bytes(), &[], 2);
+        assert!(prober.probe_temp().is_err());
+    }
 }
@@ -0,0 +1,224 @@
+//! Fan profiles active during daily time windows (ie. quiet hours)
+
+use std::{fmt, ops::Range, str::FromStr};
+
+use chrono::NaiveTime;
+
+use anyhow::Context as _;
+
+use crate::{
+    cl::{self, Percentage, TempUnit},
+    fan::{Curve, Speed},
+    probe::Temp,
+};
+
+/// Daily time window, wrapping around midnight if its end is before its start
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TimeWindow {
+    /// Start time, included
+    start: NaiveTime,
+    /// End time, excluded
+    end: NaiveTime,
+}
+
+impl TimeWindow {
+    /// Is a time of day in the window, a window with the same start and end being the whole day
+    pub(crate) fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start == self.end || (self.start..self.end).contains(&time)
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = &'static str;
+
+    /// Parse from `HH:MM-HH:MM`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once('-').ok_or("Missing time window end")?;
+        let parse = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M");
+        Ok(Self {
+            start: parse(start).map_err(|_| "Invalid time window start, expected HH:MM")?,
+            end: parse(end).map_err(|_| "Invalid time window end, expected HH:MM")?,
+        })
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+/// Fan profile, overriding drive temperature settings during its time window
+#[derive(Debug)]
+pub(crate) struct Profile {
+    /// Name
+    pub name: String,
+    /// When the profile is active
+    pub window: TimeWindow,
+    /// Drive temperature range in Celsius, for all drives
+    pub temp_range: Option<Range<Temp>>,
+    /// Drive temperature curve in Celsius
+    pub curve: Option<Curve>,
+    /// Minimum fan speed
+    pub min_speed: Option<Speed>,
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.window)
+    }
+}
+
+/// Build profiles from their time windows, in order of precedence, and their settings by name
+pub(crate) fn profiles(
+    windows: Vec<(String, TimeWindow)>,
+    temp_ranges: Vec<(String, Range<Temp>)>,
+    curves: Vec<(String, Curve)>,
+    min_speeds_prct: &[(String, Percentage)],
+    temp_unit: TempUnit,
+) -> anyhow::Result<Vec<Profile>> {
+    let mut profiles: Vec<Profile> = Vec::with_capacity(windows.len());
+    for (name, window) in windows {
+        anyhow::ensure!(
+            profiles.iter().all(|p| p.name != name),
+            "Duplicate fan profile {name:?}"
+        );
+        profiles.push(Profile {
+            name,
+            window,
+            temp_range: None,
+            curve: None,
+            min_speed: None,
+        });
+    }
+    for (name, range) in temp_ranges {
+        let range = temp_unit.range_to_celsius(&range);
+        cl::check_temp_range(&range)
+            .with_context(|| format!("Invalid fan profile {name:?} temperature range"))?;
+        find(&mut profiles, &name)?.temp_range = Some(range);
+    }
+    for (name, curve) in curves {
+        let curve = curve.into_celsius(temp_unit);
+        curve
+            .check()
+            .with_context(|| format!("Invalid fan profile {name:?} temperature curve"))?;
+        find(&mut profiles, &name)?.curve = Some(curve);
+    }
+    for (name, prct) in min_speeds_prct {
+        find(&mut profiles, name)?.min_speed = Some(Speed::from_clamped(f64::from(*prct) / 100.0));
+    }
+    for profile in &profiles {
+        anyhow::ensure!(
+            profile.temp_range.is_none() || profile.curve.is_none(),
+            "Fan profile {:?} has both a temperature range and curve",
+            profile.name
+        );
+        anyhow::ensure!(
+            profile.temp_range.is_some() || profile.curve.is_some() || profile.min_speed.is_some(),
+            "Fan profile {:?} has no settings",
+            profile.name
+        );
+    }
+    Ok(profiles)
+}
+
+/// Find profile by name
+fn find<'a>(profiles: &'a mut [Profile], name: &str) -> anyhow::Result<&'a mut Profile> {
+    profiles
+        .iter_mut()
+        .find(|p| p.name == name)
+        .ok_or_else(|| anyhow::anyhow!("Unknown fan profile {name:?}, not set with --profile"))
+}
+
+/// Profile active at a time of day, the first one in order if several windows overlap
+pub(crate) fn active(profiles: &[Profile], time: NaiveTime) -> Option<&Profile> {
+    profiles.iter().find(|p| p.window.contains(time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_time_window() {
+        let day: TimeWindow = "07:00-22:00".parse().unwrap();
+        assert!(day.contains(time(7, 0)));
+        assert!(day.contains(time(12, 30)));
+        assert!(!day.contains(time(22, 0)));
+        assert!(!day.contains(time(3, 0)));
+        assert_eq!(day.to_string(), "07:00-22:00");
+
+        let night: TimeWindow = "22:00-07:00".parse().unwrap();
+        assert!(night.contains(time(22, 0)));
+        assert!(night.contains(time(0, 0)));
+        assert!(night.contains(time(6, 59)));
+        assert!(!night.contains(time(7, 0)));
+        assert!(!night.contains(time(12, 0)));
+
+        let all_day: TimeWindow = "00:00-00:00".parse().unwrap();
+        assert!(all_day.contains(time(13, 0)));
+
+        assert!("22:00".parse::<TimeWindow>().is_err());
+        assert!("25:00-07:00".parse::<TimeWindow>().is_err());
+        assert!("22:00-7h".parse::<TimeWindow>().is_err());
+    }
+
+    #[test]
+    fn test_profiles() {
+        let window = |name: &str, w: &str| (name.to_owned(), w.parse::<TimeWindow>().unwrap());
+        let profiles = profiles(
+            vec![
+                window("night", "22:00-07:00"),
+                window("evening", "18:00-23:00"),
+            ],
+            vec![("night".to_owned(), 40.0..60.0)],
+            vec![("evening".to_owned(), "35:0,45:50,50:100".parse().unwrap())],
+            &[("night".to_owned(), 10)],
+            TempUnit::Celsius,
+        )
+        .unwrap();
+        assert_eq!(profiles.len(), 2);
+
+        // First match wins if windows overlap
+        assert_eq!(active(&profiles, time(22, 30)).unwrap().name, "night");
+        assert_eq!(active(&profiles, time(19, 0)).unwrap().name, "evening");
+        assert!(active(&profiles, time(12, 0)).is_none());
+        let night = active(&profiles, time(1, 0)).unwrap();
+        assert_eq!(night.temp_range, Some(40.0..60.0));
+        assert_eq!(night.min_speed, Some(Speed::from_clamped(0.1)));
+
+        assert!(
+            super::profiles(
+                vec![window("night", "22:00-07:00")],
+                vec![("day".to_owned(), 40.0..60.0)],
+                vec![],
+                &[],
+                TempUnit::Celsius,
+            )
+            .is_err()
+        );
+        assert!(
+            super::profiles(
+                vec![window("night", "22:00-07:00")],
+                vec![],
+                vec![],
+                &[],
+                TempUnit::Celsius,
+            )
+            .is_err()
+        );
+    }
+}
@@ -3,20 +3,33 @@
 // See https://docs.kernel.org/hwmon/pwm-fan.html
 
 use std::{
-    fmt,
+    cell::Cell,
+    fmt, fs,
     io::{self, ErrorKind},
+    iter,
+    os::unix::fs::PermissionsExt as _,
     path::{Path, PathBuf},
     time::Duration,
 };
 
 use anyhow::Context as _;
 use backoff::ExponentialBackoffBuilder;
+use nix::unistd;
 
 use crate::sysfs::{ensure_sysfs_dir, ensure_sysfs_file, read_value, write_value};
 
 /// PWM sysfs value
 pub(crate) type Value = u8;
 
+/// Consecutive write verification failures from which they are logged as errors
+const VERIFY_ERROR_THRESHOLD: u32 = 3;
+
+/// Sysfs directory of hwmon devices, where PWMs are discovered
+const HWMON_CLASS_DIR: &str = "/sys/class/hwmon";
+
+/// `pwmX_enable` value handing control to the driver automatic fan curve, for most drivers
+pub(crate) const AUTO_CURVE_MODE: u8 = 2;
+
 /// Stateless PWM control
 /// T is the type of RPM file path
 #[derive(Clone)]
@@ -27,6 +40,16 @@ pub(crate) struct Pwm<T> {
     rpm: T,
     /// `pwmX_enable` sysfs filepath
     mode: Option<PathBuf>,
+    /// Control mode written to take manual control
+    manual_mode: ControlMode,
+    /// Value for full speed
+    max: Value,
+    /// Are higher values lower speeds
+    inverted: bool,
+    /// Maximum difference between written and read back values, if writes are verified
+    verify_tolerance: Option<Value>,
+    /// Consecutive write verification failures
+    verify_failures: Cell<u32>,
     /// Kernel device name (different from PWM name)
     device: String,
     /// Index among driver
@@ -97,7 +120,7 @@ impl Pwm<()> {
                 }
                 Err(e) => Err(backoff::Error::permanent(e)),
             },
-            |_e, d| log::warn!("{path:?} does not exist, retrying in {d:?}"),
+            |_e, d| log::warn!("{} does not exist, retrying in {d:?}", path.display()),
         )
         .map_err(|e| match e {
             backoff::Error::Permanent(e) => e,
@@ -107,7 +130,7 @@ impl Pwm<()> {
         let val_path_fname = path
             .file_name()
             .and_then(|f| f.to_str())
-            .ok_or_else(|| anyhow::anyhow!("Invalid path: {path:?}"))?;
+            .ok_or_else(|| anyhow::anyhow!("Invalid path: {}", path.display()))?;
         let num = val_path_fname
             .chars()
             .skip_while(|c| !c.is_ascii_digit())
@@ -118,28 +141,100 @@ impl Pwm<()> {
             })?;
         let mode_path =
             ensure_sysfs_file(&path.with_file_name(format!("{val_path_fname}_enable"))).ok();
+        let max_path = path.with_file_name(format!("{val_path_fname}_max"));
+        let max = if max_path.is_file() {
+            let max: Value = read_value(&max_path)
+                .with_context(|| format!("Failed to read from {}", max_path.display()))?;
+            anyhow::ensure!(max > 0, "Invalid PWM maximum value {max}");
+            log::debug!("PWM {} maximum value: {max}", path.display());
+            max
+        } else {
+            Value::MAX
+        };
         let device = ensure_sysfs_dir(&path.with_file_name("device"))
             .or_else(|_| ensure_sysfs_dir(&path.with_file_name("driver")))
             .context("Failed to get path for device/driver")?
             .file_name()
             .and_then(|f| f.to_str())
-            .ok_or_else(|| anyhow::anyhow!("Invalid device path for {path:?}"))?
+            .ok_or_else(|| anyhow::anyhow!("Invalid device path for {}", path.display()))?
             .to_owned();
         Ok(Self {
             val: path.clone(),
             rpm: (),
             mode: mode_path,
+            manual_mode: ControlMode::Software,
+            max,
+            inverted: false,
+            verify_tolerance: None,
+            verify_failures: Cell::new(0),
             device,
             num,
         })
     }
 
+    /// Build a new PWM where higher values are lower speeds if `inverted`
+    ///
+    /// Values are still handled as for a normal PWM, and inverted when written or read.
+    pub(crate) fn with_inverted(self, inverted: bool) -> Self {
+        Self { inverted, ..self }
+    }
+
+    /// Build a new PWM reading back written values, and logging if they differ by more than
+    /// `tolerance`, if set
+    pub(crate) fn with_verify_writes(self, tolerance: Option<Value>) -> Self {
+        Self {
+            verify_tolerance: tolerance,
+            ..self
+        }
+    }
+
+    /// Build a new PWM with a value for full speed other than the detected one, if set
+    pub(crate) fn with_max(self, max: Option<Value>) -> Self {
+        Self {
+            max: max.unwrap_or(self.max),
+            ..self
+        }
+    }
+
+    /// Build a new PWM taking manual control with this `pwmX_enable` value if set, for drivers
+    /// not using 1
+    pub(crate) fn with_manual_mode(self, value: Option<u8>) -> Self {
+        Self {
+            manual_mode: value.map_or(self.manual_mode, ControlMode::from),
+            ..self
+        }
+    }
+
     /// Build a new PWM with RPM file path set
     pub(crate) fn with_rpm_file(self, rpm_path: &Path) -> anyhow::Result<Pwm<PathBuf>> {
         Ok(Pwm {
             val: self.val,
             rpm: ensure_sysfs_file(rpm_path)?,
             mode: self.mode,
+            manual_mode: self.manual_mode,
+            max: self.max,
+            inverted: self.inverted,
+            verify_tolerance: self.verify_tolerance,
+            verify_failures: self.verify_failures,
+            device: self.device,
+            num: self.num,
+        })
+    }
+
+    /// Build a new PWM with RPM file path optionally set
+    pub(crate) fn with_optional_rpm_file(
+        self,
+        rpm_path: Option<&Path>,
+    ) -> anyhow::Result<Pwm<Option<PathBuf>>> {
+        Ok(Pwm {
+            val: self.val,
+            rpm: rpm_path.map(ensure_sysfs_file).transpose()?,
+            mode: self.mode,
+            manual_mode: self.manual_mode,
+            max: self.max,
+            inverted: self.inverted,
+            verify_tolerance: self.verify_tolerance,
+            verify_failures: self.verify_failures,
             device: self.device,
             num: self.num,
         })
@@ -153,15 +248,83 @@ impl Pwm<()> {
 }
 
 impl<T> Pwm<T> {
+    /// Get value for full speed
+    pub(crate) fn max(&self) -> Value {
+        self.max
+    }
+
+    /// Get control mode for manual control
+    pub(crate) fn manual_mode(&self) -> ControlMode {
+        self.manual_mode
+    }
+
+    /// Check that the PWM value and control mode files can be written, to fail at startup instead
+    /// of at the first speed change if permissions are missing
+    pub(crate) fn check_writable(&self) -> anyhow::Result<()> {
+        for path in iter::once(&self.val).chain(self.mode.as_ref()) {
+            unistd::access(path, unistd::AccessFlags::W_OK).with_context(|| {
+                format!(
+                    "{} is not writable, run as root, or give write access to the daemon user with a udev rule",
+                    path.display()
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Convert between values for a normal PWM and sysfs values
+    pub(crate) fn raw_value(&self, val: Value) -> Value {
+        if self.inverted {
+            self.max.saturating_sub(val)
+        } else {
+            val
+        }
+    }
+
     /// Set PWM value
     pub(crate) fn set(&self, val: Value) -> anyhow::Result<()> {
-        log::trace!("Set PWM {self} to {val}");
-        write_value(&self.val, val).with_context(|| format!("Failed to write to {:?}", self.val))
+        let raw_val = self.raw_value(val);
+        log::trace!("Set PWM {self} to {raw_val}");
+        write_value(&self.val, raw_val)
+            .with_context(|| format!("Failed to write to {}", self.val.display()))?;
+        if let Some(tolerance) = self.verify_tolerance {
+            self.verify(raw_val, tolerance)?;
+        }
+        Ok(())
+    }
+
+    /// Read back written raw value, and log if it differs by more than `tolerance`
+    ///
+    /// Persistent differences are logged as errors, because fan control is not working then,
+    /// ie. if another driver controls the PWM.
+    fn verify(&self, written: Value, tolerance: Value) -> anyhow::Result<()> {
+        let read: Value = read_value(&self.val)
+            .with_context(|| format!("Failed to read from {}", self.val.display()))?;
+        let failures = self.verify_failures.get();
+        if read.abs_diff(written) <= tolerance {
+            if failures > 0 {
+                log::info!("PWM {self} value read back matches written value again");
+                self.verify_failures.set(0);
+            }
+            return Ok(());
+        }
+        let failures = failures.saturating_add(1);
+        self.verify_failures.set(failures);
+        if failures >= VERIFY_ERROR_THRESHOLD {
+            log::error!(
+                "PWM {self} value read back {read} differs from written value {written} for {failures} consecutive writes, fan speed is not controlled, another driver or the firmware may control it"
+            );
+        } else {
+            log::warn!("PWM {self} value read back {read} differs from written value {written}");
+        }
+        Ok(())
     }
 
     /// Get PWM value
     pub(crate) fn get(&self) -> anyhow::Result<Value> {
-        read_value(&self.val).with_context(|| format!("Failed to read from {:?}", self.val))
+        read_value(&self.val)
+            .map(|v| self.raw_value(v))
+            .with_context(|| format!("Failed to read from {}", self.val.display()))
     }
 
     /// Get PWM control mode
@@ -169,7 +332,7 @@ impl<T> Pwm<T> {
         if let Some(mode) = self.mode.as_ref() {
             Ok(Some(
                 read_value::<u8>(mode)
-                    .with_context(|| format!("Failed to read from {mode:?}"))?
+                    .with_context(|| format!("Failed to read from {}", mode.display()))?
                     .into(),
             ))
         } else {
@@ -181,12 +344,49 @@ impl<T> Pwm<T> {
     pub(crate) fn set_mode(&self, mode: ControlMode) -> anyhow::Result<()> {
         if let Some(mode_path) = self.mode.as_ref() {
             write_value::<u8>(mode_path, mode.into())
-                .with_context(|| format!("Failed to write to {mode_path:?}"))
+                .with_context(|| format!("Failed to write to {}", mode_path.display()))
         } else {
             Ok(())
         }
     }
 
+    /// Number of automatic fan curve points supported by the driver, from its
+    /// `pwmX_auto_pointY_temp` and `pwmX_auto_pointY_pwm` files
+    pub(crate) fn auto_point_count(&self) -> usize {
+        let mut count = 0;
+        loop {
+            let (temp_path, val_path) = self.auto_point_paths(count + 1);
+            if !temp_path.exists() || !val_path.exists() {
+                return count;
+            }
+            count += 1;
+        }
+    }
+
+    /// Temperature and value sysfs filepaths of an automatic fan curve point, starting from 1
+    fn auto_point_paths(&self, index: usize) -> (PathBuf, PathBuf) {
+        let name = self
+            .val
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        (
+            self.val
+                .with_file_name(format!("{name}_auto_point{index}_temp")),
+            self.val
+                .with_file_name(format!("{name}_auto_point{index}_pwm")),
+        )
+    }
+
+    /// Set an automatic fan curve point, starting from 1, with temperature in millidegrees Celsius
+    pub(crate) fn set_auto_point(&self, index: usize, temp: i64, val: Value) -> anyhow::Result<()> {
+        let (temp_path, val_path) = self.auto_point_paths(index);
+        write_value(&temp_path, temp)
+            .with_context(|| format!("Failed to write to {}", temp_path.display()))?;
+        write_value(&val_path, self.raw_value(val))
+            .with_context(|| format!("Failed to write to {}", val_path.display()))
+    }
+
     /// Get current state
     pub(crate) fn get_state(&self) -> anyhow::Result<State> {
         Ok(State {
@@ -208,10 +408,114 @@ impl<T> Pwm<T> {
 impl Pwm<PathBuf> {
     /// Get fan RPM value
     pub(crate) fn get_rpm(&self) -> anyhow::Result<u32> {
-        read_value(&self.rpm).with_context(|| format!("Failed to read from {:?}", self.rpm))
+        read_value(&self.rpm).with_context(|| format!("Failed to read from {}", self.rpm.display()))
     }
 }
 
+impl Pwm<Option<PathBuf>> {
+    /// Get fan RPM value, if RPM file path is set
+    pub(crate) fn get_rpm(&self) -> anyhow::Result<Option<u32>> {
+        self.rpm
+            .as_ref()
+            .map(|rpm| {
+                read_value(rpm).with_context(|| format!("Failed to read from {}", rpm.display()))
+            })
+            .transpose()
+    }
+}
+
+/// Discover PWMs that can be controlled, in hwmon devices
+///
+/// `manual_mode` gives the `pwmN_enable` value taking manual control of a PWM.
+pub(crate) fn discover<F>(dry_run: bool, manual_mode: F) -> anyhow::Result<Vec<PathBuf>>
+where
+    F: Fn(&Path) -> u8,
+{
+    let pwm_paths = discover_in(Path::new(HWMON_CLASS_DIR), dry_run, manual_mode)?;
+    for pwm_path in &pwm_paths {
+        log::info!("Discovered PWM {}", pwm_path.display());
+    }
+    anyhow::ensure!(!pwm_paths.is_empty(), "No controllable PWM found");
+    Ok(pwm_paths)
+}
+
+/// Discover writable `pwmN` files in the device directories of `class_dir`, skipping those whose
+/// `pwmN_enable` file can not be set to manual control
+///
+/// In dry run mode, nothing is written and `pwmN_enable` files are only checked to be writable.
+fn discover_in<F>(class_dir: &Path, dry_run: bool, manual_mode: F) -> anyhow::Result<Vec<PathBuf>>
+where
+    F: Fn(&Path) -> u8,
+{
+    let mut pwm_paths: Vec<PathBuf> = fs::read_dir(class_dir)
+        .with_context(|| format!("Failed to list {}", class_dir.display()))?
+        .map_while(Result::ok)
+        .filter_map(|e| fs::read_dir(e.path()).ok())
+        .flat_map(|entries| entries.map_while(Result::ok))
+        .filter(|e| {
+            e.file_name().to_str().is_some_and(|f| {
+                f.strip_prefix("pwm")
+                    .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+            })
+        })
+        .map(|e| e.path())
+        .filter(|p| {
+            let writable = p
+                .metadata()
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o200 != 0);
+            if !writable {
+                log::debug!("Skipping read only PWM {}", p.display());
+            }
+            writable
+        })
+        .collect();
+    pwm_paths.retain(|p| match can_set_manual(p, manual_mode(p), dry_run) {
+        Ok(()) => true,
+        Err(e) => {
+            log::info!("Skipping PWM {}: {e:#}", p.display());
+            false
+        }
+    });
+    pwm_paths.sort_unstable();
+    Ok(pwm_paths)
+}
+
+/// Check that the control mode of a PWM can be set to manual with `manual` value, restoring it
+/// afterwards, or only that it is writable in dry run mode
+fn can_set_manual(pwm_path: &Path, manual: u8, dry_run: bool) -> anyhow::Result<()> {
+    let mut mode_path = pwm_path.as_os_str().to_owned();
+    mode_path.push("_enable");
+    let mode_path = PathBuf::from(mode_path);
+    if !mode_path.exists() {
+        return Ok(());
+    }
+    let mode: u8 = read_value(&mode_path)
+        .with_context(|| format!("Failed to read from {}", mode_path.display()))?;
+    if mode == manual {
+        return Ok(());
+    }
+    if dry_run {
+        unistd::access(&mode_path, unistd::AccessFlags::W_OK)
+            .with_context(|| format!("{} is not writable", mode_path.display()))?;
+        log::info!(
+            "Support of manual control mode by PWM {} is not verified in dry run mode",
+            pwm_path.display()
+        );
+        return Ok(());
+    }
+    let set = write_value(&mode_path, manual)
+        .with_context(|| format!("Failed to write to {}", mode_path.display()))
+        .and_then(|()| {
+            let new_mode: u8 = read_value(&mode_path)
+                .with_context(|| format!("Failed to read from {}", mode_path.display()))?;
+            anyhow::ensure!(new_mode == manual, "Manual control mode is not supported");
+            Ok(())
+        });
+    write_value(&mode_path, mode)
+        .with_context(|| format!("Failed to write to {}", mode_path.display()))?;
+    set
+}
+
 impl<T> fmt::Display for Pwm<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         write!(f, "{}/{}", self.device, self.num)
@@ -221,10 +525,11 @@ impl<T> fmt::Display for Pwm<T> {
 #[cfg(test)]
 pub(crate) mod tests {
     use std::{
-        fs::{File, OpenOptions, create_dir},
+        fs::{self, File, OpenOptions, create_dir},
         io::Read as _,
         os::unix::{fs::symlink, prelude::OpenOptionsExt as _},
         str,
+        time::SystemTime,
     };
 
     use io::Write as _;
@@ -233,15 +538,112 @@ pub(crate) mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_discover_in() {
+        let class_dir = TempDir::new().unwrap();
+        let hwmon1 = class_dir.path().join("hwmon1");
+        create_dir(&hwmon1).unwrap();
+        for (name, content) in [
+            ("pwm1", "128\n"),
+            ("pwm1_enable", "2\n"),
+            ("pwm2", "128\n"),
+            ("pwm2_mode", "1\n"),
+            ("pwm3", "128\n"),
+            ("pwm4", "128\n"),
+            ("fan1_input", "1200\n"),
+        ] {
+            fs::write(hwmon1.join(name), content).unwrap();
+        }
+        // Read only
+        fs::set_permissions(hwmon1.join("pwm3"), fs::Permissions::from_mode(0o444)).unwrap();
+        // Mode can not be written
+        create_dir(hwmon1.join("pwm4_enable")).unwrap();
+        fs::write(class_dir.path().join("not_a_dir"), "").unwrap();
+
+        assert_eq!(
+            discover_in(class_dir.path(), false, |_| 1).unwrap(),
+            vec![hwmon1.join("pwm1"), hwmon1.join("pwm2")]
+        );
+        // Original mode is restored
+        assert_eq!(
+            fs::read_to_string(hwmon1.join("pwm1_enable")).unwrap(),
+            "2\n"
+        );
+
+        // Dry run does not write the mode
+        let mode_file = File::options()
+            .write(true)
+            .open(hwmon1.join("pwm1_enable"))
+            .unwrap();
+        let old_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        mode_file.set_modified(old_time).unwrap();
+        assert_eq!(
+            discover_in(class_dir.path(), true, |_| 1).unwrap(),
+            vec![hwmon1.join("pwm1"), hwmon1.join("pwm2")]
+        );
+        assert_eq!(
+            fs::metadata(hwmon1.join("pwm1_enable"))
+                .unwrap()
+                .modified()
+                .unwrap(),
+            old_time
+        );
+
+        // PWM already in its configured manual mode is not written
+        fs::write(hwmon1.join("pwm1_enable"), "5\n").unwrap();
+        mode_file.set_modified(old_time).unwrap();
+        let manual_mode = |p: &Path| if p.ends_with("pwm1") { 5 } else { 1 };
+        assert_eq!(
+            discover_in(class_dir.path(), false, manual_mode).unwrap(),
+            vec![hwmon1.join("pwm1"), hwmon1.join("pwm2")]
+        );
+        assert_eq!(
+            fs::metadata(hwmon1.join("pwm1_enable"))
+                .unwrap()
+                .modified()
+                .unwrap(),
+            old_time
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let dir = TempDir::new().unwrap();
+        let pwm_path = dir.path().join("pwm1");
+        fs::write(&pwm_path, "0\n").unwrap();
+        let pwm = Pwm {
+            val: pwm_path.clone(),
+            rpm: (),
+            mode: None,
+            manual_mode: ControlMode::Software,
+            max: Value::MAX,
+            inverted: false,
+            verify_tolerance: None,
+            verify_failures: Cell::new(0),
+            device: "dev".to_owned(),
+            num: 1,
+        }
+        .with_verify_writes(Some(2));
+
+        pwm.set(100).unwrap();
+        assert_eq!(pwm.verify_failures.get(), 0);
+        for failures in 1..=3 {
+            pwm.verify(104, 2).unwrap();
+            assert_eq!(pwm.verify_failures.get(), failures);
+        }
+        pwm.verify(102, 2).unwrap();
+        assert_eq!(pwm.verify_failures.get(), 0);
+    }
+
     pub(crate) struct FakePwm {
         _dir: TempDir,
         pub pwm_path: PathBuf,
         pub val_file_read: File,
-        val_file_write: File,
+        pub val_file_write: File,
         pub rpm_path: PathBuf,
         _rpm_file_read: File,
-        rpm_file_write: File,
-        mode_file_read: File,
+        pub rpm_file_write: File,
+        pub mode_file_read: File,
         pub mode_file_write: File,
     }
 
@@ -356,6 +758,100 @@ pub(crate) mod tests {
         assert_file_content(&mut fake_pwm.mode_file_read, "2\n");
     }
 
+    #[test]
+    fn test_auto_points() {
+        let fake_pwm = FakePwm::new();
+        let pwm = Pwm::new(&fake_pwm.pwm_path).unwrap().with_inverted(true);
+        assert_eq!(pwm.auto_point_count(), 0);
+
+        for index in 1..=3 {
+            for suffix in ["temp", "pwm"] {
+                fs::write(
+                    fake_pwm
+                        .pwm_path
+                        .with_file_name(format!("pwm2_auto_point{index}_{suffix}")),
+                    "0\n",
+                )
+                .unwrap();
+            }
+        }
+        fs::write(
+            fake_pwm.pwm_path.with_file_name("pwm2_auto_point4_temp"),
+            "0\n",
+        )
+        .unwrap();
+        assert_eq!(pwm.auto_point_count(), 3);
+
+        pwm.set_auto_point(2, 45000, 200).unwrap();
+        assert_eq!(
+            fs::read_to_string(fake_pwm.pwm_path.with_file_name("pwm2_auto_point2_temp")).unwrap(),
+            "45000\n"
+        );
+        assert_eq!(
+            fs::read_to_string(fake_pwm.pwm_path.with_file_name("pwm2_auto_point2_pwm")).unwrap(),
+            "55\n"
+        );
+    }
+
+    #[test]
+    fn test_check_writable() {
+        let fake_pwm = FakePwm::new();
+        let pwm = Pwm::new(&fake_pwm.pwm_path).unwrap();
+        assert!(pwm.check_writable().is_ok());
+
+        fs::remove_file(fake_pwm.pwm_path.with_file_name("pwm2_enable")).unwrap();
+        let err = pwm.check_writable().unwrap_err();
+        assert!(err.to_string().contains("pwm2_enable"), "{err}");
+    }
+
+    #[test]
+    fn test_manual_mode() {
+        let fake_pwm = FakePwm::new();
+        let pwm = Pwm::new(&fake_pwm.pwm_path).unwrap();
+        assert_eq!(pwm.manual_mode(), ControlMode::Software);
+        assert_eq!(
+            pwm.clone().with_manual_mode(None).manual_mode(),
+            ControlMode::Software
+        );
+        assert_eq!(
+            pwm.clone().with_manual_mode(Some(0)).manual_mode(),
+            ControlMode::Off
+        );
+        assert_eq!(
+            pwm.with_manual_mode(Some(5)).manual_mode(),
+            ControlMode::Other(5)
+        );
+    }
+
+    #[test]
+    fn test_inverted() {
+        let mut fake_pwm = FakePwm::new();
+        let pwm = Pwm::new(&fake_pwm.pwm_path).unwrap().with_inverted(true);
+        pwm.set(255).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "0\n");
+        pwm.set(55).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "200\n");
+        fake_pwm.val_file_write.write_all(b"200\n").unwrap();
+        assert_eq!(pwm.get().unwrap(), 55);
+
+        let pwm = pwm.with_max(Some(100));
+        pwm.set(30).unwrap();
+        assert_file_content(&mut fake_pwm.val_file_read, "70\n");
+    }
+
+    #[test]
+    fn test_max() {
+        let fake_pwm = FakePwm::new();
+        assert_eq!(Pwm::new(&fake_pwm.pwm_path).unwrap().max(), 255);
+        fs::write(fake_pwm.pwm_path.with_file_name("pwm2_max"), "100\n").unwrap();
+        let pwm = Pwm::new(&fake_pwm.pwm_path).unwrap();
+        assert_eq!(pwm.max(), 100);
+        assert_eq!(pwm.clone().with_max(None).max(), 100);
+        assert_eq!(pwm.with_max(Some(80)).max(), 80);
+        fs::write(fake_pwm.pwm_path.with_file_name("pwm2_max"), "0\n").unwrap();
+        assert!(Pwm::new(&fake_pwm.pwm_path).is_err());
+    }
+
     #[test]
     fn test_display() {
         let fake_pwm = FakePwm::new();
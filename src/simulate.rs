@@ -0,0 +1,180 @@
+//! Fan speed simulation for synthetic drive temperatures, without touching hardware
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    ops::Range,
+    path::Path,
+};
+
+use anyhow::Context as _;
+
+use crate::{
+    cl::CurveShape,
+    fan::{self, Curve, Speed},
+    probe::Temp,
+};
+
+/// Maximum number of temperatures of a ramp
+const MAX_RAMP_LEN: usize = 100_000;
+
+/// How drive temperatures map to fan speed
+#[derive(Debug)]
+pub(crate) enum Model {
+    /// Temperature range, with its ramp shape
+    Range {
+        /// Temperatures at which the fan is set to minimum/maximum speed, in Celsius
+        range: Range<Temp>,
+        /// Ramp shape in the range
+        shape: CurveShape,
+    },
+    /// Fan curve, with temperatures in Celsius
+    Curve(Curve),
+}
+
+impl Model {
+    /// Fan speed for a temperature in Celsius, as the daemon computes it
+    pub(crate) fn speed(&self, temp: Temp, min_speed: Speed) -> Speed {
+        match self {
+            Self::Range { range, shape } => fan::target_speed(temp, range, min_speed, *shape),
+            Self::Curve(curve) => fan::target_speed_curve(temp, curve, min_speed),
+        }
+    }
+}
+
+/// Temperatures from `from` to `to` included, every `step`, descending if `to` is below `from`
+pub(crate) fn ramp(from: Temp, to: Temp, step: Temp) -> anyhow::Result<Vec<Temp>> {
+    anyhow::ensure!(
+        from.is_finite() && to.is_finite(),
+        "Invalid ramp temperatures"
+    );
+    anyhow::ensure!(step.is_finite() && step > 0.0, "Ramp step must be positive");
+    let steps = ((to - from).abs() / step).floor();
+    #[expect(clippy::cast_precision_loss)]
+    let max_steps = (MAX_RAMP_LEN - 1) as f64;
+    anyhow::ensure!(
+        steps <= max_steps,
+        "Ramp has more than {MAX_RAMP_LEN} temperatures, increase its step"
+    );
+    let step = if to < from { -step } else { step };
+    #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let steps = steps as usize;
+    #[expect(clippy::cast_precision_loss)]
+    Ok((0..=steps).map(|i| from + step * i as f64).collect())
+}
+
+/// Read temperatures from a CSV file, see [`read_csv`]
+pub(crate) fn read_csv_file(path: &Path, column: Option<&str>) -> anyhow::Result<Vec<Temp>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    read_csv(BufReader::new(file), column)
+        .with_context(|| format!("Failed to read temperatures from {}", path.display()))
+}
+
+/// Read temperatures from CSV lines, in the column named `column` in the header line, or the
+/// first column.
+/// Empty values are skipped (ie. drives not probed in a `--csv-log` file), as well as a
+/// first line that is not a temperature if no column is named.
+pub(crate) fn read_csv<R: BufRead>(reader: R, column: Option<&str>) -> anyhow::Result<Vec<Temp>> {
+    let mut lines = reader.lines().enumerate();
+    let index = if let Some(column) = column {
+        let (_, header) = lines.next().context("Missing CSV header line")?;
+        let header = header.context("Failed to read CSV header line")?;
+        header
+            .split(',')
+            .position(|c| unquote(c) == column)
+            .ok_or_else(|| anyhow::anyhow!("No CSV column {column:?}"))?
+    } else {
+        0
+    };
+    let mut temps = Vec::new();
+    for (i, line) in lines {
+        let line = line.context("Failed to read CSV line")?;
+        let value = unquote(line.split(',').nth(index).unwrap_or_default());
+        if value.is_empty() {
+            continue;
+        }
+        match value.parse::<Temp>() {
+            Ok(temp) if temp.is_finite() => temps.push(temp),
+            _ if i == 0 => {}
+            _ => anyhow::bail!("Invalid temperature {value:?} at line {}", i + 1),
+        }
+    }
+    Ok(temps)
+}
+
+/// Trim a CSV field, and remove its quotes
+fn unquote(field: &str) -> &str {
+    let field = field.trim();
+    field
+        .strip_prefix('"')
+        .and_then(|f| f.strip_suffix('"'))
+        .unwrap_or(field)
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_ramp() {
+        assert_eq!(ramp(30.0, 33.0, 1.0).unwrap(), vec![30.0, 31.0, 32.0, 33.0]);
+        assert_eq!(ramp(30.0, 31.0, 0.4).unwrap().len(), 3);
+        assert_eq!(ramp(33.0, 31.0, 1.0).unwrap(), vec![33.0, 32.0, 31.0]);
+        assert_eq!(ramp(30.0, 30.0, 1.0).unwrap(), vec![30.0]);
+        assert!(ramp(30.0, 50.0, 0.0).is_err());
+        assert!(ramp(30.0, 50.0, -1.0).is_err());
+        assert!(ramp(0.0, 1e9, 1.0).is_err());
+        assert!(ramp(f64::NAN, 50.0, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_read_csv() {
+        assert_eq!(
+            read_csv("temp\n30\n\n35.5\n".as_bytes(), None).unwrap(),
+            vec![30.0, 35.5]
+        );
+        assert_eq!(
+            read_csv("30,a\n40,b\n".as_bytes(), None).unwrap(),
+            vec![30.0, 40.0]
+        );
+        assert!(read_csv("30\nhot\n".as_bytes(), None).is_err());
+
+        let csv_log = "time,/dev/sda temp,/dev/sda spun down\n\
+                       2024-01-01T00:00:00+00:00,38.5,0\n\
+                       2024-01-01T00:01:00+00:00,,1\n\
+                       2024-01-01T00:02:00+00:00,39.0,0\n";
+        assert_eq!(
+            read_csv(csv_log.as_bytes(), Some("/dev/sda temp")).unwrap(),
+            vec![38.5, 39.0]
+        );
+        assert!(read_csv(csv_log.as_bytes(), Some("/dev/sdb temp")).is_err());
+        assert!(read_csv(csv_log.as_bytes(), Some("time")).is_err());
+    }
+
+    #[test]
+    fn test_model() {
+        let min_speed = Speed::try_from(0.2).unwrap();
+        let range = Model::Range {
+            range: 30.0..50.0,
+            shape: CurveShape::Linear,
+        };
+        assert_eq!(range.speed(20.0, min_speed), min_speed);
+        assert!(approx_eq!(
+            f64,
+            f64::from(range.speed(45.0, min_speed)),
+            0.75
+        ));
+        assert!(range.speed(55.0, min_speed).is_max());
+
+        let curve = Model::Curve("35:0,45:50,50:100".parse().unwrap());
+        assert_eq!(curve.speed(30.0, min_speed), min_speed);
+        assert!(approx_eq!(
+            f64,
+            f64::from(curve.speed(40.0, min_speed)),
+            0.25
+        ));
+        assert!(curve.speed(50.0, min_speed).is_max());
+    }
+}
@@ -0,0 +1,60 @@
+//! Latest readings of the control loop
+//!
+//! Only the control loop probes devices, other consumers (ie. control socket) read the last
+//! snapshot, so that querying the daemon never wakes up a drive.
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local};
+
+use crate::probe::Temp;
+
+/// Drive reading
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct DriveReading {
+    /// Pretty name
+    pub name: String,
+    /// Device filepath
+    pub path: PathBuf,
+    /// Power state
+    pub state: String,
+    /// Is the drive spun down
+    pub spun_down: bool,
+    /// Temperature, if it was probed
+    pub temp: Option<Temp>,
+}
+
+/// Hwmon reading
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct HwmonReading {
+    /// Pretty name
+    pub name: String,
+    /// Temperature
+    pub temp: Temp,
+}
+
+/// Fan state
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct FanReading {
+    /// Pretty name
+    pub name: String,
+    /// PWM filepath
+    pub pwm: PathBuf,
+    /// Speed percentage set
+    pub speed_prct: f64,
+    /// Is the speed forced by a boost
+    pub boosted: bool,
+}
+
+/// Readings of a control loop iteration
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct Snapshot {
+    /// When the iteration completed
+    pub time: DateTime<Local>,
+    /// Drives
+    pub drives: Vec<DriveReading>,
+    /// Additional hwmon probes
+    pub hwmons: Vec<HwmonReading>,
+    /// Fans
+    pub fans: Vec<FanReading>,
+}
@@ -0,0 +1,109 @@
+//! Daemon state persisted across restarts
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+
+use crate::probe::Temp;
+
+/// Persisted state
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct State {
+    /// Highest temperature seen for each drive, since first run or last reset
+    pub max_temps: BTreeMap<PathBuf, Temp>,
+}
+
+impl State {
+    /// Load state from file, or default state if it does not exist yet
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(s) => serde_json::from_str(&s)
+                .with_context(|| format!("Failed to parse state file {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(anyhow::Error::new(e)
+                .context(format!("Failed to read state file {}", path.display()))),
+        }
+    }
+
+    /// Save state to file, atomically
+    pub(crate) fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)
+            .and_then(|()| fs::rename(&tmp_path, path))
+            .with_context(|| format!("Failed to write state file {}", path.display()))
+    }
+
+    /// Record a drive temperature, returns true if it is a new maximum
+    pub(crate) fn update_max_temp(&mut self, drive: &Path, temp: Temp) -> bool {
+        match self.max_temps.get_mut(drive) {
+            Some(max_temp) if *max_temp >= temp => false,
+            Some(max_temp) => {
+                *max_temp = temp;
+                true
+            }
+            None => {
+                self.max_temps.insert(drive.to_owned(), temp);
+                true
+            }
+        }
+    }
+
+    /// Reset maximum temperatures for some drives, or all if empty
+    pub(crate) fn reset_max_temps(&mut self, drives: &[PathBuf]) {
+        if drives.is_empty() {
+            self.max_temps.clear();
+        } else {
+            self.max_temps.retain(|d, _| !drives.contains(d));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_temps() {
+        let mut state = State::default();
+        assert!(state.update_max_temp(Path::new("/dev/sda"), 40.0));
+        assert!(!state.update_max_temp(Path::new("/dev/sda"), 39.0));
+        assert!(!state.update_max_temp(Path::new("/dev/sda"), 40.0));
+        assert!(state.update_max_temp(Path::new("/dev/sda"), 41.0));
+        assert!(state.update_max_temp(Path::new("/dev/sdb"), 30.0));
+        assert_eq!(state.max_temps.len(), 2);
+
+        state.reset_max_temps(&[PathBuf::from("/dev/sda")]);
+        assert_eq!(
+            state.max_temps.keys().collect::<Vec<_>>(),
+            vec![Path::new("/dev/sdb")]
+        );
+        state.reset_max_temps(&[]);
+        assert!(state.max_temps.is_empty());
+    }
+
+    #[test]
+    fn test_load_save() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("state.json");
+        assert!(State::load(&path).unwrap().max_temps.is_empty());
+
+        let mut state = State::default();
+        state.update_max_temp(Path::new("/dev/sda"), 42.5);
+        state.save(&path).unwrap();
+        let loaded = State::load(&path).unwrap();
+        assert_eq!(loaded.max_temps.get(Path::new("/dev/sda")), Some(&42.5));
+
+        fs::write(&path, "{}").unwrap();
+        assert!(State::load(&path).unwrap().max_temps.is_empty());
+
+        fs::write(&path, "not json").unwrap();
+        assert!(State::load(&path).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+//! Effective daemon configuration, once drives, ranges and fans are resolved
+
+use std::{fmt::Write as _, ops::Range, path::PathBuf, time::Duration};
+
+use crate::{
+    cl::{ControlMode, CurveShape, TempUnit},
+    fan::{Curve, Speed, Thresholds},
+    probe::Temp,
+    pwm,
+};
+
+/// Resolved drive settings
+pub(crate) struct DriveSummary {
+    /// Pretty name
+    pub name: String,
+    /// Device filepath
+    pub path: PathBuf,
+    /// Name of the temperature probing method
+    pub method: String,
+    /// Temperature range, in Celsius
+    pub temp_range: Range<Temp>,
+    /// Temperature offset, in Celsius
+    pub temp_offset: Temp,
+    /// Weight for aggregation
+    pub weight: f64,
+}
+
+/// Resolved hwmon settings
+pub(crate) struct HwmonSummary {
+    /// Pretty name
+    pub name: String,
+    /// Temperature range, in Celsius
+    pub temp_range: Range<Temp>,
+}
+
+/// Resolved fan settings
+pub(crate) struct FanSummary {
+    /// PWM sysfs filepath
+    pub path: PathBuf,
+    /// Start/stop thresholds, if known
+    pub thresholds: Option<Thresholds>,
+    /// Are higher PWM values lower speeds
+    pub inverted: bool,
+}
+
+/// Everything the daemon resolved from its arguments at startup
+pub(crate) struct EffectiveConfig {
+    /// Monitored drives
+    pub drives: Vec<DriveSummary>,
+    /// Monitored hwmons
+    pub hwmons: Vec<HwmonSummary>,
+    /// Controlled fans
+    pub fans: Vec<FanSummary>,
+    /// Fan zones, as displayed
+    pub zones: Vec<String>,
+    /// Drive fan curve, replacing drive temperature ranges if set
+    pub curve: Option<Curve>,
+    /// Ramp shape in temperature ranges
+    pub curve_shape: CurveShape,
+    /// Speed control strategy
+    pub control_mode: ControlMode,
+    /// Interval between iterations
+    pub interval: Duration,
+    /// Interval bounds, if the interval adapts to fan speed
+    pub intervals: Option<Range<Duration>>,
+    /// Minimum fan speed
+    pub min_fan_speed: Speed,
+    /// Minimum PWM value, replacing the minimum fan speed if set
+    pub min_fan_speed_raw: Option<pwm::Value>,
+    /// Drive and hwmon speed weights, if zone speeds are blended instead of the highest used
+    pub blend_weights: Option<(f64, f64)>,
+    /// Unit to display temperatures in
+    pub temp_unit: TempUnit,
+}
+
+impl EffectiveConfig {
+    /// Summary lines
+    pub(crate) fn lines(&self) -> Vec<String> {
+        let unit = self.temp_unit;
+        let mut lines = Vec::new();
+        for drive in &self.drives {
+            let mut line = format!(
+                "Drive {} ({}): probing method {}",
+                drive.name,
+                drive.path.display(),
+                drive.method
+            );
+            if self.curve.is_none() {
+                let _ = write!(
+                    line,
+                    ", temperature range {}-{}",
+                    unit.display(drive.temp_range.start),
+                    unit.display(drive.temp_range.end)
+                );
+            }
+            if drive.temp_offset != 0.0 {
+                let _ = write!(
+                    line,
+                    ", offset {:+}{}",
+                    unit.delta_from_celsius(drive.temp_offset),
+                    unit.symbol()
+                );
+            }
+            #[expect(clippy::float_cmp)]
+            if drive.weight != 1.0 {
+                let _ = write!(line, ", weight {}", drive.weight);
+            }
+            lines.push(line);
+        }
+        for hwmon in &self.hwmons {
+            lines.push(format!(
+                "Hwmon {}: temperature range {}-{}",
+                hwmon.name,
+                unit.display(hwmon.temp_range.start),
+                unit.display(hwmon.temp_range.end)
+            ));
+        }
+        for fan in &self.fans {
+            let mut line = format!("Fan {}: thresholds ", fan.path.display());
+            if let Some(thresholds) = fan.thresholds.as_ref() {
+                line.push_str(&thresholds.to_string());
+            } else {
+                line.push_str("unknown");
+            }
+            if fan.inverted {
+                line.push_str(", inverted");
+            }
+            lines.push(line);
+        }
+        for zone in &self.zones {
+            lines.push(format!("Zone: {zone}"));
+        }
+        if let Some(curve) = self.curve.as_ref() {
+            lines.push(format!(
+                "Drive fan curve: {}",
+                curve
+                    .points()
+                    .iter()
+                    .map(|(temp, speed)| format!("{}:{speed}", unit.display(*temp)))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        } else {
+            lines.push(format!("Curve shape: {:?}", self.curve_shape));
+        }
+        lines.push(format!("Control mode: {:?}", self.control_mode));
+        if let Some(intervals) = self.intervals.as_ref() {
+            lines.push(format!(
+                "Interval: {:?} to {:?}, depending on fan speed",
+                intervals.start, intervals.end
+            ));
+        } else {
+            lines.push(format!("Interval: {:?}", self.interval));
+        }
+        if let Some(value) = self.min_fan_speed_raw {
+            lines.push(format!("Minimum fan speed: PWM value {value}"));
+        } else {
+            lines.push(format!("Minimum fan speed: {}", self.min_fan_speed));
+        }
+        if let Some((drive_weight, hwmon_weight)) = self.blend_weights {
+            lines.push(format!(
+                "Drive/hwmon speed blend weights: {drive_weight}/{hwmon_weight}"
+            ));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines() {
+        let mut config = EffectiveConfig {
+            drives: vec![DriveSummary {
+                name: "WDC WD40EFRX".to_owned(),
+                path: "/dev/sda".into(),
+                method: "SCT".to_owned(),
+                temp_range: 30.0..50.0,
+                temp_offset: -2.0,
+                weight: 1.0,
+            }],
+            hwmons: vec![HwmonSummary {
+                name: "coretemp.0/Package id 0".to_owned(),
+                temp_range: 40.0..80.0,
+            }],
+            fans: vec![FanSummary {
+                path: "/sys/class/hwmon/hwmon1/pwm2".into(),
+                thresholds: Some(Thresholds {
+                    min_start: 200,
+                    max_stop: 75,
+                }),
+                inverted: true,
+            }],
+            zones: Vec::new(),
+            curve: None,
+            curve_shape: CurveShape::Linear,
+            control_mode: ControlMode::Curve,
+            interval: Duration::from_secs(20),
+            intervals: None,
+            min_fan_speed: Speed::try_from(0.2).unwrap(),
+            min_fan_speed_raw: None,
+            blend_weights: None,
+            temp_unit: TempUnit::Celsius,
+        };
+        assert_eq!(
+            config.lines(),
+            vec![
+                "Drive WDC WD40EFRX (/dev/sda): probing method SCT, temperature range 30°C-50°C, offset -2°C",
+                "Hwmon coretemp.0/Package id 0: temperature range 40°C-80°C",
+                "Fan /sys/class/hwmon/hwmon1/pwm2: thresholds 200/75, inverted",
+                "Curve shape: Linear",
+                "Control mode: Curve",
+                "Interval: 20s",
+                "Minimum fan speed: 20.0%",
+            ]
+        );
+
+        config.curve = Some("35:0,50:100".parse().unwrap());
+        config.intervals = Some(Duration::from_secs(5)..Duration::from_secs(60));
+        config.drives[0].temp_offset = 0.0;
+        config.drives[0].weight = 2.0;
+        config.min_fan_speed_raw = Some(60);
+        config.blend_weights = Some((0.7, 0.3));
+        let lines = config.lines();
+        assert_eq!(
+            lines[0],
+            "Drive WDC WD40EFRX (/dev/sda): probing method SCT, weight 2"
+        );
+        assert_eq!(lines[3], "Drive fan curve: 35°C:0.0%, 50°C:100.0%");
+        assert_eq!(lines[5], "Interval: 5s to 60s, depending on fan speed");
+        assert_eq!(lines[6], "Minimum fan speed: PWM value 60");
+        assert_eq!(lines[7], "Drive/hwmon speed blend weights: 0.7/0.3");
+    }
+}
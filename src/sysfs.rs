@@ -1,17 +1,23 @@
-//! Helpers to manipulate sysfs files
+//! Helpers to manipulate sysfs and procfs files
 
 use std::{
+    collections::HashMap,
     error::Error,
     fmt,
-    fs::File,
+    fs::{self, File},
     io::{Read as _, Write as _},
     os::linux::fs::MetadataExt as _,
     path::{Path, PathBuf},
     str::{self, FromStr},
 };
 
+use anyhow::Context as _;
 use nix::sys::stat;
 
+/// Block device I/O statistics
+/// See <https://docs.kernel.org/admin-guide/iostats.html>
+const DISKSTATS_PATH: &str = "/proc/diskstats";
+
 /// Ensure path is a valid sysfs file path, and normalizes it
 pub(crate) fn ensure_sysfs_file(path: &Path) -> anyhow::Result<PathBuf> {
     let path = path.canonicalize()?;
@@ -23,7 +29,8 @@ pub(crate) fn ensure_sysfs_file(path: &Path) -> anyhow::Result<PathBuf> {
         } else {
             path.is_file()
         },
-        "{path:?} missing or not a file"
+        "{} missing or not a file",
+        path.display()
     );
     Ok(path)
 }
@@ -31,7 +38,11 @@ pub(crate) fn ensure_sysfs_file(path: &Path) -> anyhow::Result<PathBuf> {
 /// Ensure path is a valid sysfs dir path, and normalizes it
 pub(crate) fn ensure_sysfs_dir(path: &Path) -> anyhow::Result<PathBuf> {
     let path = path.canonicalize()?;
-    anyhow::ensure!(path.is_dir(), "{path:?} missing or not a directory");
+    anyhow::ensure!(
+        path.is_dir(),
+        "{} missing or not a directory",
+        path.display()
+    );
     Ok(path)
 }
 
@@ -59,3 +70,45 @@ where
     let s = str::from_utf8(&buf)?.trim_end();
     Ok(s.parse::<T>()?)
 }
+
+/// Read total sectors read and written by block device name (ie. `sda`)
+pub(crate) fn read_diskstats() -> anyhow::Result<HashMap<String, u64>> {
+    let content = fs::read_to_string(DISKSTATS_PATH)
+        .with_context(|| format!("Failed to read {DISKSTATS_PATH}"))?;
+    Ok(parse_diskstats(&content))
+}
+
+/// Parse diskstats content, ignoring invalid lines
+fn parse_diskstats(content: &str) -> HashMap<String, u64> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let name = fields.get(2)?;
+            let sectors_read: u64 = fields.get(5)?.parse().ok()?;
+            let sectors_written: u64 = fields.get(9)?.parse().ok()?;
+            Some((
+                (*name).to_owned(),
+                sectors_read.saturating_add(sectors_written),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_diskstats() {
+        let stats = parse_diskstats(
+            "   8       0 sda 1941 812 172832 1183 409 290 34656 892 0 1571 2486 0 0 0 0 38 410\n\
+             8 1 sda1 1800 812 170000 1100 409 290 34656 892 0 1500 2400 0 0 0 0 0 0\n\
+             259 0 nvme0n1 120 0 5000 30 0 0 0 0 0 40 30 0 0 0 0 0 0\n\
+             garbage\n",
+        );
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats.get("sda"), Some(&(172_832 + 34_656)));
+        assert_eq!(stats.get("nvme0n1"), Some(&5000));
+    }
+}
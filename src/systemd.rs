@@ -0,0 +1,29 @@
+//! systemd service manager notifications
+//!
+//! All functions are no-ops when not started by systemd.
+
+use std::time::Duration;
+
+use sd_notify::NotifyState;
+
+/// Send notification to the service manager
+fn notify(state: NotifyState<'_>) {
+    if let Err(e) = sd_notify::notify(&[state]) {
+        log::warn!("Failed to notify service manager: {e}");
+    }
+}
+
+/// Notify service manager that startup is complete
+pub(crate) fn notify_ready() {
+    notify(NotifyState::Ready);
+}
+
+/// Watchdog timeout, if the service manager expects watchdog keep alive notifications
+pub(crate) fn watchdog_timeout() -> Option<Duration> {
+    sd_notify::watchdog_enabled()
+}
+
+/// Notify service manager that the daemon is alive
+pub(crate) fn ping_watchdog() {
+    notify(NotifyState::Watchdog);
+}
@@ -0,0 +1,124 @@
+//! Fan thresholds found by the 'pwm-test' command, cached for the daemon
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+
+use crate::{cl::PwmSettings, fan::Thresholds};
+
+/// Thresholds cache file content
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct ThresholdsCache {
+    /// Thresholds for each PWM, by canonical filepath
+    pub pwms: BTreeMap<PathBuf, Thresholds>,
+}
+
+/// Key of a PWM in the cache, so that different paths to the same PWM match
+fn cache_key(pwm_path: &Path) -> PathBuf {
+    pwm_path
+        .canonicalize()
+        .unwrap_or_else(|_| pwm_path.to_owned())
+}
+
+impl ThresholdsCache {
+    /// Load cache from file, or empty cache if it does not exist yet
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(s) => serde_json::from_str(&s)
+                .with_context(|| format!("Failed to parse thresholds file {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(anyhow::Error::new(e)
+                .context(format!("Failed to read thresholds file {}", path.display()))),
+        }
+    }
+
+    /// Save cache to file, atomically
+    pub(crate) fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)
+            .and_then(|()| fs::rename(&tmp_path, path))
+            .with_context(|| format!("Failed to write thresholds file {}", path.display()))
+    }
+
+    /// Record thresholds of a PWM
+    pub(crate) fn insert(&mut self, pwm_path: &Path, thresholds: Thresholds) {
+        self.pwms.insert(cache_key(pwm_path), thresholds);
+    }
+
+    /// Are thresholds of a PWM cached
+    pub(crate) fn contains(&self, pwm_path: &Path) -> bool {
+        self.pwms.contains_key(&cache_key(pwm_path))
+    }
+
+    /// Set thresholds of PWM settings that have none, from the cache or defaults
+    pub(crate) fn resolve(&self, settings: PwmSettings) -> PwmSettings {
+        if settings.thresholds.is_some() {
+            return settings;
+        }
+        let thresholds = if let Some(thresholds) = self.pwms.get(&cache_key(&settings.filepath)) {
+            log::info!(
+                "PWM {} start/stop thresholds loaded from cache: {thresholds}",
+                settings.filepath.display()
+            );
+            thresholds.clone()
+        } else {
+            log::warn!(
+                "No cached start/stop thresholds for PWM {}, using defaults, run 'pwm-test' to find them",
+                settings.filepath.display()
+            );
+            Thresholds::default()
+        };
+        PwmSettings {
+            thresholds: Some(thresholds),
+            ..settings
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_save_resolve() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("thresholds.json");
+        assert!(ThresholdsCache::load(&path).unwrap().pwms.is_empty());
+
+        let mut cache = ThresholdsCache::default();
+        cache.insert(
+            Path::new("/sys/class/hwmon/hwmon1/pwm2"),
+            Thresholds {
+                min_start: 200,
+                max_stop: 75,
+            },
+        );
+        cache.save(&path).unwrap();
+        let loaded = ThresholdsCache::load(&path).unwrap();
+        assert!(loaded.contains(Path::new("/sys/class/hwmon/hwmon1/pwm2")));
+        assert!(!loaded.contains(Path::new("/sys/class/hwmon/hwmon1/pwm3")));
+
+        let resolved = loaded.resolve("/sys/class/hwmon/hwmon1/pwm2".parse().unwrap());
+        let thresholds = resolved.thresholds.unwrap();
+        assert_eq!(thresholds.min_start, 200);
+        assert_eq!(thresholds.max_stop, 75);
+
+        // Explicit thresholds take precedence
+        let explicit = loaded.resolve("/sys/class/hwmon/hwmon1/pwm2:180:60".parse().unwrap());
+        assert_eq!(explicit.thresholds.unwrap().min_start, 180);
+
+        // Stale cache
+        let stale = loaded.resolve("/sys/class/hwmon/hwmon1/pwm3".parse().unwrap());
+        assert_eq!(stale.thresholds.unwrap().min_start, 0);
+
+        fs::write(&path, "not json").unwrap();
+        assert!(ThresholdsCache::load(&path).is_err());
+    }
+}
@@ -0,0 +1,310 @@
+//! Temperature trend tracking
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::{cl::WindowStat, probe::Temp};
+
+/// Number of samples to compute rate from, more samples smooth out probe resolution
+const RATE_SAMPLES: usize = 4;
+
+/// Maximum fan speed to add according to temperature rate of change
+const MAX_DERIVATIVE_SPEED: f64 = 0.5;
+
+/// Fan speed to add for a temperature rate of change, `gain` being the speed percentage
+/// per °C per minute
+pub(crate) fn derivative_speed(rate: f64, gain: f64) -> f64 {
+    (rate * gain / 100.0).clamp(0.0, MAX_DERIVATIVE_SPEED)
+}
+
+/// Rate of change of a temperature sensor, from its recent samples
+#[derive(Debug, Default)]
+pub(crate) struct RateTracker {
+    /// Recent samples, oldest first
+    samples: VecDeque<(Instant, Temp)>,
+}
+
+impl RateTracker {
+    /// Record new sample, or lack of it (ie. drive is spun down)
+    ///
+    /// Samples separated by more than `max_gap` are not considered consecutive,
+    /// and the rate is computed from the latest samples only.
+    pub(crate) fn update(&mut self, now: Instant, temp: Option<Temp>, max_gap: Duration) {
+        let Some(temp) = temp else {
+            self.samples.clear();
+            return;
+        };
+        if self
+            .samples
+            .back()
+            .is_some_and(|(t, _)| now.saturating_duration_since(*t) > max_gap)
+        {
+            self.samples.clear();
+        }
+        if self.samples.len() >= RATE_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((now, temp));
+    }
+
+    /// Rate of change in °C per minute, if there are enough recent samples
+    pub(crate) fn rate(&self) -> Option<f64> {
+        let (first_time, first_temp) = self.samples.front()?;
+        let (last_time, last_temp) = self.samples.back()?;
+        let elapsed = last_time.saturating_duration_since(*first_time);
+        (!elapsed.is_zero()).then(|| (last_temp - first_temp) * 60.0 / elapsed.as_secs_f64())
+    }
+}
+
+/// Drive temperatures over a sliding time window
+#[derive(Debug)]
+pub(crate) struct TempWindow {
+    /// Window duration, zero to disable the window
+    duration: Duration,
+    /// Statistic of the temperatures in the window
+    stat: WindowStat,
+    /// Samples in the window, oldest first
+    samples: VecDeque<(Instant, Temp)>,
+}
+
+impl TempWindow {
+    /// New empty window
+    pub(crate) fn new(duration: Duration, stat: WindowStat) -> Self {
+        Self {
+            duration,
+            stat,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Is the window enabled
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.duration.is_zero()
+    }
+
+    /// Record new sample, evict those older than the window, and get the statistic of the window
+    ///
+    /// No sample (ie. drive spun down) gives no temperature, but samples are kept until they get
+    /// older than the window.
+    pub(crate) fn update(&mut self, now: Instant, temp: Option<Temp>) -> Option<Temp> {
+        if !self.is_enabled() {
+            return temp;
+        }
+        while self
+            .samples
+            .front()
+            .is_some_and(|(t, _)| now.saturating_duration_since(*t) > self.duration)
+        {
+            self.samples.pop_front();
+        }
+        let temp = temp?;
+        self.samples.push_back((now, temp));
+        let temps: Vec<Temp> = self.samples.iter().map(|(_, t)| *t).collect();
+        self.stat.compute(&temps)
+    }
+}
+
+/// Peak drive temperature, followed instantly when rising, and decaying slowly when falling
+#[derive(Debug)]
+pub(crate) struct PeakHold {
+    /// Decay rate in °C per minute, `None` to disable holding
+    decay: Option<Temp>,
+    /// Held temperature, and when it was computed
+    held: Option<(Temp, Instant)>,
+}
+
+impl PeakHold {
+    /// New hold with no peak
+    pub(crate) fn new(decay: Option<Temp>) -> Self {
+        Self { decay, held: None }
+    }
+
+    /// Record new sample, and get the highest of it and the decayed peak
+    ///
+    /// No sample (ie. drive spun down) resets the peak.
+    pub(crate) fn update(&mut self, now: Instant, temp: Option<Temp>) -> Option<Temp> {
+        let Some(decay) = self.decay else {
+            return temp;
+        };
+        let Some(temp) = temp else {
+            self.held = None;
+            return None;
+        };
+        let held = self.held.map_or(temp, |(peak, time)| {
+            let elapsed = now.saturating_duration_since(time).as_secs_f64();
+            (decay * elapsed).mul_add(-1.0 / 60.0, peak).max(temp)
+        });
+        self.held = Some((held, now));
+        Some(held)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_derivative_speed() {
+        assert!(approx_eq!(f64, derivative_speed(2.0, 10.0), 0.2));
+        assert!(approx_eq!(f64, derivative_speed(-2.0, 10.0), 0.0));
+        assert!(approx_eq!(
+            f64,
+            derivative_speed(20.0, 10.0),
+            MAX_DERIVATIVE_SPEED
+        ));
+        assert!(approx_eq!(f64, derivative_speed(2.0, 0.0), 0.0));
+    }
+
+    #[test]
+    fn test_rate() {
+        let start = Instant::now();
+        let interval = Duration::from_secs(30);
+        let max_gap = interval * 2;
+        let mut tracker = RateTracker::default();
+        assert!(tracker.rate().is_none());
+
+        tracker.update(start, Some(40.0), max_gap);
+        assert!(tracker.rate().is_none());
+        tracker.update(start + interval, Some(41.0), max_gap);
+        assert!(approx_eq!(f64, tracker.rate().unwrap(), 2.0));
+        tracker.update(start + interval * 2, Some(41.0), max_gap);
+        tracker.update(start + interval * 3, Some(41.0), max_gap);
+        assert!(approx_eq!(f64, tracker.rate().unwrap(), 2.0 / 3.0));
+        // Oldest sample is dropped
+        tracker.update(start + interval * 4, Some(41.0), max_gap);
+        assert!(approx_eq!(f64, tracker.rate().unwrap(), 0.0));
+
+        // Spun down
+        tracker.update(start + interval * 5, None, max_gap);
+        assert!(tracker.rate().is_none());
+        tracker.update(start + interval * 6, Some(35.0), max_gap);
+        assert!(tracker.rate().is_none());
+
+        // Sparse samples
+        tracker.update(start + interval * 9, Some(40.0), max_gap);
+        assert!(tracker.rate().is_none());
+        tracker.update(start + interval * 10, Some(39.0), max_gap);
+        assert!(approx_eq!(f64, tracker.rate().unwrap(), -2.0));
+    }
+
+    #[test]
+    fn test_temp_window() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut window = TempWindow::new(Duration::from_secs(300), WindowStat::Max);
+        assert!(approx_eq!(
+            f64,
+            window.update(at(0), Some(40.0)).unwrap(),
+            40.0
+        ));
+        // Spike is kept for the window duration
+        assert!(approx_eq!(
+            f64,
+            window.update(at(60), Some(50.0)).unwrap(),
+            50.0
+        ));
+        assert!(approx_eq!(
+            f64,
+            window.update(at(120), Some(41.0)).unwrap(),
+            50.0
+        ));
+        assert!(window.update(at(180), None).is_none());
+        assert!(approx_eq!(
+            f64,
+            window.update(at(360), Some(42.0)).unwrap(),
+            50.0
+        ));
+        assert!(approx_eq!(
+            f64,
+            window.update(at(400), Some(42.0)).unwrap(),
+            42.0
+        ));
+        assert_eq!(window.samples.len(), 3);
+
+        let mut p50_window = TempWindow::new(Duration::from_secs(300), WindowStat::Percentile(50));
+        p50_window.update(at(0), Some(40.0));
+        p50_window.update(at(30), Some(60.0));
+        assert!(approx_eq!(
+            f64,
+            p50_window.update(at(60), Some(41.0)).unwrap(),
+            41.0
+        ));
+
+        let mut disabled = TempWindow::new(Duration::ZERO, WindowStat::Max);
+        assert!(!disabled.is_enabled());
+        assert!(approx_eq!(
+            f64,
+            disabled.update(at(0), Some(50.0)).unwrap(),
+            50.0
+        ));
+        assert!(approx_eq!(
+            f64,
+            disabled.update(at(30), Some(40.0)).unwrap(),
+            40.0
+        ));
+        assert!(disabled.samples.is_empty());
+    }
+
+    #[test]
+    fn test_peak_hold() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut hold = PeakHold::new(Some(1.0));
+        assert!(approx_eq!(
+            f64,
+            hold.update(at(0), Some(40.0)).unwrap(),
+            40.0
+        ));
+        // Rises are followed instantly
+        assert!(approx_eq!(
+            f64,
+            hold.update(at(30), Some(45.0)).unwrap(),
+            45.0
+        ));
+        // Falls decay at 1°C per minute
+        assert!(approx_eq!(
+            f64,
+            hold.update(at(90), Some(40.0)).unwrap(),
+            44.0
+        ));
+        assert!(approx_eq!(
+            f64,
+            hold.update(at(210), Some(40.0)).unwrap(),
+            42.0
+        ));
+        assert!(approx_eq!(
+            f64,
+            hold.update(at(570), Some(40.0)).unwrap(),
+            40.0
+        ));
+        assert!(approx_eq!(
+            f64,
+            hold.update(at(600), Some(39.0)).unwrap(),
+            39.5
+        ));
+
+        // Spun down
+        assert!(hold.update(at(630), None).is_none());
+        assert!(approx_eq!(
+            f64,
+            hold.update(at(660), Some(35.0)).unwrap(),
+            35.0
+        ));
+
+        let mut disabled = PeakHold::new(None);
+        assert!(approx_eq!(
+            f64,
+            disabled.update(at(0), Some(45.0)).unwrap(),
+            45.0
+        ));
+        assert!(approx_eq!(
+            f64,
+            disabled.update(at(30), Some(40.0)).unwrap(),
+            40.0
+        ));
+    }
+}
@@ -0,0 +1,331 @@
+//! Fan zones, associating drives and hwmons with the fans they control
+
+use std::{
+    fmt,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+
+use crate::cl::{HwmonSettings, PwmSettings, ZoneSettings};
+
+/// How early a zone can be updated before its interval has elapsed, to absorb timer jitter
+const DUE_TOLERANCE: Duration = Duration::from_millis(50);
+
+/// Resolve fan zones, or a single zone with everything if there are no zone settings
+///
+/// If `lenient`, zone inputs matching no monitored drive (ie. unplugged) are ignored.
+pub(crate) fn zones(
+    settings: &[ZoneSettings],
+    drive_paths: &[PathBuf],
+    hwmons: &[HwmonSettings],
+    pwms: &[PwmSettings],
+    lenient: bool,
+) -> anyhow::Result<Vec<Zone>> {
+    if settings.is_empty() {
+        return Ok(vec![Zone::all(drive_paths.len(), hwmons.len(), pwms.len())]);
+    }
+    let zones = settings
+        .iter()
+        .map(|z| Zone::new(z, drive_paths, hwmons, pwms, lenient))
+        .collect::<anyhow::Result<Vec<_>>>()
+        .context("Failed to setup fan zones")?;
+    for (fan_idx, pwm) in pwms.iter().enumerate() {
+        anyhow::ensure!(
+            zones.iter().any(|z| z.has_fan(fan_idx)),
+            "Fan PWM {} is not in any zone",
+            pwm.filepath.display()
+        );
+    }
+    for zone in &zones {
+        log::info!("Fan zone: {zone}");
+    }
+    Ok(zones)
+}
+
+/// Has `interval` elapsed at `now` since `last`, with some tolerance
+pub(crate) fn is_elapsed(last: Instant, interval: Duration, now: Instant) -> bool {
+    now + DUE_TOLERANCE >= last + interval
+}
+
+/// Fan zone, referencing drives, hwmons and fans by their index
+#[derive(Debug, Default)]
+pub(crate) struct Zone {
+    /// Drive indexes
+    drives: Vec<usize>,
+    /// Hwmon indexes
+    hwmons: Vec<usize>,
+    /// Fan indexes
+    fans: Vec<usize>,
+    /// Interval between updates, if set, instead of the daemon one
+    interval: Option<Duration>,
+    /// Last update time, with an interval
+    last_update: Option<Instant>,
+}
+
+impl Zone {
+    /// Zone with all drives, hwmons and fans
+    fn all(drive_count: usize, hwmon_count: usize, fan_count: usize) -> Self {
+        Self {
+            drives: (0..drive_count).collect(),
+            hwmons: (0..hwmon_count).collect(),
+            fans: (0..fan_count).collect(),
+            interval: None,
+            last_update: None,
+        }
+    }
+
+    /// Resolve zone settings against the canonical paths of the monitored drives, and the
+    /// hwmon and PWM settings
+    fn new(
+        settings: &ZoneSettings,
+        drive_paths: &[PathBuf],
+        hwmons: &[HwmonSettings],
+        pwms: &[PwmSettings],
+        lenient: bool,
+    ) -> anyhow::Result<Self> {
+        let mut zone = Self {
+            interval: settings.interval,
+            ..Self::default()
+        };
+        for input in &settings.inputs {
+            if let Some(hwmon_idx) = hwmons.iter().position(|h| h.is_selected_by(input)) {
+                zone.hwmons.push(hwmon_idx);
+                continue;
+            }
+            let indexes = input
+                .to_drive_paths()
+                .and_then(|paths| paths.iter().map(|p| p.canonicalize()).collect())
+                .with_context(|| format!("Failed to match drives for selector {input}"))
+                .and_then(|selector_paths: Vec<PathBuf>| {
+                    let indexes: Vec<usize> = drive_paths
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, p)| selector_paths.contains(p))
+                        .map(|(i, _)| i)
+                        .collect();
+                    anyhow::ensure!(
+                        !indexes.is_empty(),
+                        "Zone input {input} is not a monitored drive or hwmon"
+                    );
+                    Ok(indexes)
+                });
+            match indexes {
+                Ok(indexes) => zone.drives.extend(indexes),
+                Err(e) if lenient => log::warn!("Ignoring zone input: {e:#}"),
+                Err(e) => return Err(e),
+            }
+        }
+        for pwm in &settings.pwms {
+            let fan_idx = pwms
+                .iter()
+                .position(|p| p.filepath == *pwm)
+                .ok_or_else(|| anyhow::anyhow!("Zone PWM {} is not a fan PWM", pwm.display()))?;
+            zone.fans.push(fan_idx);
+        }
+        zone.drives.sort_unstable();
+        zone.drives.dedup();
+        zone.hwmons.sort_unstable();
+        zone.hwmons.dedup();
+        zone.fans.sort_unstable();
+        zone.fans.dedup();
+        Ok(zone)
+    }
+
+    /// Values of the zone drives, from values for all drives
+    pub(crate) fn drive_values<'a, T>(&'a self, values: &'a [T]) -> impl Iterator<Item = &'a T> {
+        self.drives.iter().filter_map(|i| values.get(*i))
+    }
+
+    /// Values of the zone hwmons, from values for all hwmons
+    pub(crate) fn hwmon_values<'a, T>(&'a self, values: &'a [T]) -> impl Iterator<Item = &'a T> {
+        self.hwmons.iter().filter_map(|i| values.get(*i))
+    }
+
+    /// Does the zone control a fan
+    pub(crate) fn has_fan(&self, fan_idx: usize) -> bool {
+        self.fans.contains(&fan_idx)
+    }
+
+    /// Does the zone reference a drive
+    pub(crate) fn has_drive(&self, drive_idx: usize) -> bool {
+        self.drives.contains(&drive_idx)
+    }
+
+    /// Does the zone have its own update interval
+    pub(crate) fn has_interval(&self) -> bool {
+        self.interval.is_some()
+    }
+
+    /// Should the zone drives be updated at `now`, zones without their own interval being
+    /// updated when `default_due` is
+    pub(crate) fn is_due(&self, now: Instant, default_due: bool) -> bool {
+        match self.interval {
+            Some(interval) => self
+                .last_update
+                .is_none_or(|last| is_elapsed(last, interval, now)),
+            None => default_due,
+        }
+    }
+
+    /// Record an update at `now` if the zone was due
+    pub(crate) fn set_updated(&mut self, now: Instant, default_due: bool) {
+        if self.interval.is_some() && self.is_due(now, default_due) {
+            self.last_update = Some(now);
+        }
+    }
+
+    /// Time of the next update, for zones with their own interval updated at least once
+    pub(crate) fn next_update(&self) -> Option<Instant> {
+        Some(self.last_update? + self.interval?)
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "drives {:?}, hwmons {:?}, fans {:?}",
+            self.drives, self.hwmons, self.fans
+        )?;
+        if let Some(interval) = self.interval {
+            write!(f, ", interval {interval:?}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let drive_paths: Vec<PathBuf> = ["sda", "sdb", "nvme0n1"]
+            .iter()
+            .map(|n| {
+                let path = dir.path().join(n);
+                File::create(&path).unwrap();
+                path.canonicalize().unwrap()
+            })
+            .collect();
+        let hwmons: Vec<HwmonSettings> =
+            vec!["/sys/class/hwmon/hwmon2/temp1_input:40:60".parse().unwrap()];
+        let pwms: Vec<PwmSettings> = ["pwm1", "pwm2"]
+            .iter()
+            .map(|n| PwmSettings {
+                filepath: PathBuf::from("/sys/class/hwmon/hwmon1").join(n),
+                thresholds: None,
+                inverted: false,
+            })
+            .collect();
+
+        let settings: ZoneSettings = format!(
+            "{},{},/sys/class/hwmon/hwmon2/temp1_input=/sys/class/hwmon/hwmon1/pwm2",
+            drive_paths[2].display(),
+            drive_paths[0].display()
+        )
+        .parse()
+        .unwrap();
+        let zone = Zone::new(&settings, &drive_paths, &hwmons, &pwms, false).unwrap();
+        assert_eq!(zone.drives, vec![0, 2]);
+        assert_eq!(zone.hwmons, vec![0]);
+        assert_eq!(zone.fans, vec![1]);
+        assert!(!zone.has_fan(0));
+        assert!(zone.has_fan(1));
+        assert_eq!(
+            zone.drive_values(&[10, 11, 12])
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![10, 12]
+        );
+        assert_eq!(
+            zone.hwmon_values(&[20]).copied().collect::<Vec<_>>(),
+            vec![20]
+        );
+
+        // Unknown drive
+        let other_drive = dir.path().join("sdc");
+        File::create(&other_drive).unwrap();
+        let unknown_drive: ZoneSettings =
+            format!("{}=/sys/class/hwmon/hwmon1/pwm2", other_drive.display())
+                .parse()
+                .unwrap();
+        assert!(Zone::new(&unknown_drive, &drive_paths, &hwmons, &pwms, false).is_err());
+        // Unplugged drive
+        let lenient_zone = Zone::new(&unknown_drive, &drive_paths, &hwmons, &pwms, true).unwrap();
+        assert!(lenient_zone.drives.is_empty());
+        assert_eq!(lenient_zone.fans, vec![1]);
+
+        // Unknown fan
+        let unknown_fan: ZoneSettings =
+            format!("{}=/sys/class/hwmon/hwmon1/pwm3", drive_paths[0].display())
+                .parse()
+                .unwrap();
+        assert!(Zone::new(&unknown_fan, &drive_paths, &hwmons, &pwms, true).is_err());
+
+        assert!("/dev/sda".parse::<ZoneSettings>().is_err());
+        assert!(
+            "=/sys/class/hwmon/hwmon1/pwm2"
+                .parse::<ZoneSettings>()
+                .is_err()
+        );
+        assert!("/dev/sda=".parse::<ZoneSettings>().is_err());
+    }
+
+    #[test]
+    fn test_interval() {
+        let settings: ZoneSettings = "/dev/nvme0n1=/sys/class/hwmon/hwmon1/pwm1@3s"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            settings.pwms,
+            vec![PathBuf::from("/sys/class/hwmon/hwmon1/pwm1")]
+        );
+        assert_eq!(settings.interval, Some(Duration::from_secs(3)));
+        assert!(
+            "/dev/sda=/sys/class/hwmon/hwmon1/pwm1"
+                .parse::<ZoneSettings>()
+                .unwrap()
+                .interval
+                .is_none()
+        );
+        assert!(
+            "/dev/sda=/sys/class/hwmon/hwmon1/pwm1@fast"
+                .parse::<ZoneSettings>()
+                .is_err()
+        );
+        assert!(
+            "/dev/sda=/sys/class/hwmon/hwmon1/pwm1@0s"
+                .parse::<ZoneSettings>()
+                .is_err()
+        );
+        assert!("/dev/sda=@3s".parse::<ZoneSettings>().is_err());
+
+        let mut zone = Zone {
+            drives: vec![0],
+            interval: settings.interval,
+            ..Zone::default()
+        };
+        let start = Instant::now();
+        assert!(zone.has_interval());
+        assert!(zone.is_due(start, false));
+        assert!(zone.next_update().is_none());
+        zone.set_updated(start, false);
+        assert_eq!(zone.next_update(), Some(start + Duration::from_secs(3)));
+        assert!(!zone.is_due(start + Duration::from_secs(1), true));
+        assert!(zone.is_due(start + Duration::from_millis(2990), false));
+        zone.set_updated(start + Duration::from_secs(1), true);
+        assert_eq!(zone.next_update(), Some(start + Duration::from_secs(3)));
+
+        let default_zone = Zone::all(1, 0, 1);
+        assert!(!default_zone.has_interval());
+        assert!(default_zone.is_due(start, true));
+        assert!(!default_zone.is_due(start, false));
+        assert!(default_zone.next_update().is_none());
+    }
+}